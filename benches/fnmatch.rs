@@ -0,0 +1,23 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use pmv::fnmatch;
+
+fn bench_fnmatch(c: &mut Criterion) {
+    let long_name = "a".repeat(1000);
+    let long_name_txt = format!("{}.txt", "a.".repeat(500));
+
+    c.bench_function("literal", |b| {
+        b.iter(|| fnmatch(black_box("foobar.txt"), black_box("foobar.txt")))
+    });
+    c.bench_function("star_long_name", |b| {
+        b.iter(|| fnmatch(black_box("*.txt"), black_box(&long_name_txt)))
+    });
+    c.bench_function("many_stars_no_match", |b| {
+        b.iter(|| fnmatch(black_box("*a*a*a*a*a*a*a*a*b"), black_box(&long_name)))
+    });
+    c.bench_function("star_questions", |b| {
+        b.iter(|| fnmatch(black_box("*??????????"), black_box(&long_name)))
+    });
+}
+
+criterion_group!(benches, bench_fnmatch);
+criterion_main!(benches);
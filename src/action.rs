@@ -5,6 +5,9 @@ use std::path::{Path, PathBuf};
 pub struct Action {
     src: PathBuf,
     dest: PathBuf,
+    label: Option<String>,
+    id: Option<usize>,
+    exact_dest: bool,
 }
 
 impl Action {
@@ -13,9 +16,31 @@ impl Action {
         Action {
             src: src.into(),
             dest: dest.into(),
+            label: None,
+            id: None,
+            exact_dest: false,
         }
     }
 
+    /// Attaches a label explaining why this action was planned.
+    pub fn with_label<S: Into<String>>(mut self, label: S) -> Action {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Attaches an ID identifying this action in a plan.
+    pub fn with_id(mut self, id: usize) -> Action {
+        self.id = Some(id);
+        self
+    }
+
+    /// Makes the destination the exact path of the moved file even if it is an existing
+    /// directory or a symbolic link to one, which the file would be moved into otherwise.
+    pub fn with_exact_dest(mut self) -> Action {
+        self.exact_dest = true;
+        self
+    }
+
     /// Returns the path to the file to move.
     pub fn src(self: &Action) -> &Path {
         self.src.as_path()
@@ -25,6 +50,35 @@ impl Action {
     pub fn dest(self: &Action) -> &Path {
         self.dest.as_path()
     }
+
+    /// Returns the label explaining why this action was planned, if any.
+    pub fn label(self: &Action) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Returns the ID identifying this action in a plan, if any.
+    pub fn id(self: &Action) -> Option<usize> {
+        self.id
+    }
+
+    /// Tests whether the destination is the exact path of the moved file. See `with_exact_dest`.
+    pub fn exact_dest(self: &Action) -> bool {
+        self.exact_dest
+    }
+
+    /// Returns the path where the file will be after the move.
+    ///
+    /// Like mv(1), if the destination is an existing directory (following symbolic links), the
+    /// file is moved into it keeping its name, unless the action has `with_exact_dest`.
+    pub fn final_dest(self: &Action) -> PathBuf {
+        let mut dest = self.dest.clone();
+        if !self.exact_dest && dest.is_dir() {
+            if let Some(name) = self.src.file_name() {
+                dest.push(name);
+            }
+        }
+        dest
+    }
 }
 
 impl<'a> From<&'a Action> for (&'a Path, &'a Path) {
@@ -36,13 +90,54 @@ impl<'a> From<&'a Action> for (&'a Path, &'a Path) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use function_name::named;
 
     #[test]
     fn derive_debug() {
         let action = Action::new("A", "B");
         assert_eq!(
             format!("{:?}", action),
-            "Action { src: \"A\", dest: \"B\" }"
+            "Action { src: \"A\", dest: \"B\", label: None, id: None, exact_dest: false }"
         );
     }
+
+    #[test]
+    fn label() {
+        let action = Action::new("A", "B");
+        assert_eq!(action.label(), None);
+        let action = action.with_label("reason");
+        assert_eq!(action.label(), Some("reason"));
+    }
+
+    #[test]
+    fn id() {
+        let action = Action::new("A", "B");
+        assert_eq!(action.id(), None);
+        assert_eq!(action.with_id(3).id(), Some(3));
+    }
+
+    #[cfg(unix)]
+    #[named]
+    #[test]
+    fn final_dest() {
+        use std::fs;
+        use std::os::unix::fs::symlink;
+
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("d")).unwrap();
+        symlink("d", dir.join("link")).unwrap();
+
+        let action = Action::new("a/f", dir.join("d"));
+        assert_eq!(action.final_dest(), dir.join("d/f"));
+        let action = Action::new("a/f", dir.join("new"));
+        assert_eq!(action.final_dest(), dir.join("new"));
+
+        // Symbolic links to directories are followed by default
+        let action = Action::new("a/f", dir.join("link"));
+        assert_eq!(action.final_dest(), dir.join("link/f"));
+        let action = action.with_exact_dest();
+        assert!(action.exact_dest());
+        assert_eq!(action.final_dest(), dir.join("link"));
+    }
 }
@@ -0,0 +1,497 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Formats of archives, determined by the extension of their names.
+#[cfg(feature = "archive")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+#[cfg(feature = "archive")]
+impl Format {
+    fn of(archive: &Path) -> Result<Format, String> {
+        let name = archive
+            .file_name()
+            .map(|name| name.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".zip") {
+            Ok(Format::Zip)
+        } else if name.ends_with(".tar") {
+            Ok(Format::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Ok(Format::TarGz)
+        } else {
+            Err(format!(
+                "unknown archive format of \"{}\"; use .zip, .tar, .tar.gz or .tgz",
+                archive.to_string_lossy()
+            ))
+        }
+    }
+}
+
+/// Makes the name of a member from a path relative to the root of an archive.
+///
+/// Components are joined with `/`. Fails if the path is absolute, goes up with `..` or is not
+/// valid UTF-8.
+pub(crate) fn member_name(path: &Path) -> Result<String, String> {
+    use std::path::Component;
+
+    let invalid = |reason: &str| {
+        format!(
+            "\"{}\" cannot be a member of an archive; {}",
+            path.to_string_lossy(),
+            reason
+        )
+    };
+    let mut names = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => match name.to_str() {
+                Some(name) => names.push(name),
+                None => return Err(invalid("it is not valid UTF-8")),
+            },
+            Component::CurDir => (),
+            Component::ParentDir => return Err(invalid("it is outside the archive")),
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(invalid("it is an absolute path"))
+            }
+        }
+    }
+    if names.is_empty() {
+        return Err(invalid("it is empty"));
+    }
+    Ok(names.join("/"))
+}
+
+/// Returns the names of the files in an archive, in the order they are stored.
+#[cfg(feature = "archive")]
+pub(crate) fn list_files(archive: &Path) -> Result<Vec<String>, String> {
+    let failed =
+        |err: io::Error| format!("failed to read \"{}\": {}", archive.to_string_lossy(), err);
+    let format = Format::of(archive)?;
+    let file = std::fs::File::open(archive).map_err(failed)?;
+    match format {
+        Format::Zip => zip_format::list_files(file),
+        Format::Tar => tar_format::list_files(file),
+        Format::TarGz => tar_format::list_files(flate2::read::GzDecoder::new(file)),
+    }
+    .map_err(failed)
+}
+
+#[cfg(not(feature = "archive"))]
+pub(crate) fn list_files(_archive: &Path) -> Result<Vec<String>, String> {
+    Err(String::from(
+        "archives are not available; pmv was built without the `archive` feature",
+    ))
+}
+
+/// Adds files to an archive as members of the given names, creating the archive if it does not
+/// exist.
+///
+/// `files` are pairs of a path and the name of its member. Fails if a member of the same name
+/// already exists. The archive is written to a temporary file which replaces the original only
+/// after all the files were added, so the archive is left untouched on failure.
+#[cfg(feature = "archive")]
+pub(crate) fn add_files(archive: &Path, files: &[(PathBuf, String)]) -> Result<(), String> {
+    use std::fs::{self, File};
+
+    let format = Format::of(archive)?;
+    let exists = archive.exists();
+    if exists {
+        let names = list_files(archive)?;
+        if let Some((_, name)) = files.iter().find(|(_, name)| names.contains(name)) {
+            return Err(format!(
+                "\"{}\" already exists in \"{}\"",
+                name,
+                archive.to_string_lossy()
+            ));
+        }
+    }
+
+    let temp = crate::plan::temp_name(archive, &[]).ok_or_else(|| {
+        format!(
+            "failed to find a temporary name for \"{}\"",
+            archive.to_string_lossy()
+        )
+    })?;
+    let write = || -> io::Result<()> {
+        let input = if exists {
+            Some(File::open(archive)?)
+        } else {
+            None
+        };
+        let output = File::create(&temp)?;
+        match format {
+            Format::Zip => zip_format::write(input, output, files),
+            Format::Tar => tar_format::write(input, output, files).map(drop),
+            Format::TarGz => {
+                use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+                let output = GzEncoder::new(output, Compression::default());
+                let output = tar_format::write(input.map(GzDecoder::new), output, files)?;
+                output.finish().map(drop)
+            }
+        }?;
+        fs::rename(&temp, archive)
+    };
+    write().map_err(|err| {
+        let _ = fs::remove_file(&temp);
+        format!("failed to write \"{}\": {}", archive.to_string_lossy(), err)
+    })
+}
+
+#[cfg(not(feature = "archive"))]
+pub(crate) fn add_files(_archive: &Path, _files: &[(PathBuf, String)]) -> Result<(), String> {
+    Err(String::from(
+        "archives are not available; pmv was built without the `archive` feature",
+    ))
+}
+
+/// Extracts members of an archive to the given paths, creating missing parent directories.
+///
+/// `members` are pairs of the name of a member and the path to extract it to. Existing files are
+/// never overwritten. Fails if the archive cannot be read; otherwise `on_error` is called for
+/// each member which could not be extracted and the number of them is returned.
+#[cfg(feature = "archive")]
+pub(crate) fn extract_files(
+    archive: &Path,
+    members: &[(String, PathBuf)],
+    on_error: &dyn Fn(&str, &Path, &io::Error),
+) -> Result<usize, String> {
+    let failed =
+        |err: io::Error| format!("failed to read \"{}\": {}", archive.to_string_lossy(), err);
+    let format = Format::of(archive)?;
+    let file = std::fs::File::open(archive).map_err(failed)?;
+    match format {
+        Format::Zip => zip_format::extract(file, members, on_error),
+        Format::Tar => tar_format::extract(file, members, on_error),
+        Format::TarGz => tar_format::extract(flate2::read::GzDecoder::new(file), members, on_error),
+    }
+    .map_err(failed)
+}
+
+#[cfg(not(feature = "archive"))]
+pub(crate) fn extract_files(
+    _archive: &Path,
+    _members: &[(String, PathBuf)],
+    _on_error: &dyn Fn(&str, &Path, &io::Error),
+) -> Result<usize, String> {
+    Err(String::from(
+        "archives are not available; pmv was built without the `archive` feature",
+    ))
+}
+
+/// Creates a file to extract a member to, failing if it already exists.
+#[cfg(feature = "archive")]
+fn create_new(path: &Path) -> io::Result<std::fs::File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+#[cfg(feature = "archive")]
+mod zip_format {
+    use super::create_new;
+    use chrono::{Datelike, Local, TimeZone, Timelike};
+    use std::convert::TryFrom;
+    use std::fs::File;
+    use std::io::{self, Read, Seek, Write};
+    use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
+    use zip::result::ZipError;
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+    fn to_io(err: ZipError) -> io::Error {
+        match err {
+            ZipError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+
+    pub fn list_files<R: Read + Seek>(reader: R) -> io::Result<Vec<String>> {
+        let mut archive = ZipArchive::new(reader).map_err(to_io)?;
+        let mut names = Vec::new();
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i).map_err(to_io)?;
+            if file.is_file() {
+                names.push(String::from(file.name()));
+            }
+        }
+        Ok(names)
+    }
+
+    /// Writes the members of `input` followed by `files` to `output`.
+    pub fn write<R: Read + Seek, W: Write + Seek>(
+        input: Option<R>,
+        output: W,
+        files: &[(PathBuf, String)],
+    ) -> io::Result<()> {
+        let mut writer = ZipWriter::new(output);
+        if let Some(input) = input {
+            let mut archive = ZipArchive::new(input).map_err(to_io)?;
+            for i in 0..archive.len() {
+                let file = archive.by_index_raw(i).map_err(to_io)?;
+                writer.raw_copy_file(file).map_err(to_io)?;
+            }
+        }
+        for (path, name) in files {
+            let mut file = File::open(path)?;
+            let metadata = file.metadata()?;
+            let mut options = FileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .large_file(u64::from(u32::MAX) <= metadata.len());
+            if let Some(mtime) = metadata.modified().ok().and_then(to_zip_time) {
+                options = options.last_modified_time(mtime);
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                options = options.unix_permissions(metadata.permissions().mode());
+            }
+            writer.start_file(name.as_str(), options).map_err(to_io)?;
+            io::copy(&mut file, &mut writer)?;
+        }
+        writer.finish().map_err(to_io)?;
+        Ok(())
+    }
+
+    pub fn extract<R: Read + Seek>(
+        reader: R,
+        members: &[(String, PathBuf)],
+        on_error: &dyn Fn(&str, &Path, &io::Error),
+    ) -> io::Result<usize> {
+        let mut archive = ZipArchive::new(reader).map_err(to_io)?;
+        let mut num_errors = 0;
+        for (name, dest) in members {
+            let mut extract = || -> io::Result<()> {
+                let mut member = archive.by_name(name).map_err(to_io)?;
+                let mut file = create_new(dest)?;
+                if let Err(err) = io::copy(&mut member, &mut file) {
+                    let _ = std::fs::remove_file(dest);
+                    return Err(err);
+                }
+                if let Some(mtime) = from_zip_time(member.last_modified()) {
+                    file.set_modified(mtime)?;
+                }
+                #[cfg(unix)]
+                if let Some(mode) = member.unix_mode() {
+                    use std::os::unix::fs::PermissionsExt;
+                    file.set_permissions(std::fs::Permissions::from_mode(mode & 0o7777))?;
+                }
+                Ok(())
+            };
+            if let Err(err) = extract() {
+                on_error(name, dest, &err);
+                num_errors += 1;
+            }
+        }
+        Ok(num_errors)
+    }
+
+    fn to_zip_time(time: SystemTime) -> Option<DateTime> {
+        let time = chrono::DateTime::<Local>::from(time);
+        DateTime::from_date_and_time(
+            u16::try_from(time.year()).ok()?,
+            time.month() as u8,
+            time.day() as u8,
+            time.hour() as u8,
+            time.minute() as u8,
+            time.second() as u8,
+        )
+        .ok()
+    }
+    fn from_zip_time(time: DateTime) -> Option<SystemTime> {
+        let time = Local
+            .with_ymd_and_hms(
+                i32::from(time.year()),
+                u32::from(time.month()),
+                u32::from(time.day()),
+                u32::from(time.hour()),
+                u32::from(time.minute()),
+                u32::from(time.second()),
+            )
+            .earliest()?;
+        Some(time.into())
+    }
+}
+
+#[cfg(feature = "archive")]
+mod tar_format {
+    use std::collections::HashMap;
+    use std::io::{self, Read, Write};
+    use std::path::{Path, PathBuf};
+    use tar::{Archive, Builder, EntryType};
+
+    pub fn list_files<R: Read>(reader: R) -> io::Result<Vec<String>> {
+        let mut archive = Archive::new(reader);
+        let mut names = Vec::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            if is_file(entry.header().entry_type()) {
+                names.push(entry.path()?.to_string_lossy().into_owned());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Writes the entries of `input` followed by `files` to `output`, returning `output`.
+    pub fn write<R: Read, W: Write>(
+        input: Option<R>,
+        output: W,
+        files: &[(PathBuf, String)],
+    ) -> io::Result<W> {
+        let mut builder = Builder::new(output);
+        if let Some(input) = input {
+            let mut archive = Archive::new(input);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let mut header = entry.header().clone();
+                let path = entry.path()?.into_owned();
+                builder.append_data(&mut header, path, &mut entry)?;
+            }
+        }
+        for (path, name) in files {
+            builder.append_path_with_name(path, name)?;
+        }
+        builder.into_inner()
+    }
+
+    pub fn extract<R: Read>(
+        reader: R,
+        members: &[(String, PathBuf)],
+        on_error: &dyn Fn(&str, &Path, &io::Error),
+    ) -> io::Result<usize> {
+        let mut dests: HashMap<&str, &Path> = members
+            .iter()
+            .map(|(name, dest)| (name.as_str(), dest.as_path()))
+            .collect();
+        let mut archive = Archive::new(reader);
+        let mut num_errors = 0;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !is_file(entry.header().entry_type()) {
+                continue;
+            }
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let dest = match dests.remove(name.as_str()) {
+                Some(dest) => dest,
+                None => continue,
+            };
+            // Reserve the destination so that an existing file is never overwritten
+            let result = super::create_new(dest).and_then(|_| {
+                entry.unpack(dest).map(drop).inspect_err(|_| {
+                    let _ = std::fs::remove_file(dest);
+                })
+            });
+            if let Err(err) = result {
+                on_error(&name, dest, &err);
+                num_errors += 1;
+            }
+        }
+        for (name, dest) in dests {
+            let err = io::Error::new(io::ErrorKind::NotFound, "no such member");
+            on_error(name, dest, &err);
+            num_errors += 1;
+        }
+        Ok(num_errors)
+    }
+
+    fn is_file(entry_type: EntryType) -> bool {
+        matches!(entry_type, EntryType::Regular | EntryType::Continuous)
+    }
+}
+
+#[cfg(all(test, feature = "archive"))]
+mod tests {
+    use super::*;
+    use function_name::named;
+    use std::fs;
+
+    fn prepare(function_name: &str) -> PathBuf {
+        let dir = PathBuf::from("temp").join(function_name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("b.txt"), "b").unwrap();
+        dir
+    }
+
+    #[test]
+    fn member_names() {
+        assert_eq!(member_name(Path::new("a/b.txt")).unwrap(), "a/b.txt");
+        assert_eq!(member_name(Path::new("./a/./b.txt")).unwrap(), "a/b.txt");
+        assert!(member_name(Path::new("../a.txt")).is_err());
+        assert!(member_name(Path::new("/a.txt")).is_err());
+        assert!(member_name(Path::new(".")).is_err());
+    }
+
+    fn add_twice(dir: &Path, archive: &Path) {
+        let files = [(dir.join("a.txt"), String::from("x/a.txt"))];
+        add_files(archive, &files).unwrap();
+        let files = [(dir.join("b.txt"), String::from("b.txt"))];
+        add_files(archive, &files).unwrap();
+        assert_eq!(list_files(archive).unwrap(), vec!["x/a.txt", "b.txt"]);
+
+        // Existing members are never replaced
+        let files = [(dir.join("b.txt"), String::from("x/a.txt"))];
+        assert!(add_files(archive, &files).is_err());
+        assert_eq!(list_files(archive).unwrap(), vec!["x/a.txt", "b.txt"]);
+    }
+
+    fn extract(dir: &Path, archive: &Path) {
+        let members = [
+            (String::from("x/a.txt"), dir.join("out/a.txt")),
+            (String::from("b.txt"), dir.join("b.txt")),
+            (String::from("missing"), dir.join("out/missing")),
+        ];
+        let errors = std::cell::RefCell::new(Vec::new());
+        let num_errors = extract_files(archive, &members, &|name, _, _| {
+            errors.borrow_mut().push(String::from(name));
+        });
+        assert_eq!(num_errors, Ok(2));
+        assert_eq!(errors.into_inner(), vec!["b.txt", "missing"]);
+        assert_eq!(fs::read_to_string(dir.join("out/a.txt")).unwrap(), "a");
+        assert!(!dir.join("out/missing").exists());
+    }
+
+    #[named]
+    #[test]
+    fn zip() {
+        let dir = prepare(function_name!());
+        add_twice(&dir, &dir.join("a.zip"));
+        extract(&dir, &dir.join("a.zip"));
+    }
+
+    #[named]
+    #[test]
+    fn tar() {
+        let dir = prepare(function_name!());
+        add_twice(&dir, &dir.join("a.tar"));
+        extract(&dir, &dir.join("a.tar"));
+    }
+
+    #[named]
+    #[test]
+    fn tar_gz() {
+        let dir = prepare(function_name!());
+        add_twice(&dir, &dir.join("a.tgz"));
+        extract(&dir, &dir.join("a.tgz"));
+    }
+
+    #[named]
+    #[test]
+    fn unknown_format() {
+        let dir = prepare(function_name!());
+        let files = [(dir.join("a.txt"), String::from("a.txt"))];
+        assert!(add_files(&dir.join("a.rar"), &files).is_err());
+        assert!(!dir.join("a.rar").exists());
+    }
+}
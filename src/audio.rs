@@ -0,0 +1,276 @@
+use std::path::Path;
+
+/// Tags of an audio file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AudioTags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<u32>,
+}
+
+/// Reads ID3 tags of MP3 files or Vorbis comments of FLAC, Ogg Vorbis and Opus files.
+///
+/// The format is determined by the extension. Files of other formats have no tags.
+#[cfg(feature = "audio")]
+pub fn read_tags(path: &Path) -> Result<AudioTags, String> {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let failed = |err: String| {
+        format!(
+            "failed to read tags of \"{}\": {}",
+            path.to_string_lossy(),
+            err
+        )
+    };
+    if ext == "mp3" {
+        return read_id3(path).map_err(failed);
+    }
+    let comments = match ext.as_str() {
+        "flac" | "ogg" | "oga" | "opus" => {
+            let file = File::open(path).map_err(|err| failed(err.to_string()))?;
+            let mut reader = BufReader::new(file);
+            let comments = if ext == "flac" {
+                flac::read_comments(&mut reader)
+            } else {
+                ogg::read_comments(&mut reader)
+            };
+            comments.map_err(|err| failed(err.to_string()))?
+        }
+        _ => Vec::new(),
+    };
+    Ok(from_comments(&comments))
+}
+
+#[cfg(not(feature = "audio"))]
+pub fn read_tags(_path: &Path) -> Result<AudioTags, String> {
+    Err(String::from(
+        "audio tags are not available; pmv was built without the `audio` feature",
+    ))
+}
+
+#[cfg(feature = "audio")]
+fn read_id3(path: &Path) -> Result<AudioTags, String> {
+    use id3::{ErrorKind, TagLike};
+
+    match id3::v1v2::read_from_path(path) {
+        Ok(tag) => Ok(AudioTags {
+            artist: tag.artist().map(String::from),
+            album: tag.album().map(String::from),
+            title: tag.title().map(String::from),
+            track: tag.track(),
+        }),
+        Err(err) if matches!(err.kind, ErrorKind::NoTag) => Ok(AudioTags::default()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Builds tags from Vorbis comments (`KEY=value` pairs).
+#[cfg(feature = "audio")]
+fn from_comments(comments: &[String]) -> AudioTags {
+    let mut tags = AudioTags::default();
+    for comment in comments {
+        let (key, value) = match comment.split_once('=') {
+            Some((key, value)) if !value.is_empty() => (key, value),
+            _ => continue,
+        };
+        let value = String::from(value);
+        match key.to_ascii_uppercase().as_str() {
+            "ARTIST" => tags.artist = tags.artist.or(Some(value)),
+            "ALBUM" => tags.album = tags.album.or(Some(value)),
+            "TITLE" => tags.title = tags.title.or(Some(value)),
+            "TRACKNUMBER" => {
+                // Track numbers may be written like `3/12`
+                let number = value.split('/').next().unwrap_or("");
+                tags.track = tags.track.or_else(|| number.trim().parse().ok());
+            }
+            _ => (),
+        }
+    }
+    tags
+}
+
+/// Parses a Vorbis comment header, which is used by both FLAC and Ogg.
+#[cfg(feature = "audio")]
+fn parse_comments(data: &[u8]) -> Option<Vec<String>> {
+    fn read_u32(data: &[u8], pos: &mut usize) -> Option<usize> {
+        let bytes = data.get(*pos..*pos + 4)?;
+        *pos += 4;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    let mut pos = 0;
+    let vendor_len = read_u32(data, &mut pos)?;
+    pos += vendor_len;
+    let count = read_u32(data, &mut pos)?;
+    let mut comments = Vec::new();
+    for _ in 0..count {
+        let len = read_u32(data, &mut pos)?;
+        let comment = data.get(pos..pos + len)?;
+        pos += len;
+        comments.push(String::from_utf8_lossy(comment).into_owned());
+    }
+    Some(comments)
+}
+
+#[cfg(feature = "audio")]
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(feature = "audio")]
+mod flac {
+    use super::{invalid_data, parse_comments};
+    use std::io::{self, Read, Seek, SeekFrom};
+
+    const VORBIS_COMMENT: u8 = 4;
+
+    pub fn read_comments<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<String>> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != b"fLaC" {
+            return Err(invalid_data("not a FLAC file"));
+        }
+        loop {
+            let mut header = [0u8; 4];
+            reader.read_exact(&mut header)?;
+            let is_last = header[0] & 0x80 != 0;
+            let block_type = header[0] & 0x7f;
+            let len = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+            if block_type == VORBIS_COMMENT {
+                let mut data = vec![0u8; len as usize];
+                reader.read_exact(&mut data)?;
+                return parse_comments(&data).ok_or_else(|| invalid_data("broken comments"));
+            }
+            if is_last {
+                return Ok(Vec::new());
+            }
+            reader.seek(SeekFrom::Current(i64::from(len)))?;
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+mod ogg {
+    use super::{invalid_data, parse_comments};
+    use std::io::{self, Read};
+
+    /// Reads the comment header, which is the second packet of a Vorbis or Opus stream.
+    pub fn read_comments<R: Read>(reader: &mut R) -> io::Result<Vec<String>> {
+        let mut packets: Vec<Vec<u8>> = vec![Vec::new()];
+        while packets.len() <= 2 {
+            let mut header = [0u8; 27];
+            reader.read_exact(&mut header)?;
+            if &header[..4] != b"OggS" {
+                return Err(invalid_data("not an Ogg file"));
+            }
+            let mut segments = vec![0u8; header[26] as usize];
+            reader.read_exact(&mut segments)?;
+            for len in segments {
+                let mut data = vec![0u8; len as usize];
+                reader.read_exact(&mut data)?;
+                packets.last_mut().unwrap().extend_from_slice(&data);
+                if len < 255 {
+                    packets.push(Vec::new());
+                }
+            }
+        }
+
+        let packet = &packets[1];
+        let data = if packet.starts_with(b"\x03vorbis") {
+            &packet[7..]
+        } else if packet.starts_with(b"OpusTags") {
+            &packet[8..]
+        } else {
+            return Err(invalid_data("unsupported Ogg stream"));
+        };
+        parse_comments(data).ok_or_else(|| invalid_data("broken comments"))
+    }
+}
+
+#[cfg(all(test, feature = "audio"))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn comment_header(comments: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(b"test");
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+        data
+    }
+
+    fn ogg_page(packet: &[u8]) -> Vec<u8> {
+        let mut page = Vec::new();
+        page.extend_from_slice(b"OggS");
+        page.extend_from_slice(&[0u8; 22]);
+        let mut segments = vec![255u8; packet.len() / 255];
+        segments.push((packet.len() % 255) as u8);
+        page.push(segments.len() as u8);
+        page.extend_from_slice(&segments);
+        page.extend_from_slice(packet);
+        page
+    }
+
+    #[test]
+    fn comments() {
+        let tags = from_comments(&[
+            String::from("artist=Foo"),
+            String::from("ALBUM=Bar"),
+            String::from("TITLE="),
+            String::from("TRACKNUMBER=3/12"),
+        ]);
+        assert_eq!(
+            tags,
+            AudioTags {
+                artist: Some(String::from("Foo")),
+                album: Some(String::from("Bar")),
+                title: None,
+                track: Some(3),
+            }
+        );
+    }
+
+    #[test]
+    fn flac_comments() {
+        let comments = comment_header(&["TITLE=Baz"]);
+        let mut data = Vec::from(&b"fLaC"[..]);
+        data.extend_from_slice(&[0, 0, 0, 2, 0xff, 0xff]); // STREAMINFO (truncated)
+        data.extend_from_slice(&[0x80 | 4, 0, 0, comments.len() as u8]);
+        data.extend_from_slice(&comments);
+        assert_eq!(
+            flac::read_comments(&mut Cursor::new(data)).unwrap(),
+            vec![String::from("TITLE=Baz")]
+        );
+    }
+
+    #[test]
+    fn ogg_comments() {
+        let title = format!("TITLE={}", "x".repeat(300));
+        let mut packet = Vec::from(&b"\x03vorbis"[..]);
+        packet.extend_from_slice(&comment_header(&[&title]));
+        let mut data = ogg_page(b"\x01vorbis");
+        data.extend_from_slice(&ogg_page(&packet));
+        data.extend_from_slice(&ogg_page(b"\x05vorbis"));
+        assert_eq!(
+            ogg::read_comments(&mut Cursor::new(data)).unwrap(),
+            vec![title]
+        );
+    }
+
+    #[test]
+    fn not_ogg() {
+        assert!(ogg::read_comments(&mut Cursor::new(b"fLaC".to_vec())).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::quote::quote_path;
+use crate::Action;
+use std::path::Path;
+
+/// Renders chains of actions as indented trees.
+///
+/// Each chain is shown under a heading, starting with the action to execute first; each of the
+/// other actions is nested under the one which vacates its destination. Circular chains are
+/// marked with `(cycle)`, and actions with a label (e.g. moves to a temporary name) are followed
+/// by it. Paths are shown relative to `base` if possible.
+pub fn render_chain_tree(chains: &[Vec<Action>], base: &Path) -> String {
+    let mut out = String::new();
+    for (i, chain) in chains.iter().enumerate() {
+        out.push_str(&format!(
+            "Chain {}{}:\n",
+            i + 1,
+            if is_circular(chain) { " (cycle)" } else { "" }
+        ));
+        for (depth, action) in chain.iter().enumerate() {
+            if 0 < depth {
+                out.push_str(&"    ".repeat(depth - 1));
+                out.push_str("└── ");
+            }
+            if let Some(id) = action.id() {
+                out.push_str(&format!("[{}] ", id));
+            }
+            out.push_str(&format!(
+                "{} --> {}",
+                relative(action.src(), base),
+                relative(action.dest(), base)
+            ));
+            if let Some(label) = action.label() {
+                out.push_str(&format!(" ({})", label));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Renders chains of actions as a graph in the DOT language of Graphviz.
+///
+/// Each path is a node and each action is an edge labeled with its step in the order of
+/// execution (and its ID if any.) Each chain is a cluster, and temporary names inserted to
+/// break cycles are drawn with dashed lines. Paths are shown relative to `base` if possible.
+pub fn render_chain_dot(chains: &[Vec<Action>], base: &Path) -> String {
+    let mut out = String::from("digraph plan {\n");
+    let mut step = 0;
+    for (i, chain) in chains.iter().enumerate() {
+        out.push_str(&format!("    subgraph cluster_{} {{\n", i + 1));
+        out.push_str(&format!(
+            "        label=\"chain {}{}\";\n",
+            i + 1,
+            if is_circular(chain) { " (cycle)" } else { "" }
+        ));
+        if is_circular(chain) {
+            out.push_str(&format!(
+                "        {} [style=dashed];\n",
+                dot_id(&relative(chain[0].dest(), base))
+            ));
+        }
+        for action in chain {
+            step += 1;
+            let mut label = format!("step {}", step);
+            if let Some(id) = action.id() {
+                label.push_str(&format!(" [{}]", id));
+            }
+            out.push_str(&format!(
+                "        {} -> {} [label={}{}];\n",
+                dot_id(&relative(action.src(), base)),
+                dot_id(&relative(action.dest(), base)),
+                dot_id(&label),
+                if action.label().is_some() {
+                    ", style=dashed"
+                } else {
+                    ""
+                }
+            ));
+        }
+        out.push_str("    }\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Tests whether a chain was circular, i.e. it moves a file to a temporary name first and
+/// moves it back from there last.
+fn is_circular(chain: &[Action]) -> bool {
+    match (chain.first(), chain.last()) {
+        (Some(first), Some(last)) => 2 <= chain.len() && first.dest() == last.src(),
+        _ => false,
+    }
+}
+
+/// Quotes a string as an ID of the DOT language.
+fn dot_id(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn relative(path: &Path, base: &Path) -> String {
+    let path = path.strip_prefix(base).unwrap_or(path);
+    quote_path(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::sort_chains;
+    use std::path::PathBuf;
+
+    fn chains(base: &Path) -> Vec<Vec<Action>> {
+        // A→B→C→A is a cycle and D→E→F is not
+        let actions = vec![
+            Action::new(base.join("A"), base.join("B")).with_id(1),
+            Action::new(base.join("B"), base.join("C")).with_id(2),
+            Action::new(base.join("C"), base.join("A")).with_id(3),
+            Action::new(base.join("D"), base.join("E")).with_id(4),
+            Action::new(base.join("E"), base.join("F")).with_id(5),
+        ];
+        sort_chains(&actions, &Default::default()).unwrap()
+    }
+
+    fn base() -> PathBuf {
+        std::env::current_dir().unwrap().join("temp").join("chains")
+    }
+
+    #[test]
+    fn tree() {
+        let base = base();
+        let chains = chains(&base);
+        let tmp = relative(chains[0][0].dest(), &base);
+        assert_eq!(
+            render_chain_tree(&chains, &base),
+            format!(
+                "Chain 1 (cycle):\n\
+                 C --> {tmp} (temporary name to break a cycle)\n\
+                 └── [2] B --> C\n    \
+                 └── [1] A --> B\n        \
+                 └── {tmp} --> A (temporary name to break a cycle)\n\
+                 Chain 2:\n\
+                 [5] E --> F\n\
+                 └── [4] D --> E\n",
+                tmp = tmp
+            )
+        );
+    }
+
+    #[test]
+    fn dot() {
+        let base = base();
+        let chains = chains(&base);
+        let tmp = relative(chains[0][0].dest(), &base);
+        assert_eq!(
+            render_chain_dot(&chains, &base),
+            format!(
+                "digraph plan {{\n    \
+                 subgraph cluster_1 {{\n        \
+                 label=\"chain 1 (cycle)\";\n        \
+                 \"{tmp}\" [style=dashed];\n        \
+                 \"C\" -> \"{tmp}\" [label=\"step 1\", style=dashed];\n        \
+                 \"B\" -> \"C\" [label=\"step 2 [2]\"];\n        \
+                 \"A\" -> \"B\" [label=\"step 3 [1]\"];\n        \
+                 \"{tmp}\" -> \"A\" [label=\"step 4\", style=dashed];\n    \
+                 }}\n    \
+                 subgraph cluster_2 {{\n        \
+                 label=\"chain 2\";\n        \
+                 \"E\" -> \"F\" [label=\"step 5 [5]\"];\n        \
+                 \"D\" -> \"E\" [label=\"step 6 [4]\"];\n    \
+                 }}\n\
+                 }}\n",
+                tmp = tmp
+            )
+        );
+        assert_eq!(dot_id("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}
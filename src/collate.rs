@@ -0,0 +1,137 @@
+use std::cmp::Ordering;
+use std::path::Path;
+
+/// How to order paths for display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collation {
+    /// Compares paths byte by byte.
+    #[default]
+    Bytewise,
+    /// Compares runs of digits by their numeric values (e.g.: `2` comes before `10`.)
+    Natural,
+    /// Compares paths according to the collation rules of the current locale.
+    #[cfg(feature = "locale")]
+    Locale,
+}
+
+/// Sorts items by their paths in the given collation.
+pub fn sort_by_path<T, F>(items: &mut [T], collation: Collation, key: F)
+where
+    F: Fn(&T) -> &Path,
+{
+    match collation {
+        Collation::Bytewise => items.sort_by(|a, b| key(a).cmp(key(b))),
+        Collation::Natural => {
+            items.sort_by(|a, b| natural_cmp(&key(a).to_string_lossy(), &key(b).to_string_lossy()))
+        }
+        #[cfg(feature = "locale")]
+        Collation::Locale => {
+            let collator = locale_collator();
+            items.sort_by(|a, b| {
+                let a = key(a).to_string_lossy();
+                let b = key(b).to_string_lossy();
+                match collator {
+                    Some(ref collator) => collator.compare(&a, &b),
+                    None => a.cmp(&b),
+                }
+            })
+        }
+    }
+}
+
+/// Compares two strings treating each run of digits as a number.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let na = take_digits(&mut a);
+                let nb = take_digits(&mut b);
+                // Compare by the number of significant digits first, then lexically
+                let (ta, tb) = (na.trim_start_matches('0'), nb.trim_start_matches('0'));
+                let ord = ta.len().cmp(&tb.len()).then_with(|| ta.cmp(tb));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                let ord = ca.cmp(cb);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_digits<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(*c);
+        chars.next();
+    }
+    digits
+}
+
+/// Makes a collator for the locale specified by the environment variables.
+#[cfg(feature = "locale")]
+fn locale_collator() -> Option<icu_collator::Collator> {
+    use icu_collator::{Collator, CollatorOptions};
+    use icu_locid::Locale;
+
+    // Convert POSIX style locale names (e.g.: `ja_JP.UTF-8`) into BCP 47 (e.g.: `ja-JP`)
+    let name = ["LC_ALL", "LC_COLLATE", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    let name = name
+        .split(['.', '@'])
+        .next()
+        .unwrap_or("")
+        .replace('_', "-");
+    let locale: Locale = name.parse().unwrap_or(Locale::UND);
+    Collator::try_new(&locale.into(), CollatorOptions::new()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn sorted(names: &[&str], collation: Collation) -> Vec<String> {
+        let mut paths: Vec<PathBuf> = names.iter().map(PathBuf::from).collect();
+        sort_by_path(&mut paths, collation, |p| p.as_path());
+        paths
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn bytewise() {
+        assert_eq!(
+            sorted(&["b10", "b2", "B1", "a"], Collation::Bytewise),
+            vec!["B1", "a", "b10", "b2"]
+        );
+    }
+
+    #[test]
+    fn natural() {
+        assert_eq!(
+            sorted(&["b10", "b2", "b02x", "a"], Collation::Natural),
+            vec!["a", "b2", "b02x", "b10"]
+        );
+        assert_eq!(natural_cmp("x007", "x7"), Ordering::Equal);
+        assert_eq!(natural_cmp("img9.jpg", "img10.jpg"), Ordering::Less);
+    }
+}
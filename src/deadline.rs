@@ -0,0 +1,85 @@
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for pmv to stop by itself after the time limit before exiting forcibly.
+const GRACE: Duration = Duration::from_secs(10);
+
+/// A time limit of a whole run, checked between files.
+///
+/// A check cannot interrupt a system call which hangs (e.g. on an unresponsive network mount),
+/// so a watchdog thread exits the process if pmv is still running `GRACE` after the limit. The
+/// watchdog stops when the `Deadline` is dropped.
+#[derive(Debug)]
+pub(crate) struct Deadline {
+    limit: Duration,
+    at: Instant,
+    _watchdog: Sender<()>,
+}
+
+impl Deadline {
+    /// Starts counting the time limit.
+    pub(crate) fn start(limit: Duration) -> Deadline {
+        let at = Instant::now() + limit;
+        let (sender, receiver) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(limit + GRACE) {
+                crate::print_error(format!(
+                    "timed out after {} second(s) and did not stop in time; exiting",
+                    limit.as_secs()
+                ));
+                process::exit(1);
+            }
+        });
+        Deadline {
+            limit,
+            at,
+            _watchdog: sender,
+        }
+    }
+
+    /// Returns whether the time limit has passed.
+    pub(crate) fn expired(&self) -> bool {
+        self.at <= Instant::now()
+    }
+
+    /// Returns the message telling that the limit has passed during the given phase.
+    pub(crate) fn error(&self, phase: &str) -> String {
+        format!(
+            "timed out after {} second(s) while {}",
+            self.limit.as_secs(),
+            phase
+        )
+    }
+
+    /// Fails with `error` if the time limit has passed.
+    pub(crate) fn check(&self, phase: &str) -> Result<(), String> {
+        if self.expired() {
+            Err(self.error(phase))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiry() {
+        let deadline = Deadline::start(Duration::from_secs(3600));
+        assert!(!deadline.expired());
+        assert_eq!(deadline.check("moving files"), Ok(()));
+
+        let deadline = Deadline::start(Duration::from_secs(0));
+        assert!(deadline.expired());
+        assert_eq!(
+            deadline.check("moving files"),
+            Err(String::from(
+                "timed out after 0 second(s) while moving files"
+            ))
+        );
+    }
+}
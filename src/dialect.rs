@@ -0,0 +1,177 @@
+/// Syntax of SOURCE and DEST patterns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// The syntax of pmv.
+    #[default]
+    Pmv,
+    /// The syntax of mmv(1), which uses `#l1` and `#u1` for case conversion.
+    Mmv,
+}
+
+/// Translates SOURCE and DEST patterns written in the dialect to the syntax of pmv.
+pub fn translate(src: &str, dest: &str, dialect: Dialect) -> Result<(String, String), String> {
+    match dialect {
+        Dialect::Pmv => Ok((String::from(src), String::from(dest))),
+        Dialect::Mmv => {
+            let (src, recursive) = translate_mmv_source(src)?;
+            Ok((src, translate_mmv_dest(dest, &recursive)?))
+        }
+    }
+}
+
+/// Translates a "from" pattern of mmv, returning it with the indices of the recursive wildcards.
+///
+/// `*`, `?` and `[...]` are the same in both tools and are numbered in the same order. The
+/// recursive wildcard `;`, which matches zero or more directories at the beginning of a path
+/// component, is translated into `**/`.
+fn translate_mmv_source(src: &str) -> Result<(String, Vec<usize>), String> {
+    let mut translated = String::new();
+    let mut recursive = Vec::new();
+    let mut index = 0;
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if !(translated.is_empty() || translated.ends_with('/')) {
+                    return Err(String::from(
+                        "the recursive wildcard `;` must be at the beginning of a path component",
+                    ));
+                }
+                index += 1;
+                recursive.push(index);
+                translated.push_str("**/");
+                continue;
+            }
+            '*' => {
+                // Consecutive `*`s are a single wildcard in pmv
+                while chars.peek() == Some(&'*') {
+                    chars.next();
+                    translated.push('*');
+                }
+                index += 1;
+            }
+            '?' => index += 1,
+            '[' if src.contains(']') => {
+                translated.push(c);
+                for c in chars.by_ref() {
+                    translated.push(c);
+                    if c == ']' {
+                        break;
+                    }
+                }
+                index += 1;
+                continue;
+            }
+            _ => (),
+        }
+        translated.push(c);
+    }
+    Ok((translated, recursive))
+}
+
+/// Translates a "to" pattern of mmv.
+///
+/// `#l1` and `#u1` (lowercase and uppercase of `#1`) are translated into `#{1:lower}` and
+/// `#{1:upper}`. As the directories matched by `;` end with a slash in mmv but not in pmv, a
+/// slash is appended to the wildcards in `recursive` unless they matched nothing.
+fn translate_mmv_dest(dest: &str, recursive: &[usize]) -> Result<String, String> {
+    let mut translated = String::new();
+    let mut chars = dest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '#' {
+            translated.push(c);
+            continue;
+        }
+        let filter = match chars.peek() {
+            Some('l') => Some("lower"),
+            Some('u') => Some("upper"),
+            _ => None,
+        };
+        if filter.is_some() {
+            chars.next();
+        }
+        let mut digits = String::new();
+        while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+            digits.push(*d);
+            chars.next();
+        }
+        match (digits.as_str(), filter) {
+            ("", None) => translated.push('#'),
+            ("", Some(filter)) => {
+                return Err(format!(
+                    "`#{}` must be followed by a wildcard index",
+                    &filter[..1]
+                ))
+            }
+            (index, _) if index.len() != 1 || index == "0" => {
+                return Err(format!(
+                    "`#{}` is out of range; pmv supports only `#1` to `#9`",
+                    index
+                ))
+            }
+            (index, filter) => {
+                let variable = match filter {
+                    None => format!("#{}", index),
+                    Some(filter) => format!("#{{{}:{}}}", index, filter),
+                };
+                if recursive.iter().any(|n| n.to_string() == index) {
+                    translated.push_str(&format!("#{{{}:?{}/}}", index, variable));
+                } else {
+                    translated.push_str(&variable);
+                }
+            }
+        }
+    }
+    Ok(translated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pmv() {
+        assert_eq!(
+            translate("*;", "#l1", Dialect::Pmv),
+            Ok((String::from("*;"), String::from("#l1")))
+        );
+    }
+
+    #[test]
+    fn mmv_dest() {
+        assert_eq!(
+            translate_mmv_dest("#l1_#2.#u3", &[]),
+            Ok(String::from("#{1:lower}_#2.#{3:upper}"))
+        );
+        assert_eq!(translate_mmv_dest("a#b", &[]), Ok(String::from("a#b")));
+        assert!(translate_mmv_dest("#10", &[]).is_err());
+        assert!(translate_mmv_dest("#l", &[]).is_err());
+    }
+
+    #[test]
+    fn mmv_source() {
+        assert_eq!(
+            translate_mmv_source("*.c"),
+            Ok((String::from("*.c"), Vec::new()))
+        );
+        assert!(translate_mmv_source("a;*.c").is_err());
+    }
+
+    #[test]
+    fn mmv_recursive() {
+        assert_eq!(
+            translate("src/;[ab]*.c", "dst/#1#u3.#2", Dialect::Mmv),
+            Ok((
+                String::from("src/**/[ab]*.c"),
+                String::from("dst/#{1:?#1/}#{3:upper}.#2")
+            ))
+        );
+        assert_eq!(
+            translate(";*.c", "#l1#2.h", Dialect::Mmv),
+            Ok((
+                String::from("**/*.c"),
+                String::from("#{1:?#{1:lower}/}#2.h")
+            ))
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::path::Path;
+
+/// Reads the width and height of a PNG or JPEG image or an MP4 video from its header.
+///
+/// Returns `None` if the file is not in one of those formats.
+#[cfg(feature = "dimensions")]
+pub fn read_dimensions(path: &Path) -> Result<Option<(u32, u32)>, String> {
+    use std::fs::File;
+    use std::io::{BufReader, Read, Seek, SeekFrom};
+
+    let failed = |err: std::io::Error| {
+        format!(
+            "failed to read dimensions of \"{}\": {}",
+            path.to_string_lossy(),
+            err
+        )
+    };
+    let file = File::open(path).map_err(failed)?;
+    let mut reader = BufReader::new(file);
+    let mut magic = [0u8; 8];
+    let n = reader.read(&mut magic).map_err(failed)?;
+    reader.seek(SeekFrom::Start(0)).map_err(failed)?;
+    let magic = &magic[..n];
+    let dimensions = if magic.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png::read_dimensions(&mut reader)
+    } else if magic.starts_with(b"\xff\xd8") {
+        jpeg::read_dimensions(&mut reader)
+    } else if magic.len() == 8 && &magic[4..] == b"ftyp" {
+        mp4::read_dimensions(&mut reader)
+    } else {
+        return Ok(None);
+    };
+    dimensions.map_err(failed)
+}
+
+#[cfg(not(feature = "dimensions"))]
+pub fn read_dimensions(_path: &Path) -> Result<Option<(u32, u32)>, String> {
+    Err(String::from(
+        "dimensions are not available; pmv was built without the `dimensions` feature",
+    ))
+}
+
+#[cfg(feature = "dimensions")]
+fn read_u16<R: std::io::Read>(reader: &mut R) -> std::io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+#[cfg(feature = "dimensions")]
+fn read_u32<R: std::io::Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+#[cfg(feature = "dimensions")]
+mod png {
+    use super::read_u32;
+    use std::io::{self, Read, Seek, SeekFrom};
+
+    /// Reads the IHDR chunk, which always comes first.
+    pub fn read_dimensions<R: Read + Seek>(reader: &mut R) -> io::Result<Option<(u32, u32)>> {
+        reader.seek(SeekFrom::Start(8))?;
+        let _length = read_u32(reader)?;
+        let mut chunk_type = [0u8; 4];
+        reader.read_exact(&mut chunk_type)?;
+        if &chunk_type != b"IHDR" {
+            return Ok(None);
+        }
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        Ok(Some((width, height)))
+    }
+}
+
+#[cfg(feature = "dimensions")]
+mod jpeg {
+    use super::read_u16;
+    use std::io::{self, Read, Seek, SeekFrom};
+
+    /// Scans the segments for a start-of-frame (SOFn) one.
+    pub fn read_dimensions<R: Read + Seek>(reader: &mut R) -> io::Result<Option<(u32, u32)>> {
+        reader.seek(SeekFrom::Start(2))?;
+        loop {
+            let mut byte = [0u8; 1];
+            reader.read_exact(&mut byte)?;
+            if byte[0] != 0xff {
+                return Ok(None);
+            }
+            // Skip fill bytes
+            let mut marker = 0xff;
+            while marker == 0xff {
+                reader.read_exact(&mut byte)?;
+                marker = byte[0];
+            }
+            match marker {
+                // Markers without payload
+                0x01 | 0xd0..=0xd7 => continue,
+                // End of image or start of scan
+                0xd9 | 0xda => return Ok(None),
+                // SOFn, except DHT, JPG and DAC which share the range
+                0xc0..=0xcf if marker != 0xc4 && marker != 0xc8 && marker != 0xcc => {
+                    let _length = read_u16(reader)?;
+                    reader.read_exact(&mut byte)?; // sample precision
+                    let height = read_u16(reader)?;
+                    let width = read_u16(reader)?;
+                    return Ok(Some((u32::from(width), u32::from(height))));
+                }
+                _ => {
+                    let length = read_u16(reader)?;
+                    reader.seek(SeekFrom::Current(i64::from(length) - 2))?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "dimensions")]
+mod mp4 {
+    use super::read_u32;
+    use std::io::{self, Read, Seek, SeekFrom};
+
+    /// Reads the size and the type of a box, returning the size of its payload.
+    fn read_box_header<R: Read>(reader: &mut R) -> io::Result<(u64, [u8; 4])> {
+        let size = read_u32(reader)?;
+        let mut box_type = [0u8; 4];
+        reader.read_exact(&mut box_type)?;
+        let payload = match size {
+            0 => u64::MAX, // extends to the end of the file
+            1 => {
+                let large = (u64::from(read_u32(reader)?) << 32) | u64::from(read_u32(reader)?);
+                large.saturating_sub(16)
+            }
+            _ => u64::from(size).saturating_sub(8),
+        };
+        Ok((payload, box_type))
+    }
+
+    /// Finds the first track header (`moov/trak/tkhd`) with non-zero width.
+    pub fn read_dimensions<R: Read + Seek>(reader: &mut R) -> io::Result<Option<(u32, u32)>> {
+        let mut moov = Vec::new();
+        loop {
+            let (size, box_type) = match read_box_header(reader) {
+                Ok(header) => header,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            if &box_type == b"moov" {
+                reader.take(size).read_to_end(&mut moov)?;
+                break;
+            }
+            if size == u64::MAX {
+                return Ok(None);
+            }
+            reader.seek(SeekFrom::Current(size as i64))?;
+        }
+
+        let mut moov = io::Cursor::new(moov);
+        while let Ok((size, box_type)) = read_box_header(&mut moov) {
+            let end = moov.position().saturating_add(size);
+            if &box_type == b"trak" {
+                while moov.position() < end {
+                    let (size, box_type) = read_box_header(&mut moov)?;
+                    let next = moov.position().saturating_add(size);
+                    if &box_type == b"tkhd" {
+                        if let Some(dimensions) = read_tkhd(&mut moov)? {
+                            return Ok(Some(dimensions));
+                        }
+                    }
+                    moov.set_position(next);
+                }
+            }
+            moov.set_position(end);
+        }
+        Ok(None)
+    }
+
+    fn read_tkhd<R: Read + Seek>(reader: &mut R) -> io::Result<Option<(u32, u32)>> {
+        let version = read_u32(reader)? >> 24;
+        // Skip times, track ID and duration, then reserved fields, layer, alternate group,
+        // volume and the matrix
+        let skip = if version == 1 { 32 } else { 20 } + 8 + 8 + 36;
+        reader.seek(SeekFrom::Current(skip))?;
+        // Width and height are 16.16 fixed-point numbers
+        let width = read_u32(reader)? >> 16;
+        let height = read_u32(reader)? >> 16;
+        if width == 0 || height == 0 {
+            return Ok(None);
+        }
+        Ok(Some((width, height)))
+    }
+}
+
+#[cfg(all(test, feature = "dimensions"))]
+mod tests {
+    use super::*;
+    use function_name::named;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn prepare_test(id: &str) -> PathBuf {
+        let dir = std::env::current_dir().unwrap().join("temp").join(id);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn mp4_box(box_type: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn tkhd(width: u32, height: u32) -> Vec<u8> {
+        let mut payload = vec![0u8; 4 + 20 + 8 + 8 + 36];
+        payload.extend_from_slice(&(width << 16).to_be_bytes());
+        payload.extend_from_slice(&(height << 16).to_be_bytes());
+        mp4_box(b"tkhd", &payload)
+    }
+
+    #[named]
+    #[test]
+    fn png() {
+        let dir = prepare_test(function_name!());
+        let path = dir.join("a.png");
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend_from_slice(&640u32.to_be_bytes());
+        data.extend_from_slice(&480u32.to_be_bytes());
+        fs::write(&path, data).unwrap();
+        assert_eq!(read_dimensions(&path), Ok(Some((640, 480))));
+    }
+
+    #[named]
+    #[test]
+    fn jpeg() {
+        let dir = prepare_test(function_name!());
+        let path = dir.join("a.jpg");
+        let mut data = b"\xff\xd8".to_vec();
+        data.extend_from_slice(b"\xff\xe0\x00\x04\x00\x00"); // APP0
+        data.extend_from_slice(b"\xff\xc0\x00\x0b\x08\x02\xd0\x05\x00\x01\x01\x11\x00"); // SOF0
+        fs::write(&path, data).unwrap();
+        assert_eq!(read_dimensions(&path), Ok(Some((1280, 720))));
+    }
+
+    #[named]
+    #[test]
+    fn mp4() {
+        let dir = prepare_test(function_name!());
+        let path = dir.join("a.mp4");
+        let audio = mp4_box(b"trak", &tkhd(0, 0));
+        let video = mp4_box(b"trak", &tkhd(1920, 1080));
+        let mut moov = audio;
+        moov.extend_from_slice(&video);
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend_from_slice(&mp4_box(b"mdat", &[0u8; 16]));
+        data.extend_from_slice(&mp4_box(b"moov", &moov));
+        fs::write(&path, data).unwrap();
+        assert_eq!(read_dimensions(&path), Ok(Some((1920, 1080))));
+    }
+
+    #[named]
+    #[test]
+    fn unknown_format() {
+        let dir = prepare_test(function_name!());
+        let path = dir.join("a.txt");
+        fs::write(&path, "hello").unwrap();
+        assert_eq!(read_dimensions(&path), Ok(None));
+        assert!(read_dimensions(&dir.join("missing")).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::Action;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Estimated cost of executing actions.
+#[derive(Debug, Default, PartialEq)]
+pub struct Estimate {
+    /// Number of moves within a device, which are plain renames.
+    pub renames: usize,
+    /// Number of moves across devices, which will fail as pmv does not copy files.
+    pub cross_device: usize,
+    /// Directories which must exist before moving files into them.
+    pub missing_dirs: Vec<PathBuf>,
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rename(s), {} move(s) across file systems (will fail), {} missing director{}",
+            self.renames,
+            self.cross_device,
+            self.missing_dirs.len(),
+            if self.missing_dirs.len() == 1 {
+                "y"
+            } else {
+                "ies"
+            }
+        )
+    }
+}
+
+/// Estimates the cost of executing actions by comparing devices of sources and destinations.
+pub fn estimate(actions: &[Action]) -> Estimate {
+    let mut estimate = Estimate::default();
+    for action in actions {
+        let final_dest = action.final_dest();
+        let dest_dir = final_dest.parent();
+        if let Some(dir) = dest_dir {
+            if !dir.exists() && !estimate.missing_dirs.iter().any(|d| d == dir) {
+                estimate.missing_dirs.push(dir.to_owned());
+            }
+        }
+
+        let same_device = match dest_dir.and_then(existing_ancestor) {
+            Some(dir) => is_same_device(action.src(), dir),
+            None => true,
+        };
+        if same_device {
+            estimate.renames += 1;
+        } else {
+            estimate.cross_device += 1;
+        }
+    }
+    estimate
+}
+
+/// Returns the nearest ancestor (including itself) of the path which exists.
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|p| p.exists())
+}
+
+/// Tests whether both paths are on the same device or not.
+#[cfg(unix)]
+fn is_same_device(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.symlink_metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true, // Let the rename report the problem
+    }
+}
+
+/// Tests whether both paths are on the same device or not.
+#[cfg(not(unix))]
+fn is_same_device(a: &Path, b: &Path) -> bool {
+    // Compare drive letters or UNC shares
+    let root = |path: &Path| {
+        path.canonicalize()
+            .ok()
+            .and_then(|p| p.components().next().map(|c| c.as_os_str().to_owned()))
+    };
+    match (root(a), root(b)) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(&b),
+        _ => true,
+    }
+}
+
+/// Returns the total size of a file or the files under a directory.
+pub(crate) fn size_of(path: &Path) -> u64 {
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| size_of(&entry.path()))
+            .sum(),
+        Err(_) => 0,
+    }
+}
+
+/// Formats a size in bytes with a binary prefix (e.g.: `1.5 MiB`).
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while 1024.0 <= value && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use function_name::named;
+
+    fn prepare_test(id: &str) -> PathBuf {
+        let dir = std::env::current_dir().unwrap().join("temp").join(id);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("d")).unwrap();
+        fs::write(dir.join("a"), "aaaa").unwrap();
+        fs::write(dir.join("d/b"), "bb").unwrap();
+        dir
+    }
+
+    #[named]
+    #[test]
+    fn same_device() {
+        let dir = prepare_test(function_name!());
+        let actions = vec![
+            Action::new(dir.join("a"), dir.join("d/a")),
+            Action::new(dir.join("d"), dir.join("new/sub/d")),
+        ];
+        let estimate = estimate(&actions);
+        assert_eq!(estimate.renames, 2);
+        assert_eq!(estimate.cross_device, 0);
+        assert_eq!(estimate.missing_dirs, vec![dir.join("new/sub")]);
+    }
+
+    #[named]
+    #[test]
+    fn sizes() {
+        let dir = prepare_test(function_name!());
+        assert_eq!(size_of(&dir.join("a")), 4);
+        assert_eq!(size_of(&dir), 6);
+        assert_eq!(size_of(&dir.join("missing")), 0);
+    }
+
+    #[test]
+    fn display() {
+        let estimate = Estimate {
+            renames: 3,
+            cross_device: 1,
+            missing_dirs: vec![PathBuf::from("x")],
+        };
+        assert_eq!(
+            estimate.to_string(),
+            "3 rename(s), 1 move(s) across file systems (will fail), 1 missing directory"
+        );
+        assert_eq!(format_bytes(10), "10 bytes");
+        assert_eq!(format_bytes(3 * 1024 * 1024 / 2), "1.5 MiB");
+    }
+}
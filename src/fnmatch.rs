@@ -1,5 +1,75 @@
+use crate::pathnorm::{chars_eq, to_nfc};
+use crate::regexp::RegexPattern;
+use crate::walk::FollowSymlinks;
+use std::ffi::{OsStr, OsString};
+
+/// Options to control how patterns are interpreted and matched with directory trees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchOptions {
+    /// If true, only parts enclosed by parentheses are captured instead of every wildcard.
+    /// Inside parentheses, `|` separates alternatives (e.g.: `(*)_final.(mp4|mov)`).
+    pub capture_groups: bool,
+    /// How `walk` treats symbolic links.
+    pub follow_symlinks: FollowSymlinks,
+    /// Whether names are matched ignoring case.
+    pub case_sensitivity: CaseSensitivity,
+    /// If true, patterns are regular expressions instead of wildcard patterns. Each capture
+    /// group yields a matched part.
+    pub regex: bool,
+    /// If true, patterns and names are compared in Unicode Normalization Form C, so that
+    /// composed and decomposed forms of a character (e.g. `é` and `e` + U+0301) match. Matched
+    /// parts are in NFC too. This requires the `unicode-normalization` feature.
+    pub normalize_unicode: bool,
+}
+
+/// How cases of letters are compared in matching names.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaseSensitivity {
+    /// Ignore case of ASCII letters on Windows and compare case sensitively elsewhere, as file
+    /// systems usually do.
+    #[default]
+    Platform,
+    /// Compare case sensitively, even on Windows (e.g. for case sensitive NTFS directories.)
+    Sensitive,
+    /// Ignore case of letters on every platform.
+    Insensitive,
+}
+
+impl CaseSensitivity {
+    /// Returns whether names differing only in case are regarded as the same.
+    pub fn ignores_case(self) -> bool {
+        match self {
+            CaseSensitivity::Platform => cfg!(windows),
+            CaseSensitivity::Sensitive => false,
+            CaseSensitivity::Insensitive => true,
+        }
+    }
+}
+
 /// Matches a file name with a pattern and returns matched parts.
 ///
+/// Each wildcard in the pattern yields one matched part. The wildcards are:
+///
+/// - `?` matches exactly one character.
+/// - `*` matches zero or more characters. If a name can be matched in several ways, each `*`
+///   matches as few characters as possible, from left to right (so a `*` followed by `?`
+///   leaves the characters to the `?`, and `*` at the end of a pattern matches the rest.)
+/// - Consecutive `*`s are the same as a single `*` and yield only one matched part.
+/// - `[...]` matches one character listed in the brackets, such as `[abc]`, `[0-9]` or
+///   `[A-Za-z_]`. A range must not be reversed (e.g.: `[z-a]` is an error.) It
+///   matches a character not listed if the list starts with `!` or `^`. To list `]`, put it
+///   first (`[]a]`). A `[` without the closing `]` is an ordinary character, and `[[]` matches
+///   `[` itself.
+/// - In brackets, a POSIX character class `[:NAME:]` lists the characters of a kind, such as
+///   `[[:digit:]]` or `[![:space:]_]`. NAME is one of `alnum`, `alpha`, `blank`, `cntrl`,
+///   `digit`, `graph`, `lower`, `print`, `punct`, `space`, `upper` and `xdigit`, and an unknown
+///   NAME is an error. Letters are Unicode letters (e.g. `[[:alpha:]]` matches `写`) while
+///   `digit` and `xdigit` are ASCII only.
+///
+/// Names are compared case insensitively on Windows, and on every platform with
+/// `CaseSensitivity::Insensitive`. `CaseSensitivity::Sensitive` compares them case sensitively
+/// even on Windows.
+///
 /// # Examples
 ///
 /// ```no run
@@ -13,121 +83,598 @@
 /// assert_eq!(fnmatch("f*??r", "blah"), None);
 /// ```
 pub fn fnmatch(pattern: &str, name: &str) -> Option<Vec<String>> {
-    let pattern: Vec<char> = pattern.chars().collect();
-    let pattern: &[char] = &pattern[..];
-    let name: Vec<char> = name.chars().collect();
-    let name: &[char] = &name[..];
-    let mut i: usize = 0;
-    let mut j: usize = 0;
-    let mut matches: Vec<String> = Vec::new();
-    loop {
-        if pattern[i] == '?' {
-            if name.len() <= j {
-                return None; // no more chars available for this '?'
-            }
+    fnmatch_with_options(pattern, name, &MatchOptions::default()).unwrap_or(None)
+}
 
-            // Match one character
-            matches.push(name[j..=j].iter().collect());
-            i += 1;
-            j += 1;
-        } else if pattern[i] == '*' {
-            if pattern.len() <= i + 1 {
-                // Match all the remainings
-                matches.push(name[j..].iter().collect());
-                i += 1;
-                j = name.len();
-            } else if pattern[i + 1] == '*' {
-                // Match an empty string (consume nothing)
-                i += 1;
-                matches.push(String::new());
-            } else if pattern[i + 1] == '?' {
-                // Count how many question marks are there
-                let num_questions = 1 + strspn(pattern, i + 2, '?');
-                let ii = i + 1 + num_questions;
-                let matched_len = if ii < pattern.len() {
-                    let term = pattern[ii];
-                    if term == '*' {
-                        return None; // Patterns like `*?*` are ambiguous
-                    }
-                    strcspn(name, j, term)
-                } else {
-                    name.len() - j
-                };
-                if matched_len < num_questions {
-                    return None; // Too short for the question marks
-                }
+/// Matches a file name with a pattern interpreted according to the options.
+///
+/// This function fails if the pattern is malformed (e.g.: unbalanced parentheses with
+/// `capture_groups` enabled.) See `fnmatch` for the rules of matching.
+pub fn fnmatch_with_options(
+    pattern: &str,
+    name: &str,
+    options: &MatchOptions,
+) -> Result<Option<Vec<String>>, String> {
+    Ok(NamePattern::new(pattern, options)?.matches(name))
+}
 
-                // Keep matched parts
-                let substr_for_star = &name[j..(j + matched_len - num_questions)];
-                matches.push(substr_for_star.iter().collect());
-                for jj in j + substr_for_star.len()..j + matched_len {
-                    matches.push(name[jj..=jj].iter().collect());
-                }
-                i = ii;
-                j += matched_len;
-            } else {
-                debug_assert!(i + 1 < pattern.len());
-                let jj = j + strcspn(name, j, pattern[i + 1]);
-                matches.push(name[j..jj].iter().collect());
-                i += 1;
-                j = jj;
-            }
-        } else if j < name.len() && match_chars(pattern[i], name[j]) {
-            i += 1;
-            j += 1;
+/// A pattern of a file name compiled to match many names.
+#[derive(Debug)]
+pub(crate) struct NamePattern {
+    text: String,
+    matcher: Matcher,
+    has_wildcard: bool,
+    case_sensitivity: CaseSensitivity,
+    normalize_unicode: bool,
+}
+
+/// How a `NamePattern` matches names.
+#[derive(Debug)]
+enum Matcher {
+    /// Wildcards, compiled into sequences (one for each combination of alternatives.)
+    Wildcard(Vec<Sequence>),
+    /// A regular expression.
+    Regex(RegexPattern),
+}
+
+impl NamePattern {
+    /// Compiles a pattern, failing if it is malformed.
+    pub fn new(pattern: &str, options: &MatchOptions) -> Result<NamePattern, String> {
+        if options.normalize_unicode && cfg!(not(feature = "unicode-normalization")) {
+            return Err(String::from(
+                "Unicode normalization is not available; pmv was built without the \
+                 `unicode-normalization` feature",
+            ));
+        }
+        let text = pattern;
+        let pattern = &*if options.normalize_unicode {
+            to_nfc(pattern)
         } else {
-            return None;
+            pattern.into()
+        };
+
+        if options.regex {
+            let regex = RegexPattern::new(pattern, options)?;
+            return Ok(NamePattern {
+                text: String::from(text),
+                has_wildcard: regex.has_wildcard() || needs_scan(pattern, options),
+                matcher: Matcher::Regex(regex),
+                case_sensitivity: options.case_sensitivity,
+                normalize_unicode: options.normalize_unicode,
+            });
         }
 
-        if pattern.len() <= i {
-            if name.len() == j {
-                return Some(matches);
-            } else {
-                return None;
+        let sequences = compile(pattern, options)?;
+        let ranges = sequences
+            .iter()
+            .flat_map(|seq| &seq.tokens)
+            .flat_map(|token| match token {
+                Token::Class(class) => class.ranges.as_slice(),
+                _ => &[],
+            });
+        for (first, last) in ranges {
+            if last < first {
+                return Err(format!("invalid range `{}-{}`: {}", first, last, pattern));
+            }
+        }
+        let names = sequences
+            .iter()
+            .flat_map(|seq| &seq.tokens)
+            .flat_map(|token| match token {
+                Token::Class(class) => class.names.as_slice(),
+                _ => &[],
+            });
+        for name in names {
+            if !CLASS_NAMES.contains(&name.as_str()) {
+                return Err(format!(
+                    "unknown character class `[:{}:]`: {}",
+                    name, pattern
+                ));
+            }
+        }
+        Ok(NamePattern {
+            text: String::from(text),
+            matcher: Matcher::Wildcard(sequences),
+            has_wildcard: has_wildcard(pattern, options),
+            case_sensitivity: options.case_sensitivity,
+            normalize_unicode: options.normalize_unicode,
+        })
+    }
+
+    /// Returns the pattern as written.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Tests whether the pattern contains any wildcard or not. See `has_wildcard`.
+    pub fn has_wildcard(&self) -> bool {
+        self.has_wildcard
+    }
+
+    /// Returns the number of parts which a match yields.
+    pub fn captures(&self) -> usize {
+        match &self.matcher {
+            // Every alternative has the same number of captures
+            Matcher::Wildcard(sequences) => sequences.first().map_or(0, |seq| seq.captures.len()),
+            Matcher::Regex(regex) => regex.captures(),
+        }
+    }
+
+    /// Matches a name and returns matched parts.
+    pub fn matches(&self, name: &str) -> Option<Vec<String>> {
+        let parts = self.matches_os(OsStr::new(name))?;
+        // Every part is valid Unicode as the name is
+        let parts = parts
+            .iter()
+            .map(|part| part.to_string_lossy().into_owned())
+            .collect();
+        Some(parts)
+    }
+
+    /// Matches a name which may not be valid Unicode and returns matched parts.
+    ///
+    /// Parts of the name which are not valid Unicode are matched only by wildcards and they are
+    /// captured without loss.
+    pub fn matches_os(&self, name: &OsStr) -> Option<Vec<OsString>> {
+        // A name which is not valid Unicode is matched as is
+        let normalized;
+        let name = match name.to_str() {
+            Some(s) if self.normalize_unicode => {
+                normalized = OsString::from(to_nfc(s).into_owned());
+                normalized.as_os_str()
             }
+            _ => name,
+        };
+        if let Matcher::Regex(regex) = &self.matcher {
+            return regex.matches_os(name);
         }
+        let name = decode(name);
+        let ranges = self.match_units(&name)?;
+        let parts = ranges
+            .iter()
+            .map(|(start, end)| encode(&name[*start..*end]))
+            .collect();
+        Some(parts)
     }
+
+    fn match_units(&self, name: &[Unit]) -> Option<Vec<(usize, usize)>> {
+        match &self.matcher {
+            Matcher::Wildcard(sequences) => sequences
+                .iter()
+                .find_map(|seq| match_sequence(seq, name, self.case_sensitivity)),
+            Matcher::Regex(_) => None,
+        }
+    }
+}
+
+/// A character of a file name, or a part of it which is not valid Unicode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unit {
+    Char(char),
+    /// A byte which is not a part of a valid UTF-8 sequence.
+    #[cfg(unix)]
+    Byte(u8),
+    /// An unpaired surrogate in a UTF-16 name.
+    #[cfg(windows)]
+    Surrogate(u16),
 }
 
-fn strspn(s: &[char], i: usize, accept: char) -> usize {
-    let mut j = i;
-    while j < s.len() {
-        if accept != s[j] {
-            return j - i;
+/// Splits a file name into units without loss.
+#[cfg(unix)]
+fn decode(name: &OsStr) -> Vec<Unit> {
+    use std::os::unix::ffi::OsStrExt;
+    let mut units = Vec::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        units.extend(chunk.valid().chars().map(Unit::Char));
+        units.extend(chunk.invalid().iter().map(|b| Unit::Byte(*b)));
+    }
+    units
+}
+
+/// Splits a file name into units without loss.
+#[cfg(windows)]
+fn decode(name: &OsStr) -> Vec<Unit> {
+    use std::os::windows::ffi::OsStrExt;
+    std::char::decode_utf16(name.encode_wide())
+        .map(|c| match c {
+            Ok(c) => Unit::Char(c),
+            Err(err) => Unit::Surrogate(err.unpaired_surrogate()),
+        })
+        .collect()
+}
+
+/// Splits a file name into units (not valid Unicode parts are replaced on this platform.)
+#[cfg(not(any(unix, windows)))]
+fn decode(name: &OsStr) -> Vec<Unit> {
+    name.to_string_lossy().chars().map(Unit::Char).collect()
+}
+
+/// Builds a file name from units.
+#[cfg(unix)]
+fn encode(units: &[Unit]) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    let mut bytes = Vec::new();
+    for unit in units {
+        match unit {
+            Unit::Char(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            Unit::Byte(b) => bytes.push(*b),
         }
-        j += 1;
     }
-    s.len() - i
+    OsString::from_vec(bytes)
 }
 
-fn strcspn(s: &[char], i: usize, reject: char) -> usize {
-    let mut j = i;
-    while j < s.len() {
-        if reject == s[j] {
-            return j - i;
+/// Builds a file name from units.
+#[cfg(windows)]
+fn encode(units: &[Unit]) -> OsString {
+    use std::os::windows::ffi::OsStringExt;
+    let mut wide = Vec::new();
+    for unit in units {
+        match unit {
+            Unit::Char(c) => wide.extend_from_slice(c.encode_utf16(&mut [0; 2])),
+            Unit::Surrogate(w) => wide.push(*w),
         }
-        j += 1;
     }
-    s.len() - i
+    OsString::from_wide(&wide)
 }
 
-fn match_chars(a: char, b: char) -> bool {
-    if cfg!(windows) {
-        let offset = 'a' as u32 - 'A' as u32;
+/// Builds a file name from units.
+#[cfg(not(any(unix, windows)))]
+fn encode(units: &[Unit]) -> OsString {
+    units
+        .iter()
+        .map(|Unit::Char(c)| *c)
+        .collect::<String>()
+        .into()
+}
 
-        let a = match a {
-            'A'..='Z' => std::char::from_u32(a as u32 + offset).unwrap(),
-            _ => a,
-        };
+/// Tests whether a pattern contains any wildcard or not.
+///
+/// A pattern without wildcards matches only a name identical to itself (except for case folding
+/// on Windows) so the caller can look up the name directly instead of scanning a directory.
+/// If case sensitivity differs from the file system of the platform, a letter is also treated as
+/// a wildcard since looking up the name would not tell whether its case matched. Likewise, a
+/// non-ASCII character is treated as a wildcard with `normalize_unicode`.
+pub fn has_wildcard(pattern: &str, options: &MatchOptions) -> bool {
+    if needs_scan(pattern, options) {
+        return true;
+    }
+    let chars: Vec<char> = pattern.chars().collect();
+    chars.iter().enumerate().any(|(i, c)| match c {
+        '?' | '*' => true,
+        '[' => parse_class(&chars[i + 1..]).is_some(),
+        '(' | ')' | '|' => options.capture_groups,
+        c => {
+            options.case_sensitivity.ignores_case() != cfg!(windows)
+                && (c.is_lowercase() || c.is_uppercase())
+        }
+    })
+}
+
+/// Tests whether a name must be found by scanning a directory since it may be written in a
+/// different Unicode normalization form than the pattern.
+fn needs_scan(pattern: &str, options: &MatchOptions) -> bool {
+    options.normalize_unicode && !pattern.is_ascii()
+}
 
-        let b = match b {
-            'A'..='Z' => std::char::from_u32(b as u32 + offset).unwrap(),
-            _ => b,
+/// Tests whether two characters of file names are the same in the case sensitivity.
+fn chars_match(a: char, b: char, case_sensitivity: CaseSensitivity) -> bool {
+    match case_sensitivity {
+        CaseSensitivity::Platform => chars_eq(a, b),
+        CaseSensitivity::Sensitive => a == b,
+        CaseSensitivity::Insensitive => a == b || a.to_lowercase().eq(b.to_lowercase()),
+    }
+}
+
+/// An element of a compiled pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    /// A literal character.
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]`
+    Class(CharClass),
+    /// A zero-width marker at the start or the end of a capture group.
+    Mark,
+}
+
+/// A set of characters written as a bracket expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CharClass {
+    negated: bool,
+    /// Inclusive ranges of the listed characters (a single character is a range of itself.)
+    ranges: Vec<(char, char)>,
+    /// Names of the listed POSIX character classes, such as `digit` for `[:digit:]`.
+    names: Vec<String>,
+}
+
+/// Names of the supported POSIX character classes.
+const CLASS_NAMES: [&str; 12] = [
+    "alnum", "alpha", "blank", "cntrl", "digit", "graph", "lower", "print", "punct", "space",
+    "upper", "xdigit",
+];
+
+/// Tests whether a character belongs to a POSIX character class.
+///
+/// With case folding, `upper` and `lower` match letters of either case like other shells do.
+fn class_contains(name: &str, c: char, case_sensitivity: CaseSensitivity) -> bool {
+    match name {
+        "alnum" => c.is_alphanumeric(),
+        "alpha" => c.is_alphabetic(),
+        "blank" => c.is_whitespace() && !is_line_break(c),
+        "cntrl" => c.is_control(),
+        "digit" => c.is_ascii_digit(),
+        "graph" => !c.is_control() && !c.is_whitespace(),
+        "lower" | "upper" if case_sensitivity.ignores_case() => {
+            c.is_lowercase() || c.is_uppercase()
+        }
+        "lower" => c.is_lowercase(),
+        "print" => !c.is_control() && !is_line_break(c),
+        "punct" => !c.is_alphanumeric() && !c.is_control() && !c.is_whitespace(),
+        "space" => c.is_whitespace(),
+        "upper" => c.is_uppercase(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+fn is_line_break(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\x0b' | '\x0c' | '\r' | '\u{85}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+impl CharClass {
+    fn contains(&self, c: char, case_sensitivity: CaseSensitivity) -> bool {
+        let listed = self.ranges.iter().any(|&(first, last)| {
+            let range = first..=last;
+            if first == last {
+                return chars_match(first, c, case_sensitivity);
+            }
+            match case_sensitivity {
+                CaseSensitivity::Insensitive => {
+                    range.contains(&c)
+                        || c.to_lowercase().any(|c| range.contains(&c))
+                        || c.to_uppercase().any(|c| range.contains(&c))
+                }
+                CaseSensitivity::Platform if cfg!(windows) => {
+                    range.contains(&c.to_ascii_lowercase())
+                        || range.contains(&c.to_ascii_uppercase())
+                }
+                _ => range.contains(&c),
+            }
+        }) || self
+            .names
+            .iter()
+            .any(|name| class_contains(name, c, case_sensitivity));
+        listed != self.negated
+    }
+}
+
+/// Parses a bracket expression following `[` and returns it with the number of characters
+/// consumed including the closing `]`, or `None` if it is not closed.
+fn parse_class(chars: &[char]) -> Option<(CharClass, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut names = Vec::new();
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == ']' && !first {
+            let class = CharClass {
+                negated,
+                ranges,
+                names,
+            };
+            return Some((class, i + 1));
+        }
+        first = false;
+        if c == '[' && chars.get(i + 1) == Some(&':') {
+            // A POSIX character class such as `[:digit:]`
+            let end = (i + 2..chars.len().saturating_sub(1))
+                .find(|&j| chars[j] == ':' && chars[j + 1] == ']');
+            if let Some(end) = end {
+                names.push(chars[i + 2..end].iter().collect());
+                i = end + 2;
+                continue;
+            }
+        }
+        if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i + 2] != ']' {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+/// A compiled pattern without alternatives.
+#[derive(Debug, Default)]
+struct Sequence {
+    tokens: Vec<Token>,
+    /// Token index ranges of which matched substrings will be captured.
+    captures: Vec<(usize, usize)>,
+}
+
+impl Sequence {
+    fn push(&mut self, token: Token) {
+        if token == Token::Star && self.tokens.last() == Some(&Token::Star) {
+            return; // `**` is the same as `*`
+        }
+        self.tokens.push(token);
+    }
+}
+
+/// Compiles a pattern into sequences, one for each combination of alternatives.
+fn compile(pattern: &str, options: &MatchOptions) -> Result<Vec<Sequence>, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+    if !options.capture_groups {
+        let mut seq = Sequence::default();
+        while i < chars.len() {
+            let c = chars[i];
+            i += 1;
+            seq.push(match c {
+                '?' => Token::Any,
+                '*' => Token::Star,
+                '[' => match parse_class(&chars[i..]) {
+                    Some((class, len)) => {
+                        i += len;
+                        Token::Class(class)
+                    }
+                    None => Token::Char(c),
+                },
+                _ => Token::Char(c),
+            });
+        }
+        seq.captures = (0..seq.tokens.len())
+            .filter(|i| !matches!(seq.tokens[*i], Token::Char(_)))
+            .map(|i| (i, i + 1))
+            .collect();
+        return Ok(vec![seq]);
+    }
+
+    let mut seqs = vec![Sequence::default()];
+    let mut group: Option<Vec<Vec<Token>>> = None;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        let token = match c {
+            '?' => Token::Any,
+            '*' => Token::Star,
+            '[' => match parse_class(&chars[i..]) {
+                Some((class, len)) => {
+                    i += len;
+                    Token::Class(class)
+                }
+                None => Token::Char(c),
+            },
+            '(' => {
+                if group.is_some() {
+                    return Err(format!("nested groups are not supported: {}", pattern));
+                }
+                group = Some(vec![vec![]]);
+                continue;
+            }
+            '|' if group.is_some() => {
+                group.as_mut().unwrap().push(vec![]);
+                continue;
+            }
+            ')' => {
+                let alternatives = match group.take() {
+                    Some(alternatives) => alternatives,
+                    None => return Err(format!("unmatched parenthesis: {}", pattern)),
+                };
+                let mut expanded = Vec::new();
+                for seq in &seqs {
+                    for alternative in &alternatives {
+                        let mut seq = Sequence {
+                            tokens: seq.tokens.clone(),
+                            captures: seq.captures.clone(),
+                        };
+                        let start = seq.tokens.len();
+                        seq.tokens.push(Token::Mark);
+                        for token in alternative {
+                            seq.push(token.clone());
+                        }
+                        seq.tokens.push(Token::Mark);
+                        seq.captures.push((start, seq.tokens.len() - 1));
+                        expanded.push(seq);
+                    }
+                }
+                seqs = expanded;
+                continue;
+            }
+            _ => Token::Char(c),
         };
+        match group {
+            Some(ref mut alternatives) => alternatives.last_mut().unwrap().push(token),
+            None => seqs.iter_mut().for_each(|seq| seq.push(token.clone())),
+        }
+    }
+    if group.is_some() {
+        return Err(format!("unmatched parenthesis: {}", pattern));
+    }
+    Ok(seqs)
+}
+
+/// Matches a name with a compiled sequence and returns index ranges of the captured units.
+fn match_sequence(
+    seq: &Sequence,
+    name: &[Unit],
+    case_sensitivity: CaseSensitivity,
+) -> Option<Vec<(usize, usize)>> {
+    let tokens = &seq.tokens;
+
+    // This is the well known two-pointer algorithm. When a mismatch occurs, we go back to the
+    // last star seen and let it consume one more character. There is no need to revisit stars
+    // before the last one, so each star consumes as few characters as possible.
+    let mut positions = vec![0; tokens.len() + 1]; // where each token started to match
+    let mut i: usize = 0; // index of the token
+    let mut j: usize = 0; // index of the char in the name
+    let mut last_star: Option<(usize, usize)> = None; // (token index, end of the matched chars)
+    loop {
+        positions[i] = j;
+        if i < tokens.len() {
+            match &tokens[i] {
+                Token::Star => {
+                    last_star = Some((i, j));
+                    i += 1;
+                    continue;
+                }
+                Token::Mark => {
+                    i += 1;
+                    continue;
+                }
+                Token::Any if j < name.len() => {
+                    i += 1;
+                    j += 1;
+                    continue;
+                }
+                Token::Char(c)
+                    if j < name.len()
+                        && matches!(name[j], Unit::Char(n) if chars_match(*c, n, case_sensitivity)) =>
+                {
+                    i += 1;
+                    j += 1;
+                    continue;
+                }
+                Token::Class(class)
+                    if j < name.len()
+                        && matches!(name[j], Unit::Char(n) if class.contains(n, case_sensitivity)) =>
+                {
+                    i += 1;
+                    j += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        } else if j == name.len() {
+            let matches = seq
+                .captures
+                .iter()
+                .map(|(start, end)| (positions[*start], positions[*end]))
+                .collect();
+            return Some(matches);
+        }
 
-        a == b
-    } else {
-        a == b
+        // Mismatch; let the last star consume one more character and retry
+        let (star_i, star_end) = last_star?;
+        if name.len() <= star_end {
+            return None;
+        }
+        last_star = Some((star_i, star_end + 1));
+        i = star_i + 1;
+        j = star_end + 1;
     }
 }
 
@@ -136,19 +683,21 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_strspn() {
-        let s: Vec<char> = "foobar".chars().collect();
-        assert_eq!(strspn(&s[..], 0, 'o'), 0);
-        assert_eq!(strspn(&s[..], 1, 'o'), 2);
-        assert_eq!(strspn(&s[..], 5, 'r'), 1);
-    }
+    fn test_has_wildcard() {
+        let options = MatchOptions::default();
+        assert!(!has_wildcard("foobar", &options));
+        assert!(!has_wildcard("", &options));
+        assert!(!has_wildcard("(foobar)", &options));
+        assert!(has_wildcard("foo?ar", &options));
+        assert!(has_wildcard("*bar", &options));
 
-    #[test]
-    fn test_strcspn() {
-        let s: Vec<char> = "foobar".chars().collect();
-        assert_eq!(strcspn(&s[..], 0, 'f'), 0);
-        assert_eq!(strcspn(&s[..], 1, 'b'), 2);
-        assert_eq!(strcspn(&s[..], 2, 'x'), 4);
+        let options = MatchOptions {
+            capture_groups: true,
+            ..Default::default()
+        };
+        assert!(has_wildcard("(foobar)", &options));
+        assert!(has_wildcard("IMG_[0-9].jpg", &options));
+        assert!(!has_wildcard("IMG_[0-9.jpg", &options));
     }
 
     mod fnmatch {
@@ -210,10 +759,22 @@ mod tests {
 
         #[test]
         fn star_star() {
+            assert_eq!(fnmatch("f**r", "foobar"), Some(vec![String::from("ooba")]));
+            assert_eq!(fnmatch("***", "foobar"), Some(vec![String::from("foobar")]));
+            assert_eq!(fnmatch("f**", "f"), Some(vec![String::from("")]));
+        }
+
+        #[test]
+        fn star_question() {
+            assert_eq!(
+                fnmatch("*?", "abc"),
+                Some(vec![String::from("ab"), String::from("c")])
+            );
             assert_eq!(
-                fnmatch("f**r", "foobar"),
-                Some(vec![String::from(""), String::from("ooba")])
+                fnmatch("?*", "abc"),
+                Some(vec![String::from("a"), String::from("bc")])
             );
+            assert_eq!(fnmatch("*?", ""), None);
         }
 
         #[test]
@@ -242,9 +803,330 @@ mod tests {
             );
         }
 
+        #[test]
+        fn star_lazy() {
+            assert_eq!(
+                fnmatch("*.*", "a.b.c"),
+                Some(vec![String::from("a"), String::from("b.c")])
+            );
+            assert_eq!(
+                fnmatch("*b*", "abba"),
+                Some(vec![String::from("a"), String::from("ba")])
+            );
+        }
+
         #[test]
         fn star_question_star() {
-            assert_eq!(fnmatch("f*?*r", "foobar"), None);
+            assert_eq!(
+                fnmatch("f*?*r", "foobar"),
+                Some(vec![
+                    String::from(""),
+                    String::from("o"),
+                    String::from("oba")
+                ])
+            );
+        }
+
+        #[test]
+        fn star_backtrack() {
+            assert_eq!(fnmatch("*.txt", "a.b.txt"), Some(vec![String::from("a.b")]));
+            assert_eq!(
+                fnmatch("*_*.rs", "foo_bar_baz.rs"),
+                Some(vec![String::from("foo"), String::from("bar_baz")])
+            );
+            assert_eq!(fnmatch("*a*a*b", "aaaaaaaaaaaaaaaaaaaa"), None);
+        }
+
+        #[test]
+        fn class() {
+            assert_eq!(
+                fnmatch("IMG_[0-9abc].jpg", "IMG_7.jpg"),
+                Some(vec![String::from("7")])
+            );
+            assert_eq!(
+                fnmatch("IMG_[0-9abc].jpg", "IMG_b.jpg"),
+                Some(vec![String::from("b")])
+            );
+            assert_eq!(fnmatch("IMG_[0-9abc].jpg", "IMG_d.jpg"), None);
+            assert_eq!(fnmatch("IMG_[0-9abc].jpg", "IMG_.jpg"), None);
+            assert_eq!(
+                fnmatch("*[0-9]", "ab12"),
+                Some(vec![String::from("ab1"), String::from("2")])
+            );
+            assert_eq!(fnmatch("[写真]", "真"), Some(vec![String::from("真")]));
+        }
+
+        #[test]
+        fn class_negated() {
+            assert_eq!(fnmatch("[!a]", "b"), Some(vec![String::from("b")]));
+            assert_eq!(fnmatch("[^a]", "b"), Some(vec![String::from("b")]));
+            assert_eq!(fnmatch("[!a]", "a"), None);
+            assert_eq!(
+                fnmatch("IMG_[!0-9_]*", "IMG_x12"),
+                Some(vec![String::from("x"), String::from("12")])
+            );
+            assert_eq!(fnmatch("IMG_[^0-9_]*", "IMG_12"), None);
+            assert_eq!(fnmatch("[!a]", ""), None);
+            assert_eq!(fnmatch("[!]]", "]"), None); // `]` right after `!` is listed
+            assert_eq!(fnmatch("[!]]", "a"), Some(vec![String::from("a")]));
+        }
+
+        #[test]
+        fn class_special() {
+            // `]` first and `-` last are listed characters
+            assert_eq!(fnmatch("[]-]", "]"), Some(vec![String::from("]")]));
+            assert_eq!(fnmatch("[]-]", "-"), Some(vec![String::from("-")]));
+            assert_eq!(fnmatch("[[]", "["), Some(vec![String::from("[")]));
+
+            // Unclosed brackets are ordinary characters
+            assert_eq!(fnmatch("a[b", "a[b"), Some(vec![]));
+            assert_eq!(fnmatch("a[b", "ab"), None);
+        }
+
+        #[test]
+        fn class_ranges() {
+            let ptn = "[A-Za-z_][0-9]";
+            assert_eq!(
+                fnmatch(ptn, "_7"),
+                Some(vec![String::from("_"), String::from("7")])
+            );
+            assert_eq!(
+                fnmatch(ptn, "q0"),
+                Some(vec![String::from("q"), String::from("0")])
+            );
+            assert_eq!(fnmatch(ptn, "-0"), None);
+            assert_eq!(fnmatch(ptn, "aa"), None);
+            assert_eq!(fnmatch("[a-a]", "a"), Some(vec![String::from("a")]));
+            assert_eq!(fnmatch("[ぁ-ん]", "ね"), Some(vec![String::from("ね")]));
+            assert_eq!(fnmatch("[a-]", "-"), Some(vec![String::from("-")]));
+        }
+
+        #[test]
+        fn class_posix() {
+            assert_eq!(
+                fnmatch("IMG_[[:digit:]]*", "IMG_7a"),
+                Some(vec![String::from("7"), String::from("a")])
+            );
+            assert_eq!(fnmatch("[[:digit:]]", "a"), None);
+            assert_eq!(fnmatch("[[:alpha:]]", "写"), Some(vec![String::from("写")]));
+            assert_eq!(fnmatch("[[:alnum:]_]", "_"), Some(vec![String::from("_")]));
+            assert_eq!(fnmatch("[![:space:]]", " "), None);
+            assert_eq!(fnmatch("[![:space:]]", "a"), Some(vec![String::from("a")]));
+            assert_eq!(
+                fnmatch("[[:space:]]", "\u{3000}"),
+                Some(vec![String::from("\u{3000}")])
+            );
+            assert_eq!(fnmatch("[[:blank:]]", "\n"), None);
+            assert_eq!(fnmatch("[[:punct:]]", "-"), Some(vec![String::from("-")]));
+            assert_eq!(fnmatch("[[:punct:]]", "a"), None);
+            assert_eq!(fnmatch("[[:xdigit:]]", "F"), Some(vec![String::from("F")]));
+            assert_eq!(fnmatch("[[:xdigit:]]", "g"), None);
+
+            // `[:` without `:]` is listed as is
+            assert_eq!(fnmatch("[[:a]", ":"), Some(vec![String::from(":")]));
+        }
+
+        #[test]
+        fn class_case_sensitivity() {
+            let expected = if cfg!(windows) {
+                Some(vec![String::from("B")])
+            } else {
+                None
+            };
+            assert_eq!(fnmatch("[a-c]", "B"), expected);
+            assert_eq!(fnmatch("[b]", "B"), expected);
+        }
+    }
+
+    mod fnmatch_with_options {
+        use super::*;
+
+        fn groups() -> MatchOptions {
+            MatchOptions {
+                capture_groups: true,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn reversed_range() {
+            let options = MatchOptions::default();
+            assert!(fnmatch_with_options("[z-a]", "b", &options).is_err());
+            assert!(fnmatch_with_options("([9-0])", "b", &groups()).is_err());
+        }
+
+        #[test]
+        fn unknown_class() {
+            let options = MatchOptions::default();
+            assert_eq!(
+                fnmatch_with_options("[[:digits:]]", "1", &options),
+                Err(String::from(
+                    "unknown character class `[:digits:]`: [[:digits:]]"
+                ))
+            );
+        }
+
+        #[test]
+        fn posix_class_case() {
+            let options = MatchOptions {
+                case_sensitivity: CaseSensitivity::Insensitive,
+                ..Default::default()
+            };
+            assert_eq!(
+                fnmatch_with_options("[[:upper:]]", "a", &options),
+                Ok(Some(vec![String::from("a")]))
+            );
+            let options = MatchOptions {
+                case_sensitivity: CaseSensitivity::Sensitive,
+                ..Default::default()
+            };
+            assert_eq!(fnmatch_with_options("[[:upper:]]", "a", &options), Ok(None));
+            assert_eq!(
+                fnmatch_with_options("[[:upper:]]", "A", &options),
+                Ok(Some(vec![String::from("A")]))
+            );
+        }
+
+        #[test]
+        fn class_in_group() {
+            assert_eq!(
+                fnmatch_with_options("(IMG|DSC)_[0-9]*", "DSC_1a", &groups()),
+                Ok(Some(vec![String::from("DSC")]))
+            );
+            assert_eq!(
+                fnmatch_with_options("([0-9]*).jpg", "12.jpg", &groups()),
+                Ok(Some(vec![String::from("12")]))
+            );
+        }
+
+        #[test]
+        fn no_groups() {
+            assert_eq!(
+                fnmatch_with_options("(*)", "(foo)", &MatchOptions::default()),
+                Ok(Some(vec![String::from("foo")]))
+            );
+            assert_eq!(
+                fnmatch_with_options("*_*", "foo_bar", &groups()),
+                Ok(Some(vec![]))
+            );
+        }
+
+        #[test]
+        fn group() {
+            assert_eq!(
+                fnmatch_with_options("(*)_*.txt", "foo_bar.txt", &groups()),
+                Ok(Some(vec![String::from("foo")]))
+            );
+            assert_eq!(
+                fnmatch_with_options("IMG_(??)??.(*)", "IMG_0123.jpg", &groups()),
+                Ok(Some(vec![String::from("01"), String::from("jpg")]))
+            );
+            assert_eq!(
+                fnmatch_with_options("(foo)", "foo", &groups()),
+                Ok(Some(vec![String::from("foo")]))
+            );
+            assert_eq!(fnmatch_with_options("(foo)", "bar", &groups()), Ok(None));
+        }
+
+        #[test]
+        fn alternatives() {
+            let pattern = "(*)_final.(mp4|mov)";
+            assert_eq!(
+                fnmatch_with_options(pattern, "a_final.mp4", &groups()),
+                Ok(Some(vec![String::from("a"), String::from("mp4")]))
+            );
+            assert_eq!(
+                fnmatch_with_options(pattern, "b_final.mov", &groups()),
+                Ok(Some(vec![String::from("b"), String::from("mov")]))
+            );
+            assert_eq!(
+                fnmatch_with_options(pattern, "c_final.avi", &groups()),
+                Ok(None)
+            );
+            assert_eq!(
+                fnmatch_with_options("x(|_old)", "x", &groups()),
+                Ok(Some(vec![String::from("")]))
+            );
+        }
+
+        #[cfg(feature = "unicode-normalization")]
+        #[test]
+        fn normalize_unicode() {
+            let options = MatchOptions {
+                normalize_unicode: true,
+                ..Default::default()
+            };
+            // Composed and decomposed forms of `é`
+            assert_eq!(
+                fnmatch_with_options("caf\u{e9}_*", "cafe\u{301}_e\u{301}", &options),
+                Ok(Some(vec![String::from("\u{e9}")]))
+            );
+            assert_eq!(
+                fnmatch_with_options("cafe\u{301}", "caf\u{e9}", &options),
+                Ok(Some(Vec::new()))
+            );
+            assert_eq!(
+                fnmatch_with_options("caf\u{e9}", "cafe\u{301}", &Default::default()),
+                Ok(None)
+            );
+            assert!(has_wildcard("caf\u{e9}", &options));
+            assert!(!has_wildcard("cafe", &options));
+        }
+
+        #[cfg(not(feature = "unicode-normalization"))]
+        #[test]
+        fn normalize_unicode_unavailable() {
+            let options = MatchOptions {
+                normalize_unicode: true,
+                ..Default::default()
+            };
+            assert!(fnmatch_with_options("caf\u{e9}", "caf\u{e9}", &options).is_err());
+        }
+
+        #[test]
+        fn malformed() {
+            assert!(fnmatch_with_options("(*", "foo", &groups()).is_err());
+            assert!(fnmatch_with_options("*)", "foo", &groups()).is_err());
+            assert!(fnmatch_with_options("((*))", "foo", &groups()).is_err());
+        }
+
+        #[test]
+        fn ignore_case() {
+            let options = MatchOptions {
+                case_sensitivity: CaseSensitivity::Insensitive,
+                ..Default::default()
+            };
+            assert_eq!(
+                fnmatch_with_options("Abc*.JPG", "aBCd.jpg", &options),
+                Ok(Some(vec![String::from("d")]))
+            );
+            assert_eq!(
+                fnmatch_with_options("[a-c][X]", "Bx", &options),
+                Ok(Some(vec![String::from("B"), String::from("x")]))
+            );
+            assert_eq!(fnmatch_with_options("[!a-c]", "B", &options), Ok(None));
+            assert_eq!(
+                fnmatch_with_options("é", "É", &options),
+                Ok(Some(Vec::new()))
+            );
+            assert!(has_wildcard("abc", &options));
+            assert!(!has_wildcard("123", &options));
+        }
+
+        #[test]
+        fn case_sensitive() {
+            let options = MatchOptions {
+                case_sensitivity: CaseSensitivity::Sensitive,
+                ..Default::default()
+            };
+            assert_eq!(fnmatch_with_options("Abc", "abC", &options), Ok(None));
+            assert_eq!(fnmatch_with_options("[a-c]", "B", &options), Ok(None));
+            assert_eq!(
+                fnmatch_with_options("A[b]*", "Abc", &options),
+                Ok(Some(vec![String::from("b"), String::from("c")]))
+            );
+            // Names cannot be looked up directly on case insensitive file systems
+            assert_eq!(has_wildcard("abc", &options), cfg!(windows));
         }
     }
 }
@@ -1,27 +1,97 @@
-use crate::Action;
-use std::cmp;
+use crate::preview::{format_move, Columns};
+use crate::quote::quote_path;
+use crate::{print_warning, Action};
+use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
+/// A function called with the source, the destination and the error when a move failed.
 pub type Callback = dyn Fn(&Path, &Path, &io::Error);
 
+/// A function called with the source and the destination when a file was moved.
+pub type MovedCallback<'a> = dyn Fn(&Path, &Path) + 'a;
+
+/// A function called with the source and the destination right before a move, which aborts the
+/// remaining moves by returning an error.
+pub(crate) type BeforeMoveCallback<'a> = dyn Fn(&Path, &Path) -> Result<(), String> + 'a;
+
+/// A function called with the path and the error when sending a file to the trash failed.
+pub type TrashCallback = dyn Fn(&Path, &io::Error);
+
+/// When to flush directories affected by moves to disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Fsync {
+    /// Leaves flushing to the operating system.
+    #[default]
+    Never,
+    /// Flushes the source and destination directories after each move.
+    EachMove,
+    /// Flushes all the affected directories once after all moves.
+    Batch,
+}
+
+/// Options to control how `move_files` works.
+#[derive(Clone, Debug, Default)]
+pub struct MoveOptions {
+    /// Only prints what would be done.
+    pub dry_run: bool,
+    /// Asks the user before each move.
+    pub interactive: bool,
+    /// Prints each move.
+    pub verbose: bool,
+    /// How long to keep retrying a move while another process locks the file (Windows only.)
+    pub wait_for_unlock: Option<Duration>,
+    /// How long to keep retrying a move which failed as the destination ran out of space.
+    pub wait_for_space: Option<Duration>,
+    /// How many times to retry a move which failed with a possibly transient error.
+    pub retries: u32,
+    /// Checks that the file actually moved after each successful rename.
+    pub verify: bool,
+    /// When to flush the source and destination directories to disk.
+    pub fsync: Fsync,
+    /// Pauses for `pause` each time this many files were moved.
+    pub chunk_size: Option<usize>,
+    /// How long to pause between chunks of moves. See `chunk_size`.
+    pub pause: Duration,
+    /// Prints the result of each action in a stable, machine readable format instead of the
+    /// usual messages. See `print_porcelain` for the format.
+    pub porcelain: bool,
+}
+
+/// Moves files as the actions describe.
+///
+/// The actions are executed in the given order, so they should be sorted with `sort_actions`
+/// beforehand. Returns the number of failed moves.
 pub fn move_files(
     actions: &[Action],
-    dry_run: bool,
-    interactive: bool,
-    verbose: bool,
+    options: &MoveOptions,
+    on_error: Option<&Callback>,
+    on_moved: Option<&MovedCallback<'_>>,
+) -> i32 {
+    move_files_with(actions, options, on_error, on_moved, None)
+}
+
+/// Moves files like `move_files`, calling `before_move` right before each move.
+pub(crate) fn move_files_with(
+    actions: &[Action],
+    options: &MoveOptions,
     on_error: Option<&Callback>,
+    on_moved: Option<&MovedCallback<'_>>,
+    before_move: Option<&BeforeMoveCallback<'_>>,
 ) -> i32 {
+    let dry_run = options.dry_run;
+    let interactive = options.interactive;
+    let verbose = options.verbose;
     let mut num_errors = 0;
+    let mut num_moved = 0;
+    let mut num_moved_before_pause = 0;
 
-    // Calculate max width for printing
-    let src_max_len = actions
-        .iter()
-        .map(|a| a.src().to_string_lossy().len())
-        .fold(0, cmp::max);
+    let columns = Columns::of(actions);
 
     // Move files
-    let mut line = String::new();
+    let mut dirs_to_sync: Vec<PathBuf> = Vec::new();
     for action in actions {
         let (src, dest) = action.into();
 
@@ -29,70 +99,838 @@ pub fn move_files(
         // (Windows accepts this case but Linux does not)
         if src.is_dir() && Path::new(dest).is_file() {
             if let Some(f) = on_error {
-                let err = io::Error::new(
-                    io::ErrorKind::Other,
-                    "overwriting a file with a directory is not allowed",
-                );
+                let err = io::Error::other("overwriting a file with a directory is not allowed");
                 f(src, dest, &err);
             }
+            if options.porcelain {
+                print_porcelain(src, dest, "failed");
+            }
             num_errors += 1;
             continue;
         }
 
         // Append basename of src to dest if dest is a directory
-        let mut dest = PathBuf::from(dest);
-        if dest.is_dir() {
-            dest.push(src.file_name().unwrap());
-        }
-        let dest_str = dest.to_string_lossy();
-        let src_str = src.to_string_lossy();
+        let mut dest = action.final_dest();
+        let dest_str = quote_path(&dest);
+        let src_str = quote_path(src);
 
-        line.clear();
-        line.push_str(&src_str);
-        for _ in src_str.len()..src_max_len {
-            line.push(' ');
+        let mut line = format_move(&columns, action.id(), &src_str, &dest_str);
+        if let (true, Some(label)) = (verbose, action.label()) {
+            line.push_str(&format!("  ({})", label));
         }
-        line.push_str(" --> "); //TODO: Wrap line if it's too long
-        line.push_str(&dest_str);
         if dry_run || (verbose && !interactive) {
-            println!("{}", line);
+            if !options.porcelain {
+                println!("{}", line);
+            }
         } else if interactive {
-            // Ask user to proceed or not
-            print!("{} ... ok? [y/N]: ", line);
-            let _ = io::stdout().lock().flush();
-            let mut line = String::new();
-            let nbytes_read = io::stdin().read_line(&mut line).unwrap_or(0);
-            if nbytes_read == 0 {
+            // Ask user to proceed, to skip, or to edit the destination
+            print!("{} ... ok? [y/N/e]: ", line);
+            let answer = match read_answer() {
+                Some(answer) => answer,
+                None => {
+                    if let Some(f) = on_error {
+                        let err = io::Error::other("error on reading user input");
+                        f(src, dest.as_path(), &err);
+                    }
+                    num_errors += 1;
+                    continue;
+                }
+            };
+
+            if answer.eq_ignore_ascii_case("e") {
+                // Replace the destination with what the user entered (skip if empty), asking
+                // again while it is taken as it was not checked like the planned ones
+                let new_dest = loop {
+                    print!("new destination: ");
+                    let new_dest = match read_answer() {
+                        Some(new_dest) if new_dest.is_empty() => break Some(None),
+                        Some(new_dest) => PathBuf::from(new_dest),
+                        None => break None,
+                    };
+                    let new_dest = if new_dest.is_dir() {
+                        new_dest.join(src.file_name().unwrap())
+                    } else {
+                        new_dest
+                    };
+                    match check_edited_dest(&new_dest, action, actions) {
+                        Ok(()) => break Some(Some(new_dest)),
+                        Err(msg) => println!("{}", msg),
+                    }
+                };
+                match new_dest {
+                    Some(Some(new_dest)) => dest = new_dest,
+                    Some(None) => {
+                        if options.porcelain {
+                            print_porcelain(src, &dest, "skipped");
+                        }
+                        continue;
+                    }
+                    None => {
+                        if let Some(f) = on_error {
+                            let err = io::Error::other("error on reading user input");
+                            f(src, dest.as_path(), &err);
+                        }
+                        num_errors += 1;
+                        continue;
+                    }
+                }
+            } else if !answer.eq_ignore_ascii_case("y") {
+                // Skip if the input was not "y"
+                if options.porcelain {
+                    print_porcelain(src, &dest, "skipped");
+                }
+                continue;
+            }
+        }
+        if dry_run {
+            if options.porcelain {
+                print_porcelain(src, &dest, "planned");
+            }
+        } else {
+            if let Some(Err(msg)) = before_move.map(|f| f(src, &dest)) {
                 if let Some(f) = on_error {
-                    let err = io::Error::new(io::ErrorKind::Other, "error on reading user input");
-                    f(src, dest.as_path(), &err);
+                    f(src, &dest, &io::Error::other(msg));
+                }
+                if options.porcelain {
+                    print_porcelain(src, &dest, "failed");
                 }
                 num_errors += 1;
-                continue;
+                break;
+            }
+            if let Some(n) = options.chunk_size {
+                // Let indexers, backup agents and sync clients catch up with the moves
+                if num_moved - num_moved_before_pause == n {
+                    if verbose && !options.porcelain {
+                        println!("(pausing after {} moves)", num_moved);
+                    }
+                    thread::sleep(options.pause);
+                    num_moved_before_pause = num_moved;
+                }
             }
+            let result = loop {
+                let result = rename(src, &dest, options);
+                if let (true, Err(err)) = (interactive, &result) {
+                    if is_out_of_space(err) {
+                        // Let the user free some space and retry
+                        print!("{}; retry? [y/N]: ", err);
+                        if read_answer().is_some_and(|a| a.eq_ignore_ascii_case("y")) {
+                            continue;
+                        }
+                    }
+                }
+                break result;
+            };
+            match result {
+                Ok(()) => {
+                    num_moved += 1;
+                    if options.porcelain {
+                        print_porcelain(src, &dest, "moved");
+                    }
+                    if options.fsync == Fsync::Batch {
+                        for dir in [src.parent(), dest.parent()].iter().flatten() {
+                            if !dirs_to_sync.iter().any(|d| d == dir) {
+                                dirs_to_sync.push(dir.to_path_buf());
+                            }
+                        }
+                    }
+                    if let Some(f) = on_moved {
+                        f(src, dest.as_path());
+                    }
+                    // Verify after reporting the move, as the rename succeeded even if the
+                    // result looks wrong
+                    if options.verify {
+                        if let Err(err) = verify_renamed(src, &dest) {
+                            if let Some(f) = on_error {
+                                f(src, dest.as_path(), &err);
+                            }
+                            num_errors += 1;
+                        }
+                    }
+                    if options.fsync == Fsync::EachMove {
+                        // The file has been moved anyway, so failing to flush is just warned
+                        for dir in [src.parent(), dest.parent()].iter().flatten() {
+                            if let Err(err) = sync_dir(dir) {
+                                print_warning(format!(
+                                    "failed to flush directory \"{}\": {}",
+                                    dir.to_string_lossy(),
+                                    err
+                                ));
+                            }
+                        }
+                    }
+                }
+                Err(err) => {
+                    // Moving the remaining files would fail likewise
+                    let out_of_space = is_out_of_space(&err);
+                    let err = if out_of_space {
+                        let msg = format!("{} (stopped moving the remaining files)", err);
+                        io::Error::new(err.kind(), msg)
+                    } else {
+                        err
+                    };
+                    if let Some(f) = on_error {
+                        f(src, dest.as_path(), &err);
+                    }
+                    if options.porcelain {
+                        print_porcelain(src, &dest, "failed");
+                    }
+                    num_errors += 1;
+                    if out_of_space {
+                        break;
+                    }
+                }
+            }
+        }
+    }
 
-            // Skip if the input was not "y"
-            let line = line.trim();
-            if line.to_ascii_lowercase() != "y" {
-                continue;
+    for dir in dirs_to_sync {
+        if let Err(err) = sync_dir(&dir) {
+            if let Some(f) = on_error {
+                let err = io::Error::new(err.kind(), format!("failed to flush directory: {}", err));
+                f(&dir, &dir, &err);
             }
+            num_errors += 1;
+        }
+    }
+
+    num_errors
+}
+
+/// Sends files to the trash of the operating system.
+///
+/// This works like `move_files` but with no destination; `dry_run`, `interactive`, `verbose`
+/// and `porcelain` of `options` are used and the others are ignored. Returns the number of
+/// errors occurred.
+pub fn trash_files(
+    paths: &[PathBuf],
+    options: &MoveOptions,
+    on_error: Option<&TrashCallback>,
+) -> i32 {
+    let mut num_errors = 0;
+    for path in paths {
+        let line = format!("{} --> (trash)", quote_path(path));
+        if options.dry_run || (options.verbose && !options.interactive) {
+            if !options.porcelain {
+                println!("{}", line);
+            }
+        } else if options.interactive {
+            print!("{} ... ok? [y/N]: ", line);
+            match read_answer() {
+                Some(answer) if answer.eq_ignore_ascii_case("y") => (),
+                Some(_) => {
+                    if options.porcelain {
+                        print_porcelain(path, Path::new(""), "skipped");
+                    }
+                    continue;
+                }
+                None => {
+                    if let Some(f) = on_error {
+                        f(path, &io::Error::other("error on reading user input"));
+                    }
+                    num_errors += 1;
+                    continue;
+                }
+            }
+        }
+        if options.dry_run {
+            if options.porcelain {
+                print_porcelain(path, Path::new(""), "planned");
+            }
+            continue;
         }
-        if !dry_run {
-            if let Err(err) = std::fs::rename(src, &dest) {
+        match send_to_trash(path) {
+            Ok(()) => {
+                if options.porcelain {
+                    print_porcelain(path, Path::new(""), "trashed");
+                }
+            }
+            Err(err) => {
                 if let Some(f) = on_error {
-                    f(src, dest.as_path(), &err);
+                    f(path, &err);
+                }
+                if options.porcelain {
+                    print_porcelain(path, Path::new(""), "failed");
                 }
                 num_errors += 1;
             }
         }
     }
-
     num_errors
 }
 
+#[cfg(feature = "to-trash")]
+fn send_to_trash(path: &Path) -> io::Result<()> {
+    trash::delete(path).map_err(|err| io::Error::other(err.to_string()))
+}
+
+#[cfg(not(feature = "to-trash"))]
+fn send_to_trash(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "pmv was built without the `to-trash` feature",
+    ))
+}
+
+/// Prints a result of an action as a line of `SRC<TAB>DEST<TAB>STATUS`.
+///
+/// STATUS is one of `planned` (dry-run), `moved`, `trashed`, `skipped` and `failed`. DEST is
+/// empty for files sent to the trash. Backslashes, tabs and
+/// newlines in the paths are escaped as `\\`, `\t` and `\n`. This format will not change in
+/// future versions so that scripts can rely on it.
+fn print_porcelain(src: &Path, dest: &Path, status: &str) {
+    println!(
+        "{}\t{}\t{}",
+        escape_porcelain(src),
+        escape_porcelain(dest),
+        status
+    );
+}
+
+fn escape_porcelain(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Fails if any destination directory of the actions is not writable.
+///
+/// This detects read-only mounts and write-protected directories up front so that the user
+/// gets a single error listing them instead of one error per file.
+pub fn check_writable(actions: &[Action]) -> Result<(), String> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for action in actions {
+        let dest = action.final_dest();
+        if let Some(dir) = dest.parent() {
+            if dir.is_dir() && !dirs.iter().any(|d| d == dir) && !is_writable_dir(dir) {
+                dirs.push(dir.to_owned());
+            }
+        }
+    }
+
+    if dirs.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<String> = dirs
+        .iter()
+        .map(|d| format!("  {}", d.to_string_lossy()))
+        .collect();
+    Err(format!(
+        "cannot move files into read-only directories:\n{}",
+        list.join("\n")
+    ))
+}
+
+/// Fails if a probe file cannot be created in any destination directory of the actions.
+///
+/// An empty file is created and removed in each distinct destination directory (or its nearest
+/// existing ancestor if it will be created) so that problems which `check_writable` cannot
+/// detect, such as exhausted quotas or ACLs, are reported for all directories before moving.
+pub(crate) fn preflight(actions: &[Action]) -> Result<(), String> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for action in actions {
+        let dest = action.final_dest();
+        let mut dir = dest.parent().unwrap_or(&dest);
+        while !dir.as_os_str().is_empty() && !dir.is_dir() {
+            dir = dir.parent().unwrap_or(Path::new(""));
+        }
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        if !dirs.iter().any(|d| d == dir) {
+            dirs.push(dir.to_owned());
+        }
+    }
+
+    let name = format!(".pmv-probe-{}", std::process::id());
+    let errors: Vec<String> = dirs
+        .iter()
+        .filter_map(|dir| {
+            let probe = dir.join(&name);
+            let result = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&probe)
+                .and_then(|_| fs::remove_file(&probe));
+            result
+                .err()
+                .map(|err| format!("  {}: {}", dir.to_string_lossy(), err))
+        })
+        .collect();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "cannot create files in destination directories:\n{}",
+        errors.join("\n")
+    ))
+}
+
+/// Fails if the destination of any action goes through a symbolic link pointing outside `tree`.
+///
+/// Only the part of each destination under `tree` is checked, and the destination itself is
+/// checked only if it is a link to a directory into which the file would be moved.
+pub(crate) fn check_symlink_traversal(actions: &[Action], tree: &Path) -> Result<(), String> {
+    let canonical_tree = tree.canonicalize().unwrap_or_else(|_| tree.to_path_buf());
+    let mut links: Vec<PathBuf> = Vec::new();
+    for action in actions {
+        let relative = match action.dest().strip_prefix(tree) {
+            Ok(relative) => relative,
+            Err(_) => continue,
+        };
+        let mut path = tree.to_path_buf();
+        for component in relative.components() {
+            path.push(component);
+            let is_link = fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink());
+            if !is_link {
+                if !path.exists() {
+                    break;
+                }
+                continue;
+            }
+            if path == action.dest() && !path.is_dir() {
+                break;
+            }
+            let escapes = path
+                .canonicalize()
+                .map_or(true, |target| !target.starts_with(&canonical_tree));
+            if escapes && !links.contains(&path) {
+                links.push(path.clone());
+            }
+        }
+    }
+
+    if links.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<String> = links
+        .iter()
+        .map(|l| format!("  {}", l.to_string_lossy()))
+        .collect();
+    Err(format!(
+        "destinations go through symbolic links pointing outside \"{}\":\n{}",
+        tree.to_string_lossy(),
+        list.join("\n")
+    ))
+}
+
+/// Restores the default SELinux security contexts of the moved files with restorecon(8).
+///
+/// Directories are relabeled recursively. The paths are passed in chunks to keep the command
+/// lines short.
+#[cfg(target_os = "linux")]
+pub(crate) fn restorecon(paths: &[PathBuf]) -> Result<(), String> {
+    use std::process::Command;
+
+    for chunk in paths.chunks(256) {
+        let status = Command::new("restorecon")
+            .arg("-R")
+            .arg("--")
+            .args(chunk)
+            .status()
+            .map_err(|err| format!("failed to run restorecon: {}", err))?;
+        if !status.success() {
+            return Err(format!("restorecon failed: {}", status));
+        }
+    }
+    Ok(())
+}
+
+/// Restores the default SELinux security contexts of the moved files with restorecon(8).
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn restorecon(_paths: &[PathBuf]) -> Result<(), String> {
+    Err(String::from(
+        "--restorecon is not available; SELinux is supported only on Linux",
+    ))
+}
+
+/// Exchanges two paths atomically with renameat2(2) and `RENAME_EXCHANGE`.
+///
+/// Returns `Ok(false)` without changing anything if the kernel or the file system does not
+/// support it, so that the caller can swap them via a temporary name instead.
+#[cfg(target_os = "linux")]
+pub(crate) fn exchange(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    let flags = libc::RENAME_EXCHANGE;
+    let ret = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            flags,
+        )
+    };
+    if ret == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Exchanges two paths atomically with renamex_np(2) and `RENAME_SWAP`.
+///
+/// Returns `Ok(false)` without changing anything if the file system does not support it, so
+/// that the caller can swap them via a temporary name instead.
+#[cfg(target_os = "macos")]
+pub(crate) fn exchange(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    if unsafe { libc::renamex_np(a.as_ptr(), b.as_ptr(), libc::RENAME_SWAP) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EINVAL) | Some(libc::ENOTSUP) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Exchanges two paths atomically if the platform supports it, which it does not.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn exchange(_a: &Path, _b: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Sets the modification time of a file or a directory to now.
+pub(crate) fn touch(path: &Path) -> io::Result<()> {
+    open_for_times(path)?.set_modified(std::time::SystemTime::now())
+}
+
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<std::fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    // FILE_WRITE_ATTRIBUTES, and FILE_FLAG_BACKUP_SEMANTICS to open directories
+    std::fs::OpenOptions::new()
+        .access_mode(0x0100)
+        .custom_flags(0x0200_0000)
+        .open(path)
+}
+
+#[cfg(not(windows))]
+fn open_for_times(path: &Path) -> io::Result<std::fs::File> {
+    std::fs::File::open(path)
+}
+
+/// Tests whether files can be created in the directory or not.
+#[cfg(unix)]
+fn is_writable_dir(dir: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = match CString::new(dir.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return true, // Let the rename report the problem
+    };
+    // access(2) also reports EROFS for read-only file systems
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// Tests whether files can be created in the directory or not, which is not known here.
+///
+/// The read-only attribute of a directory on Windows does not prevent creating files in it and
+/// is set on shell folders such as Documents, so the renames (or `preflight`) report problems.
+#[cfg(not(unix))]
+fn is_writable_dir(_dir: &Path) -> bool {
+    true
+}
+
+/// Renames a file, retrying for a while if another process locks it.
+///
+/// Depending on the options, this also retries on transient errors.
+fn rename(src: &Path, dest: &Path, options: &MoveOptions) -> io::Result<()> {
+    let started = Instant::now();
+    let mut retries = 0;
+    loop {
+        let err = match std::fs::rename(src, dest) {
+            Ok(()) => break,
+            Err(err) => err,
+        };
+        if is_sharing_violation(&err) {
+            match options.wait_for_unlock {
+                Some(timeout) if started.elapsed() < timeout => {
+                    thread::sleep(Duration::from_millis(200));
+                    continue;
+                }
+                _ => {
+                    let holders = lock_holders(src);
+                    let holder = if holders.is_empty() {
+                        String::from("another process")
+                    } else {
+                        holders.join(", ")
+                    };
+                    let msg = format!("{} (the file is opened by {})", err, holder);
+                    return Err(io::Error::new(err.kind(), msg));
+                }
+            }
+        }
+        if is_out_of_space(&err) {
+            if let Some(timeout) = options.wait_for_space {
+                if started.elapsed() < timeout {
+                    thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
+            }
+        }
+        if retries < options.retries && is_transient(&err) {
+            retries += 1;
+            thread::sleep(Duration::from_millis(200) * retries);
+            continue;
+        }
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// Tests whether retrying may resolve the error or not.
+///
+/// EIO and EBUSY are not retried; they tell a real I/O failure or a mount point or directory in
+/// use, which retrying does not cure.
+fn is_transient(err: &io::Error) -> bool {
+    if matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+    ) {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        // NFS reports this while a file handle is being revalidated, e.g. after a server reboot
+        if err.raw_os_error() == Some(libc::ESTALE) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Tests whether the error was caused by a full file system or an exceeded quota.
+fn is_out_of_space(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+    )
+}
+
+/// Fails if the file is not at the destination or still at the source after a rename.
+fn verify_renamed(src: &Path, dest: &Path) -> io::Result<()> {
+    if dest.symlink_metadata().is_err() {
+        return Err(io::Error::other(
+            "the file is not at the destination after renaming",
+        ));
+    }
+    // Renaming only the case of a name leaves the source "existing" on case-insensitive systems
+    let same_name = src.to_string_lossy().to_lowercase() == dest.to_string_lossy().to_lowercase();
+    if !same_name && src.symlink_metadata().is_ok() {
+        return Err(io::Error::other("the file still exists after renaming"));
+    }
+    Ok(())
+}
+
+/// Flushes changes of directory entries to disk.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    std::fs::File::open(dir)?.sync_all()
+}
+
+/// Flushes changes of directory entries to disk.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(()) // The standard library cannot open a directory as a file on this platform
+}
+
+/// Tests whether the error was caused by another process having the file opened.
+#[cfg(windows)]
+fn is_sharing_violation(err: &io::Error) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    matches!(
+        err.raw_os_error(),
+        Some(ERROR_SHARING_VIOLATION) | Some(ERROR_LOCK_VIOLATION)
+    )
+}
+
+/// Tests whether the error was caused by another process having the file opened.
+#[cfg(not(windows))]
+fn is_sharing_violation(_err: &io::Error) -> bool {
+    false // Other processes never prevent renaming a file
+}
+
+/// Returns the names and the IDs of the processes having the file opened, as far as the Restart
+/// Manager of Windows tells.
+#[cfg(windows)]
+fn lock_holders(path: &Path) -> Vec<String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::System::RestartManager::{
+        RmEndSession, RmGetList, RmRegisterResources, RmStartSession, CCH_RM_SESSION_KEY,
+        RM_PROCESS_INFO,
+    };
+    const ERROR_SUCCESS: u32 = 0;
+    const ERROR_MORE_DATA: u32 = 234;
+
+    let mut session = 0;
+    let mut key = [0u16; CCH_RM_SESSION_KEY as usize + 1];
+    if unsafe { RmStartSession(&mut session, 0, key.as_mut_ptr()) } != ERROR_SUCCESS {
+        return Vec::new();
+    }
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let paths = [path.as_ptr()];
+    let mut infos = Vec::new();
+    let ret = unsafe {
+        RmRegisterResources(
+            session,
+            1,
+            paths.as_ptr(),
+            0,
+            std::ptr::null(),
+            0,
+            std::ptr::null(),
+        )
+    };
+    if ret == ERROR_SUCCESS {
+        // The list may grow between the calls
+        infos.resize(4, RM_PROCESS_INFO::default());
+        loop {
+            let (mut needed, mut count, mut reasons) = (0, infos.len() as u32, 0);
+            let ret = unsafe {
+                RmGetList(
+                    session,
+                    &mut needed,
+                    &mut count,
+                    infos.as_mut_ptr(),
+                    &mut reasons,
+                )
+            };
+            match ret {
+                ERROR_SUCCESS => {
+                    infos.truncate(count as usize);
+                    break;
+                }
+                ERROR_MORE_DATA => infos.resize(needed as usize, RM_PROCESS_INFO::default()),
+                _ => {
+                    infos.clear();
+                    break;
+                }
+            }
+        }
+    }
+    unsafe { RmEndSession(session) };
+    infos
+        .iter()
+        .map(|info| {
+            let name = &info.strAppName;
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            format!(
+                "{} (PID {})",
+                String::from_utf16_lossy(&name[..len]),
+                info.Process.dwProcessId
+            )
+        })
+        .collect()
+}
+
+/// Returns the processes having the file opened, which are never reported on this platform.
+#[cfg(not(windows))]
+fn lock_holders(_path: &Path) -> Vec<String> {
+    Vec::new()
+}
+
+/// Tests whether a destination entered interactively for `action` can be used, i.e. nothing
+/// exists there and none of the other `actions` moves a file from or to there.
+fn check_edited_dest(dest: &Path, action: &Action, actions: &[Action]) -> Result<(), String> {
+    let path = match std::env::current_dir() {
+        Ok(curdir) => curdir.join(dest),
+        Err(_) => dest.to_path_buf(),
+    };
+    if path.symlink_metadata().is_ok() {
+        return Err(format!("\"{}\" already exists", dest.to_string_lossy()));
+    }
+    let others = actions.iter().filter(|other| !std::ptr::eq(*other, action));
+    for other in others {
+        if other.src() == path || other.final_dest() == path {
+            return Err(format!(
+                "\"{}\" is used by another move",
+                dest.to_string_lossy()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Reads a line from stdin after flushing stdout.
+///
+/// Returns `None` if no input was available.
+pub(crate) fn read_answer() -> Option<String> {
+    let _ = io::stdout().lock().flush();
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_owned()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use function_name::named;
+
+    mod trash_files {
+        use super::*;
+
+        use function_name::named;
+        use std::fs;
+
+        #[named]
+        #[test]
+        fn trash_files_dry_run() {
+            let dir = PathBuf::from("temp").join(function_name!());
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("a"), "a").unwrap();
+
+            let options = MoveOptions {
+                dry_run: true,
+                ..Default::default()
+            };
+            assert_eq!(trash_files(&[dir.join("a")], &options, None), 0);
+            assert!(dir.join("a").exists());
+        }
+
+        #[cfg(not(feature = "to-trash"))]
+        #[named]
+        #[test]
+        fn trash_files_unsupported() {
+            let dir = PathBuf::from("temp").join(function_name!());
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("a"), "a").unwrap();
+
+            assert_eq!(
+                trash_files(&[dir.join("a")], &MoveOptions::default(), None),
+                1
+            );
+            assert!(dir.join("a").exists());
+        }
+    }
 
     mod move_files {
         use super::*;
@@ -102,6 +940,13 @@ mod tests {
         #[cfg(unix)]
         use std::os;
 
+        fn options(dry_run: bool) -> MoveOptions {
+            MoveOptions {
+                dry_run,
+                ..Default::default()
+            }
+        }
+
         fn prepare_test(id: &str) -> Result<(), io::Error> {
             let _ = fs::create_dir("temp");
             let path = format!("temp/{}", id);
@@ -163,7 +1008,7 @@ mod tests {
 
             let dry_run = true;
             let actions = make_actions(id, vec![("f1", "f2")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(mkpathbuf(id, "f1").exists());
@@ -182,7 +1027,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("f1", "\0")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 1);
             assert!(mkpathbuf(id, "f1").exists());
@@ -201,7 +1046,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("f1", "f2")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "f1").exists());
@@ -220,7 +1065,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("f1", "d1")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "f1").exists());
@@ -240,7 +1085,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("f1", "lf1")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "f1").exists());
@@ -261,7 +1106,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("f1", "ld1")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "f1").exists());
@@ -281,7 +1126,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("d1", "f1")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 1);
             assert!(mkpathbuf(id, "d1").exists());
@@ -300,7 +1145,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("d1", "d2")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "d1").exists());
@@ -321,7 +1166,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("d1", "lf1")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 1);
             assert!(mkpathbuf(id, "d1").is_dir());
@@ -341,7 +1186,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("d1", "ld2")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "d1").exists());
@@ -362,7 +1207,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("lf1", "f2")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "lf1").is_file());
@@ -383,7 +1228,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("lf1", "d1")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "lf1").exists());
@@ -405,7 +1250,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("lf1", "lf2")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "lf1").exists());
@@ -429,7 +1274,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("lf1", "ld1")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "lf1").exists());
@@ -451,7 +1296,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("ld1", "f1")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 1);
             assert!(mkpathbuf(id, "ld1").exists());
@@ -472,7 +1317,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("ld1", "d2")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "ld1").exists());
@@ -494,7 +1339,7 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("ld1", "lf1")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 1);
             assert!(mkpathbuf(id, "ld1").exists());
@@ -515,12 +1360,291 @@ mod tests {
 
             let dry_run = false;
             let actions = make_actions(id, vec![("ld1", "ld2")]);
-            let num_errors = move_files(&actions, dry_run, false, false, None);
+            let num_errors = move_files(&actions, &options(dry_run), None, None);
 
             assert_eq!(num_errors, 0);
             assert!(!mkpathbuf(id, "ld1").exists());
             assert!(mkpathbuf(id, "d2/ld1").exists());
             assert!(mkpathbuf(id, "ld2/ld1").exists());
         }
+
+        #[named]
+        #[test]
+        fn network_safe() {
+            let id = function_name!();
+
+            prepare_test(id).unwrap();
+            mkfile(id, "f1").unwrap();
+            mkdir(id, "d1").unwrap();
+
+            let options = MoveOptions {
+                retries: 3,
+                verify: true,
+                fsync: Fsync::EachMove,
+                ..Default::default()
+            };
+            let actions = make_actions(id, vec![("f1", "d1/f2"), ("missing", "f3")]);
+            let num_errors = move_files(&actions, &options, None, None);
+
+            assert_eq!(num_errors, 1);
+            assert!(!mkpathbuf(id, "f1").exists());
+            assert_eq!(content_of(id, "d1/f2"), format!("temp/{}/f1", id));
+        }
+    }
+
+    mod fsync {
+        use super::*;
+        use function_name::named;
+        use std::fs;
+
+        #[named]
+        #[test]
+        fn batch() {
+            let dir = std::env::current_dir()
+                .unwrap()
+                .join("temp")
+                .join(function_name!());
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("d")).unwrap();
+            fs::write(dir.join("a"), "a").unwrap();
+            fs::write(dir.join("b"), "b").unwrap();
+
+            let options = MoveOptions {
+                fsync: Fsync::Batch,
+                ..Default::default()
+            };
+            let actions = vec![
+                Action::new(dir.join("a"), dir.join("d/a")),
+                Action::new(dir.join("b"), dir.join("d/b")),
+            ];
+            let num_errors = move_files(&actions, &options, None, None);
+
+            assert_eq!(num_errors, 0);
+            assert!(dir.join("d/a").exists());
+            assert!(dir.join("d/b").exists());
+        }
+    }
+
+    #[named]
+    #[test]
+    fn chunks() {
+        use std::fs;
+
+        let dir = std::env::current_dir()
+            .unwrap()
+            .join("temp")
+            .join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut actions = Vec::new();
+        for name in ["a", "b", "c"].iter() {
+            fs::write(dir.join(name), name).unwrap();
+            actions.push(Action::new(dir.join(name), dir.join(format!("{}2", name))));
+        }
+
+        // Pauses once between the first two moves and the last one
+        let options = MoveOptions {
+            chunk_size: Some(2),
+            pause: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let num_errors = move_files(&actions, &options, None, None);
+
+        assert_eq!(num_errors, 0);
+        assert!(Duration::from_millis(200) <= started.elapsed());
+        assert_eq!(fs::read_to_string(dir.join("c2")).unwrap(), "c");
+    }
+
+    #[named]
+    #[test]
+    fn touch_file() {
+        use std::fs;
+        use std::time::SystemTime;
+
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a");
+        fs::write(&path, "a").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        touch(&path).unwrap();
+        touch(&dir).unwrap();
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+        assert!(SystemTime::now().duration_since(mtime).unwrap().as_secs() < 60);
+    }
+
+    #[named]
+    #[test]
+    fn preflight_probe() {
+        use std::fs;
+
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("d")).unwrap();
+
+        let actions = vec![
+            Action::new(dir.join("a"), dir.join("d")),
+            Action::new(dir.join("b"), dir.join("d/b")),
+            Action::new(dir.join("c"), dir.join("new/sub/c")),
+            Action::new("e", "e2"),
+        ];
+        assert_eq!(preflight(&actions), Ok(()));
+        assert_eq!(fs::read_dir(dir.join("d")).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[named]
+    #[test]
+    fn symlink_traversal() {
+        use std::fs;
+        use std::os::unix::fs::symlink;
+
+        let dir = std::env::current_dir()
+            .unwrap()
+            .join("temp")
+            .join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        let tree = dir.join("tree");
+        fs::create_dir_all(tree.join("inner")).unwrap();
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        symlink(dir.join("outside"), tree.join("out")).unwrap();
+        symlink("inner", tree.join("in")).unwrap();
+
+        let check = |dest: &str| {
+            let actions = [Action::new(tree.join("a"), tree.join(dest))];
+            check_symlink_traversal(&actions, &tree)
+        };
+        assert!(check("out/a").is_err());
+        assert!(check("out").is_err());
+        assert!(check("in/a").is_ok());
+        assert!(check("new/a").is_ok());
+        assert!(check("../a").is_ok());
+    }
+
+    #[test]
+    fn porcelain_escape() {
+        assert_eq!(escape_porcelain(Path::new("a\tb\nc\\d")), "a\\tb\\nc\\\\d");
+        assert_eq!(escape_porcelain(Path::new("写真.jpg")), "写真.jpg");
+    }
+
+    #[test]
+    fn out_of_space() {
+        assert!(is_out_of_space(&io::Error::from(
+            io::ErrorKind::StorageFull
+        )));
+        assert!(is_out_of_space(&io::Error::from(
+            io::ErrorKind::QuotaExceeded
+        )));
+        assert!(!is_out_of_space(&io::Error::from(io::ErrorKind::NotFound)));
+    }
+
+    mod is_transient {
+        use super::*;
+
+        #[test]
+        fn kinds() {
+            assert!(is_transient(&io::Error::from(io::ErrorKind::Interrupted)));
+            assert!(!is_transient(&io::Error::from(io::ErrorKind::NotFound)));
+            assert!(!is_transient(&io::Error::from(
+                io::ErrorKind::PermissionDenied
+            )));
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn os_errors() {
+            assert!(is_transient(&io::Error::from_raw_os_error(libc::ESTALE)));
+            assert!(is_transient(&io::Error::from_raw_os_error(libc::EAGAIN)));
+            assert!(is_transient(&io::Error::from_raw_os_error(libc::EINTR)));
+            assert!(!is_transient(&io::Error::from_raw_os_error(libc::EIO)));
+            assert!(!is_transient(&io::Error::from_raw_os_error(libc::EBUSY)));
+            assert!(!is_transient(&io::Error::from_raw_os_error(libc::ENOENT)));
+        }
+    }
+
+    mod check_edited_dest {
+        use super::*;
+
+        use function_name::named;
+        use std::fs;
+
+        #[named]
+        #[test]
+        fn taken() {
+            let dir = std::env::current_dir()
+                .unwrap()
+                .join("temp")
+                .join(function_name!());
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("a"), "a").unwrap();
+            fs::write(dir.join("x"), "x").unwrap();
+            let actions = vec![
+                Action::new(dir.join("a"), dir.join("b")),
+                Action::new(dir.join("c"), dir.join("d")),
+            ];
+            let check = |dest: &str| check_edited_dest(&dir.join(dest), &actions[0], &actions);
+
+            assert_eq!(check("e"), Ok(()));
+            assert_eq!(check("b"), Ok(())); // Its own destination
+            assert!(check("x").is_err()); // Existing file
+            assert!(check("c").is_err()); // Source of another move
+            assert!(check("d").is_err()); // Destination of another move
+        }
+    }
+
+    mod check_writable {
+        use super::*;
+
+        use function_name::named;
+        use std::fs;
+
+        #[named]
+        #[test]
+        fn writable() {
+            let dir = std::env::current_dir()
+                .unwrap()
+                .join("temp")
+                .join(function_name!());
+            let _ = fs::create_dir_all(&dir);
+            let actions = vec![Action::new(dir.join("a"), dir.join("b"))];
+            assert!(check_writable(&actions).is_ok());
+        }
+
+        #[cfg(unix)]
+        #[named]
+        #[test]
+        fn readonly() {
+            use std::os::unix::fs::PermissionsExt;
+
+            let dir = std::env::current_dir()
+                .unwrap()
+                .join("temp")
+                .join(function_name!());
+            let _ = fs::create_dir_all(dir.join("ro"));
+            fs::set_permissions(dir.join("ro"), fs::Permissions::from_mode(0o555)).unwrap();
+            let actions = vec![
+                Action::new(dir.join("a"), dir.join("ro/a")),
+                Action::new(dir.join("b"), dir.join("ro/b")),
+                Action::new(dir.join("c"), dir.join("c2")),
+            ];
+            let result = check_writable(&actions);
+            fs::set_permissions(dir.join("ro"), fs::Permissions::from_mode(0o755)).unwrap();
+            if unsafe { libc::geteuid() } == 0 {
+                return; // root can write anywhere
+            }
+            let msg = result.unwrap_err();
+            assert!(msg.contains("read-only"));
+            assert_eq!(msg.lines().count(), 2); // a message and a directory
+            assert!(msg.contains(&*dir.join("ro").to_string_lossy()));
+        }
     }
 }
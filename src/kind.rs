@@ -0,0 +1,126 @@
+use std::path::Path;
+
+/// Kinds of files which `--kind` and `#{kind}` distinguish.
+pub const KINDS: [&str; 8] = [
+    "image",
+    "video",
+    "audio",
+    "document",
+    "archive",
+    "font",
+    "application",
+    "other",
+];
+
+/// Type of a file detected from its content.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileType {
+    pub mime: &'static str,
+    pub kind: &'static str,
+}
+
+/// Detects the type of a file from the magic bytes at its beginning.
+///
+/// Files of unknown type are reported as `application/octet-stream` of kind `other`.
+#[cfg(feature = "mime")]
+pub fn sniff(path: &Path) -> Result<FileType, String> {
+    use infer::MatcherType;
+
+    let t = match infer::get_from_path(path) {
+        Ok(t) => t,
+        Err(err) => {
+            return Err(format!(
+                "failed to read \"{}\": {}",
+                path.to_string_lossy(),
+                err
+            ))
+        }
+    };
+    Ok(match t {
+        Some(t) => FileType {
+            mime: t.mime_type(),
+            kind: match t.matcher_type() {
+                MatcherType::Image => "image",
+                MatcherType::Video => "video",
+                MatcherType::Audio => "audio",
+                MatcherType::Doc | MatcherType::Book | MatcherType::Text => "document",
+                MatcherType::Archive => "archive",
+                MatcherType::Font => "font",
+                MatcherType::App => "application",
+                MatcherType::Custom => "other",
+            },
+        },
+        None => FileType {
+            mime: "application/octet-stream",
+            kind: "other",
+        },
+    })
+}
+
+#[cfg(not(feature = "mime"))]
+pub fn sniff(_path: &Path) -> Result<FileType, String> {
+    Err(String::from(
+        "file type detection is not available; pmv was built without the `mime` feature",
+    ))
+}
+
+/// Tests whether the file is of one of the kinds or not.
+///
+/// An empty list accepts any file without reading it.
+pub fn has_kind(path: &Path, kinds: &[String]) -> Result<bool, String> {
+    if kinds.is_empty() {
+        return Ok(true);
+    }
+    let kind = sniff(path)?.kind;
+    Ok(kinds.iter().any(|k| k == kind))
+}
+
+#[cfg(all(test, feature = "mime"))]
+mod tests {
+    use super::*;
+    use function_name::named;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn prepare_test(id: &str) -> PathBuf {
+        let dir = std::env::current_dir().unwrap().join("temp").join(id);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("image"), b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR").unwrap();
+        fs::write(dir.join("archive"), b"PK\x03\x04\x14\0\0\0\0\0").unwrap();
+        fs::write(dir.join("unknown"), b"hello").unwrap();
+        dir
+    }
+
+    #[named]
+    #[test]
+    fn sniff_types() {
+        let dir = prepare_test(function_name!());
+        assert_eq!(
+            sniff(&dir.join("image")),
+            Ok(FileType {
+                mime: "image/png",
+                kind: "image"
+            })
+        );
+        assert_eq!(sniff(&dir.join("archive")).unwrap().kind, "archive");
+        assert_eq!(
+            sniff(&dir.join("unknown")),
+            Ok(FileType {
+                mime: "application/octet-stream",
+                kind: "other"
+            })
+        );
+        assert!(sniff(&dir.join("missing")).is_err());
+    }
+
+    #[named]
+    #[test]
+    fn filter_by_kind() {
+        let dir = prepare_test(function_name!());
+        let kinds = vec![String::from("image"), String::from("video")];
+        assert_eq!(has_kind(&dir.join("image"), &kinds), Ok(true));
+        assert_eq!(has_kind(&dir.join("archive"), &kinds), Ok(false));
+        assert_eq!(has_kind(&dir.join("missing"), &[]), Ok(true));
+    }
+}
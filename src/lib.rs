@@ -1,26 +1,191 @@
+//! Pattern based mv(1).
+//!
+//! Besides the `pmv` command, this crate exposes the building blocks of it as a library:
+//!
+//! - `fnmatch` and `fnmatch_with_options` match a file name with a pattern.
+//! - `Pattern` is a SOURCE pattern compiled once to match many paths.
+//! - `walk` and `walk_with` search a directory tree for entries matching a pattern.
+//! - `substitute_variables` builds a destination from a DEST pattern and matched parts.
+//! - `rename_pattern` builds the destination of a single path without walking.
+//! - `resolve_conflicts` resolves destinations shared by multiple `Action`s.
+//! - `sort_actions` sorts `Action`s so that they can be executed safely.
+//! - `split_via_temp` splits `Action`s into two phases moving files via temporary names.
+//! - `Plan` renders the preview of `Action`s exactly as `pmv --dry-run` prints it.
+//! - `move_files` executes `Action`s.
+//! - `trash_files` sends files to the trash instead of moving them.
+//! - `pathnorm` normalizes paths by the same rules as the above.
+//!
+//! Only the items re-exported here (and the `pathnorm` module) are public API and they follow
+//! semantic versioning. Anything else is an implementation detail of the command.
+
 mod action;
+mod archive;
+mod audio;
+mod chains;
+mod collate;
+mod deadline;
+mod dialect;
+mod dimensions;
+mod estimate;
 mod fnmatch;
 mod fsutil;
+mod kind;
+mod manifest;
+pub mod pathnorm;
+mod pattern;
 mod plan;
+mod planfile;
+mod preview;
+mod quote;
+mod regexp;
+mod report;
+mod settings;
+mod summary;
+mod symlink;
+mod token;
+mod tree;
 mod walk;
+mod watch;
+
+pub use action::Action;
+pub use collate::Collation;
+pub use fnmatch::{fnmatch, fnmatch_with_options, CaseSensitivity, MatchOptions};
+pub use fsutil::{
+    move_files, trash_files, Callback, Fsync, MoveOptions, MovedCallback, TrashCallback,
+};
+pub use pattern::Pattern;
+pub use plan::{
+    rename_pattern, resolve_conflicts, sort_actions, sort_actions_with, split_via_temp,
+    split_via_temp_with, substitute_variables, OnConflict, TempNaming,
+};
+pub use preview::{Plan, PreviewLine};
+use walk::walk_with_warnings;
+pub use walk::{walk, walk_with, FollowSymlinks, Match};
+use watch::DestWatcher;
 
-use action::Action;
-use fsutil::move_files;
-use plan::sort_actions;
-use plan::substitute_variables;
+use chains::{render_chain_dot, render_chain_tree};
+use collate::sort_by_path;
+use deadline::Deadline;
+use dialect::{translate, Dialect};
+use estimate::estimate;
+use fsutil::{
+    check_symlink_traversal, check_writable, exchange, move_files_with, preflight, read_answer,
+    restorecon, touch,
+};
+use kind::{has_kind, KINDS};
+use manifest::write_manifest;
+use plan::{
+    find_aliased_sources, find_case_collisions, find_shadowing_dirs, sort_chains, split_via_stage,
+    staging_dir, substitute_tokens, temp_name, temporaries, TEMP_DIR,
+};
+use planfile::{
+    mark_done, parse_ids, plan_hash, plan_schema, read_plan, record_applied, was_applied_last,
+    write_plan,
+};
+use quote::{quote, quote_message, quote_path, set_literal_paths};
+use report::render_diff;
+use settings::{config_paths, default_args, Setting};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
-use std::io::{self, Write};
-use std::process::exit;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use summary::{render_counts, render_groups, render_rule_stats, render_summary};
+use symlink::fix_symlinks;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
-use walk::walk;
+use token::{parse_duration, MissingTag, TokenContext, TokenOptions};
+use tree::render_tree;
 
 #[derive(Debug)]
 struct Config {
     src_ptn: String,
     dest_ptn: String,
+    root: Option<PathBuf>,
+    examples: Vec<String>,
+    settings_command: Option<SettingsCommand>,
+    repl: bool,
+    print_renamed: bool,
+    schema: Option<String>,
+    extensions: Vec<String>,
+    kinds: Vec<String>,
+    missing_tag: MissingTag,
+    capture_groups: bool,
+    case_sensitivity: CaseSensitivity,
+    regex: bool,
+    normalize_unicode: bool,
     dry_run: bool,
+    check: bool,
+    tree: bool,
+    summary: bool,
+    group_by_dest: bool,
+    explain_plan: Option<PlanFormat>,
+    count: Option<Count>,
     verbose: bool,
+    explain: bool,
+    entry_filter: EntryFilter,
     interactive: bool,
+    match_limit: usize,
+    max_files: Option<usize>,
+    yes: bool,
+    warnings_as_errors: bool,
+    wait_for_unlock: Option<u64>,
+    wait_for_space: Option<u64>,
+    chunk_size: Option<usize>,
+    pause: Option<u64>,
+    timeout: Option<Duration>,
+    stream: Option<usize>,
+    manifest: Option<PathBuf>,
+    diff: bool,
+    save_plan: Option<PathBuf>,
+    plan: Option<PathBuf>,
+    cycle: Vec<PathBuf>,
+    rotate_numbered: Option<(PathBuf, usize)>,
+    only: Vec<RangeInclusive<usize>>,
+    force: bool,
+    strict: bool,
+    resume: bool,
+    skip: Vec<RangeInclusive<usize>>,
+    flatten: bool,
+    on_conflict: OnConflict,
+    fix_symlinks: Option<PathBuf>,
+    restorecon: bool,
+    watch: bool,
+    preflight: bool,
+    no_symlink_traversal: bool,
+    no_follow_dest_symlinks: bool,
+    touch: bool,
+    porcelain: bool,
+    literal_paths: bool,
+    to_trash: bool,
+    into_archive: Option<PathBuf>,
+    rules: Vec<(String, String)>,
+    from_archive: Option<PathBuf>,
+    dedupe_sources: bool,
+    via_temp: bool,
+    stage: bool,
+    temp_naming: TempNaming,
+    network_safe: bool,
+    fsync: Fsync,
+    follow_symlinks: FollowSymlinks,
+    collation: Collation,
+}
+
+/// Prints a warning message.
+pub fn print_warning<S: AsRef<str>>(msg: S) {
+    fn do_print(msg: &str) -> Result<(), io::Error> {
+        let mut stdout = StandardStream::stderr(ColorChoice::Auto);
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+        write!(&mut stdout, "warning")?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+        writeln!(&mut stdout, ": {}", msg)
+    }
+
+    let msg = quote_message(msg.as_ref());
+    if do_print(&msg).is_err() {
+        eprintln!("warning: {}", msg);
+    }
 }
 
 /// Prints an error message.
@@ -32,132 +197,3643 @@ pub fn print_error<S: AsRef<str>>(msg: S) {
         stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
         writeln!(&mut stdout, ": {}", msg)
     }
-
-    let msg = msg.as_ref();
-    if do_print(msg).is_err() {
-        eprintln!("error: {}", msg);
+
+    let msg = quote_message(msg.as_ref());
+    if do_print(&msg).is_err() {
+        eprintln!("error: {}", msg);
+    }
+}
+
+fn command() -> clap::Command<'static> {
+    clap::Command::new("pmv")
+        .version(clap::crate_version!())
+        .about(clap::crate_description!())
+        .arg(
+            clap::Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Does not move files but just shows what would be done")
+                .long_help(
+                    "Does not move files but just shows what would be done, followed by an \
+                     estimate of how many moves are renames, how many are across file systems \
+                     and will fail as pmv does not copy files, and how many destination \
+                     directories are missing",
+                ),
+        )
+        .arg(
+            clap::Arg::new("check")
+                .long("check")
+                .action(clap::builder::ArgAction::SetTrue)
+                .requires("dry-run")
+                .conflicts_with_all(&["tree", "summary", "group-by-dest"])
+                .help("Fails if any file would be moved (requires --dry-run)")
+                .long_help(
+                    "Exits with a non-zero status if any file would be moved, or zero if \
+                     there is nothing to do, so that scripts can tell whether files are \
+                     already organized. Files already at their destinations are not counted. \
+                     Requires --dry-run.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("tree")
+                .long("tree")
+                .requires("dry-run")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Shows trees of affected directories before and after (with --dry-run)")
+                .long_help(
+                    "Shows trees of the affected paths before and after moving instead of listing \
+                     each move. Directories which will be created are marked with `+` and \
+                     directories which will become empty are marked with `-`. This can be used \
+                     only with --dry-run.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("interactive")
+                .short('i')
+                .long("interactive")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Prompts before moving an each file")
+                .long_help(
+                    "Prompts before moving an each file. Answer `y` to move it, `e` to enter a \
+                     different destination for it, or anything else to skip it. An entered \
+                     destination is asked again if a file exists there or another move uses \
+                     it.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("summary")
+                .long("summary")
+                .requires("dry-run")
+                .conflicts_with("tree")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Shows the number and size of files per destination (with --dry-run)")
+                .long_help(
+                    "Shows the number and the total size of files moved into each destination \
+                     directory instead of listing each move, which is easier to review for \
+                     large reorganizations. This can be used only with --dry-run.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("group-by-dest")
+                .long("group-by-dest")
+                .requires("dry-run")
+                .conflicts_with_all(&["tree", "summary"])
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Lists moves grouped by destination directory (with --dry-run)")
+                .long_help(
+                    "Lists the moves grouped by destination directory, each under a heading \
+                     with the number of files moved into it, instead of in the order of the \
+                     sources. This can be used only with --dry-run.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("explain-plan")
+                .long("explain-plan")
+                .value_name("FORMAT")
+                .value_parser(["tree", "dot"])
+                .min_values(0)
+                .max_values(1)
+                .require_equals(true)
+                .default_missing_value("tree")
+                .requires("dry-run")
+                .conflicts_with_all(&[
+                    "tree",
+                    "summary",
+                    "group-by-dest",
+                    "check",
+                    "via-temp",
+                    "stage",
+                    "network-safe",
+                    "stream",
+                    "count",
+                    "porcelain",
+                ])
+                .help("Shows the chains of dependent moves in execution order (with --dry-run)")
+                .long_help(
+                    "Shows the chains of moves which must be done in order, as pmv will execute \
+                     them, instead of listing each move. In each chain, a move comes after the \
+                     one which vacates its destination. A circular chain is broken by moving a \
+                     file to a temporary name first and back from there last. `tree` (default) \
+                     shows each chain as an indented tree, and `dot` writes a graph in the DOT \
+                     language of Graphviz (e.g. `pmv -n --explain-plan=dot ... | dot -Tsvg`). \
+                     This can be used only with --dry-run.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("count")
+                .long("count")
+                .value_name("BY")
+                .value_parser(["total", "dest"])
+                .min_values(0)
+                .max_values(1)
+                .require_equals(true)
+                .default_missing_value("total")
+                .conflicts_with_all(&[
+                    "interactive",
+                    "tree",
+                    "summary",
+                    "group-by-dest",
+                    "check",
+                    "save-plan",
+                    "porcelain",
+                ])
+                .help("Prints only the number of files which would be moved")
+                .long_help(
+                    "Prints only the number of files which would be moved, and exits without \
+                     moving them. `total` (default) prints a single number and `dest` prints \
+                     the number per destination directory. Files already at their \
+                     destinations are not counted.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("match-limit")
+                .long("match-limit")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("5000")
+                .help("Asks whether to continue scanning every N matches (with --interactive)")
+                .long_help(
+                    "In interactive mode, stops scanning each time N files matched and asks \
+                     whether to continue or not. Specify 0 to never stop.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("max-files")
+                .long("max-files")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Asks for confirmation before moving more than N files")
+                .long_help(
+                    "Asks for confirmation before moving more than N files, guarding against \
+                     patterns which unexpectedly match many files. Fails if confirmation is \
+                     not possible and --yes is not given.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Moves files without asking for confirmation of --max-files"),
+        )
+        .arg(
+            clap::Arg::new("wait-for-unlock")
+                .long("wait-for-unlock")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Retries moving a file opened by another process for SECS seconds")
+                .long_help(
+                    "On Windows, a file opened by another process cannot be moved. With this \
+                     option, pmv keeps retrying to move such a file for SECS seconds before \
+                     giving up. The error message names the processes having the file opened \
+                     if Windows can tell.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("wait-for-space")
+                .long("wait-for-space")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Retries a move failed for lack of disk space or quota for SECS seconds")
+                .long_help(
+                    "If the destination runs out of disk space or quota, pmv stops moving the \
+                     remaining files instead of failing on each of them. In interactive mode, \
+                     pmv asks whether to retry instead. With this option, pmv keeps retrying \
+                     the move for SECS seconds before giving up, so that space can be freed \
+                     meanwhile.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("chunk-size")
+                .long("chunk-size")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .requires("pause")
+                .help("Pauses each time N files were moved (requires --pause)"),
+        )
+        .arg(
+            clap::Arg::new("pause")
+                .long("pause")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64))
+                .requires("chunk-size")
+                .help("Seconds to pause between chunks of moves (requires --chunk-size)")
+                .long_help(
+                    "Pauses for SECS seconds each time --chunk-size files were moved, so that \
+                     indexers, backup agents and sync clients can keep up with a very large \
+                     batch instead of being flooded with renames at once.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("timeout")
+                .long("timeout")
+                .value_name("DURATION")
+                .value_parser(|s: &str| {
+                    parse_duration(s)
+                        .ok_or_else(|| format!("invalid duration `{}` (e.g.: 90s, 30m, 12h)", s))
+                })
+                .help("Aborts if the whole run takes longer than DURATION (e.g.: 30m)")
+                .long_help(
+                    "Aborts if searching, planning and moving files take longer than DURATION \
+                     in total, such as `90s`, `30m` or `2h` (`d` and `w` are also accepted.) \
+                     Once the limit has passed, no more files are moved and the files already \
+                     moved are reported as usual (e.g. in --manifest), so that a cron job on an \
+                     unresponsive network mount does not pile up stuck processes. If pmv is \
+                     stuck in a single file system operation, it exits 10 seconds after the \
+                     limit.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("stream")
+                .long("stream")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with_all(&[
+                    "interactive",
+                    "via-temp",
+                    "stage",
+                    "tree",
+                    "summary",
+                    "group-by-dest",
+                    "count",
+                    "check",
+                    "max-files",
+                    "save-plan",
+                    "diff",
+                    "manifest",
+                    "to-trash",
+                    "into-archive",
+                    "from-archive",
+                    "watch",
+                    "dedupe-sources",
+                    "fix-symlinks",
+                    "touch",
+                    "restorecon",
+                    "preflight",
+                    "no-symlink-traversal",
+                    "warnings-as-errors",
+                    "chunk-size",
+                ])
+                .help("Moves files in windows of N files while searching for more")
+                .long_help(
+                    "Moves files in windows of N files while searching for more, instead of \
+                     collecting every matched file before moving any of them, so that millions \
+                     of files can be moved with bounded memory. As files are moved before \
+                     all of them are found, pmv stops if a destination matches SOURCE (it \
+                     could be found and moved again) or already exists (it could be a file \
+                     moved in an earlier window), leaving the files of earlier windows moved. \
+                     Options which need every file beforehand, such as --interactive, \
+                     --via-temp or --summary, cannot be used with this option.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("dereference-command-line")
+                .short('H')
+                .long("dereference-command-line")
+                .action(clap::builder::ArgAction::SetTrue)
+                .overrides_with_all(&["dereference", "no-dereference"])
+                .help(
+                    "Follows symbolic links only in the leading part of SOURCE without wildcards",
+                ),
+        )
+        .arg(
+            clap::Arg::new("dereference")
+                .short('L')
+                .long("dereference")
+                .action(clap::builder::ArgAction::SetTrue)
+                .overrides_with_all(&["dereference-command-line", "no-dereference"])
+                .help("Follows all symbolic links to directories (default)"),
+        )
+        .arg(
+            clap::Arg::new("no-dereference")
+                .short('P')
+                .long("no-dereference")
+                .action(clap::builder::ArgAction::SetTrue)
+                .overrides_with_all(&["dereference-command-line", "dereference"])
+                .help("Never follows symbolic links; moves the links themselves"),
+        )
+        .arg(
+            clap::Arg::new("collate")
+                .long("collate")
+                .value_name("ORDER")
+                .value_parser(["bytewise", "natural", "locale"])
+                .default_value("bytewise")
+                .help("Order of files in output")
+                .long_help(
+                    "Order of files in output. `bytewise` compares paths byte by byte, \
+                     `natural` compares numbers in paths by their values (e.g.: `2` comes before \
+                     `10`), and `locale` follows the collation rules of the current locale \
+                     (available only if pmv was built with the `locale` feature.)",
+                ),
+        )
+        .arg(
+            clap::Arg::new("dedupe-sources")
+                .long("dedupe-sources")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Skips matched files which are the same file as another matched file")
+                .long_help(
+                    "Skips matched files which are the same file as another matched file, i.e. \
+                     hard links to the same file or symbolic links to another matched file. \
+                     Without this option, pmv only warns about them.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("source")
+                .long("source")
+                .value_name("SOURCE")
+                .allow_hyphen_values(true)
+                .help("Source pattern, which may start with `-`, instead of SOURCE"),
+        )
+        .arg(
+            clap::Arg::new("dest")
+                .long("dest")
+                .value_name("DEST")
+                .allow_hyphen_values(true)
+                .requires("source")
+                .help("Destination pattern, which may start with `-`, instead of DEST"),
+        )
+        .arg(
+            clap::Arg::new("rules")
+                .long("rules")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with_all(&[
+                    "SOURCE",
+                    "DEST",
+                    "source",
+                    "dest",
+                    "stream",
+                    "print-renamed",
+                    "to-trash",
+                    "into-archive",
+                    "from-archive",
+                ])
+                .help("Reads pairs of SOURCE and DEST from FILE instead of the arguments")
+                .long_help(
+                    "Reads rules, pairs of SOURCE and DEST separated by a tab, from FILE, one \
+                     per line, instead of taking SOURCE and DEST from the arguments. Empty \
+                     lines and lines starting with `#` are ignored. Every rule is matched \
+                     against the files as they are before moving any of them, and a file \
+                     matched by multiple rules is moved by the first of them. After moving, \
+                     pmv shows how many files each rule matched and moved, and warns about \
+                     rules which matched nothing.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("print-renamed")
+                .long("print-renamed")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with_all(&["root", "dry-run", "interactive", "to-trash", "into-archive"])
+                .help("Reads paths from stdin and prints them renamed, without moving files")
+                .long_help(
+                    "Reads paths from the standard input, one per line, and prints each of \
+                     them renamed by SOURCE and DEST without accessing the file system, so \
+                     that pmv can transform paths in pipelines. Lines not matching SOURCE are \
+                     printed as they are. Tokens such as `#{mtime}` are left as they are.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("root")
+                .long("root")
+                .value_name("DIR")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Resolves relative SOURCE and DEST from DIR instead of the current directory")
+                .long_help(
+                    "Resolves relative SOURCE and DEST from DIR instead of the current \
+                     directory, so `#1`, `#2`... capture paths inside DIR (e.g. a project) \
+                     regardless of where pmv is invoked. Paths are still shown relative to the \
+                     current directory.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("dialect")
+                .long("dialect")
+                .value_name("DIALECT")
+                .value_parser(["pmv", "mmv"])
+                .default_value("pmv")
+                .help("Syntax of SOURCE and DEST")
+                .long_help(
+                    "Syntax of SOURCE and DEST. `pmv` is the default. `mmv` accepts patterns \
+                     of mmv(1) so that existing scripts can be used as they are; `#l1` and \
+                     `#u1` in DEST are replaced with `#1` converted to lowercase and \
+                     uppercase. The recursive wildcard `;` of mmv matches like `**/`.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("flatten")
+                .long("flatten")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Moves all matched files into DEST keeping only their names")
+                .long_help(
+                    "Moves all matched files directly into the directory DEST keeping only \
+                     their file names, regardless of where they were found. Use \
+                     --on-conflict to choose what to do with files having the same name.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("on-conflict")
+                .long("on-conflict")
+                .value_name("POLICY")
+                .value_parser(["fail", "skip", "suffix"])
+                .default_value("fail")
+                .help("What to do if multiple files would be moved to the same location")
+                .long_help(
+                    "What to do if multiple files would be moved to the same location. `fail` \
+                     (default) fails without moving any file, `skip` moves only the first one \
+                     and `suffix` appends a number to the names of the others (e.g. \
+                     `a_2.txt`).",
+                ),
+        )
+        .arg(
+            clap::Arg::new("via-temp")
+                .long("via-temp")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with("interactive")
+                .help("Moves files to temporary names first, then renames them")
+                .long_help(
+                    "Moves every file to a unique temporary name in its destination directory \
+                     first, then renames all of them to the final names. This avoids any \
+                     problem caused by the order of moves, including collisions of names \
+                     differing only in case, at the cost of extra renames. Cannot be used with \
+                     --interactive.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("stage")
+                .long("stage")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with_all(&["via-temp", "network-safe"])
+                .help("Moves files into a staging directory first, then to their destinations")
+                .long_help(
+                    "Moves all files into a hidden staging directory (`.pmv-stage...`) in the \
+                     deepest existing directory containing the destinations first, then moves \
+                     them from there to their destinations in a quick second phase. This \
+                     shortens the time during which other processes see a half-reorganized \
+                     tree. The staging directory is removed afterwards if it is empty.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("temp-prefix")
+                .long("temp-prefix")
+                .value_name("PREFIX")
+                .value_parser(|s: &str| {
+                    if s.is_empty() || s.chars().any(pathnorm::is_separator) {
+                        Err(format!(
+                            "invalid prefix `{}` (must be a part of a filename)",
+                            s
+                        ))
+                    } else {
+                        Ok(String::from(s))
+                    }
+                })
+                .conflicts_with("stage")
+                .help("Names temporary files with PREFIX followed by the original names")
+                .long_help(
+                    "Names the temporary files, used to break cycles of moves (e.g. swapping \
+                     two names) and by --via-temp, with PREFIX followed by the original name \
+                     (e.g. `.~a.txt` for `--temp-prefix=.~`) instead of appending `.pmv` and a \
+                     number to it (e.g. `a.txt.pmv0000`), which may be mistaken for backup \
+                     files. A number is appended only if the name is taken.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("temp-dir")
+                .long("temp-dir")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with_all(&["stage", "stream"])
+                .help("Places temporary files in `.pmv-tmp` directories")
+                .long_help(
+                    "Places the temporary files, used to break cycles of moves and by \
+                     --via-temp, in a `.pmv-tmp` directory next to each original file, \
+                     which is on the same file system. The directories are created as needed \
+                     and removed afterwards if they are empty.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("network-safe")
+                .long("network-safe")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with("interactive")
+                .help("Moves files carefully for network file systems such as NFS or SMB")
+                .long_help(
+                    "Moves files carefully for network file systems such as NFS or SMB. This \
+                     implies --via-temp, retries moves failed with possibly transient errors \
+                     (stale NFS file handles, EAGAIN and EINTR), verifies each move, and \
+                     flushes the affected directories to disk after each move. Cannot be used \
+                     with --interactive.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("fsync")
+                .long("fsync")
+                .value_name("WHEN")
+                .value_parser(["each", "batch"])
+                .min_values(0)
+                .max_values(1)
+                .require_equals(true)
+                .default_missing_value("each")
+                .help("Flushes the source and destination directories to disk")
+                .long_help(
+                    "Flushes the source and destination directories to disk so that \
+                     completed moves survive a power loss. `each` (default) flushes them after \
+                     each move and `batch` flushes all of them once after all moves.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("manifest")
+                .long("manifest")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Writes SHA-256 hashes, sizes and paths of the moved files to FILE"),
+        )
+        .arg(
+            clap::Arg::new("diff")
+                .long("diff")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with("dry-run")
+                .help("Lists how the moves went compared with the plan after moving files")
+                .long_help(
+                    "After moving files, lists each planned move prefixed with a mark telling \
+                     how it went: a space if moved as planned, `-` not moved, `~` moved elsewhere, \
+                     or `!` moved but changed afterwards by another process (e.g. the moved \
+                     file disappeared). Fails if any move did not go as planned, so that \
+                     scripts can assert the reorganization happened exactly as planned.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("no-symlink-traversal")
+                .long("no-symlink-traversal")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Fails if a destination goes through a symbolic link pointing elsewhere")
+                .long_help(
+                    "Fails without moving any file if the destination of a file goes through \
+                     a symbolic link to a directory outside the current directory, so that \
+                     directories prepared by someone else cannot trick DEST into writing \
+                     files elsewhere.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("no-follow-dest-symlinks")
+                .long("no-follow-dest-symlinks")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Replaces a symbolic link at a destination instead of moving into it")
+                .long_help(
+                    "Treats a destination which is an existing symbolic link to a directory \
+                     as the path of the moved file, so the link itself is replaced. By \
+                     default, pmv follows such a link and moves the file into the directory \
+                     it points to, keeping the file name, as mv(1) does.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("save-plan")
+                .long("save-plan")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Writes the planned moves with their IDs to FILE as JSON")
+                .long_help(
+                    "Writes the planned moves to FILE as JSON, each with the ID shown as \
+                     `[ID]` in the output. Use it with --dry-run to review the plan, then \
+                     execute all or some of the moves with `pmv apply FILE`.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("fix-symlinks")
+                .long("fix-symlinks")
+                .value_name("DIR")
+                .value_parser(clap::value_parser!(PathBuf))
+                .min_values(0)
+                .max_values(1)
+                .require_equals(true)
+                .default_missing_value(".")
+                .help("Rewrites symbolic links under DIR which pointed at moved files")
+                .long_help(
+                    "After moving files, searches DIR (the current directory by default) for \
+                     symbolic links which pointed at the moved files or into the moved \
+                     directories, and rewrites them to point at the new locations. Relative \
+                     links are kept relative. With --dry-run, the links to rewrite are listed.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("restorecon")
+                .long("restorecon")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Restores the default SELinux contexts of the moved files")
+                .long_help(
+                    "After moving files, restores the default SELinux security contexts of \
+                     the moved files and directories by running restorecon(8). A renamed \
+                     file keeps its context, which may not be the one expected in the \
+                     destination directory. This is available only on Linux.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("preflight")
+                .long("preflight")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Tests creating a file in each destination directory before moving")
+                .long_help(
+                    "Creates and removes an empty probe file in each destination directory \
+                     (or its nearest existing ancestor) before moving files, so that \
+                     permission and quota problems are reported for all directories up front \
+                     instead of midway. Nothing is probed with --dry-run.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("watch")
+                .long("watch")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Aborts if another process creates a file at a destination meanwhile")
+                .long_help(
+                    "Watches the destination directories while moving files, and aborts the \
+                     remaining moves if another process creates a file at a destination which \
+                     was not moved to yet, instead of overwriting it. Destinations are checked \
+                     before moving, but a file can still appear after that. This is available \
+                     only on Linux.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("touch")
+                .long("touch")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with("keep-mtime")
+                .help("Sets the modification times of the moved files to now")
+                .long_help(
+                    "Sets the modification times of the moved files and directories to now \
+                     after moving them, for build systems and sync tools which look at \
+                     modification times. By default, moved files keep their modification \
+                     times (--keep-mtime).",
+                ),
+        )
+        .arg(
+            clap::Arg::new("keep-mtime")
+                .long("keep-mtime")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Keeps the modification times of the moved files (default)"),
+        )
+        .arg(
+            clap::Arg::new("to-trash")
+                .long("to-trash")
+                .visible_alias("delete")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with_all(&[
+                    "DEST",
+                    "dest",
+                    "flatten",
+                    "on-conflict",
+                    "via-temp",
+                    "tree",
+                    "summary",
+                    "group-by-dest",
+                    "fix-symlinks",
+                    "restorecon",
+                    "watch",
+                    "preflight",
+                    "no-follow-dest-symlinks",
+                    "touch",
+                    "manifest",
+                    "diff",
+                ])
+                .help("Sends matched files to the trash instead of moving them")
+                .long_help(
+                    "Sends the files matching SOURCE to the trash (or the recycle bin) of the \
+                     operating system instead of moving them, so they can be restored later. \
+                     DEST must not be given. Filtering options, --dry-run and --interactive work \
+                     as they do for moves. This is available only if pmv was built with the \
+                     `to-trash` feature.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("into-archive")
+                .long("into-archive")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with_all(&[
+                    "to-trash",
+                    "interactive",
+                    "porcelain",
+                    "on-conflict",
+                    "via-temp",
+                    "stage",
+                    "tree",
+                    "summary",
+                    "group-by-dest",
+                    "fix-symlinks",
+                    "restorecon",
+                    "watch",
+                    "preflight",
+                    "no-follow-dest-symlinks",
+                    "touch",
+                    "manifest",
+                    "diff",
+                    "save-plan",
+                ])
+                .help("Moves matched files into a zip or tar archive")
+                .long_help(
+                    "Adds the files matching SOURCE to the archive FILE as members named by \
+                     DEST, then removes them. FILE is a `.zip`, `.tar`, `.tar.gz` or `.tgz` \
+                     file, which is created if it does not exist. DEST is relative to the root \
+                     of the archive and must not go out of it; without DEST, members are named \
+                     by the file names. Adding a member which already exists is an error. The \
+                     files are removed only after the archive was written. --from-archive \
+                     extracts them back. This is available only if pmv was built with the \
+                     `archive` feature.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("from-archive")
+                .long("from-archive")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with_all(&[
+                    "into-archive",
+                    "to-trash",
+                    "interactive",
+                    "porcelain",
+                    "on-conflict",
+                    "via-temp",
+                    "stage",
+                    "tree",
+                    "summary",
+                    "group-by-dest",
+                    "fix-symlinks",
+                    "restorecon",
+                    "watch",
+                    "preflight",
+                    "no-follow-dest-symlinks",
+                    "touch",
+                    "manifest",
+                    "diff",
+                    "save-plan",
+                    "dedupe-sources",
+                    "dirs-only",
+                    "kind",
+                ])
+                .help("Extracts members of a zip or tar archive matching SOURCE")
+                .long_help(
+                    "Extracts the files in the archive FILE whose names match SOURCE to the \
+                     destinations given by DEST instead of moving files. FILE is a `.zip`, \
+                     `.tar`, `.tar.gz` or `.tgz` file and SOURCE is matched against the paths of \
+                     its members, such as `logs/a.log`. Tokens other than `#1`, `#2`, ... are \
+                     left as they are. Existing files are never overwritten. With --dry-run, pmv \
+                     lists which member would be extracted where. This is available only if pmv \
+                     was built with the `archive` feature.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("literal-paths")
+                .long("literal-paths")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Prints paths as they are, without escaping control characters")
+                .long_help(
+                    "Prints paths as they are. By default, control characters in paths are \
+                     shown escaped as `\\t`, `\\n`, `\\r`, `\\xHH` or `\\u{HHHH}` so that a \
+                     filename containing an escape sequence cannot corrupt the terminal.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("porcelain")
+                .long("porcelain")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with_all(&["interactive", "tree", "summary", "group-by-dest"])
+                .help("Prints results in a stable format for scripts")
+                .long_help(
+                    "Prints the result of each move as a line of `SRC<TAB>DEST<TAB>STATUS` \
+                     instead of the usual messages. STATUS is one of `planned` (with \
+                     --dry-run), `moved`, `trashed` (with --to-trash), `skipped` and `failed`; \
+                     DEST is empty with --to-trash. Backslashes, tabs and newlines \
+                     in the paths are escaped as `\\\\`, `\\t` and `\\n`. This format will not \
+                     change in future versions. Cannot be used with --interactive, --tree, \
+                     --summary or --group-by-dest.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("warnings-as-errors")
+                .long("warnings-as-errors")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Fails without moving any file if a warning occurred")
+                .long_help(
+                    "Treats warnings, such as directories skipped because they cannot be read, \
+                     tokens left as is because they are unknown, and destinations which differ \
+                     only in case, as errors. If any warning occurred while planning the moves, \
+                     pmv fails without moving any file. This is useful for scripts which need \
+                     to stop on anything unexpected.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(clap::builder::ArgAction::Count)
+                .help("Writes verbose message (`-vvv` implies --explain)"),
+        )
+        .arg(
+            clap::Arg::new("explain")
+                .long("explain")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with("porcelain")
+                .help("Shows what each wildcard captured for each matched file")
+                .long_help(
+                    "Shows each matched file followed by what each wildcard of SOURCE \
+                     captured (`#1 = \"...\"`, `#2 = \"...\"`, ...) and the destination built \
+                     from them, which helps to find out why a destination came out wrong. \
+                     `-vvv` implies this option.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("dirs-only")
+                .long("dirs-only")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with("files-only")
+                .help("Moves only directories matching SOURCE")
+                .long_help(
+                    "Moves only directories matching SOURCE, so that a pattern like `20*` \
+                     targets directories of years without catching files.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("files-only")
+                .long("files-only")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Moves only regular files matching SOURCE"),
+        )
+        .arg(
+            clap::Arg::new("ext")
+                .long("ext")
+                .value_name("EXTS")
+                .action(clap::builder::ArgAction::Append)
+                .value_delimiter(',')
+                .help("Moves only files having one of the given extensions (e.g.: jpg,png)")
+                .long_help(
+                    "Moves only files having one of the given extensions (e.g.: jpg,png), \
+                     compared case insensitively. Directories matching SOURCE are not moved \
+                     with this option, even if their names end with one of the extensions.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("kind")
+                .long("kind")
+                .value_name("KINDS")
+                .action(clap::builder::ArgAction::Append)
+                .value_delimiter(',')
+                .value_parser(KINDS)
+                .help("Moves only files of the given kinds (e.g.: image,video)")
+                .long_help(
+                    "Moves only files of the given kinds, detected from their content. Kinds \
+                     are `image`, `video`, `audio`, `document`, `archive`, `font`, \
+                     `application` and `other` (available only if pmv was built with the \
+                     `mime` feature.)",
+                ),
+        )
+        .arg(
+            clap::Arg::new("missing-tag")
+                .long("missing-tag")
+                .value_name("POLICY")
+                .value_parser(["skip", "unknown"])
+                .default_value("skip")
+                .help("What to do if a file lacks an audio tag or dimensions used in DEST")
+                .long_help(
+                    "What to do if a file lacks an audio tag or dimensions used in DEST. `skip` \
+                     skips the file and `unknown` uses `Unknown` instead of the value. pmv \
+                     warns in both cases.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("capture-groups")
+                .long("capture-groups")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Captures only parts of SOURCE enclosed by parentheses")
+                .long_help(
+                    "Captures only parts of SOURCE enclosed by parentheses instead of every \
+                     wildcard. Inside parentheses, `|` separates alternatives. For example, \
+                     SOURCE `(*)_final.(mp4|mov)` captures the name before `_final` as `#1` and \
+                     the extension as `#2`.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("ignore-case")
+                .short('f')
+                .long("ignore-case")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Matches SOURCE ignoring case")
+                .long_help(
+                    "Matches SOURCE ignoring case of letters, as is always done on Windows. \
+                     Without this option, names are matched case sensitively on other \
+                     platforms.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("case-sensitive")
+                .long("case-sensitive")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with("ignore-case")
+                .help("Matches SOURCE case sensitively, even on Windows")
+                .long_help(
+                    "Matches SOURCE case sensitively, even on Windows where names are matched \
+                     ignoring case by default. This is useful for case sensitive directories \
+                     such as ones of WSL or NTFS directories with case sensitivity enabled.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("regex")
+                .short('E')
+                .long("regex")
+                .action(clap::builder::ArgAction::SetTrue)
+                .conflicts_with("capture-groups")
+                .help("Interprets SOURCE as regular expressions")
+                .long_help(
+                    "Interprets each component of SOURCE, separated by `/`, as a regular \
+                     expression which must match the whole name instead of a wildcard \
+                     pattern. Each capture group is available in DEST as `#1`, `#2`, ... in \
+                     order (empty if the group did not match.) For example, SOURCE \
+                     `photos/IMG_(\\d+)\\.(jpe?g)` and DEST `photos/#1.#2` renames \
+                     `photos/IMG_0123.jpeg` to `photos/0123.jpeg`.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("normalize-unicode")
+                .long("normalize-unicode")
+                .action(clap::builder::ArgAction::SetTrue)
+                .help("Matches SOURCE regardless of Unicode normalization forms")
+                .long_help(
+                    "Compares SOURCE and file names after converting them into Unicode \
+                     Normalization Form C (NFC), so that a name written with a composed \
+                     character such as `é` matches a name with the decomposed one (`e` \
+                     followed by a combining accent), as macOS returns. The files keep their \
+                     names until moved, and the parts captured for DEST are in NFC. This is \
+                     available only if pmv was built with the `unicode-normalization` \
+                     feature.",
+                ),
+        )
+        .arg(
+            clap::Arg::new("SOURCE")
+                .required_unless_present_any(["source", "rules"])
+                .conflicts_with("source")
+                .index(1)
+                .help("Source pattern (use --help for details)")
+                .long_help(
+                    "A pattern string specifying files to move. If the pattern contains \
+                     wildcard(s), multiple files matching to the pattern will be targeted. \
+                     Supported wildcards are:\n\n    \
+                     ? ... Matches a single character\n    \
+                     * ... Matches zero or more characters\n    \
+                     [...] ... Matches a single character listed (e.g.: `[A-Za-z_]`)\n    \
+                     [!...] ... Matches a single character not listed (or `[^...]`)\n    \
+                     ** ... As a whole path component, matches zero or more directories \
+                     (e.g.: `src/**/*.rs`)\n\n\
+                     Each wildcard is captured for DEST. If a name can be matched in several \
+                     ways, each `*` matches as few characters as possible, from left to right. \
+                     Consecutive `*`s are the same as a single `*`. Use `[[]` to match `[`. \
+                     In brackets, POSIX character classes such as `[:digit:]`, `[:alpha:]` and \
+                     `[:space:]` list characters of a kind (e.g.: `[[:digit:]_]`). \
+                     The directories matched by `**` are captured as a path (empty if none); \
+                     symbolic links to directories are not descended into.\n\n\
+                     SOURCE itself must be valid Unicode, so a name which is not (e.g. `café` \
+                     written in Latin-1 on Linux) cannot be written literally in it. Such a \
+                     name is matched with wildcards instead, which match each invalid byte as \
+                     a character (e.g.: `caf?.txt`), and the captured parts are copied to DEST \
+                     byte for byte.\n\n\
+                     If SOURCE or DEST starts with `-`, put `--` before them (e.g.: \
+                     `pmv -- '-old*' 'new#1'`) or use --source and --dest. SOURCE naming a \
+                     subcommand such as `check` is taken as the subcommand if the other \
+                     arguments are valid for it, so put `--` before it as well (e.g.: \
+                     `pmv -- apply apply.old`).",
+                ),
+        )
+        .arg(
+            clap::Arg::new("DEST")
+                .conflicts_with("dest")
+                .index(2)
+                .help("Destination pattern [default: .] (use --help for details)")
+                .long_help(
+                    "A pattern string specifying where to move the targeted files. If the pattern \
+                     contains tokens like `#1` or `#2`, each of them will be replaced with a \
+                     substring extracted from the targeted file path. Those substrings matches \
+                     the wildcard patterns in SOURCE; `#1` matches the first wildcard, `#2` \
+                     matches the second, respectively. For example, if SOURCE is `*_test.py` and \
+                     DEST is `tests/test_#1.py`:\n\n    \
+                     Exisitng File | Destination\n    \
+                     ------------- | -----------------\n    \
+                     foo_test.py   | tests/test_foo.py\n    \
+                     bar_test.py   | tests/test_bar.py\n    \
+                     hoge_test.py  | tests/test_hoge.py\n\n\
+                     `#{1:?THEN:ELSE}` is replaced with THEN if `#1` is not empty, or with \
+                     ELSE otherwise, both of which may contain tokens (e.g.: \
+                     `#{1:?prefix_#{1:upper}:unnamed}`). `#{1:alpha}` and \
+                     `#{1:roman}` convert a number in `#1` to `a`, `b`, ..., `aa` or to a Roman \
+                     numeral (use `ALPHA` or `ROMAN` for uppercase.) `#{1:lower}` and \
+                     `#{1:upper}` convert `#1` to lowercase and uppercase. `#{-1}` is replaced \
+                     with the name of the directory containing the targeted file, `#{-2}` with \
+                     that of its parent, and so on. `#{depth}` is replaced with how deep the \
+                     file is below the leading part of SOURCE without wildcards. `#{env:VAR}` is \
+                     replaced with the value of the environment variable VAR, failing if it is \
+                     not set. `#{now:FORMAT}` is replaced with the time pmv started, formatted \
+                     like strftime(3) (e.g.: `#{now:%Y%m%d-%H%M%S}`; `%Y%m%d` by default.) \
+                     `#{host}` and `#{user}` are replaced with the name of the machine and the \
+                     name of the user running pmv.\n\n\
+                     Tokens like `#{sizebucket:1M,10M}` are replaced with a property of the \
+                     targeted file. `#{sizebucket:SIZE,...}` is replaced with the range of sizes \
+                     the file falls in, such as `0-1M`, `1M-10M` or `10M+`. \
+                     `#{agebucket:PERIOD,...}` is replaced with how long ago the file was \
+                     modified, such as `week`, `month`, `year` or `older` for `7d,30d,365d` (the \
+                     default). `#{mime}` and `#{kind}` are replaced with the type of the file \
+                     detected from its content, such as `image/png` and `image` (available only if \
+                     pmv was built with the `mime` feature.) `#{artist}`, `#{album}`, `#{title}` \
+                     and `#{track}` are replaced with tags of MP3, FLAC, Ogg Vorbis or Opus files, \
+                     with slashes and backslashes in them replaced with `_`, and `#{track:%02}` \
+                     pads the track number with zeros (available only if pmv was built with the \
+                     `audio` feature.) `#{width}` and `#{height}` are replaced with dimensions of \
+                     PNG, JPEG or MP4 files (available only if pmv was built with the `dimensions` \
+                     feature.)\n\n\
+                     If DEST is omitted, the targeted files are moved into the current \
+                     directory keeping their names (e.g.: `pmv 'subdir/*.pdf'`).",
+                ),
+        )
+        .subcommand_negates_reqs(true)
+        .disable_help_subcommand(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            clap::Command::new("config")
+                .about("Shows or changes settings in configuration files")
+                .long_about(
+                    "Shows settings read from configuration files, or gets or sets one of \
+                     them with `get` and `set`. Settings are read from \
+                     `/etc/pmv/config.toml` (`%ProgramData%\\pmv\\config.toml` on Windows), \
+                     `~/.config/pmv/config.toml` (`$XDG_CONFIG_HOME/pmv/config.toml` if set, \
+                     or `%APPDATA%\\pmv\\config.toml` on Windows) and `.pmv.toml` in the \
+                     current directory or the nearest parent having one, in this order; a \
+                     later file overrides the earlier ones and options on the command line \
+                     override all of them. `defaults.NAME` in the files is the default of \
+                     the option `--NAME` when moving files:\n\n    \
+                     [defaults]\n    \
+                     interactive = true\n    \
+                     collate = \"natural\"",
+                )
+                .arg(
+                    clap::Arg::new("show-origin")
+                        .long("show-origin")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Shows the file each setting came from"),
+                )
+                .subcommand(
+                    clap::Command::new("get")
+                        .about("Prints the value of a setting")
+                        .arg(
+                            clap::Arg::new("KEY")
+                                .required(true)
+                                .help("Name of the setting (e.g.: defaults.interactive)"),
+                        ),
+                )
+                .subcommand(
+                    clap::Command::new("set")
+                        .about("Writes a setting into the configuration file of the user")
+                        .long_about(
+                            "Writes a setting into the configuration file of the user, or \
+                             into the nearest `.pmv.toml` with --project (created in the \
+                             current directory if there is none.) VALUE is read as a TOML \
+                             value such as `true`, `3` or `[\"jpg\", \"png\"]`, or as a \
+                             string otherwise (e.g.: `pmv config set defaults.collate \
+                             natural`). Comments in the file are not kept.",
+                        )
+                        .arg(
+                            clap::Arg::new("project")
+                                .long("project")
+                                .action(clap::builder::ArgAction::SetTrue)
+                                .help("Writes into the project-local `.pmv.toml`"),
+                        )
+                        .arg(
+                            clap::Arg::new("KEY")
+                                .required(true)
+                                .help("Name of the setting (e.g.: defaults.interactive)"),
+                        )
+                        .arg(
+                            clap::Arg::new("VALUE")
+                                .required(true)
+                                .allow_hyphen_values(true)
+                                .help("Value of the setting"),
+                        ),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("check")
+                .about("Shows where example file names would be moved without searching files")
+                .long_about(
+                    "Shows where example file names would be moved, and what each wildcard \
+                     of SOURCE captured, without searching the file system. Tokens depending \
+                     on the file content or metadata are left as is. Fails if any example does \
+                     not match SOURCE. To move files matching a pattern `check`, use \
+                     `pmv -- check DEST` or --source.",
+                )
+                .arg(
+                    clap::Arg::new("example")
+                        .long("example")
+                        .value_name("NAME")
+                        .required(true)
+                        .action(clap::builder::ArgAction::Append)
+                        .help("An example path to match with SOURCE (can be repeated)"),
+                )
+                .arg(
+                    clap::Arg::new("capture-groups")
+                        .long("capture-groups")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Captures only parts of SOURCE enclosed by parentheses"),
+                )
+                .arg(
+                    clap::Arg::new("ignore-case")
+                        .short('f')
+                        .long("ignore-case")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Matches SOURCE ignoring case"),
+                )
+                .arg(
+                    clap::Arg::new("case-sensitive")
+                        .long("case-sensitive")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .conflicts_with("ignore-case")
+                        .help("Matches SOURCE case sensitively, even on Windows"),
+                )
+                .arg(
+                    clap::Arg::new("regex")
+                        .short('E')
+                        .long("regex")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .conflicts_with("capture-groups")
+                        .help("Interprets SOURCE as regular expressions"),
+                )
+                .arg(
+                    clap::Arg::new("normalize-unicode")
+                        .long("normalize-unicode")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Matches SOURCE regardless of Unicode normalization forms"),
+                )
+                .arg(
+                    clap::Arg::new("SOURCE")
+                        .required(true)
+                        .allow_hyphen_values(true)
+                        .index(1)
+                        .help("Source pattern"),
+                )
+                .arg(
+                    clap::Arg::new("DEST")
+                        .required(true)
+                        .allow_hyphen_values(true)
+                        .index(2)
+                        .help("Destination pattern"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("apply")
+                .about("Executes moves planned with --save-plan")
+                .long_about(
+                    "Executes the moves in a plan written with --save-plan. --only and --skip \
+                     take IDs of the moves separated by commas, with ranges such as `12-20`.\n\n\
+                     The moves applied last without failures are recorded in \
+                     `.pmv-last-applied` next to the plan file. Applying the same moves again, \
+                     which is likely an accidental re-run, is warned about, or fails with \
+                     --strict unless --force is given.\n\n\
+                     While moving files, the executed moves are marked as done in the plan \
+                     file from time to time. If pmv was killed or some moves failed, run it \
+                     again with --resume to execute the rest after checking that the files \
+                     of the moves marked as done are where they were moved to.",
+                )
+                .arg(
+                    clap::Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Does not move files but just shows what would be done"),
+                )
+                .arg(
+                    clap::Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(clap::builder::ArgAction::Count)
+                        .help("Writes verbose message"),
+                )
+                .arg(
+                    clap::Arg::new("only")
+                        .long("only")
+                        .value_name("IDS")
+                        .value_parser(parse_ids)
+                        .action(clap::builder::ArgAction::Append)
+                        .help("Executes only the moves with the IDs (e.g.: 3,7,12-20)"),
+                )
+                .arg(
+                    clap::Arg::new("skip")
+                        .long("skip")
+                        .value_name("IDS")
+                        .value_parser(parse_ids)
+                        .action(clap::builder::ArgAction::Append)
+                        .help("Skips the moves with the IDs (e.g.: 3,7,12-20)"),
+                )
+                .arg(
+                    clap::Arg::new("force")
+                        .long("force")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Applies the plan even if the same moves were applied last time"),
+                )
+                .arg(
+                    clap::Arg::new("strict")
+                        .long("strict")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Fails instead of warning if the same moves were applied last time"),
+                )
+                .arg(
+                    clap::Arg::new("resume")
+                        .long("resume")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Executes only the moves not marked as done in the plan"),
+                )
+                .arg(
+                    clap::Arg::new("PLAN")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("A plan file written with --save-plan"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("swap")
+                .about("Exchanges the names of two files or directories")
+                .long_about(
+                    "Exchanges the names of two files or directories, so that A gets the \
+                     content of B and vice versa. Where the platform and the file system \
+                     support it (renameat2(2) on Linux and renamex_np(2) on macOS), they are \
+                     exchanged atomically. Otherwise they are swapped via a temporary name \
+                     like a cycle of moves, and a file left at the temporary name on failure \
+                     is moved back if possible.",
+                )
+                .arg(
+                    clap::Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Does not swap them but just shows what would be done"),
+                )
+                .arg(
+                    clap::Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(clap::builder::ArgAction::Count)
+                        .help("Writes verbose message"),
+                )
+                .arg(
+                    clap::Arg::new("A")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("A file or directory to swap"),
+                )
+                .arg(
+                    clap::Arg::new("B")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("The other file or directory to swap"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("rotate")
+                .about("Renames files or directories in a cycle")
+                .long_about(
+                    "Renames each PATH to the next one and the last PATH to the first (e.g. \
+                     `pmv rotate A B C` moves A to B, B to C and C to A), via a temporary \
+                     name like any cycle of moves. A file left at the temporary name on \
+                     failure is moved back if possible. Rotating two paths is the same as \
+                     `pmv swap`.",
+                )
+                .arg(
+                    clap::Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Does not rename them but just shows what would be done"),
+                )
+                .arg(
+                    clap::Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(clap::builder::ArgAction::Count)
+                        .help("Writes verbose message"),
+                )
+                .arg(
+                    clap::Arg::new("PATH")
+                        .required(true)
+                        .min_values(2)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Files or directories to rename, in the order of the cycle"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("rotate-numbered")
+                .about("Shifts numbered copies of a file like logrotate")
+                .long_about(
+                    "Shifts numbered copies of BASE like logrotate: BASE.1 is renamed to \
+                     BASE.2, BASE.2 to BASE.3 and so on, then BASE is renamed to BASE.1. The \
+                     oldest copy, BASE.N where N is the number given with --keep, is moved \
+                     to a temporary name first and deleted after all the renames succeeded so \
+                     that at most N copies are kept. The renames are sorted and executed like \
+                     any other plan, and missing copies are just skipped. If any rename \
+                     failed, the others are undone.",
+                )
+                .arg(
+                    clap::Arg::new("keep")
+                        .long("keep")
+                        .value_name("N")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .help("Keeps N numbered copies at most, deleting older ones"),
+                )
+                .arg(
+                    clap::Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Does not rename or delete files but just shows what would be done"),
+                )
+                .arg(
+                    clap::Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(clap::builder::ArgAction::Count)
+                        .help("Writes verbose message"),
+                )
+                .arg(
+                    clap::Arg::new("BASE")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("The file or directory to rotate"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("schema")
+                .about("Prints the JSON Schema of a file format of pmv")
+                .long_about(
+                    "Prints the JSON Schema of a file format of pmv, so that other tools can \
+                     validate the files they read or write. `plan` is the format of the plans \
+                     written with --save-plan and read by `pmv apply`. The schema fixes the \
+                     `version` of the format, which pmv checks when reading a plan.",
+                )
+                .arg(
+                    clap::Arg::new("FORMAT")
+                        .required(true)
+                        .value_parser(["plan"])
+                        .help("The file format"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("repl")
+                .about("Searches files once and previews DEST patterns entered interactively")
+                .long_about(
+                    "Searches files matching SOURCE once, then repeatedly reads a DEST pattern \
+                     and shows where the files would be moved with it, without searching the \
+                     file system again. Enter `:apply` to move the files with the last DEST \
+                     pattern, or `:quit` (or end of input) to exit without moving them.",
+                )
+                .arg(
+                    clap::Arg::new("capture-groups")
+                        .long("capture-groups")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Captures only parts of SOURCE enclosed by parentheses"),
+                )
+                .arg(
+                    clap::Arg::new("ignore-case")
+                        .short('f')
+                        .long("ignore-case")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Matches SOURCE ignoring case"),
+                )
+                .arg(
+                    clap::Arg::new("case-sensitive")
+                        .long("case-sensitive")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .conflicts_with("ignore-case")
+                        .help("Matches SOURCE case sensitively, even on Windows"),
+                )
+                .arg(
+                    clap::Arg::new("regex")
+                        .short('E')
+                        .long("regex")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .conflicts_with("capture-groups")
+                        .help("Interprets SOURCE as regular expressions"),
+                )
+                .arg(
+                    clap::Arg::new("normalize-unicode")
+                        .long("normalize-unicode")
+                        .action(clap::builder::ArgAction::SetTrue)
+                        .help("Matches SOURCE regardless of Unicode normalization forms"),
+                )
+                .arg(
+                    clap::Arg::new("SOURCE")
+                        .required(true)
+                        .allow_hyphen_values(true)
+                        .index(1)
+                        .help("Source pattern"),
+                )
+                .arg(
+                    clap::Arg::new("DEST")
+                        .allow_hyphen_values(true)
+                        .index(2)
+                        .help("Initial destination pattern"),
+                ),
+        )
+}
+
+/// Parses the command line, using `defaults.*` settings for options not given on it.
+/// Returns `args` with `--` inserted before the first argument if it names a subcommand but the
+/// arguments are valid only as SOURCE and DEST of an existing file (e.g.: `pmv check check.bak`).
+fn escape_subcommand(args: &[OsString]) -> Vec<OsString> {
+    let name = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some(name) if command().get_subcommands().any(|c| c.get_name() == name) => name,
+        _ => return args.to_vec(),
+    };
+    if Path::new(name).symlink_metadata().is_err() {
+        return args.to_vec();
+    }
+    match command().try_get_matches_from(args) {
+        Err(err)
+            if !matches!(
+                err.kind(),
+                clap::ErrorKind::DisplayHelp | clap::ErrorKind::DisplayVersion
+            ) =>
+        {
+            let mut escaped = args.to_vec();
+            escaped.insert(1, OsString::from("--"));
+            match command().try_get_matches_from(&escaped) {
+                Ok(_) => escaped,
+                Err(_) => args.to_vec(),
+            }
+        }
+        _ => args.to_vec(),
+    }
+}
+
+fn parse_args(args: &[OsString], settings: &[Setting]) -> Result<Config, String> {
+    let args = &escape_subcommand(args);
+    let matches = command().get_matches_from(args);
+    let matches = if matches.subcommand().is_none() {
+        let is_given = |id: &str| matches.value_source(id) == Some(clap::ValueSource::CommandLine);
+        let defaults = default_args(settings, &command(), &is_given)?;
+        if defaults.is_empty() {
+            matches
+        } else {
+            let args = args
+                .iter()
+                .take(1)
+                .chain(&defaults)
+                .chain(args.iter().skip(1));
+            command().get_matches_from(args)
+        }
+    } else {
+        matches
+    };
+
+    let dialect = match matches.get_one::<String>("dialect").unwrap().as_str() {
+        "mmv" => Dialect::Mmv,
+        _ => Dialect::Pmv,
+    };
+    // Subcommands other than `apply`, `config`, `swap`, `rotate`, `rotate-numbered` and `schema`
+    // share SOURCE, DEST and --capture-groups
+    let sub_matches = matches
+        .subcommand()
+        .filter(|(name, _)| {
+            ![
+                "apply",
+                "config",
+                "swap",
+                "rotate",
+                "rotate-numbered",
+                "schema",
+            ]
+            .contains(name)
+        })
+        .map(|(_, m)| m);
+    let apply_matches = matches.subcommand_matches("apply");
+    let cycle_matches = matches
+        .subcommand_matches("swap")
+        .or_else(|| matches.subcommand_matches("rotate"))
+        .or_else(|| matches.subcommand_matches("rotate-numbered"));
+    let cycle: Vec<PathBuf> = match matches.subcommand() {
+        Some(("swap", m)) => ["A", "B"]
+            .iter()
+            .map(|id| m.get_one::<PathBuf>(id).unwrap().clone())
+            .collect(),
+        Some(("rotate", m)) => m.get_many::<PathBuf>("PATH").unwrap().cloned().collect(),
+        _ => Vec::new(),
+    };
+    let rotate_numbered = matches.subcommand_matches("rotate-numbered").map(|m| {
+        (
+            m.get_one::<PathBuf>("BASE").unwrap().clone(),
+            *m.get_one::<u64>("keep").unwrap() as usize,
+        )
+    });
+    // Subcommands moving files directly take --dry-run and --verbose
+    let run_matches = apply_matches.or(cycle_matches);
+    let plan = apply_matches.and_then(|m| m.get_one::<PathBuf>("PLAN").cloned());
+    let id_ranges = |id: &str| -> Vec<RangeInclusive<usize>> {
+        apply_matches
+            .and_then(|m| m.get_many::<Vec<RangeInclusive<usize>>>(id))
+            .map_or_else(Vec::new, |values| values.flatten().cloned().collect())
+    };
+    let only = id_ranges("only");
+    let skip = id_ranges("skip");
+    let force = apply_matches.is_some_and(|m| *m.get_one::<bool>("force").unwrap());
+    let strict = apply_matches.is_some_and(|m| *m.get_one::<bool>("strict").unwrap());
+    let resume = apply_matches.is_some_and(|m| *m.get_one::<bool>("resume").unwrap());
+    let repl = matches.subcommand_name() == Some("repl");
+    let settings_command = matches
+        .subcommand_matches("config")
+        .map(|m| match m.subcommand() {
+            Some(("get", m)) => SettingsCommand::Get {
+                key: m.get_one::<String>("KEY").unwrap().clone(),
+            },
+            Some(("set", m)) => SettingsCommand::Set {
+                key: m.get_one::<String>("KEY").unwrap().clone(),
+                value: m.get_one::<String>("VALUE").unwrap().clone(),
+                project: *m.get_one::<bool>("project").unwrap(),
+            },
+            _ => SettingsCommand::Show {
+                show_origin: *m.get_one::<bool>("show-origin").unwrap(),
+            },
+        });
+    let print_renamed = *matches.get_one::<bool>("print-renamed").unwrap();
+    let schema = matches
+        .subcommand_matches("schema")
+        .map(|m| m.get_one::<String>("FORMAT").unwrap().clone());
+    let examples: Vec<String> = matches
+        .subcommand_matches("check")
+        .and_then(|m| m.get_many::<String>("example"))
+        .map_or_else(Vec::new, |values| values.cloned().collect());
+    let src_ptn = match sub_matches {
+        Some(m) => m.get_one::<String>("SOURCE"),
+        None => matches
+            .get_one::<String>("source")
+            .or_else(|| matches.get_one::<String>("SOURCE")),
+    }
+    .map_or("", String::as_str); // required by clap unless applying a plan
+    let dest_ptn = match sub_matches {
+        Some(m) => m.get_one::<String>("DEST"),
+        None => matches
+            .get_one::<String>("dest")
+            .or_else(|| matches.get_one::<String>("DEST")),
+    };
+    // Without DEST, files are moved into the current directory keeping their names
+    let flatten = *matches.get_one::<bool>("flatten").unwrap()
+        || (dest_ptn.is_none() && sub_matches.is_none() && !matches.contains_id("rules"));
+    let dest_ptn = dest_ptn.map_or("", String::as_str);
+    let (src_ptn, dest_ptn) = translate(src_ptn, dest_ptn, dialect)?;
+    let rules = match matches.get_one::<PathBuf>("rules") {
+        Some(path) => read_rules(path)?
+            .into_iter()
+            .map(|(src_ptn, dest_ptn)| translate(&src_ptn, &dest_ptn, dialect))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    let root = match matches.get_one::<PathBuf>("root") {
+        Some(dir) => {
+            //TODO: Fix for when curdir is not available
+            let dir = std::env::current_dir().unwrap().join(dir);
+            if !dir.is_dir() {
+                return Err(format!(
+                    "--root: \"{}\" is not a directory",
+                    dir.to_string_lossy()
+                ));
+            }
+            Some(dir)
+        }
+        None => None,
+    };
+    let entry_filter = if *matches.get_one::<bool>("dirs-only").unwrap() {
+        EntryFilter::Dirs
+    } else if *matches.get_one::<bool>("files-only").unwrap() {
+        EntryFilter::Files
+    } else {
+        EntryFilter::Any
+    };
+    let extensions = matches
+        .get_many::<String>("ext")
+        .map(|values| {
+            values
+                .map(|v| v.trim_start_matches('.').to_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    let kinds: Vec<String> = matches
+        .get_many::<String>("kind")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    if cfg!(not(feature = "mime")) && !kinds.is_empty() {
+        return Err(String::from(
+            "--kind is not available; pmv was built without the `mime` feature",
+        ));
+    }
+    let missing_tag = match matches.get_one::<String>("missing-tag").unwrap().as_str() {
+        "unknown" => MissingTag::Unknown,
+        _ => MissingTag::Skip,
+    };
+    let capture_groups = *matches.get_one::<bool>("capture-groups").unwrap()
+        || sub_matches.is_some_and(|m| *m.get_one::<bool>("capture-groups").unwrap());
+    let flag = |id: &str| {
+        *matches.get_one::<bool>(id).unwrap()
+            || sub_matches.is_some_and(|m| *m.get_one::<bool>(id).unwrap())
+    };
+    let regex = flag("regex");
+    let normalize_unicode = flag("normalize-unicode");
+    let case_sensitivity = if flag("ignore-case") {
+        CaseSensitivity::Insensitive
+    } else if flag("case-sensitive") {
+        CaseSensitivity::Sensitive
+    } else {
+        CaseSensitivity::Platform
+    };
+    let dry_run = *matches.get_one::<bool>("dry-run").unwrap()
+        || run_matches.is_some_and(|m| *m.get_one::<bool>("dry-run").unwrap());
+    let check = *matches.get_one::<bool>("check").unwrap();
+    let tree = *matches.get_one::<bool>("tree").unwrap();
+    let summary = *matches.get_one::<bool>("summary").unwrap();
+    let group_by_dest = *matches.get_one::<bool>("group-by-dest").unwrap();
+    let explain_plan = match matches
+        .get_one::<String>("explain-plan")
+        .map(|s| s.as_str())
+    {
+        Some("dot") => Some(PlanFormat::Dot),
+        Some(_) => Some(PlanFormat::Tree),
+        None => None,
+    };
+    let count = match matches.get_one::<String>("count").map(|s| s.as_str()) {
+        Some("dest") => Some(Count::PerDest),
+        Some(_) => Some(Count::Total),
+        None => None,
+    };
+    let verbosity = *matches.get_one::<u8>("verbose").unwrap()
+        + run_matches.map_or(0, |m| *m.get_one::<u8>("verbose").unwrap());
+    let verbose = 0 < verbosity;
+    let interactive = *matches.get_one::<bool>("interactive").unwrap();
+    let match_limit = *matches.get_one::<usize>("match-limit").unwrap();
+    let max_files = matches.get_one::<usize>("max-files").copied();
+    let yes = *matches.get_one::<bool>("yes").unwrap();
+    let warnings_as_errors = *matches.get_one::<bool>("warnings-as-errors").unwrap();
+    let wait_for_unlock = matches.get_one::<u64>("wait-for-unlock").copied();
+    let wait_for_space = matches.get_one::<u64>("wait-for-space").copied();
+    let chunk_size = matches.get_one::<u64>("chunk-size").map(|&n| n as usize);
+    let pause = matches.get_one::<u64>("pause").copied();
+    let timeout = matches.get_one::<Duration>("timeout").copied();
+    let stream = matches.get_one::<u64>("stream").map(|&n| n as usize);
+    let manifest = matches.get_one::<PathBuf>("manifest").cloned();
+    let diff = *matches.get_one::<bool>("diff").unwrap();
+    let save_plan = matches.get_one::<PathBuf>("save-plan").cloned();
+    let on_conflict = match matches.get_one::<String>("on-conflict").unwrap().as_str() {
+        "skip" => OnConflict::Skip,
+        "suffix" => OnConflict::Suffix,
+        _ => OnConflict::Fail,
+    };
+    let fix_symlinks = matches.get_one::<PathBuf>("fix-symlinks").cloned();
+    let restorecon = *matches.get_one::<bool>("restorecon").unwrap();
+    let watch = *matches.get_one::<bool>("watch").unwrap();
+    let preflight = *matches.get_one::<bool>("preflight").unwrap();
+    let no_symlink_traversal = *matches.get_one::<bool>("no-symlink-traversal").unwrap();
+    let no_follow_dest_symlinks = *matches.get_one::<bool>("no-follow-dest-symlinks").unwrap();
+    let touch = *matches.get_one::<bool>("touch").unwrap();
+    let porcelain = *matches.get_one::<bool>("porcelain").unwrap();
+    let literal_paths = *matches.get_one::<bool>("literal-paths").unwrap();
+    let explain = *matches.get_one::<bool>("explain").unwrap() || (3 <= verbosity && !porcelain);
+    let to_trash = *matches.get_one::<bool>("to-trash").unwrap();
+    if cfg!(not(feature = "to-trash")) && to_trash {
+        return Err(String::from(
+            "--to-trash is not available; pmv was built without the `to-trash` feature",
+        ));
+    }
+    let into_archive = matches.get_one::<PathBuf>("into-archive").cloned();
+    if cfg!(not(feature = "archive")) && into_archive.is_some() {
+        return Err(String::from(
+            "--into-archive is not available; pmv was built without the `archive` feature",
+        ));
+    }
+    let from_archive = matches.get_one::<PathBuf>("from-archive").cloned();
+    if cfg!(not(feature = "archive")) && from_archive.is_some() {
+        return Err(String::from(
+            "--from-archive is not available; pmv was built without the `archive` feature",
+        ));
+    }
+    if restorecon && cfg!(not(target_os = "linux")) {
+        return Err(String::from(
+            "--restorecon is not available; SELinux is supported only on Linux",
+        ));
+    }
+    if watch && cfg!(not(target_os = "linux")) {
+        return Err(String::from(
+            "--watch is not available; watching directories is supported only on Linux",
+        ));
+    }
+    let dedupe_sources = *matches.get_one::<bool>("dedupe-sources").unwrap();
+    let network_safe = *matches.get_one::<bool>("network-safe").unwrap();
+    let via_temp = *matches.get_one::<bool>("via-temp").unwrap() || network_safe;
+    let stage = *matches.get_one::<bool>("stage").unwrap();
+    let temp_naming = TempNaming {
+        prefix: matches.get_one::<String>("temp-prefix").cloned(),
+        in_dir: *matches.get_one::<bool>("temp-dir").unwrap(),
+    };
+    let fsync = match matches.get_one::<String>("fsync").map(|s| s.as_str()) {
+        Some("batch") => Fsync::Batch,
+        Some(_) => Fsync::EachMove,
+        None if network_safe => Fsync::EachMove,
+        None => Fsync::Never,
+    };
+    let collation = match matches.get_one::<String>("collate").unwrap().as_str() {
+        "natural" => Collation::Natural,
+        #[cfg(feature = "locale")]
+        "locale" => Collation::Locale,
+        #[cfg(not(feature = "locale"))]
+        "locale" => {
+            return Err(String::from(
+                "--collate=locale is not available; pmv was built without the `locale` feature",
+            ))
+        }
+        _ => Collation::Bytewise,
+    };
+    let follow_symlinks = if *matches.get_one::<bool>("no-dereference").unwrap() {
+        FollowSymlinks::Never
+    } else if *matches.get_one::<bool>("dereference-command-line").unwrap() {
+        FollowSymlinks::CommandLine
+    } else {
+        FollowSymlinks::Always
+    };
+
+    Ok(Config {
+        src_ptn,
+        dest_ptn,
+        root,
+        examples,
+        settings_command,
+        repl,
+        print_renamed,
+        schema,
+        extensions,
+        kinds,
+        missing_tag,
+        capture_groups,
+        case_sensitivity,
+        regex,
+        normalize_unicode,
+        dry_run,
+        check,
+        tree,
+        summary,
+        group_by_dest,
+        explain_plan,
+        count,
+        verbose,
+        explain,
+        entry_filter,
+        interactive,
+        match_limit,
+        max_files,
+        yes,
+        warnings_as_errors,
+        wait_for_unlock,
+        wait_for_space,
+        chunk_size,
+        pause,
+        timeout,
+        stream,
+        manifest,
+        diff,
+        save_plan,
+        plan,
+        cycle,
+        rotate_numbered,
+        only,
+        force,
+        strict,
+        resume,
+        skip,
+        flatten,
+        on_conflict,
+        fix_symlinks,
+        restorecon,
+        watch,
+        preflight,
+        no_symlink_traversal,
+        no_follow_dest_symlinks,
+        touch,
+        porcelain,
+        literal_paths,
+        to_trash,
+        into_archive,
+        rules,
+        from_archive,
+        dedupe_sources,
+        via_temp,
+        stage,
+        temp_naming,
+        network_safe,
+        fsync,
+        follow_symlinks,
+        collation,
+    })
+}
+
+/// Reads pairs of SOURCE and DEST separated by a tab, one per line, from a file of `--rules`.
+///
+/// Empty lines and lines starting with `#` are skipped.
+fn read_rules(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("failed to read \"{}\": {}", path.to_string_lossy(), err))?;
+    let mut rules = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').filter(|field| !field.is_empty()).collect();
+        match fields.as_slice() {
+            [src_ptn, dest_ptn] => rules.push((String::from(*src_ptn), String::from(*dest_ptn))),
+            _ => {
+                return Err(format!(
+                    "{}:{}: expected SOURCE and DEST separated by a tab",
+                    path.to_string_lossy(),
+                    i + 1
+                ))
+            }
+        }
+    }
+    if rules.is_empty() {
+        return Err(format!("no rule is in \"{}\"", path.to_string_lossy()));
+    }
+    Ok(rules)
+}
+
+/// Tests whether the path has one of the extensions or not.
+///
+/// Extensions are compared case insensitively. An empty list accepts any path.
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    if extensions.is_empty() {
+        return true;
+    }
+    let ext = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext,
+        None => return false,
+    };
+    extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))
+}
+
+/// Options to control how matched files are collected.
+#[derive(Debug, Default)]
+struct ScanOptions<'a> {
+    /// Collects only files having one of these extensions, if any.
+    extensions: Vec<String>,
+    /// Collects only files of these kinds, if any.
+    kinds: Vec<String>,
+    /// Asks whether to continue each time this many files matched.
+    match_limit: Option<usize>,
+    /// Prints what each wildcard captured for each matched file.
+    explain: bool,
+    /// Which types of entries to collect.
+    entry_filter: EntryFilter,
+    /// Searches from this directory instead of the current directory.
+    root: Option<PathBuf>,
+    /// Stops searching once the time limit has passed.
+    deadline: Option<&'a Deadline>,
+}
+
+/// What `pmv config` does.
+#[derive(Clone, Debug, PartialEq)]
+enum SettingsCommand {
+    /// Prints the settings, with the files they came from if `show_origin` is true.
+    Show { show_origin: bool },
+    /// Prints the value of a setting.
+    Get { key: String },
+    /// Writes a setting into the file of the user, or the project-local file if `project`.
+    Set {
+        key: String,
+        value: String,
+        project: bool,
+    },
+}
+
+/// What `--count` prints.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Count {
+    /// The total number of files.
+    Total,
+    /// The number of files per destination directory.
+    PerDest,
+}
+
+/// How `--explain-plan` renders the chains of moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlanFormat {
+    /// Indented trees.
+    Tree,
+    /// A graph in the DOT language.
+    Dot,
+}
+
+/// Which types of entries to move.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum EntryFilter {
+    /// Any types of entries.
+    #[default]
+    Any,
+    /// Only directories.
+    Dirs,
+    /// Only regular files.
+    Files,
+}
+
+fn matches_to_actions(
+    src_ptn: &str,
+    dest_ptn: &str,
+    options: &MatchOptions,
+    token_options: &TokenOptions,
+    scan_options: &ScanOptions,
+) -> Result<(Vec<Action>, Vec<String>), String> {
+    let mut actions = Vec::new();
+    let warnings = scan_actions(
+        src_ptn,
+        dest_ptn,
+        options,
+        token_options,
+        scan_options,
+        &mut |action| {
+            actions.push(action);
+            Ok(true)
+        },
+    )?;
+    Ok((actions, warnings))
+}
+
+/// Which rule of `--rules` planned each move, and how many files each rule matched.
+#[derive(Debug, Default)]
+struct RuleStats {
+    /// The number of files each rule matched, including ones moved by an earlier rule.
+    matched: Vec<usize>,
+    /// The index of the rule moving each source.
+    rule_of: HashMap<PathBuf, usize>,
+}
+
+/// Collects the actions of every rule like `matches_to_actions`.
+///
+/// Every rule is matched before moving any file. A file matched by multiple rules is moved by the
+/// first of them. Each action is labeled with the number of its rule.
+fn rules_to_actions(
+    rules: &[(String, String)],
+    options: &MatchOptions,
+    token_options: &TokenOptions,
+    scan_options: &ScanOptions,
+) -> Result<(Vec<Action>, Vec<String>, RuleStats), String> {
+    let mut actions = Vec::new();
+    let mut warnings = Vec::new();
+    let mut stats = RuleStats::default();
+    for (i, (src_ptn, dest_ptn)) in rules.iter().enumerate() {
+        let (rule_actions, rule_warnings) =
+            matches_to_actions(src_ptn, dest_ptn, options, token_options, scan_options)?;
+        warnings.extend(rule_warnings);
+        stats.matched.push(rule_actions.len());
+        for action in rule_actions {
+            if !stats.rule_of.contains_key(action.src()) {
+                stats.rule_of.insert(action.src().to_owned(), i);
+                actions.push(action.with_label(format!("rule {}", i + 1)));
+            }
+        }
+    }
+    Ok((actions, warnings, stats))
+}
+
+/// Searches files matching SOURCE and passes the actions to move them to `on_action` as soon as
+/// each of them was found. Searching stops when `on_action` returns false or fails.
+///
+/// Returns warnings occurred while searching.
+fn scan_actions(
+    src_ptn: &str,
+    dest_ptn: &str,
+    options: &MatchOptions,
+    token_options: &TokenOptions,
+    scan_options: &ScanOptions,
+    on_action: &mut dyn FnMut(Action) -> Result<bool, String>,
+) -> Result<Vec<String>, String> {
+    let curdir = work_dir(&scan_options.root);
+    let mut num_matches = 0;
+    let mut aborted = false;
+    let mut error = None;
+    let warnings = RefCell::new(Vec::new());
+    let root = Pattern::new(src_ptn, options)?.root(&curdir);
+    let mut timed_out = false;
+    let mut on_match = |m: Match| {
+        if scan_options.deadline.is_some_and(|d| d.expired()) {
+            timed_out = true;
+            return false;
+        }
+        let src = m.path();
+        match scan_options.entry_filter {
+            EntryFilter::Dirs if !src.is_dir() => return true,
+            EntryFilter::Files if !src.is_file() => return true,
+            _ => (),
+        }
+        match has_kind(&src, &scan_options.kinds) {
+            Ok(true) => (),
+            Ok(false) => return true,
+            Err(err) => {
+                error = Some(err);
+                return false;
+            }
+        }
+        let action = match match_to_action(
+            &m,
+            dest_ptn,
+            &curdir,
+            &root,
+            token_options,
+            &mut warnings.borrow_mut(),
+        ) {
+            Ok(Some(action)) => action,
+            Ok(None) => return true,
+            Err(err) => {
+                error = Some(err);
+                return false;
+            }
+        };
+        if scan_options.explain {
+            print_explanation(&src.to_string_lossy(), &m.matched_parts, action.dest());
+        }
+        match on_action(action) {
+            Ok(true) => (),
+            Ok(false) => return false,
+            Err(err) => {
+                error = Some(err);
+                return false;
+            }
+        }
+
+        // Ask whether to continue each time the number of matches reached the limit
+        num_matches += 1;
+        if let Some(limit) = scan_options.match_limit {
+            if 0 < limit && num_matches % limit == 0 {
+                print!(
+                    "{} files matched so far. Continue scanning? [y/N]: ",
+                    num_matches
+                );
+                let answer = read_answer().unwrap_or_default();
+                if !answer.eq_ignore_ascii_case("y") {
+                    aborted = true;
+                    return false;
+                }
+            }
+        }
+        true
+    };
+    let result = walk_with_warnings(
+        &curdir,
+        src_ptn,
+        options,
+        &scan_options.extensions,
+        &mut on_match,
+        &mut |warning| warnings.borrow_mut().push(warning),
+    );
+    if let Err(err) = result {
+        return Err(format!("failed to scan directory tree: {}", err));
+    }
+    if let Some(err) = error {
+        return Err(err);
+    }
+    if aborted {
+        return Err(String::from("aborted by user"));
+    }
+    if let (true, Some(deadline)) = (timed_out, scan_options.deadline) {
+        return Err(deadline.error("searching files"));
+    }
+    Ok(warnings.into_inner())
+}
+
+/// Returns the directory from which relative SOURCE and DEST are resolved.
+fn work_dir(root: &Option<PathBuf>) -> PathBuf {
+    match root {
+        Some(dir) => dir.clone(),
+        //TODO: Fix for when curdir is not available
+        None => std::env::current_dir().unwrap(),
+    }
+}
+
+/// Builds the action for a matched file by substituting tokens in DEST.
+///
+/// `root` is where searching started, from which `#{depth}` is counted. Warnings are appended
+/// to `warnings`. Returns `None` if the file should be skipped (e.g.: a missing tag.)
+fn match_to_action(
+    m: &Match,
+    dest_ptn: &str,
+    curdir: &Path,
+    root: &Path,
+    token_options: &TokenOptions,
+    warnings: &mut Vec<String>,
+) -> Result<Option<Action>, String> {
+    let src = m.path();
+    let depth = src.strip_prefix(root).map_or(0, |p| p.components().count());
+    let context = TokenContext::new(&src, token_options).with_depth(depth);
+    let unresolved = RefCell::new(Vec::new());
+    let dest = substitute_tokens(dest_ptn, m.matched_os_parts(), &|name, arg| {
+        let resolved = context.resolve(name, arg);
+        if let Ok(None) = resolved {
+            unresolved.borrow_mut().push(String::from(name));
+        }
+        resolved
+    })?;
+    warnings.extend(context.take_warnings());
+    for name in unresolved.into_inner() {
+        warnings.push(format!(
+            "unknown token \"#{{{}}}\" was left as is for \"{}\"",
+            name,
+            src.to_string_lossy()
+        ));
+    }
+    if context.skipped() {
+        return Ok(None);
+    }
+    Ok(Some(Action::new(&src, curdir.join(dest))))
+}
+
+/// Prints a matched path, the parts captured from it and the destination built from them.
+fn print_explanation(src: &str, matched_parts: &[String], dest: &Path) {
+    println!("{}", quote(src));
+    for (i, part) in matched_parts.iter().enumerate() {
+        println!("  #{} = \"{}\"", i + 1, quote(part));
+    }
+    println!("  --> {}", quote_path(dest));
+}
+
+/// Prints where each example path would be moved and what each wildcard captured.
+///
+/// Fails if any example does not match SOURCE.
+fn check_examples(config: &Config) -> Result<(), String> {
+    let options = MatchOptions {
+        capture_groups: config.capture_groups,
+        case_sensitivity: config.case_sensitivity,
+        regex: config.regex,
+        normalize_unicode: config.normalize_unicode,
+        ..Default::default()
+    };
+    let pattern = Pattern::new(&config.src_ptn, &options)?;
+    let mut num_unmatched = 0;
+    for example in &config.examples {
+        let matched_parts = match pattern.matches(example) {
+            Some(matched_parts) => matched_parts,
+            None => {
+                println!("{} does not match \"{}\"", example, config.src_ptn);
+                num_unmatched += 1;
+                continue;
+            }
+        };
+        let os_parts: Vec<OsString> = matched_parts.iter().map(OsString::from).collect();
+        let dest = substitute_tokens(&config.dest_ptn, &os_parts, &|_, _| Ok(None))?;
+        print_explanation(example, &matched_parts, Path::new(&dest));
+    }
+    if 0 < num_unmatched {
+        return Err(format!("{} example(s) did not match SOURCE", num_unmatched));
+    }
+    Ok(())
+}
+
+/// Reads paths line by line and writes where each of them would be moved.
+///
+/// SOURCE and DEST are applied as a string transformation without accessing the file system.
+/// Lines not matching SOURCE are written as they are.
+fn print_renamed(
+    config: &Config,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<(), String> {
+    let options = MatchOptions {
+        capture_groups: config.capture_groups,
+        case_sensitivity: config.case_sensitivity,
+        regex: config.regex,
+        normalize_unicode: config.normalize_unicode,
+        ..Default::default()
+    };
+    let pattern = Pattern::new(&config.src_ptn, &options)?;
+    for line in input.lines() {
+        let line = line.map_err(|err| format!("failed to read input: {}", err))?;
+        let renamed = match pattern.matches(&line) {
+            Some(matched_parts) => {
+                let os_parts: Vec<OsString> = matched_parts.iter().map(OsString::from).collect();
+                let dest = substitute_tokens(&config.dest_ptn, &os_parts, &|_, _| Ok(None))?;
+                let mut dest = PathBuf::from(dest);
+                if config.flatten {
+                    dest.push(Path::new(&line).file_name().unwrap_or_default());
+                }
+                dest.to_string_lossy().into_owned()
+            }
+            None => line,
+        };
+        writeln!(output, "{}", renamed)
+            .map_err(|err| format!("failed to write output: {}", err))?;
+    }
+    Ok(())
+}
+
+/// Searches files once and previews DEST patterns the user entered until they are applied.
+fn run_repl(config: &Config) -> Result<(), String> {
+    let curdir = work_dir(&config.root);
+    let options = MatchOptions {
+        capture_groups: config.capture_groups,
+        case_sensitivity: config.case_sensitivity,
+        regex: config.regex,
+        normalize_unicode: config.normalize_unicode,
+        ..Default::default()
+    };
+    let root = Pattern::new(&config.src_ptn, &options)?.root(&curdir);
+    let mut matches = Vec::new();
+    let mut warnings = Vec::new();
+    walk_with_warnings(
+        &curdir,
+        &config.src_ptn,
+        &options,
+        &config.extensions,
+        &mut |m| {
+            matches.push(m);
+            true
+        },
+        &mut |warning| warnings.push(warning),
+    )
+    .map_err(|err| format!("failed to scan directory tree: {}", err))?;
+    for warning in warnings {
+        print_warning(warning);
+    }
+    println!(
+        "{} file(s) matched. Enter a DEST pattern to preview, `:apply` to move the files \
+         or `:quit` to exit.",
+        matches.len()
+    );
+
+    let token_options = TokenOptions {
+        missing_tag: config.missing_tag,
+        ..Default::default()
+    };
+    let on_error: &Callback = &|src, _dest, err| {
+        print_error(format!(
+            "failed to move \"{}\": {}",
+            src.to_string_lossy(),
+            err
+        ));
+    };
+    // Shows where the files would be moved with a pattern and returns the planned actions
+    let preview = |dest_ptn: &str| -> Option<Vec<Action>> {
+        let mut warnings = Vec::new();
+        let actions = matches
+            .iter()
+            .map(|m| match_to_action(m, dest_ptn, &curdir, &root, &token_options, &mut warnings))
+            .collect::<Result<Vec<Option<Action>>, String>>()
+            .and_then(|actions| sort_actions(&actions.into_iter().flatten().collect::<Vec<_>>()));
+        for warning in warnings {
+            print_warning(warning);
+        }
+        match actions {
+            Ok(actions) => {
+                let options = MoveOptions {
+                    dry_run: true,
+                    ..Default::default()
+                };
+                move_files(&actions, &options, Some(on_error), None);
+                Some(actions)
+            }
+            Err(err) => {
+                print_error(err);
+                None
+            }
+        }
+    };
+    let mut planned = if config.dest_ptn.is_empty() {
+        None
+    } else {
+        preview(&config.dest_ptn)
+    };
+    loop {
+        print!("DEST> ");
+        let line = match read_answer() {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        match line.as_str() {
+            "" => (),
+            ":quit" => return Ok(()),
+            ":apply" => match planned {
+                Some(actions) => {
+                    check_writable(&actions)?;
+                    let options = MoveOptions {
+                        verbose: config.verbose,
+                        ..Default::default()
+                    };
+                    let num_errors = move_files(&actions, &options, Some(on_error), None);
+                    if 0 < num_errors {
+                        return Err(format!("failed to move {} file(s)", num_errors));
+                    }
+                    return Ok(());
+                }
+                None => print_error("no valid DEST pattern was entered yet"),
+            },
+            _ => planned = preview(&line),
+        }
+    }
+}
+
+/// Executes the moves in a plan file except those excluded with --only or --skip.
+fn apply_plan(config: &Config, plan: &Path) -> Result<(), String> {
+    let selected = |id: usize| {
+        (config.only.is_empty() || config.only.iter().any(|ids| ids.contains(&id)))
+            && !config.skip.iter().any(|ids| ids.contains(&id))
+    };
+    let entries = read_plan(plan)?;
+    if entries.iter().any(|(_, done)| *done) {
+        if !config.resume {
+            return Err(format!(
+                "some moves in \"{}\" were already applied; use --resume to apply the rest",
+                plan.to_string_lossy()
+            ));
+        }
+        verify_done(&entries)?;
+    }
+    let planned: Vec<Action> = entries
+        .into_iter()
+        .filter(|(a, done)| !done && a.id().is_none_or(selected))
+        .map(|(a, _)| a)
+        .collect();
+    let actions = sort_actions(&planned)?;
+    check_writable(&actions)?;
+
+    // Guard against applying the same moves twice in a row by accident
+    let dir = match plan.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let hash = plan_hash(&actions);
+    if !config.force && !config.dry_run && was_applied_last(dir, &hash) {
+        let msg = format!(
+            "the same moves in \"{}\" were applied last time",
+            plan.to_string_lossy()
+        );
+        if config.strict {
+            return Err(format!("{}; use --force to apply them again", msg));
+        }
+        print_warning(format!("{}; applying them again", msg));
+    }
+
+    // Mark the executed moves as done in the plan file from time to time, mapping moves from and
+    // to temporary names (to break cycles) to the planned moves
+    let temps = temporaries(&planned, std::slice::from_ref(&actions));
+    let done = RefCell::new(Vec::new());
+    let checkpointed = Cell::new(Instant::now());
+    let checkpoint = || {
+        let ids = done.take();
+        if !ids.is_empty() {
+            if let Err(err) = mark_done(plan, &ids) {
+                print_warning(err);
+            }
+        }
+        checkpointed.set(Instant::now());
+    };
+    let on_moved = |src: &Path, dest: &Path| {
+        if temps.iter().any(|(tmp, _)| tmp == dest) {
+            return;
+        }
+        let src = match temps.iter().find(|(tmp, _)| tmp == src) {
+            Some((_, orig)) => orig.as_path(),
+            None => src,
+        };
+        if let Some(id) = planned.iter().find(|a| a.src() == src).and_then(Action::id) {
+            done.borrow_mut().push(id);
+        }
+        if CHECKPOINT_INTERVAL <= checkpointed.get().elapsed() {
+            checkpoint();
+        }
+    };
+
+    let options = MoveOptions {
+        dry_run: config.dry_run,
+        verbose: config.verbose,
+        ..Default::default()
+    };
+    let num_errors = move_files(
+        &actions,
+        &options,
+        Some(&|src, _dest, err| {
+            print_error(format!(
+                "failed to move \"{}\": {}",
+                src.to_string_lossy(),
+                err
+            ));
+        }),
+        if config.dry_run {
+            None
+        } else {
+            Some(&on_moved)
+        },
+    );
+    checkpoint();
+    if 0 < num_errors {
+        return Err(format!(
+            "failed to move {} file(s); use --resume to apply the rest",
+            num_errors
+        ));
+    }
+    if !config.dry_run {
+        record_applied(dir, &hash)?;
+    }
+    Ok(())
+}
+
+/// How often `pmv apply` marks the executed moves as done in the plan file.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fails unless the files of the moves marked as done in a plan are where they were moved to,
+/// i.e. each destination exists and each source does not unless another move filled it.
+fn verify_done(entries: &[(Action, bool)]) -> Result<(), String> {
+    let done = entries.iter().filter(|(_, done)| *done).map(|(a, _)| a);
+    for action in done.clone() {
+        let refilled = done.clone().any(|other| other.dest() == action.src());
+        if action.dest().symlink_metadata().is_err()
+            || (!refilled && action.src().symlink_metadata().is_ok())
+        {
+            return Err(format!(
+                "cannot resume; \"{}\" is marked as moved to \"{}\" but the files have changed",
+                action.src().to_string_lossy(),
+                action.dest().to_string_lossy()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Moves each of `paths` to the next one and the last to the first, via a temporary name.
+///
+/// Two paths are exchanged atomically instead if the platform and the file system support it.
+fn run_cycle(config: &Config, paths: &[PathBuf]) -> Result<(), String> {
+    let verb = if paths.len() == 2 { "swap" } else { "rotate" };
+    //TODO: Fix for when curdir is not available
+    let curdir = std::env::current_dir().unwrap();
+    let paths: Vec<PathBuf> = paths.iter().map(|path| curdir.join(path)).collect();
+    let list = || {
+        let quoted: Vec<String> = paths
+            .iter()
+            .map(|path| format!("\"{}\"", path.to_string_lossy()))
+            .collect();
+        quoted.join(", ")
+    };
+    for (i, path) in paths.iter().enumerate() {
+        if path.symlink_metadata().is_err() {
+            return Err(format!("\"{}\" does not exist", path.to_string_lossy()));
+        }
+        for other in &paths[..i] {
+            if path == other {
+                return Err(format!(
+                    "\"{}\" is given more than once",
+                    path.to_string_lossy()
+                ));
+            }
+            if path.starts_with(other) || other.starts_with(path) {
+                return Err(format!(
+                    "cannot {} \"{}\" and \"{}\" as one contains the other",
+                    verb,
+                    other.to_string_lossy(),
+                    path.to_string_lossy()
+                ));
+            }
+        }
+    }
+
+    if let (false, [a, b]) = (config.dry_run, paths.as_slice()) {
+        match exchange(a, b) {
+            Ok(true) => {
+                if config.verbose {
+                    println!("{} <--> {}", quote_path(a), quote_path(b));
+                }
+                return Ok(());
+            }
+            Ok(false) => (), // Not supported; swap them via a temporary name
+            Err(err) => return Err(format!("failed to {} {}: {}", verb, list(), err)),
+        }
+    }
+
+    let actions: Vec<Action> = paths
+        .iter()
+        .zip(paths.iter().cycle().skip(1))
+        .map(|(src, dest)| Action::new(src, dest).with_exact_dest())
+        .collect();
+    let sorted = sort_actions(&actions)?;
+    check_writable(&sorted)?;
+    let temps = temporaries(&actions, std::slice::from_ref(&sorted));
+    let options = MoveOptions {
+        dry_run: config.dry_run,
+        verbose: config.verbose,
+        ..Default::default()
+    };
+    let num_errors = move_files(
+        &sorted,
+        &options,
+        Some(&|src, _dest, err| {
+            print_error(format!(
+                "failed to move \"{}\": {}",
+                src.to_string_lossy(),
+                err
+            ));
+        }),
+        None,
+    );
+    if 0 < num_errors {
+        restore_temporaries(&temps, &mut Vec::new(), &print_warning);
+        return Err(format!("failed to {} {}", verb, list()));
+    }
+    Ok(())
+}
+
+/// Returns the `n`-th numbered copy of `base`, i.e. `base` with `.n` appended.
+fn numbered_path(base: &Path, n: usize) -> PathBuf {
+    let mut name = base.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Renames `base` to `base.1`, `base.1` to `base.2` and so on, then deletes `base.N` where `N`
+/// is `keep`, the oldest copy to be kept otherwise, which is moved aside before the renames.
+///
+/// If any of the renames failed, the others are undone in reverse order.
+fn rotate_numbered(config: &Config, base: &Path, keep: usize) -> Result<(), String> {
+    //TODO: Fix for when curdir is not available
+    let base = std::env::current_dir().unwrap().join(base);
+    if base.symlink_metadata().is_err() {
+        return Err(format!("\"{}\" does not exist", base.to_string_lossy()));
+    }
+
+    // Shift the existing copies, newest last
+    let mut actions: Vec<Action> = (0..keep)
+        .map(|n| match n {
+            0 => base.clone(),
+            n => numbered_path(&base, n),
+        })
+        .enumerate()
+        .filter(|(_, src)| src.symlink_metadata().is_ok())
+        .map(|(n, src)| Action::new(src, numbered_path(&base, n + 1)).with_exact_dest())
+        .collect();
+
+    // Move the oldest copy aside, which would be overwritten otherwise
+    let oldest = numbered_path(&base, keep);
+    let mut aside = None;
+    if oldest.symlink_metadata().is_ok() {
+        let tmp = temp_name(&oldest, &actions).ok_or_else(|| {
+            format!(
+                "no temporary name is available for \"{}\"",
+                oldest.to_string_lossy()
+            )
+        })?;
+        actions.push(
+            Action::new(&oldest, &tmp)
+                .with_exact_dest()
+                .with_label("to be deleted"),
+        );
+        aside = Some(tmp);
+    }
+    let sorted = sort_actions(&actions)?;
+    check_writable(&sorted)?;
+
+    // Stop at the first failure as the following moves would overwrite the copy not moved
+    let started = Cell::new(0);
+    let moved = RefCell::new(Vec::new());
+    let options = MoveOptions {
+        dry_run: config.dry_run,
+        verbose: config.verbose,
+        ..Default::default()
+    };
+    let num_errors = move_files_with(
+        &sorted,
+        &options,
+        Some(&|src, _dest, err| {
+            print_error(format!(
+                "failed to move \"{}\": {}",
+                src.to_string_lossy(),
+                err
+            ));
+        }),
+        Some(&|src, dest| moved.borrow_mut().push((src.to_owned(), dest.to_owned()))),
+        Some(&|_src: &Path, _dest: &Path| {
+            if moved.borrow().len() < started.replace(started.get() + 1) {
+                return Err(String::from("stopped as the previous move failed"));
+            }
+            Ok(())
+        }),
+    );
+    if 0 < num_errors {
+        for (src, dest) in moved.borrow().iter().rev() {
+            if let Err(err) = std::fs::rename(dest, src) {
+                print_warning(format!(
+                    "failed to move \"{}\" back to \"{}\": {}",
+                    dest.to_string_lossy(),
+                    src.to_string_lossy(),
+                    err
+                ));
+            }
+        }
+        return Err(format!("failed to rotate \"{}\"", base.to_string_lossy()));
+    }
+
+    // Delete the oldest copy
+    if let Some(tmp) = aside {
+        if config.dry_run || config.verbose {
+            println!("{} --> (delete)", quote_path(&tmp));
+        }
+        if !config.dry_run {
+            let result = if tmp.is_dir() {
+                std::fs::remove_dir_all(&tmp)
+            } else {
+                std::fs::remove_file(&tmp)
+            };
+            result.map_err(|err| {
+                format!("failed to delete \"{}\": {}", tmp.to_string_lossy(), err)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves files left at temporary paths, as a move from there failed or was skipped, back to
+/// where they were before, unless another file has taken the place.
+///
+/// `temps` are pairs of a temporary path and the path before the move to it, and `moved` is
+/// updated so that it keeps only the final locations of the moved files.
+fn restore_temporaries(
+    temps: &[(PathBuf, PathBuf)],
+    moved: &mut Vec<(PathBuf, PathBuf)>,
+    warn: &dyn Fn(String),
+) {
+    for (tmp, orig) in temps {
+        if tmp.symlink_metadata().is_err() {
+            continue;
+        }
+        if orig.symlink_metadata().is_ok() {
+            warn(format!(
+                "a file was left at the temporary path \"{}\" as \"{}\" is taken",
+                tmp.to_string_lossy(),
+                orig.to_string_lossy()
+            ));
+            continue;
+        }
+        match std::fs::rename(tmp, orig) {
+            Ok(()) => {
+                warn(format!(
+                    "moved \"{}\" back to \"{}\" as it could not be moved further",
+                    tmp.to_string_lossy(),
+                    orig.to_string_lossy()
+                ));
+                if let Some(i) = moved.iter().position(|(_, path)| path == tmp) {
+                    if &moved[i].0 == orig {
+                        moved.remove(i);
+                    } else {
+                        moved[i].1 = orig.clone();
+                    }
+                }
+            }
+            Err(err) => warn(format!(
+                "a file was left at the temporary path \"{}\": {}",
+                tmp.to_string_lossy(),
+                err
+            )),
+        }
+    }
+}
+
+/// Returns pairs of the source and the final path of the planned moves.
+fn planned_moves(actions: &[Action]) -> Vec<(PathBuf, PathBuf)> {
+    actions
+        .iter()
+        .map(|a| (a.src().to_owned(), a.final_dest()))
+        .collect()
+}
+
+/// Asks for confirmation if unexpectedly many files matched (more than --max-files.)
+fn confirm_max_files(config: &Config, num_files: usize, verb: &str) -> Result<(), String> {
+    let max_files = match config.max_files {
+        Some(max_files) if max_files < num_files && !config.dry_run && !config.yes => max_files,
+        _ => return Ok(()),
+    };
+    print!(
+        "{} files will be {}, more than {} (--max-files). Continue? [y/N]: ",
+        num_files, verb, max_files
+    );
+    match read_answer() {
+        Some(answer) if answer.eq_ignore_ascii_case("y") => Ok(()),
+        Some(_) => Err(String::from("aborted by user")),
+        None => Err(format!(
+            "{} files matched, more than {} (--max-files); use --yes to have them {}",
+            num_files, max_files, verb
+        )),
+    }
+}
+
+/// Adds the sources of the actions to an archive as members named by their destinations, then
+/// removes them.
+///
+/// Destinations are relative to the working directory, which is the root of the archive. No file
+/// is removed unless the archive was written successfully.
+fn move_into_archive(config: &Config, archive: &Path, actions: &[Action]) -> Result<(), String> {
+    let root = work_dir(&config.root);
+    let mut files = Vec::new();
+    for action in actions {
+        if action.src().is_dir() {
+            return Err(format!(
+                "\"{}\" is a directory; only files can be moved into an archive",
+                action.src().to_string_lossy()
+            ));
+        }
+        let name = match action.dest().strip_prefix(&root) {
+            Ok(relative) => archive::member_name(relative)?,
+            Err(_) => {
+                return Err(format!(
+                    "\"{}\" cannot be a member of an archive; it is outside \"{}\"",
+                    action.dest().to_string_lossy(),
+                    root.to_string_lossy()
+                ))
+            }
+        };
+        files.push((action.src().to_owned(), name));
+    }
+    sort_by_path(&mut files, config.collation, |(src, _)| src.as_path());
+    let mut names = HashSet::new();
+    for (_, name) in &files {
+        if !names.insert(name) {
+            return Err(format!(
+                "multiple files would be added to \"{}\" as \"{}\"",
+                archive.to_string_lossy(),
+                name
+            ));
+        }
+    }
+
+    confirm_max_files(config, files.len(), "moved into the archive")?;
+    if config.dry_run || config.verbose {
+        for (src, name) in &files {
+            println!(
+                "{} --> {}:{}",
+                quote_path(src),
+                quote_path(archive),
+                quote(name)
+            );
+        }
+    }
+    if config.check && !files.is_empty() {
+        return Err(format!(
+            "{} file(s) would be moved into \"{}\"",
+            files.len(),
+            archive.to_string_lossy()
+        ));
+    }
+    if config.dry_run || files.is_empty() {
+        return Ok(());
+    }
+
+    archive::add_files(archive, &files)?;
+    let mut num_errors = 0;
+    for (src, _) in &files {
+        if let Err(err) = std::fs::remove_file(src) {
+            print_error(format!(
+                "failed to remove \"{}\" added to the archive: {}",
+                src.to_string_lossy(),
+                err
+            ));
+            num_errors += 1;
+        }
+    }
+    if 0 < num_errors {
+        return Err(format!(
+            "{} file(s) were archived but not removed",
+            num_errors
+        ));
     }
+    Ok(())
 }
 
-fn parse_args(args: &[OsString]) -> Config {
-    let matches = clap::Command::new("pmv")
-        .version(clap::crate_version!())
-        .about(clap::crate_description!())
-        .arg(
-            clap::Arg::new("dry-run")
-                .short('n')
-                .long("dry-run")
-                .action(clap::builder::ArgAction::SetTrue)
-                .help("Does not move files but just shows what would be done"),
-        )
-        .arg(
-            clap::Arg::new("interactive")
-                .short('i')
-                .long("interactive")
-                .action(clap::builder::ArgAction::SetTrue)
-                .help("Prompts before moving an each file"),
-        )
-        .arg(
-            clap::Arg::new("verbose")
-                .short('v')
-                .long("verbose")
-                .action(clap::builder::ArgAction::Count)
-                .help("Writes verbose message"),
-        )
-        .arg(
-            clap::Arg::new("SOURCE")
-                .required(true)
-                .index(1)
-                .help("Source pattern (use --help for details)")
-                .long_help(
-                    "A pattern string specifying files to move. If the pattern contains \
-                     wildcard(s), multiple files matching to the pattern will be targeted. \
-                     Supported wildcards are:\n\n    \
-                     ? ... Matches a single character\n    \
-                     * ... Matches zero or more characters",
-                ),
-        )
-        .arg(
-            clap::Arg::new("DEST")
-                .required(true)
-                .index(2)
-                .help("Destination pattern (use --help for details)")
-                .long_help(
-                    "A pattern string specifying where to move the targeted files. If the pattern \
-                     contains tokens like `#1` or `#2`, each of them will be replaced with a \
-                     substring extracted from the targeted file path. Those substrings matches \
-                     the wildcard patterns in SOURCE; `#1` matches the first wildcard, `#2` \
-                     matches the second, respectively. For example, if SOURCE is `*_test.py` and \
-                     DEST is `tests/test_#1.py`:\n\n    \
-                     Exisitng File | Destination\n    \
-                     ------------- | -----------------\n    \
-                     foo_test.py   | tests/test_foo.py\n    \
-                     bar_test.py   | tests/test_bar.py\n    \
-                     hoge_test.py  | tests/test_hoge.py",
-                ),
-        )
-        .get_matches_from(args);
+/// Extracts the members of an archive matching SOURCE to the paths built from DEST.
+///
+/// SOURCE is matched against the names of the members without accessing the file system and
+/// DEST is relative to the working directory. Nothing is extracted if any destination exists.
+fn extract_from_archive(config: &Config, archive: &Path) -> Result<(), String> {
+    let options = MatchOptions {
+        capture_groups: config.capture_groups,
+        case_sensitivity: config.case_sensitivity,
+        regex: config.regex,
+        normalize_unicode: config.normalize_unicode,
+        ..Default::default()
+    };
+    let pattern = Pattern::new(&config.src_ptn, &options)?;
+    let root = work_dir(&config.root);
+    let mut members = Vec::new();
+    for name in archive::list_files(archive)? {
+        if !has_extension(Path::new(&name), &config.extensions) {
+            continue;
+        }
+        let matched_parts = match pattern.matches(&name) {
+            Some(matched_parts) => matched_parts,
+            None => continue,
+        };
+        let os_parts: Vec<OsString> = matched_parts.iter().map(OsString::from).collect();
+        let mut dest = root.join(substitute_tokens(&config.dest_ptn, &os_parts, &|_, _| {
+            Ok(None)
+        })?);
+        if config.flatten {
+            dest = dest.join(Path::new(&name).file_name().unwrap_or_default());
+        }
+        members.push((name, dest));
+    }
+    let mut dests = HashSet::new();
+    for (name, dest) in &members {
+        if !dests.insert(dest) {
+            return Err(format!(
+                "multiple members would be extracted to \"{}\" (one of them is \"{}\")",
+                dest.to_string_lossy(),
+                name
+            ));
+        }
+        if dest.exists() || dest.is_symlink() {
+            return Err(format!(
+                "\"{}\" already exists; \"{}\" is not extracted",
+                dest.to_string_lossy(),
+                name
+            ));
+        }
+    }
 
-    let src_ptn = matches.get_one::<String>("SOURCE").unwrap();
-    let dest_ptn = matches.get_one::<String>("DEST").unwrap();
-    let dry_run = *matches.get_one::<bool>("dry-run").unwrap();
-    let verbose = 0 < *matches.get_one::<u8>("verbose").unwrap(); // limited by clap so it's safe
-    let interactive = *matches.get_one::<bool>("interactive").unwrap();
+    confirm_max_files(config, members.len(), "extracted")?;
+    if config.dry_run || config.verbose {
+        for (name, dest) in &members {
+            println!(
+                "{}:{} --> {}",
+                quote_path(archive),
+                quote(name),
+                quote_path(dest)
+            );
+        }
+    }
+    if config.check && !members.is_empty() {
+        return Err(format!("{} file(s) would be extracted", members.len()));
+    }
+    if config.dry_run {
+        return Ok(());
+    }
 
-    Config {
-        src_ptn: src_ptn.to_owned(),
-        dest_ptn: dest_ptn.to_owned(),
-        dry_run,
-        verbose,
-        interactive,
+    let num_errors = archive::extract_files(archive, &members, &|name, dest, err| {
+        print_error(format!(
+            "failed to extract \"{}\" to \"{}\": {}",
+            name,
+            dest.to_string_lossy(),
+            err
+        ));
+    })?;
+    if 0 < num_errors {
+        return Err(format!("{} extraction(s) failed", num_errors));
     }
+    Ok(())
 }
 
-fn matches_to_actions(src_ptn: &str, dest_ptn: &str) -> Vec<Action> {
-    //TODO: Fix for when curdir is not available
-    let curdir = std::env::current_dir().unwrap();
-    let matches = match walk(&curdir, src_ptn) {
-        Err(err) => {
-            print_error(format!("failed to scan directory tree: {}", err));
-            exit(2); //TODO: Do not exit here
+fn run_settings_command(subcommand: &SettingsCommand, settings: &[Setting]) -> Result<(), String> {
+    match subcommand {
+        SettingsCommand::Show { show_origin } => {
+            print!("{}", settings::render(settings, *show_origin));
+        }
+        SettingsCommand::Get { key } => match settings::get(settings, key) {
+            Some(value) => println!("{}", value),
+            None => return Err(format!("\"{}\" is not set", key)),
+        },
+        SettingsCommand::Set {
+            key,
+            value,
+            project,
+        } => {
+            let path = if *project {
+                settings::project_path(&std::env::current_dir().unwrap())
+            } else {
+                settings::user_path()
+                    .ok_or("failed to locate the configuration file of the user")?
+            };
+            let setting = Setting {
+                key: key.clone(),
+                value: settings::parse_value(value),
+                origin: path.clone(),
+            };
+            // Fail early on settings which would break later runs
+            default_args(std::slice::from_ref(&setting), &command(), &|_| false)?;
+            settings::set(&path, key, setting.value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Searches files and moves them in windows of `window` files while searching.
+fn run_stream(config: &Config, window: usize, deadline: Option<&Deadline>) -> Result<(), String> {
+    let options = MatchOptions {
+        capture_groups: config.capture_groups,
+        case_sensitivity: config.case_sensitivity,
+        regex: config.regex,
+        normalize_unicode: config.normalize_unicode,
+        follow_symlinks: config.follow_symlinks,
+    };
+    let pattern = Pattern::new(&config.src_ptn, &options)?;
+    let curdir = work_dir(&config.root);
+    let move_options = MoveOptions {
+        dry_run: config.dry_run,
+        verbose: config.verbose,
+        wait_for_unlock: config.wait_for_unlock.map(Duration::from_secs),
+        wait_for_space: config.wait_for_space.map(Duration::from_secs),
+        retries: if config.network_safe { 3 } else { 0 },
+        verify: config.network_safe,
+        fsync: config.fsync,
+        porcelain: config.porcelain,
+        ..Default::default()
+    };
+    let timed_out = Cell::new(false);
+    let num_errors = Cell::new(0);
+    let move_window = |actions: Vec<Action>| -> Result<(), String> {
+        let mut actions = actions;
+        if config.flatten {
+            actions = actions
+                .into_iter()
+                .map(|a| {
+                    let dest = a.dest().join(a.src().file_name().unwrap_or_default());
+                    Action::new(a.src(), dest)
+                })
+                .collect();
         }
-        Ok(matches) => matches,
+        if config.no_follow_dest_symlinks {
+            actions = actions
+                .into_iter()
+                .map(|a| {
+                    if a.dest().is_symlink() {
+                        a.with_exact_dest()
+                    } else {
+                        a
+                    }
+                })
+                .collect();
+        }
+        sort_by_path(&mut actions, config.collation, |a| a.src());
+
+        // Refuse moves which may interfere with the other windows
+        for action in &actions {
+            let dest = action.final_dest();
+            if dest == action.src() {
+                continue;
+            }
+            let rel_dest = dest.strip_prefix(&curdir).unwrap_or(&dest);
+            if pattern.matches(rel_dest).is_some() || pattern.matches(&dest).is_some() {
+                return Err(format!(
+                    "\"{}\" matches SOURCE so it may be moved again; --stream cannot be used",
+                    dest.to_string_lossy()
+                ));
+            }
+            if dest.symlink_metadata().is_ok() && !actions.iter().any(|a| a.src() == dest) {
+                return Err(format!(
+                    "\"{}\" already exists; --stream cannot tell it from a file moved earlier",
+                    dest.to_string_lossy()
+                ));
+            }
+        }
+
+        let actions = sort_actions(&actions)?;
+        check_writable(&actions)?;
+        let n = move_files_with(
+            &actions,
+            &move_options,
+            Some(&|src, _dest, err| {
+                print_error(format!(
+                    "failed to move \"{}\": {}",
+                    src.to_string_lossy(),
+                    err
+                ));
+            }),
+            None,
+            Some(&|_src: &Path, _dest: &Path| match deadline {
+                Some(deadline) if deadline.expired() => {
+                    timed_out.set(true);
+                    Err(deadline.error("moving files"))
+                }
+                _ => Ok(()),
+            }),
+        );
+        num_errors.set(num_errors.get() + n);
+        Ok(())
     };
 
-    let mut actions = Vec::new();
-    for m in matches {
-        let src = m.path();
-        let dest = substitute_variables(dest_ptn, &m.matched_parts[..]);
-        let dest = curdir.join(dest);
-        actions.push(Action::new(src, dest));
+    let mut pending = Vec::new();
+    let warnings = scan_actions(
+        &config.src_ptn,
+        &config.dest_ptn,
+        &options,
+        &TokenOptions {
+            missing_tag: config.missing_tag,
+            ..Default::default()
+        },
+        &ScanOptions {
+            extensions: config.extensions.clone(),
+            kinds: config.kinds.clone(),
+            explain: config.explain,
+            entry_filter: config.entry_filter,
+            root: config.root.clone(),
+            deadline,
+            ..Default::default()
+        },
+        &mut |action| {
+            pending.push(action);
+            if pending.len() == window {
+                move_window(std::mem::take(&mut pending))?;
+            }
+            Ok(!timed_out.get())
+        },
+    )?;
+    if !timed_out.get() {
+        move_window(pending)?;
     }
-    actions
+
+    for warning in &warnings {
+        print_warning(warning);
+    }
+    if !warnings.is_empty() {
+        eprintln!("{} warning(s)", warnings.len());
+    }
+    if let (true, Some(deadline)) = (timed_out.get(), deadline) {
+        return Err(format!(
+            "{}; aborted the remaining moves",
+            deadline.error("moving files")
+        ));
+    }
+    if 0 < num_errors.get() {
+        return Err(format!("failed to move {} file(s)", num_errors.get()));
+    }
+    Ok(())
 }
 
 pub fn try_main(args: &[OsString]) -> Result<(), String> {
     // Parse arguments
-    let config = parse_args(args);
+    //TODO: Fix for when curdir is not available
+    let settings = settings::load(&config_paths(&std::env::current_dir().unwrap()))?;
+    let config = parse_args(args, &settings)?;
+    set_literal_paths(config.literal_paths);
+    if let Some(command) = &config.settings_command {
+        return run_settings_command(command, &settings);
+    }
+    if !config.examples.is_empty() {
+        return check_examples(&config);
+    }
+    if config.repl {
+        return run_repl(&config);
+    }
+    if config.schema.is_some() {
+        // `plan` is the only format accepted by clap for now
+        println!("{}", plan_schema());
+        return Ok(());
+    }
+    if config.print_renamed {
+        return print_renamed(&config, &mut io::stdin().lock(), &mut io::stdout().lock());
+    }
+    if let Some(plan) = &config.plan {
+        return apply_plan(&config, plan);
+    }
+    if !config.cycle.is_empty() {
+        return run_cycle(&config, &config.cycle);
+    }
+    if let Some((base, keep)) = &config.rotate_numbered {
+        return rotate_numbered(&config, base, *keep);
+    }
+    let deadline = config.timeout.map(Deadline::start);
+    if let Some(window) = config.stream {
+        return run_stream(&config, window, deadline.as_ref());
+    }
+    if let Some(archive) = &config.from_archive {
+        return extract_from_archive(&config, archive);
+    }
 
     // Collect paths of the files to move with their destination
-    let actions = matches_to_actions(&config.src_ptn, &config.dest_ptn);
+    let options = MatchOptions {
+        capture_groups: config.capture_groups,
+        case_sensitivity: config.case_sensitivity,
+        regex: config.regex,
+        normalize_unicode: config.normalize_unicode,
+        follow_symlinks: config.follow_symlinks,
+    };
+    let match_limit = if config.interactive {
+        Some(config.match_limit)
+    } else {
+        None
+    };
+    let token_options = TokenOptions {
+        missing_tag: config.missing_tag,
+        ..Default::default()
+    };
+    let scan_options = ScanOptions {
+        extensions: config.extensions.clone(),
+        kinds: config.kinds.clone(),
+        match_limit,
+        explain: config.explain,
+        entry_filter: config.entry_filter,
+        root: config.root.clone(),
+        deadline: deadline.as_ref(),
+    };
+    let (actions, warnings, rule_stats) = if config.rules.is_empty() {
+        let (actions, warnings) = matches_to_actions(
+            &config.src_ptn,
+            &config.dest_ptn,
+            &options,
+            &token_options,
+            &scan_options,
+        )?;
+        (actions, warnings, None)
+    } else {
+        let (actions, warnings, stats) =
+            rules_to_actions(&config.rules, &options, &token_options, &scan_options)?;
+        (actions, warnings, Some(stats))
+    };
+    let num_warnings = Cell::new(0);
+    let warn = |msg: String| {
+        print_warning(msg);
+        num_warnings.set(num_warnings.get() + 1);
+    };
+    for warning in warnings {
+        warn(warning);
+    }
+    if let Some(stats) = &rule_stats {
+        for (i, (src_ptn, dest_ptn)) in config.rules.iter().enumerate() {
+            if stats.matched[i] == 0 {
+                warn(format!(
+                    "rule {} (\"{}\" --> \"{}\") matched nothing",
+                    i + 1,
+                    quote(src_ptn),
+                    quote(dest_ptn)
+                ));
+            }
+        }
+    }
 
-    let actions = sort_actions(&actions)?;
+    // Detect sources which are the same file as another source
+    let mut duplicates: Vec<usize> = Vec::new();
+    for (first, dup) in find_aliased_sources(&actions) {
+        warn(format!(
+            "\"{}\" is the same file as \"{}\"{}",
+            actions[dup].src().to_string_lossy(),
+            actions[first].src().to_string_lossy(),
+            if config.dedupe_sources {
+                "; skipping it"
+            } else {
+                ""
+            }
+        ));
+        duplicates.push(dup);
+    }
+    let mut actions: Vec<Action> = if config.dedupe_sources {
+        actions
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !duplicates.contains(i))
+            .map(|(_, a)| a)
+            .collect()
+    } else {
+        actions
+    };
 
-    // Move files
-    move_files(
-        &actions,
-        config.dry_run,
-        config.interactive,
-        config.verbose,
-        Some(&|src, _dest, err| {
-            print_error(format!(
-                "failed to move \"{}\": {}",
-                src.to_string_lossy(),
+    if config.to_trash {
+        let mut paths: Vec<PathBuf> = actions.iter().map(|a| a.src().to_owned()).collect();
+        sort_by_path(&mut paths, config.collation, |p| p.as_path());
+        if config.warnings_as_errors && 0 < num_warnings.get() {
+            return Err(format!(
+                "{} warning(s) occurred and --warnings-as-errors was specified",
+                num_warnings.get()
+            ));
+        }
+        confirm_max_files(&config, paths.len(), "trashed")?;
+        let options = MoveOptions {
+            dry_run: config.dry_run,
+            interactive: config.interactive,
+            verbose: config.verbose,
+            porcelain: config.porcelain,
+            ..Default::default()
+        };
+        let num_errors = trash_files(
+            &paths,
+            &options,
+            Some(&|path, err| {
+                print_error(format!(
+                    "failed to trash \"{}\": {}",
+                    path.to_string_lossy(),
+                    err
+                ));
+            }),
+        );
+        if 0 < num_warnings.get() {
+            eprintln!("{} warning(s)", num_warnings.get());
+        }
+        if config.check && !paths.is_empty() {
+            return Err(format!("{} file(s) would be trashed", paths.len()));
+        }
+        if 0 < num_errors {
+            return Err(format!("{} move(s) to the trash failed", num_errors));
+        }
+        return Ok(());
+    }
+
+    if config.flatten {
+        actions = actions
+            .into_iter()
+            .map(|a| {
+                let dest = a.dest().join(a.src().file_name().unwrap_or_default());
+                Action::new(a.src(), dest)
+            })
+            .collect();
+    }
+
+    if let Some(archive) = &config.into_archive {
+        if config.warnings_as_errors && 0 < num_warnings.get() {
+            return Err(format!(
+                "{} warning(s) occurred and --warnings-as-errors was specified",
+                num_warnings.get()
+            ));
+        }
+        let result = move_into_archive(&config, archive, &actions);
+        if 0 < num_warnings.get() {
+            eprintln!("{} warning(s)", num_warnings.get());
+        }
+        return result;
+    }
+
+    if config.no_follow_dest_symlinks {
+        // Replace symbolic links at the destinations rather than moving into their targets
+        actions = actions
+            .into_iter()
+            .map(|a| {
+                if a.dest().is_symlink() {
+                    a.with_exact_dest()
+                } else {
+                    a
+                }
+            })
+            .collect();
+    }
+
+    sort_by_path(&mut actions, config.collation, |a| a.src());
+
+    // Resolve destinations shared by multiple files
+    let (actions, skipped) = resolve_conflicts(actions, config.on_conflict);
+    for action in skipped {
+        warn(format!(
+            "skipped \"{}\" as another file is moved to \"{}\"",
+            action.src().to_string_lossy(),
+            action.dest().to_string_lossy()
+        ));
+    }
+
+    // Detect destinations which collide on case-insensitive file systems
+    for (first, other) in find_case_collisions(&actions) {
+        warn(format!(
+            "\"{}\" differs from \"{}\" only in case",
+            actions[other].dest().to_string_lossy(),
+            actions[first].dest().to_string_lossy()
+        ));
+    }
+
+    // Detect destinations which turned out to be existing directories
+    let shadowing = match &rule_stats {
+        Some(stats) => (0..actions.len())
+            .filter(|&i| {
+                let dest_ptn = &config.rules[stats.rule_of[actions[i].src()]].1;
+                !find_shadowing_dirs(&actions[i..=i], dest_ptn).is_empty()
+            })
+            .collect(),
+        None => find_shadowing_dirs(&actions, &config.dest_ptn),
+    };
+    for i in shadowing {
+        warn(format!(
+            "\"{}\" is an existing directory; \"{}\" will be moved into it",
+            actions[i].dest().to_string_lossy(),
+            actions[i].src().to_string_lossy()
+        ));
+    }
+    if config.warnings_as_errors && 0 < num_warnings.get() {
+        return Err(format!(
+            "{} warning(s) occurred and --warnings-as-errors was specified",
+            num_warnings.get()
+        ));
+    }
+
+    if let Some(count) = config.count {
+        let moves = planned_moves(&actions);
+        let actions: Vec<Action> = actions
+            .into_iter()
+            .zip(moves)
+            .filter(|(_, (src, dest))| src != dest)
+            .map(|(a, _)| a)
+            .collect();
+        match count {
+            Count::Total => println!("{}", actions.len()),
+            Count::PerDest => {
+                let curdir = std::env::current_dir().unwrap();
+                print!("{}", render_counts(&actions, &curdir));
+            }
+        }
+        return Ok(());
+    }
+
+    confirm_max_files(&config, actions.len(), "moved")?;
+
+    // Number the moves so that some of them can be picked from the plan later
+    let actions: Vec<Action> = actions
+        .into_iter()
+        .enumerate()
+        .map(|(i, a)| a.with_id(i + 1))
+        .collect();
+    if let Some(save_plan) = &config.save_plan {
+        write_plan(save_plan, &actions)?;
+    }
+
+    if config.tree {
+        let curdir = std::env::current_dir().unwrap();
+        sort_actions(&actions)?; // Only for validation
+        print!("{}", render_tree(&actions, &curdir));
+        return Ok(());
+    }
+    if config.summary {
+        let curdir = std::env::current_dir().unwrap();
+        sort_actions(&actions)?; // Only for validation
+        print!("{}", render_summary(&actions, &curdir));
+        println!("Estimate: {}", estimate(&actions));
+        println!("Warnings: {}", num_warnings.get());
+        return Ok(());
+    }
+    if let Some(format) = config.explain_plan {
+        let curdir = std::env::current_dir().unwrap();
+        let chains = sort_chains(&actions, &config.temp_naming)?;
+        match format {
+            PlanFormat::Tree => print!("{}", render_chain_tree(&chains, &curdir)),
+            PlanFormat::Dot => print!("{}", render_chain_dot(&chains, &curdir)),
+        }
+        return Ok(());
+    }
+    if config.group_by_dest {
+        let curdir = std::env::current_dir().unwrap();
+        sort_actions(&actions)?; // Only for validation
+        print!("{}", render_groups(&actions, &curdir));
+        println!("Estimate: {}", estimate(&actions));
+        return Ok(());
+    }
+
+    let mut stage = None;
+    let phases = if config.via_temp {
+        let (first, second) = split_via_temp_with(&actions, &config.temp_naming)?;
+        vec![first, second]
+    } else if config.stage && !actions.is_empty() {
+        let dir = staging_dir(&actions)
+            .ok_or_else(|| String::from("no directory is available for staging"))?;
+        let (first, second) = split_via_stage(&actions, &dir)?;
+        stage = Some(dir);
+        vec![first, second]
+    } else {
+        vec![sort_actions_with(&actions, &config.temp_naming)?]
+    };
+    check_writable(&phases[0])?;
+    if config.preflight && !config.dry_run {
+        preflight(&actions)?;
+    }
+    if config.no_symlink_traversal {
+        check_symlink_traversal(&actions, &work_dir(&config.root))?;
+    }
+    if let Some(deadline) = &deadline {
+        deadline.check("planning moves")?;
+    }
+
+    if let (Some(dir), false) = (&stage, config.dry_run) {
+        std::fs::create_dir(dir).map_err(|err| {
+            format!(
+                "failed to create staging directory \"{}\": {}",
+                dir.to_string_lossy(),
                 err
+            )
+        })?;
+    }
+
+    // Create the directories for temporary files, which are removed after moving files
+    let temps = temporaries(&actions, &phases);
+    let mut temp_dirs: Vec<PathBuf> = Vec::new();
+    if !config.dry_run {
+        for (tmp, _) in &temps {
+            match tmp.parent() {
+                Some(dir) if dir.ends_with(TEMP_DIR) && !dir.exists() => {
+                    std::fs::create_dir(dir).map_err(|err| {
+                        format!(
+                            "failed to create directory for temporary files \"{}\": {}",
+                            dir.to_string_lossy(),
+                            err
+                        )
+                    })?;
+                    temp_dirs.push(dir.to_owned());
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // Move files
+    let watcher = match (config.watch, config.dry_run) {
+        (true, false) => {
+            let dests: Vec<PathBuf> = planned_moves(&actions)
+                .into_iter()
+                .map(|(_, dest)| dest)
+                .collect();
+            Some(RefCell::new(DestWatcher::new(&dests)?))
+        }
+        _ => None,
+    };
+    let finishing = Cell::new(false); // Moves from temporaries are completed even after timeout
+    let timed_out = Cell::new(false);
+    let before_move = |_src: &Path, dest: &Path| {
+        if let Some(deadline) = deadline.as_ref().filter(|_| !finishing.get()) {
+            if deadline.expired() {
+                timed_out.set(true);
+                return Err(deadline.error("moving files"));
+            }
+        }
+        match &watcher {
+            Some(watcher) => watcher.borrow_mut().check(dest),
+            None => Ok(()),
+        }
+    };
+    let conflicted = || watcher.as_ref().is_some_and(|w| w.borrow().conflicted());
+    let moved: RefCell<Vec<(PathBuf, PathBuf)>> = RefCell::new(Vec::new());
+    let planned = if config.diff {
+        planned_moves(&actions) // Before moving as destinations may become directories
+    } else {
+        Vec::new()
+    };
+    let options = MoveOptions {
+        dry_run: config.dry_run,
+        interactive: config.interactive,
+        verbose: config.verbose,
+        wait_for_unlock: config.wait_for_unlock.map(Duration::from_secs),
+        wait_for_space: config.wait_for_space.map(Duration::from_secs),
+        retries: if config.network_safe { 3 } else { 0 },
+        verify: config.network_safe,
+        fsync: config.fsync,
+        chunk_size: config.chunk_size,
+        pause: Duration::from_secs(config.pause.unwrap_or(0)),
+        porcelain: config.porcelain,
+    };
+    let mut num_errors = 0;
+    for (i, actions) in phases.into_iter().enumerate() {
+        finishing.set(0 < i);
+        // Rename only the temporaries which were actually created in the first phase
+        let actions: Vec<Action> = if 0 < i && !config.dry_run {
+            let moved = moved.borrow();
+            actions
+                .into_iter()
+                .filter(|a| moved.iter().any(|(_, path)| path == a.src()))
+                .collect()
+        } else {
+            actions
+        };
+        num_errors += move_files_with(
+            &actions,
+            &options,
+            Some(&|src, _dest, err| {
+                print_error(format!(
+                    "failed to move \"{}\": {}",
+                    src.to_string_lossy(),
+                    err
+                ));
+            }),
+            Some(&|src, dest| {
+                // Keep only the final locations (a file may be moved again, e.g. via a temporary)
+                let mut moved = moved.borrow_mut();
+                match moved.iter_mut().find(|(_, path)| path == src) {
+                    Some((_, path)) => *path = dest.to_owned(),
+                    None => moved.push((src.to_owned(), dest.to_owned())),
+                }
+            }),
+            Some(&before_move),
+        );
+        if conflicted() {
+            break;
+        }
+    }
+    if !config.dry_run {
+        restore_temporaries(&temps, &mut moved.borrow_mut(), &warn);
+    }
+    for dir in temp_dirs {
+        if std::fs::remove_dir(&dir).is_err() {
+            warn(format!(
+                "files were left in the directory for temporary files \"{}\"",
+                dir.to_string_lossy()
             ));
-        }),
-    );
+        }
+    }
+    if let (Some(dir), false) = (&stage, config.dry_run) {
+        if std::fs::remove_dir(dir).is_err() {
+            warn(format!(
+                "files were left in the staging directory \"{}\"",
+                dir.to_string_lossy()
+            ));
+        }
+    }
+
+    if config.dry_run && !config.porcelain {
+        println!("Estimate: {}", estimate(&actions));
+    }
+    if let (Some(stats), false) = (&rule_stats, config.porcelain) {
+        let moves = if config.dry_run {
+            planned_moves(&actions)
+                .into_iter()
+                .filter(|(src, dest)| src != dest)
+                .collect()
+        } else {
+            moved.borrow().clone()
+        };
+        let mut num_moved = vec![0; config.rules.len()];
+        for (src, _) in moves {
+            if let Some(&i) = stats.rule_of.get(&src) {
+                num_moved[i] += 1;
+            }
+        }
+        print!(
+            "{}",
+            render_rule_stats(&config.rules, &stats.matched, &num_moved, config.dry_run)
+        );
+    }
+
+    if let Some(scope) = config.fix_symlinks {
+        let moves = if config.dry_run {
+            planned_moves(&actions)
+        } else {
+            moved.borrow().clone()
+        };
+        for result in fix_symlinks(&scope, &moves, config.dry_run) {
+            match result {
+                Ok(fixed) if (config.dry_run || config.verbose) && !config.porcelain => println!(
+                    "{} --> {} (was {})",
+                    quote_path(&fixed.link),
+                    quote_path(&fixed.new_target),
+                    quote_path(&fixed.old_target)
+                ),
+                Ok(_) => (),
+                Err(err) => print_error(err),
+            }
+        }
+    }
+
+    if config.touch && !config.dry_run {
+        for (_, dest) in moved.borrow().iter() {
+            if let Err(err) = touch(dest) {
+                print_error(format!(
+                    "failed to update the modification time of \"{}\": {}",
+                    dest.to_string_lossy(),
+                    err
+                ));
+            }
+        }
+    }
+
+    if config.restorecon && !config.dry_run {
+        let moved: Vec<PathBuf> = moved.borrow().iter().map(|(_, d)| d.clone()).collect();
+        if let Err(err) = restorecon(&moved) {
+            print_error(err);
+        }
+    }
 
+    if let Some(manifest) = config.manifest {
+        let moved: Vec<PathBuf> = moved.borrow().iter().map(|(_, d)| d.clone()).collect();
+        write_manifest(&manifest, &moved)?;
+    }
+
+    let num_diffs = if config.diff {
+        let (diff, num_diffs) = render_diff(&planned, &moved.borrow());
+        print!("{}", diff);
+        num_diffs
+    } else {
+        0
+    };
+
+    if 0 < num_warnings.get() {
+        eprintln!("{} warning(s)", num_warnings.get());
+    }
+    if config.check {
+        let pending = planned_moves(&actions)
+            .iter()
+            .filter(|(src, dest)| src != dest)
+            .count();
+        if 0 < pending {
+            return Err(format!("{} file(s) would be moved", pending));
+        }
+    }
+    if conflicted() {
+        return Err(String::from(
+            "aborted since another process created a file at a destination",
+        ));
+    }
+    if let (true, Some(deadline)) = (timed_out.get(), &deadline) {
+        return Err(format!(
+            "{}; aborted the remaining moves",
+            deadline.error("moving files")
+        ));
+    }
+    if 0 < num_errors {
+        return Err(format!("{} move(s) failed", num_errors));
+    }
+    if 0 < num_diffs {
+        return Err(format!("{} move(s) did not go as planned", num_diffs));
+    }
     Ok(())
 }
 
@@ -171,13 +3847,28 @@ mod tests {
 
         #[test]
         fn no_match() {
-            let actions = matches_to_actions("zzzzz", "zzzzz");
+            let actions = matches_to_actions(
+                "zzzzz",
+                "zzzzz",
+                &MatchOptions::default(),
+                &TokenOptions::default(),
+                &ScanOptions::default(),
+            )
+            .unwrap()
+            .0;
             assert_eq!(actions.len(), 0);
         }
 
         #[test]
         fn multiple_matches() {
-            let mut actions = matches_to_actions("Cargo.*", "Foobar.#1");
+            let (mut actions, _) = matches_to_actions(
+                "Cargo.*",
+                "Foobar.#1",
+                &MatchOptions::default(),
+                &TokenOptions::default(),
+                &ScanOptions::default(),
+            )
+            .unwrap();
             actions.sort();
             assert_eq!(actions.len(), 2);
             assert_eq!(
@@ -197,5 +3888,169 @@ mod tests {
                 PathBuf::from("Foobar.toml")
             );
         }
+
+        #[test]
+        fn extension_filter() {
+            let actions = matches_to_actions(
+                "Cargo.*",
+                "Foobar.#1",
+                &MatchOptions::default(),
+                &TokenOptions::default(),
+                &ScanOptions {
+                    extensions: vec![String::from("TOML")],
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .0;
+            assert_eq!(actions.len(), 1);
+            assert_eq!(
+                actions[0].src().file_name().unwrap(),
+                PathBuf::from("Cargo.toml")
+            );
+        }
+
+        #[test]
+        fn unknown_token() {
+            let (actions, warnings) = matches_to_actions(
+                "Cargo.toml",
+                "#{foo}.toml",
+                &MatchOptions::default(),
+                &TokenOptions::default(),
+                &ScanOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(
+                actions[0].dest().file_name().unwrap(),
+                PathBuf::from("#{foo}.toml")
+            );
+            assert_eq!(warnings.len(), 1);
+            assert!(warnings[0].contains("#{foo}"));
+        }
+
+        #[test]
+        fn entry_filter() {
+            let count = |src_ptn: &str, entry_filter: EntryFilter| {
+                let scan_options = ScanOptions {
+                    entry_filter,
+                    ..Default::default()
+                };
+                matches_to_actions(
+                    src_ptn,
+                    "#1",
+                    &MatchOptions::default(),
+                    &TokenOptions::default(),
+                    &scan_options,
+                )
+                .unwrap()
+                .0
+                .len()
+            };
+            assert_eq!(count("Cargo.*", EntryFilter::Files), 2);
+            assert_eq!(count("Cargo.*", EntryFilter::Dirs), 0);
+            assert_eq!(count("s*", EntryFilter::Files), 0);
+            assert_eq!(count("s*", EntryFilter::Dirs), 1);
+        }
+
+        #[test]
+        fn capture_groups() {
+            let options = MatchOptions {
+                capture_groups: true,
+                ..Default::default()
+            };
+            let actions = matches_to_actions(
+                "(Cargo).(toml|json)",
+                "#2.#1",
+                &options,
+                &TokenOptions::default(),
+                &ScanOptions::default(),
+            )
+            .unwrap()
+            .0;
+            assert_eq!(actions.len(), 1);
+            assert_eq!(
+                actions[0].dest().file_name().unwrap(),
+                PathBuf::from("toml.Cargo")
+            );
+        }
+    }
+
+    mod print_renamed {
+        use super::*;
+
+        #[test]
+        fn transform() {
+            let args: Vec<OsString> = ["pmv", "--print-renamed", "src/*.txt", "doc/#1.md"]
+                .iter()
+                .map(OsString::from)
+                .collect();
+            let config = parse_args(&args, &[]).unwrap();
+            let mut output = Vec::new();
+            print_renamed(
+                &config,
+                &mut "src/a.txt\nsrc/b.rs\nsrc/写真.txt\n".as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+            let sep = std::path::MAIN_SEPARATOR;
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                format!("doc{0}a.md\nsrc/b.rs\ndoc{0}写真.md\n", sep)
+            );
+        }
+    }
+
+    mod restore_temporaries {
+        use super::*;
+
+        #[test]
+        fn restore() {
+            let dir = std::env::current_dir()
+                .unwrap()
+                .join("temp")
+                .join("restore_temporaries");
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("a.pmv0000"), "a").unwrap();
+            std::fs::write(dir.join("b.pmv0000"), "b").unwrap();
+            std::fs::write(dir.join("b"), "another b").unwrap();
+
+            let temps = vec![
+                (dir.join("a.pmv0000"), dir.join("a")),
+                (dir.join("b.pmv0000"), dir.join("b")),
+                (dir.join("c.pmv0000"), dir.join("c")),
+            ];
+            let mut moved = vec![
+                (dir.join("a"), dir.join("a.pmv0000")),
+                (dir.join("b"), dir.join("b.pmv0000")),
+            ];
+            let warnings = RefCell::new(Vec::new());
+            restore_temporaries(&temps, &mut moved, &|msg| warnings.borrow_mut().push(msg));
+
+            // "b" is taken by another file so it is left as is
+            assert_eq!(std::fs::read_to_string(dir.join("a")).unwrap(), "a");
+            assert!(dir.join("b.pmv0000").exists());
+            assert_eq!(moved, vec![(dir.join("b"), dir.join("b.pmv0000"))]);
+            assert_eq!(warnings.borrow().len(), 2);
+        }
+    }
+
+    mod has_extension {
+        use super::*;
+
+        #[test]
+        fn no_filter() {
+            assert!(has_extension(Path::new("foo"), &[]));
+            assert!(has_extension(Path::new("foo.txt"), &[]));
+        }
+
+        #[test]
+        fn filter() {
+            let exts = vec![String::from("jpg"), String::from("png")];
+            assert!(has_extension(Path::new("foo.jpg"), &exts));
+            assert!(has_extension(Path::new("foo.PNG"), &exts));
+            assert!(!has_extension(Path::new("foo.gif"), &exts));
+            assert!(!has_extension(Path::new("jpg"), &exts));
+        }
     }
 }
@@ -0,0 +1,107 @@
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes a SHA-256 manifest of the files at the given paths.
+///
+/// Each line of the manifest consists of the hash, the size in bytes and the path of a file,
+/// separated by tabs. Directories are expanded into the files under them.
+pub fn write_manifest(manifest: &Path, paths: &[PathBuf]) -> Result<(), String> {
+    let mut file = match File::create(manifest) {
+        Ok(file) => file,
+        Err(err) => {
+            return Err(format!(
+                "failed to create manifest \"{}\": {}",
+                manifest.to_string_lossy(),
+                err
+            ))
+        }
+    };
+
+    let mut lines = String::new();
+    for path in paths {
+        if let Err(err) = append_entries(path, &mut lines) {
+            return Err(format!(
+                "failed to hash \"{}\": {}",
+                path.to_string_lossy(),
+                err
+            ));
+        }
+    }
+    if let Err(err) = file.write_all(lines.as_bytes()) {
+        return Err(format!(
+            "failed to write manifest \"{}\": {}",
+            manifest.to_string_lossy(),
+            err
+        ));
+    }
+    Ok(())
+}
+
+fn append_entries(path: &Path, lines: &mut String) -> io::Result<()> {
+    if path.is_dir() {
+        let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<_>>()?;
+        entries.sort();
+        for entry in entries {
+            append_entries(&entry, lines)?;
+        }
+        return Ok(());
+    }
+
+    let (hash, size) = sha256(path)?;
+    lines.push_str(&format!("{}\t{}\t{}\n", hash, size, path.to_string_lossy()));
+    Ok(())
+}
+
+/// Computes the SHA-256 hash of a file and returns it as a hex string with the file size.
+fn sha256(path: &Path) -> io::Result<(String, u64)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher)?;
+    let hash = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok((hash, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use function_name::named;
+
+    #[named]
+    #[test]
+    fn manifest() {
+        let dir = std::env::current_dir()
+            .unwrap()
+            .join("temp")
+            .join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("d")).unwrap();
+        fs::write(dir.join("a"), "abc").unwrap();
+        fs::write(dir.join("d/b"), "").unwrap();
+
+        let manifest = dir.join("manifest.tsv");
+        write_manifest(&manifest, &[dir.join("a"), dir.join("d")]).unwrap();
+        let content = fs::read_to_string(&manifest).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                format!(
+                    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\t3\t{}",
+                    dir.join("a").to_string_lossy()
+                ),
+                format!(
+                    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\t0\t{}",
+                    dir.join("d/b").to_string_lossy()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Rules to normalize paths, shared by matching, walking and building destinations.
+//!
+//! - In DEST patterns, both `/` and `\` separate path components on every platform and they are
+//!   replaced with the separator of the platform (`\` on Windows, `/` otherwise.)
+//! - File names are compared case-insensitively for ASCII letters on Windows and
+//!   case-sensitively on other platforms.
+//! - The root directory (`/` or `\`) means the root of the drive of the directory it applies to
+//!   on Windows, so `C:\` and `\` are resolved as expected.
+//! - With `MatchOptions::normalize_unicode`, patterns and file names are compared in Unicode
+//!   Normalization Form C, so that a decomposed `é` (as macOS returns) matches a composed one.
+use std::borrow::Cow;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+
+/// Tests whether a character in a DEST pattern separates path components.
+pub fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+/// Replaces every separator in a DEST pattern with the separator of the platform.
+///
+/// ```
+/// use pmv::pathnorm::normalize_separators;
+/// use std::path::MAIN_SEPARATOR as SEP;
+///
+/// assert_eq!(normalize_separators("a/b\\c"), format!("a{}b{}c", SEP, SEP));
+/// ```
+pub fn normalize_separators(s: &str) -> String {
+    s.chars()
+        .map(|c| if is_separator(c) { MAIN_SEPARATOR } else { c })
+        .collect()
+}
+
+/// Converts a string into Unicode Normalization Form C (NFC).
+#[cfg(feature = "unicode-normalization")]
+pub(crate) fn to_nfc(s: &str) -> Cow<'_, str> {
+    use icu_normalizer::ComposingNormalizer;
+
+    let normalizer = ComposingNormalizer::new_nfc();
+    if s.is_ascii() || normalizer.is_normalized(s) {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(normalizer.normalize(s))
+    }
+}
+
+/// Converts a string into Unicode Normalization Form C (NFC), which is not available.
+#[cfg(not(feature = "unicode-normalization"))]
+pub(crate) fn to_nfc(s: &str) -> Cow<'_, str> {
+    Cow::Borrowed(s)
+}
+
+/// Tests whether two characters of file names are the same on the platform.
+pub fn chars_eq(a: char, b: char) -> bool {
+    if cfg!(windows) {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+/// Returns a key which is equal for paths differing only in case, to detect paths colliding on
+/// case-insensitive file systems.
+pub fn case_fold_key(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+/// Returns the root directory of the drive (if any) of `dir`.
+pub fn root_of(dir: &Path) -> PathBuf {
+    // A path with a root but without a prefix keeps only the prefix of `dir`
+    dir.join(MAIN_SEPARATOR.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separators() {
+        assert!(is_separator('/'));
+        assert!(is_separator('\\'));
+        assert!(!is_separator(':'));
+        let sep = MAIN_SEPARATOR;
+        assert_eq!(
+            normalize_separators("写真/a\\b"),
+            format!("写真{0}a{0}b", sep)
+        );
+    }
+
+    #[test]
+    fn case() {
+        assert!(chars_eq('a', 'a'));
+        assert_eq!(chars_eq('A', 'a'), cfg!(windows));
+        assert!(!chars_eq('é', 'É')); // Only ASCII letters are folded
+        assert_eq!(
+            case_fold_key(Path::new("Foo/BAR")),
+            case_fold_key(Path::new("foo/bar"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn root() {
+        assert_eq!(root_of(Path::new("/home/a")), PathBuf::from("/"));
+        assert_eq!(root_of(Path::new("a")), PathBuf::from("/"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn root() {
+        assert_eq!(root_of(Path::new("C:")), PathBuf::from("C:\\"));
+        assert_eq!(root_of(Path::new("D:\\a")), PathBuf::from("D:\\"));
+        assert_eq!(root_of(Path::new("a")), PathBuf::from("\\"));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::fnmatch::{CaseSensitivity, MatchOptions, NamePattern};
+use crate::pathnorm::root_of;
+use std::ffi::{OsStr, OsString};
+use std::path::{Component, Path, PathBuf};
+
+/// A SOURCE pattern compiled once to match many paths.
+///
+/// Each component of the pattern is parsed when the pattern is created, so matching does not
+/// parse it again. A `Pattern` is `Send` and `Sync` and can be shared by threads.
+///
+/// # Examples
+///
+/// ```
+/// use pmv::{MatchOptions, Pattern};
+///
+/// let pattern = Pattern::new("*/*_test.py", &MatchOptions::default()).unwrap();
+/// assert_eq!(pattern.captures(), 2);
+/// assert_eq!(
+///     pattern.matches("src/foo_test.py"),
+///     Some(vec![String::from("src"), String::from("foo")])
+/// );
+/// assert_eq!(pattern.matches("src/foo.py"), None);
+/// ```
+#[derive(Debug)]
+pub struct Pattern {
+    segments: Vec<Segment>,
+    case_sensitivity: CaseSensitivity,
+}
+
+/// A component of a compiled pattern.
+#[derive(Debug)]
+pub(crate) enum Segment {
+    Prefix(OsString),
+    RootDir,
+    CurDir,
+    ParentDir,
+    Normal(NamePattern),
+    /// `**`, matching zero or more directories. The traversed path is captured if `capture`.
+    Recursive {
+        capture: bool,
+    },
+}
+
+impl Pattern {
+    /// Compiles a pattern, failing if any component of it is malformed.
+    pub fn new(pattern: &str, options: &MatchOptions) -> Result<Pattern, String> {
+        let components: Vec<Component> = if options.regex {
+            regex_components(pattern)
+        } else {
+            Path::new(pattern).components().collect()
+        };
+        let segments = components
+            .into_iter()
+            .map(|component| {
+                Ok(match component {
+                    Component::Prefix(prefix) => Segment::Prefix(prefix.as_os_str().to_owned()),
+                    Component::RootDir => Segment::RootDir,
+                    Component::CurDir => Segment::CurDir,
+                    Component::ParentDir => Segment::ParentDir,
+                    Component::Normal(name) if name == "**" && !options.regex => {
+                        Segment::Recursive {
+                            capture: !options.capture_groups,
+                        }
+                    }
+                    Component::Normal(name) => {
+                        Segment::Normal(NamePattern::new(&name.to_string_lossy(), options)?)
+                    }
+                })
+            })
+            .collect::<Result<Vec<Segment>, String>>()?;
+        if let Some(Segment::Recursive { .. }) = segments.last() {
+            return Err(format!("`**` must be followed by a file name: {}", pattern));
+        }
+        Ok(Pattern {
+            segments,
+            case_sensitivity: options.case_sensitivity,
+        })
+    }
+
+    /// Returns the number of parts which a match yields, that is, the number of variables
+    /// available in DEST.
+    pub fn captures(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Normal(pattern) => pattern.captures(),
+                Segment::Recursive { capture } => usize::from(*capture),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Tests whether names are compared case sensitively or not.
+    ///
+    /// Names are compared case insensitively on Windows unless otherwise requested.
+    pub fn is_case_sensitive(&self) -> bool {
+        !self.case_sensitivity.ignores_case()
+    }
+
+    /// Matches a path component by component and returns matched parts.
+    ///
+    /// The path must have as many components as the pattern, except that `**` matches any
+    /// number of directories (as few as possible.) A single file name can be matched with a
+    /// pattern of a single component.
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> Option<Vec<String>> {
+        let components: Vec<Component> = path.as_ref().components().collect();
+        match_components(&self.segments, &components)
+    }
+
+    /// Returns the directory where searching starts, which is `dir` followed by the leading
+    /// components of the pattern without wildcards (excluding the last component.)
+    pub(crate) fn root(&self, dir: &Path) -> PathBuf {
+        let mut root = dir.to_path_buf();
+        let leading = &self.segments[..self.segments.len().saturating_sub(1)];
+        for segment in leading {
+            match segment {
+                Segment::Prefix(prefix) => root = PathBuf::from(prefix),
+                Segment::RootDir => root = root_of(&root),
+                Segment::CurDir => (),
+                Segment::ParentDir => {
+                    root.pop();
+                }
+                Segment::Normal(pattern) if !pattern.has_wildcard() => root.push(pattern.as_str()),
+                Segment::Normal(_) | Segment::Recursive { .. } => break,
+            }
+        }
+        root
+    }
+
+    /// Returns the compiled components.
+    pub(crate) fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+}
+
+/// Splits a pattern of regular expressions into components.
+///
+/// Only `/` separates components since `\\` escapes characters in regular expressions, even on
+/// Windows. A leading `/`, a drive prefix such as `C:` and components `.` and `..` are
+/// interpreted as in paths.
+fn regex_components(pattern: &str) -> Vec<Component<'_>> {
+    let mut components = Vec::new();
+    for (i, part) in pattern.split('/').enumerate() {
+        match part {
+            "" if (i == 0 && !pattern.is_empty())
+                || (i == 1 && matches!(components[..], [Component::Prefix(_)])) =>
+            {
+                components.push(Component::RootDir)
+            }
+            "" => (),
+            "." if components.is_empty() => components.push(Component::CurDir),
+            "." => (),
+            ".." => components.push(Component::ParentDir),
+            _ => match Path::new(part).components().next() {
+                Some(prefix @ Component::Prefix(_)) if i == 0 => components.push(prefix),
+                _ => components.push(Component::Normal(OsStr::new(part))),
+            },
+        }
+    }
+    components
+}
+
+fn match_components(segments: &[Segment], components: &[Component]) -> Option<Vec<String>> {
+    let (segment, segments) = match segments.split_first() {
+        Some(first) => first,
+        None if components.is_empty() => return Some(Vec::new()),
+        None => return None,
+    };
+    if let Segment::Recursive { capture } = segment {
+        for n in 0..components.len() {
+            let dirs = &components[..n];
+            if !dirs.iter().all(|c| matches!(c, Component::Normal(_))) {
+                break;
+            }
+            if let Some(parts) = match_components(segments, &components[n..]) {
+                let mut matched_parts = Vec::new();
+                if *capture {
+                    let dirs: PathBuf = dirs.iter().collect();
+                    matched_parts.push(dirs.to_string_lossy().into_owned());
+                }
+                matched_parts.extend(parts);
+                return Some(matched_parts);
+            }
+        }
+        return None;
+    }
+
+    let (component, components) = components.split_first()?;
+    let mut matched_parts = match (segment, component) {
+        (Segment::Normal(pattern), Component::Normal(name)) => {
+            pattern.matches(&name.to_string_lossy())?
+        }
+        (Segment::Prefix(a), Component::Prefix(b)) if a == b.as_os_str() => Vec::new(),
+        (Segment::RootDir, Component::RootDir)
+        | (Segment::CurDir, Component::CurDir)
+        | (Segment::ParentDir, Component::ParentDir) => Vec::new(),
+        _ => return None,
+    };
+    matched_parts.extend(match_components(segments, components)?);
+    Some(matched_parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Pattern>();
+    }
+
+    #[test]
+    fn captures() {
+        let options = MatchOptions::default();
+        assert_eq!(Pattern::new("a/b", &options).unwrap().captures(), 0);
+        assert_eq!(Pattern::new("*/?_*", &options).unwrap().captures(), 3);
+
+        let options = MatchOptions {
+            capture_groups: true,
+            ..Default::default()
+        };
+        let pattern = Pattern::new("(*)_*.(mp4|mov)", &options).unwrap();
+        assert_eq!(pattern.captures(), 2);
+        assert_eq!(
+            pattern.matches("a_final.mov"),
+            Some(vec![String::from("a"), String::from("mov")])
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let options = MatchOptions {
+            capture_groups: true,
+            ..Default::default()
+        };
+        assert!(Pattern::new("a/(b", &options).is_err());
+        assert!(Pattern::new("a/**", &options).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn root() {
+        let options = MatchOptions::default();
+        let dir = Path::new("/home/foo");
+        let root = |pattern: &str| Pattern::new(pattern, &options).unwrap().root(dir);
+        assert_eq!(root("photos/*/*.jpg"), PathBuf::from("/home/foo/photos"));
+        assert_eq!(root("../a/b.txt"), PathBuf::from("/home/a"));
+        assert_eq!(root("*.txt"), PathBuf::from("/home/foo"));
+    }
+
+    #[test]
+    fn match_paths() {
+        let pattern = Pattern::new("../*/x?", &MatchOptions::default()).unwrap();
+        assert_eq!(
+            pattern.matches("../a/x1"),
+            Some(vec![String::from("a"), String::from("1")])
+        );
+        assert_eq!(pattern.matches("./a/x1"), None);
+        assert_eq!(pattern.matches("a/x1"), None);
+        assert_eq!(pattern.matches("../a/b/x1"), None);
+    }
+
+    #[test]
+    fn match_regex() {
+        let options = MatchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        let pattern = Pattern::new(r"(\d{4})/IMG_(\d+)\.jpe?g", &options).unwrap();
+        assert_eq!(pattern.captures(), 2);
+        assert_eq!(
+            pattern.matches("2023/IMG_0123.jpeg"),
+            Some(vec![String::from("2023"), String::from("0123")])
+        );
+        assert_eq!(pattern.matches("2023/IMG_0123.png"), None);
+        assert_eq!(pattern.matches("2023/x/IMG_0123.jpg"), None);
+
+        // Literal leading components are the root
+        let pattern = Pattern::new("./photos/.*", &options).unwrap();
+        let curdir = PathBuf::from("/tmp");
+        assert_eq!(pattern.root(&curdir), curdir.join("photos"));
+
+        let pattern = Pattern::new("/a/.*", &options).unwrap();
+        assert_eq!(pattern.matches("/a/b"), Some(vec![]));
+        assert!(Pattern::new("a/(b", &options).is_err());
+    }
+
+    #[test]
+    fn match_recursive() {
+        let pattern = Pattern::new("src/**/*.rs", &MatchOptions::default()).unwrap();
+        assert_eq!(pattern.captures(), 2);
+        let sep = std::path::MAIN_SEPARATOR;
+        assert_eq!(
+            pattern.matches("src/a/b/x.rs"),
+            Some(vec![format!("a{}b", sep), String::from("x")])
+        );
+        assert_eq!(
+            pattern.matches("src/x.rs"),
+            Some(vec![String::from(""), String::from("x")])
+        );
+        assert_eq!(pattern.matches("lib/a/x.rs"), None);
+        assert_eq!(pattern.matches("src/a/x.py"), None);
+
+        let options = MatchOptions {
+            capture_groups: true,
+            ..Default::default()
+        };
+        let pattern = Pattern::new("**/(*).rs", &options).unwrap();
+        assert_eq!(pattern.captures(), 1);
+        assert_eq!(pattern.matches("a/b/x.rs"), Some(vec![String::from("x")]));
+    }
+}
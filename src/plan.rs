@@ -1,40 +1,41 @@
-use crate::Action;
-use rand::random;
-use std::collections::HashSet;
+use crate::pathnorm::{case_fold_key, is_separator};
+use crate::{Action, MatchOptions, Pattern};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
 use std::path::{Path, PathBuf, MAIN_SEPARATOR};
 
 /// Sorts actions in safe order.
 ///
 /// This function fails if no safe order was not found.
 pub fn sort_actions(actions: &[Action]) -> Result<Vec<Action>, String> {
-    let mut actions: Vec<&Action> = actions.iter().collect();
-    let mut sorted: Vec<Action> = Vec::new();
-
-    // Fail if any pair of actions share a source or a destination.
-    // (that means executing them results data loss)
-    let mut src_paths = HashSet::new();
-    let mut dest_paths = HashSet::new();
-    for action in &actions {
-        let first_entry = src_paths.insert(action.src());
-        if !first_entry {
-            let msg = format!(
-                "cannot move a file to multiple destinations: '{}'",
-                action.src().to_string_lossy()
-            );
-            return Err(msg);
-        }
+    sort_actions_with(actions, &TempNaming::default())
+}
 
-        let first_entry = dest_paths.insert(action.dest());
-        if !first_entry {
-            let msg = format!(
-                "cannot move multiple files to a same location: '{}'",
-                action.dest().to_string_lossy()
-            );
-            return Err(msg);
-        }
-    }
+/// Sorts actions in safe order like `sort_actions`, naming temporary files as specified.
+pub fn sort_actions_with(actions: &[Action], naming: &TempNaming) -> Result<Vec<Action>, String> {
+    Ok(sort_chains(actions, naming)?
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+/// Sorts actions in safe order, keeping each chain of dependent actions separated.
+///
+/// Each chain is in the order of execution; an action moves a file to where the previous one
+/// has just vacated. A circular chain starts and ends with the actions moving a file to and
+/// from a temporary name. Concatenating the chains gives the result of `sort_actions`.
+pub(crate) fn sort_chains(
+    actions: &[Action],
+    naming: &TempNaming,
+) -> Result<Vec<Vec<Action>>, String> {
+    check_conflicts(actions)?;
+    let mut namer = TempNamer::new(naming, actions);
+    let mut actions: Vec<&Action> = actions.iter().collect();
+    let mut chains: Vec<Vec<Action>> = Vec::new();
 
     while !actions.is_empty() {
+        let mut sorted = Vec::new();
+
         // Pull a chain starting with the first actions.
         let mut indices = pull_a_chain(&actions)?;
         debug_assert!(!indices.is_empty());
@@ -55,7 +56,7 @@ pub fn sort_actions(actions: &[Action]) -> Result<Vec<Action>, String> {
             // To do that, firstly we resolve a temporary backup file name.
             let first = actions[indices[0]];
             let last = actions[*indices.last().unwrap()];
-            let tmp = match make_safeish_filename(first.src()) {
+            let tmp = match namer.name(first.src()) {
                 Some(path) => path,
                 None => {
                     return Err(format!(
@@ -64,16 +65,18 @@ pub fn sort_actions(actions: &[Action]) -> Result<Vec<Action>, String> {
                     ))
                 }
             };
-            sorted.push(Action::new(last.src(), tmp.clone()));
+            let label = "temporary name to break a cycle";
+            sorted.push(Action::new(last.src(), tmp.clone()).with_label(label));
             for i in indices.iter().rev().skip(1) {
                 sorted.push(actions[*i].clone());
             }
-            sorted.push(Action::new(tmp, first.src())); // moving "tmp"
+            sorted.push(Action::new(tmp, first.src()).with_label(label)); // moving "tmp"
         } else {
             for i in indices.iter().rev() {
                 sorted.push(actions[*i].clone());
             }
         }
+        chains.push(sorted);
 
         // Now remove the pulled and copied actions from the source.
         indices.sort_unstable();
@@ -82,30 +85,401 @@ pub fn sort_actions(actions: &[Action]) -> Result<Vec<Action>, String> {
         }
     }
 
-    Ok(sorted)
+    Ok(chains)
 }
 
-/// Makes a safe-ish filename which does not conflict with no other files.
+/// Fails if any pair of actions share a source or a destination.
+/// (that means executing them results data loss)
 ///
-/// This function is basically UNSAFE as it checks for an pre-existing files without creating a
-/// file.
-fn make_safeish_filename<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
-    let orig_path = path.as_ref();
-    let orig_path_str = orig_path.as_os_str();
+/// The error lists the chains of moves which the conflicting actions belong to, so that the
+/// user can see how the moves interact.
+fn check_conflicts(actions: &[Action]) -> Result<(), String> {
+    let mut src_paths = HashMap::new();
+    let mut dest_paths = HashMap::new();
+    for (i, action) in actions.iter().enumerate() {
+        if let Some(&j) = src_paths.get(action.src()) {
+            let msg = format!(
+                "cannot move a file to multiple destinations: '{}'\n{}\n{}",
+                action.src().to_string_lossy(),
+                describe_chain(actions, j),
+                describe_chain(actions, i)
+            );
+            return Err(msg);
+        }
+        src_paths.insert(action.src(), i);
 
-    // Search for a safe-ish filename with random postfix
-    let n: u16 = random();
-    for i in (n..65535).chain(0..n) {
-        let mut new_path_str = orig_path_str.to_owned();
-        new_path_str.push(format!(".pmv{:04x}", i));
-        let new_path = Path::new(&new_path_str);
-        if !new_path.exists() {
-            return Some(new_path_str.into()); // move
+        if let Some(&j) = dest_paths.get(action.dest()) {
+            let msg = format!(
+                "cannot move multiple files to a same location: '{}'\n{}\n{}",
+                action.dest().to_string_lossy(),
+                describe_chain(actions, j),
+                describe_chain(actions, i)
+            );
+            return Err(msg);
         }
+        dest_paths.insert(action.dest(), i);
     }
+    Ok(())
+}
 
-    // No filename was available.
-    None
+/// Describes the chain of moves which the i-th action belongs to.
+///
+/// The chain is written like `[1] 'A' --> [3] 'B' --> 'C'`, where each number is the ID of the
+/// action moving the path next to it (or its 1-based index if it has no ID.) A chain returning
+/// to where it started ends with `(cycle)`.
+fn describe_chain(actions: &[Action], i: usize) -> String {
+    // Go back to the start of the chain
+    let mut start = i;
+    let mut visited = HashSet::new();
+    visited.insert(i);
+    while let Some(prev) = actions
+        .iter()
+        .position(|a| a.dest() == actions[start].src())
+    {
+        if !visited.insert(prev) {
+            break;
+        }
+        start = prev;
+    }
+
+    // Then follow the chain forward
+    let mut chain = String::from(" ");
+    let mut curr = start;
+    let mut visited = HashSet::new();
+    loop {
+        visited.insert(curr);
+        let action = &actions[curr];
+        chain.push_str(&format!(
+            " [{}] '{}' -->",
+            action.id().unwrap_or(curr + 1),
+            action.src().to_string_lossy()
+        ));
+        match actions.iter().position(|a| a.src() == action.dest()) {
+            Some(next) if !visited.contains(&next) => curr = next,
+            next => {
+                chain.push_str(&format!(" '{}'", action.dest().to_string_lossy()));
+                if next.is_some() {
+                    chain.push_str(" (cycle)");
+                }
+                return chain;
+            }
+        }
+    }
+}
+
+/// Splits actions into two phases which move files via temporary names.
+///
+/// The first phase moves every file to a unique temporary name in its destination directory
+/// and the second phase renames the temporaries to the final names. This needs twice as many
+/// renames but no ordering is required in each phase.
+pub fn split_via_temp(actions: &[Action]) -> Result<(Vec<Action>, Vec<Action>), String> {
+    split_via_temp_with(actions, &TempNaming::default())
+}
+
+/// Splits actions into two phases like `split_via_temp`, naming temporary files as specified.
+pub fn split_via_temp_with(
+    actions: &[Action],
+    naming: &TempNaming,
+) -> Result<(Vec<Action>, Vec<Action>), String> {
+    check_conflicts(actions)?;
+    let mut namer = TempNamer::new(naming, actions);
+    let mut first = Vec::new();
+    let mut second = Vec::new();
+    for action in actions {
+        let dest = action.final_dest();
+        let tmp = match namer.name(&dest) {
+            Some(path) => path,
+            None => {
+                return Err(format!(
+                    "temporary filename unavailable for {}",
+                    dest.to_string_lossy()
+                ))
+            }
+        };
+        first.push(Action::new(action.src(), tmp.clone()).with_label("via temporary name (1/2)"));
+        second.push(Action::new(tmp, dest).with_label("via temporary name (2/2)"));
+    }
+    Ok((first, second))
+}
+
+/// Splits actions into two phases which move files via a staging directory.
+///
+/// The first phase moves every file into `stage` and the second phase moves them from there to
+/// their final locations. Unlike `split_via_temp`, the tree looks unchanged until the second
+/// phase starts, which is short as it consists only of renames on the same file system.
+pub(crate) fn split_via_stage(
+    actions: &[Action],
+    stage: &Path,
+) -> Result<(Vec<Action>, Vec<Action>), String> {
+    check_conflicts(actions)?;
+    let mut first = Vec::new();
+    let mut second = Vec::new();
+    for (i, action) in actions.iter().enumerate() {
+        let dest = action.final_dest();
+        // Prefix an index so that files of the same name do not clash in the staging directory
+        let mut name = OsString::from(format!("{}_", i + 1));
+        name.push(dest.file_name().unwrap_or_default());
+        let staged = stage.join(name);
+        first.push(
+            Action::new(action.src(), staged.clone()).with_label("via staging directory (1/2)"),
+        );
+        second.push(Action::new(staged, dest).with_label("via staging directory (2/2)"));
+    }
+    Ok((first, second))
+}
+
+/// Returns a new staging directory for the actions.
+///
+/// It is placed in the deepest existing directory containing all the destinations so that it is
+/// on the same file system as them, and is named `.pmv-stage` followed by a number.
+pub(crate) fn staging_dir(actions: &[Action]) -> Option<PathBuf> {
+    let mut dirs = actions.iter().filter_map(|a| a.dest().parent());
+    let mut common = dirs.next()?.to_path_buf();
+    for dir in dirs {
+        while !dir.starts_with(&common) {
+            if !common.pop() {
+                return None;
+            }
+        }
+    }
+    while !common.is_dir() {
+        if !common.pop() {
+            return None;
+        }
+    }
+    TempNamer::new(&TempNaming::default(), &[]).name(&common.join(".pmv-stage"))
+}
+
+/// Makes a name of a temporary file for the file at `path`, which is none of the paths in
+/// `actions`.
+pub(crate) fn temp_name(path: &Path, actions: &[Action]) -> Option<PathBuf> {
+    TempNamer::new(&TempNaming::default(), actions).name(path)
+}
+
+/// What to do when multiple actions share a destination.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Fails without moving any file.
+    #[default]
+    Fail,
+    /// Moves only the first file and skips the others.
+    Skip,
+    /// Appends a number to the name of the other files (e.g. `a_2.txt`).
+    Suffix,
+}
+
+/// Resolves destinations shared by multiple actions according to the policy.
+///
+/// Returns the actions to execute and the actions skipped. With `OnConflict::Fail`, the actions
+/// are returned as they are so that `sort_actions` reports the conflict.
+pub fn resolve_conflicts(actions: Vec<Action>, policy: OnConflict) -> (Vec<Action>, Vec<Action>) {
+    if policy == OnConflict::Fail {
+        return (actions, Vec::new());
+    }
+
+    let mut dests: HashSet<PathBuf> = actions.iter().map(|a| a.dest().to_owned()).collect();
+    // Files which are already at their destination keep their names
+    let mut seen: HashSet<PathBuf> = actions
+        .iter()
+        .filter(|a| a.src() == a.dest())
+        .map(|a| a.dest().to_owned())
+        .collect();
+    let mut resolved = Vec::new();
+    let mut skipped = Vec::new();
+    for action in actions {
+        if action.src() == action.dest() || seen.insert(action.dest().to_owned()) {
+            resolved.push(action);
+            continue;
+        }
+        if policy == OnConflict::Skip {
+            skipped.push(action);
+            continue;
+        }
+        let dest = (2..)
+            .map(|n| with_number(action.dest(), n))
+            .find(|dest| !dests.contains(dest) && !dest.exists())
+            .unwrap();
+        dests.insert(dest.clone());
+        seen.insert(dest.clone());
+        resolved.push(Action::new(action.src(), dest).with_label("numbered to avoid a name clash"));
+    }
+    (resolved, skipped)
+}
+
+/// Appends a number to the file stem (e.g. `a.txt` becomes `a_2.txt`.)
+fn with_number(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// Finds actions of which source is the same file as a source of another action.
+///
+/// Sources are the same file if they are hard links to the same inode or if they are (or are
+/// symbolic links to) the same file. Returns pairs of the index of the first action and the
+/// index of the action duplicating it.
+pub fn find_aliased_sources(actions: &[Action]) -> Vec<(usize, usize)> {
+    let mut first_seen: HashMap<FileId, usize> = HashMap::new();
+    let mut pairs = Vec::new();
+    for (i, action) in actions.iter().enumerate() {
+        if let Some(id) = file_id(action.src()) {
+            match first_seen.get(&id) {
+                Some(first) => pairs.push((*first, i)),
+                None => {
+                    first_seen.insert(id, i);
+                }
+            }
+        }
+    }
+    pairs
+}
+
+/// Finds actions of which destination differs from a destination of another action only in
+/// case, which collide on case-insensitive file systems.
+///
+/// Returns pairs of the index of the first action and the index of the action colliding with it.
+pub fn find_case_collisions(actions: &[Action]) -> Vec<(usize, usize)> {
+    let mut first_seen: HashMap<String, usize> = HashMap::new();
+    let mut pairs = Vec::new();
+    for (i, action) in actions.iter().enumerate() {
+        let key = case_fold_key(action.dest());
+        match first_seen.get(&key) {
+            Some(first) if actions[*first].dest() != action.dest() => pairs.push((*first, i)),
+            Some(_) => (),
+            None => {
+                first_seen.insert(key, i);
+            }
+        }
+    }
+    pairs
+}
+
+/// Finds actions of which destination built from matched parts happens to be an existing
+/// directory, so the file would be moved into the directory instead of being renamed to it.
+///
+/// Destinations written literally in DEST (e.g. `backup` or `backup/`) are not reported as the
+/// user evidently means the directory.
+pub(crate) fn find_shadowing_dirs(actions: &[Action], dest_ptn: &str) -> Vec<usize> {
+    let last = dest_ptn.rsplit(is_separator).next().unwrap_or_default();
+    if !last.contains('#') {
+        return Vec::new();
+    }
+    actions
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| a.final_dest() != a.dest() && a.src() != a.dest())
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+/// Returns an identifier of the file (following symbolic links).
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = path.metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Returns an identifier of the file (following symbolic links).
+#[cfg(not(unix))]
+fn file_id(path: &Path) -> Option<FileId> {
+    path.canonicalize().ok()
+}
+
+/// The name of the directory holding temporary files with `TempNaming::in_dir`.
+pub(crate) const TEMP_DIR: &str = ".pmv-tmp";
+
+/// How to name the temporary files used to break cycles and by `split_via_temp`.
+///
+/// By default, a temporary file is named after the original file followed by `.pmv` and a
+/// hexadecimal number, such as `a.txt.pmv0000`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TempNaming {
+    /// Names a temporary file with this followed by the original name instead, such as
+    /// `.~a.txt` for `.~`. A number is appended only if the name is taken, such as `.~a.txt.1`.
+    pub prefix: Option<String>,
+    /// Places temporary files in a `.pmv-tmp` directory next to the original files, named
+    /// like with `prefix`. The caller must create the directory before moving files.
+    pub in_dir: bool,
+}
+
+/// Makes filenames for temporary files which conflict with no other files.
+///
+/// Names are numbered from zero, so the same plan gets the same names as long as the file
+/// system is unchanged. Besides existing files, a name is never one of the paths given on
+/// creation or one made before. Note that this is basically UNSAFE as it checks for
+/// pre-existing files without creating a file.
+struct TempNamer<'a> {
+    naming: &'a TempNaming,
+    taken: HashSet<PathBuf>,
+}
+
+impl<'a> TempNamer<'a> {
+    fn new(naming: &'a TempNaming, actions: &[Action]) -> TempNamer<'a> {
+        let taken = actions
+            .iter()
+            .flat_map(|a| [a.src().to_owned(), a.dest().to_owned()])
+            .collect();
+        TempNamer { naming, taken }
+    }
+
+    /// Makes a name of a temporary file for the file at `path`.
+    fn name(&mut self, path: &Path) -> Option<PathBuf> {
+        let mut dir = path.parent().unwrap_or_else(|| Path::new("")).to_owned();
+        if self.naming.in_dir {
+            dir.push(TEMP_DIR);
+        }
+        let name = path.file_name()?;
+        for i in 0..=u16::MAX {
+            let mut new_name = OsString::new();
+            if self.naming.prefix.is_none() && !self.naming.in_dir {
+                new_name.push(name);
+                new_name.push(format!(".pmv{:04x}", i));
+            } else {
+                new_name.push(self.naming.prefix.as_deref().unwrap_or_default());
+                new_name.push(name);
+                if 0 < i {
+                    new_name.push(format!(".{}", i));
+                }
+            }
+            let new_path = dir.join(new_name);
+            if new_path.symlink_metadata().is_err() && !self.taken.contains(&new_path) {
+                self.taken.insert(new_path.clone());
+                return Some(new_path);
+            }
+        }
+
+        // No filename was available.
+        None
+    }
+}
+
+/// Returns the temporary paths which files pass through while executing `phases`, each paired
+/// with the path of the file before the move to it.
+///
+/// These are the destinations of the moves which are not destinations in `actions`, the plan
+/// before it was sorted or split. This must be called before moving files as it looks up which
+/// destinations are directories.
+pub(crate) fn temporaries(actions: &[Action], phases: &[Vec<Action>]) -> Vec<(PathBuf, PathBuf)> {
+    let dests: HashSet<PathBuf> = actions
+        .iter()
+        .flat_map(|a| [a.dest().to_owned(), a.final_dest()])
+        .collect();
+    phases
+        .iter()
+        .flatten()
+        .filter(|a| !dests.contains(a.dest()))
+        .map(|a| (a.dest().to_owned(), a.src().to_owned()))
+        .collect()
 }
 
 /// Enumerates a chain of moving actions which must be done in reversed order.
@@ -156,9 +530,66 @@ fn pull_a_chain(actions: &[&Action]) -> Result<Vec<usize>, String> {
 /// `substrings`).
 ///
 /// Note that up to 9 variables (i.e.: `#1` to `#9`) are supported.
+///
+/// A variable can also be written as `#{n}`, and `#{n:?THEN:ELSE}` is replaced
+/// with `THEN` if the n-th substring is not empty, or with `ELSE` otherwise
+/// (e.g.: `#{1:?prefix_#1:unnamed}`). `THEN` and `ELSE` may contain variables, braced
+/// ones included.
 pub fn substitute_variables(dest: &str, substrings: &[String]) -> String {
+    let substrings: Vec<OsString> = substrings.iter().map(OsString::from).collect();
+    substitute_tokens(dest, &substrings, &|_, _| Ok(None))
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Builds the destination of a single path without walking a directory tree.
+///
+/// This matches each component of `src_path` with the corresponding component of `src_pattern`
+/// and substitutes variables in `dest_pattern` with the matched parts, as the `pmv` command does
+/// for each file it found. Returns `None` if the path does not match the pattern.
+///
+/// ```
+/// use pmv::rename_pattern;
+/// use std::path::PathBuf;
+///
+/// assert_eq!(
+///     rename_pattern("src/foo_test.py", "*/*_test.py", "tests/test_#2.py"),
+///     Some(PathBuf::from("tests/test_foo.py"))
+/// );
+/// assert_eq!(rename_pattern("src/foo.py", "*/*_test.py", "#1"), None);
+/// ```
+pub fn rename_pattern<P: AsRef<Path>>(
+    src_path: P,
+    src_pattern: &str,
+    dest_pattern: &str,
+) -> Option<PathBuf> {
+    let pattern = Pattern::new(src_pattern, &MatchOptions::default()).ok()?;
+    let matched_parts = pattern.matches(src_path)?;
+    Some(PathBuf::from(substitute_variables(
+        dest_pattern,
+        &matched_parts,
+    )))
+}
+
+/// A function which returns the replacement for a `#{name:arg}` token.
+pub(crate) type Resolver<'a> = dyn Fn(&str, Option<&str>) -> Result<Option<String>, String> + 'a;
+
+/// Substitute variables and tokens.
+///
+/// In addition to what [`substitute_variables`] does, this function replaces
+/// every token notation `#{name}` or `#{name:arg}` with what `resolve`
+/// returns for it. Tokens for which `resolve` returns `None` are left as is.
+///
+/// Substrings are copied as they are even if they are not valid Unicode.
+pub(crate) fn substitute_tokens(
+    dest: &str,
+    substrings: &[OsString],
+    resolve: &Resolver<'_>,
+) -> Result<OsString, String> {
+    let text = dest;
     let dest = dest.as_bytes();
-    let mut substituted = String::new();
+    let mut substituted = OsString::new();
     let mut i = 0;
     while i < dest.len() {
         if dest[i] == b'#' && i + 1 < dest.len() && b'1' <= dest[i + 1] && dest[i + 1] <= b'9' {
@@ -166,23 +597,168 @@ pub fn substitute_variables(dest: &str, substrings: &[String]) -> String {
             let replacement = match substrings.get(index) {
                 Some(s) => s,
                 None => {
-                    substituted.push('#');
-                    substituted.push(dest[i + 1] as char);
+                    substituted.push("#");
+                    substituted.push((dest[i + 1] as char).to_string());
                     i += 2;
                     continue;
                 }
             };
-            substituted.push_str(replacement);
+            substituted.push(replacement);
             i += 2;
-        } else if dest[i] == b'\\' || dest[i] == b'/' {
-            substituted.push(MAIN_SEPARATOR);
+        } else if dest[i] == b'#' && i + 1 < dest.len() && dest[i + 1] == b'{' {
+            let end = match find_unnested(&text[i + 2..], '}') {
+                Some(n) => i + 2 + n,
+                None => {
+                    substituted.push("#{");
+                    i += 2;
+                    continue;
+                }
+            };
+            let content = &text[i + 2..end];
+            let (name, arg) = match content.find(':') {
+                Some(n) => (&content[..n], Some(&content[n + 1..])),
+                None => (content, None),
+            };
+            let replacement = match substitute_capture(name, arg, substrings, resolve)? {
+                Some(replacement) => Some(replacement),
+                None => resolve(name, arg)?.map(OsString::from),
+            };
+            match replacement {
+                Some(replacement) => substituted.push(replacement),
+                None => substituted.push(&text[i..=end]),
+            }
+            i = end + 1;
+        } else if is_separator(dest[i] as char) {
+            substituted.push(MAIN_SEPARATOR.to_string());
             i += 1;
         } else {
-            substituted.push(dest[i] as char);
-            i += 1;
+            // Copy a whole character so that multi-byte characters are kept intact
+            let c = text[i..].chars().next().unwrap_or_default();
+            substituted.push(c.encode_utf8(&mut [0; 4]));
+            i += c.len_utf8();
+        }
+    }
+    Ok(substituted)
+}
+
+/// Substitute a `#{n}` or `#{n:?THEN:ELSE}` token.
+///
+/// Returns `None` if `name` is not a variable index or `arg` is not a conditional.
+fn substitute_capture(
+    name: &str,
+    arg: Option<&str>,
+    substrings: &[OsString],
+    resolve: &Resolver<'_>,
+) -> Result<Option<OsString>, String> {
+    let index = match name.as_bytes() {
+        [c] if b'1' <= *c && *c <= b'9' => (c - b'1') as usize,
+        _ => return Ok(None),
+    };
+    let arg = match arg {
+        None => return Ok(substrings.get(index).cloned()),
+        Some(arg) => arg,
+    };
+    let branches = match arg.strip_prefix('?') {
+        Some(branches) => branches,
+        None => {
+            let substring = match substrings.get(index) {
+                Some(s) => s,
+                None => return Ok(None),
+            };
+            // Filters work on text, so a substring which is not valid Unicode is kept as is
+            return Ok(match substring.to_str() {
+                Some(s) => apply_filter(s, arg).map(OsString::from),
+                None => apply_filter(&substring.to_string_lossy(), arg).map(|_| substring.clone()),
+            });
+        }
+    };
+    let (then, otherwise) = match find_unnested(branches, ':') {
+        Some(n) => (&branches[..n], &branches[n + 1..]),
+        None => (branches, ""),
+    };
+    let is_empty = substrings.get(index).is_none_or(|s| s.is_empty());
+    let branch = if is_empty { otherwise } else { then };
+    substitute_tokens(branch, substrings, resolve).map(Some)
+}
+
+/// Returns the index of the first `c` in `text` which is not enclosed in braces, so that tokens
+/// such as `#{1:upper}` can be nested in a conditional.
+fn find_unnested(text: &str, c: char) -> Option<usize> {
+    let mut depth = 0_usize;
+    for (i, ch) in text.char_indices() {
+        match ch {
+            _ if ch == c && depth == 0 => return Some(i),
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Converts a substring with a filter.
+///
+/// `alpha` converts `1`, `2`, ..., `26`, `27` to `a`, `b`, ..., `z`, `aa` and `roman` converts
+/// numbers up to 3999 to Roman numerals (`ALPHA` and `ROMAN` for uppercase.) Substrings which
+/// cannot be converted are kept as is. `lower` and `upper` convert the case of any substring.
+/// Returns `None` if the filter is unknown.
+fn apply_filter(substring: &str, filter: &str) -> Option<String> {
+    let converted = match filter {
+        "lower" => return Some(substring.to_lowercase()),
+        "upper" => return Some(substring.to_uppercase()),
+        "alpha" | "ALPHA" => substring.parse().ok().and_then(to_alpha),
+        "roman" | "ROMAN" => substring.parse().ok().and_then(to_roman),
+        _ => return None,
+    };
+    let converted = converted.unwrap_or_else(|| String::from(substring));
+    if filter.chars().all(|c| c.is_ascii_uppercase()) {
+        Some(converted.to_ascii_uppercase())
+    } else {
+        Some(converted)
+    }
+}
+
+fn to_alpha(mut n: u64) -> Option<String> {
+    if n == 0 {
+        return None;
+    }
+    let mut letters = Vec::new();
+    while 0 < n {
+        n -= 1;
+        letters.push(b'a' + (n % 26) as u8);
+        n /= 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).ok()
+}
+
+fn to_roman(mut n: u64) -> Option<String> {
+    const NUMERALS: [(u64, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    if n == 0 || 3999 < n {
+        return None;
+    }
+    let mut roman = String::new();
+    for (value, numeral) in NUMERALS.iter() {
+        while *value <= n {
+            roman.push_str(numeral);
+            n -= value;
         }
     }
-    substituted
+    Some(roman)
 }
 
 #[cfg(test)]
@@ -204,6 +780,7 @@ mod tests {
         // - dest
         //   - empty
         //   - no variables
+        //   - non-ASCII literals
         //   - sharp
         //   - sharp 0
         //   - sharp 1
@@ -221,7 +798,7 @@ mod tests {
         static SEP: char = MAIN_SEPARATOR;
 
         fn default_substrs() -> Vec<String> {
-            vec!["v1", "v2", "v3", "v4", "v5", "v6", "v7", "v8", "v9", "vX"]
+            ["v1", "v2", "v3", "v4", "v5", "v6", "v7", "v8", "v9", "vX"]
                 .iter()
                 .map(|x| String::from(*x))
                 .collect::<Vec<_>>()
@@ -244,6 +821,20 @@ mod tests {
             );
         }
 
+        #[test]
+        fn dest_non_ascii() {
+            let substrs = [String::from("旅行"), String::from("😀")];
+            assert_eq!(
+                substitute_variables("写真/#1.jpg", &substrs[..]),
+                format!("写真{}旅行.jpg", SEP)
+            );
+            assert_eq!(
+                substitute_variables("🎉#2_é_#{1:?済:未}", &substrs[..]),
+                String::from("🎉😀_é_済")
+            );
+            assert_eq!(substitute_variables("#写#", &substrs[..]), "#写#");
+        }
+
         #[test]
         fn dest_sharp() {
             let dest = "/foo/bar/#";
@@ -337,10 +928,7 @@ mod tests {
         #[test]
         fn dest_var_index_out_of_range() {
             let dest = "/foo/#3/#1#2.txt";
-            let substrs = vec!["v1"]
-                .iter()
-                .map(|x| String::from(*x))
-                .collect::<Vec<_>>();
+            let substrs = ["v1"].iter().map(|x| String::from(*x)).collect::<Vec<_>>();
             assert_eq!(
                 substitute_variables(dest, &substrs[..]),
                 format!("{}foo{}#3{}v1#2.txt", SEP, SEP, SEP)
@@ -370,10 +958,7 @@ mod tests {
         #[test]
         fn substrs_one() {
             let dest = "foo/#1/baz";
-            let substrs = vec!["v1"]
-                .iter()
-                .map(|x| String::from(*x))
-                .collect::<Vec<_>>();
+            let substrs = ["v1"].iter().map(|x| String::from(*x)).collect::<Vec<_>>();
             assert_eq!(
                 substitute_variables(dest, &substrs[..]),
                 format!("foo{}v1{}baz", SEP, SEP)
@@ -383,7 +968,7 @@ mod tests {
         #[test]
         fn substrs_two() {
             let dest = "foo/#1/#2";
-            let substrs = vec!["v1", "v2"]
+            let substrs = ["v1", "v2"]
                 .iter()
                 .map(|x| String::from(*x))
                 .collect::<Vec<_>>();
@@ -396,7 +981,7 @@ mod tests {
         #[test]
         fn substrs_invalid_char() {
             let dest = "foo/#1/#2";
-            let substrs = vec!["/", "/"]
+            let substrs = ["/", "/"]
                 .iter()
                 .map(|x| String::from(*x))
                 .collect::<Vec<_>>();
@@ -407,6 +992,207 @@ mod tests {
         }
     }
 
+    mod rename_pattern {
+        use super::*;
+
+        static SEP: char = MAIN_SEPARATOR;
+
+        #[test]
+        fn matched() {
+            assert_eq!(
+                rename_pattern("a/b_c.txt", "?/*_*.txt", "#3/#1#2.txt"),
+                Some(PathBuf::from(format!("c{}ab.txt", SEP)))
+            );
+        }
+
+        #[test]
+        fn not_matched() {
+            assert_eq!(rename_pattern("a/b.txt", "?/*_*.txt", "#1"), None);
+            assert_eq!(rename_pattern("b_c.txt", "?/*_*.txt", "#1"), None);
+            assert_eq!(rename_pattern("/a/b_c.txt", "a/*_*.txt", "#1"), None);
+        }
+    }
+
+    mod substitute_capture {
+        use super::*;
+
+        static SEP: char = MAIN_SEPARATOR;
+
+        fn substrs(values: &[&str]) -> Vec<String> {
+            values.iter().map(|x| String::from(*x)).collect()
+        }
+
+        #[test]
+        fn braced() {
+            assert_eq!(
+                substitute_variables("#{1}0#{2}", &substrs(&["a", "b"])),
+                String::from("a0b")
+            );
+        }
+
+        #[test]
+        fn conditional_non_empty() {
+            assert_eq!(
+                substitute_variables("#{1:?prefix_#1:unnamed}.txt", &substrs(&["foo"])),
+                String::from("prefix_foo.txt")
+            );
+        }
+
+        #[test]
+        fn conditional_empty() {
+            assert_eq!(
+                substitute_variables("#{1:?prefix_#1:unnamed}.txt", &substrs(&[""])),
+                String::from("unnamed.txt")
+            );
+        }
+
+        #[test]
+        fn conditional_without_else() {
+            assert_eq!(
+                substitute_variables("a#{1:?/#1}", &substrs(&[""])),
+                String::from("a")
+            );
+            assert_eq!(
+                substitute_variables("a#{1:?/#1}", &substrs(&["b"])),
+                format!("a{}b", SEP)
+            );
+        }
+
+        #[test]
+        fn conditional_nested() {
+            let dest = "#{1:?#{1}-#2:none-#2}.txt";
+            assert_eq!(
+                substitute_variables(dest, &substrs(&["a", "b"])),
+                String::from("a-b.txt")
+            );
+            assert_eq!(
+                substitute_variables(dest, &substrs(&["", "c"])),
+                String::from("none-c.txt")
+            );
+            assert_eq!(
+                substitute_variables("#{1:?#{1:upper}:#{2:?x:y}}", &substrs(&["", ""])),
+                String::from("y")
+            );
+            assert_eq!(
+                substitute_variables("#{1:?#{1:upper}:#{2:?x:y}}", &substrs(&["a", ""])),
+                String::from("A")
+            );
+        }
+
+        #[test]
+        fn out_of_range() {
+            assert_eq!(
+                substitute_variables("#{2:?x:y}#{2}", &substrs(&["a"])),
+                String::from("y#{2}")
+            );
+        }
+    }
+
+    mod apply_filter {
+        use super::*;
+
+        #[test]
+        fn alpha() {
+            assert_eq!(apply_filter("1", "alpha"), Some(String::from("a")));
+            assert_eq!(apply_filter("26", "alpha"), Some(String::from("z")));
+            assert_eq!(apply_filter("27", "alpha"), Some(String::from("aa")));
+            assert_eq!(apply_filter("703", "ALPHA"), Some(String::from("AAA")));
+        }
+
+        #[test]
+        fn roman() {
+            assert_eq!(apply_filter("4", "roman"), Some(String::from("iv")));
+            assert_eq!(apply_filter("1994", "ROMAN"), Some(String::from("MCMXCIV")));
+            assert_eq!(
+                apply_filter("3999", "roman"),
+                Some(String::from("mmmcmxcix"))
+            );
+        }
+
+        #[test]
+        fn case() {
+            assert_eq!(apply_filter("Foo", "lower"), Some(String::from("foo")));
+            assert_eq!(apply_filter("Foo", "upper"), Some(String::from("FOO")));
+            assert_eq!(apply_filter("Foo", "LOWER"), None);
+        }
+
+        #[test]
+        fn not_convertible() {
+            assert_eq!(apply_filter("0", "alpha"), Some(String::from("0")));
+            assert_eq!(apply_filter("4000", "roman"), Some(String::from("4000")));
+            assert_eq!(apply_filter("foo", "roman"), Some(String::from("foo")));
+            assert_eq!(apply_filter("1", "unknown"), None);
+        }
+
+        #[test]
+        fn in_dest() {
+            let substrs = vec![String::from("3"), String::from("12")];
+            assert_eq!(
+                substitute_variables("exhibit_#{1:ALPHA}_#{2:roman}", &substrs),
+                String::from("exhibit_C_xii")
+            );
+        }
+    }
+
+    mod substitute_tokens {
+        use super::*;
+
+        static SEP: char = MAIN_SEPARATOR;
+
+        fn resolve(name: &str, arg: Option<&str>) -> Result<Option<String>, String> {
+            match (name, arg) {
+                ("upper", Some(arg)) => Ok(Some(arg.to_uppercase())),
+                ("fail", _) => Err(String::from("failed")),
+                _ => Ok(None),
+            }
+        }
+
+        #[test]
+        fn resolved() {
+            let substrs = vec![OsString::from("v1")];
+            assert_eq!(
+                substitute_tokens("#{upper:abc}/#1", &substrs, &resolve),
+                Ok(OsString::from(format!("ABC{}v1", SEP)))
+            );
+        }
+
+        #[test]
+        fn unresolved() {
+            assert_eq!(
+                substitute_tokens("#{unknown}#{upper}", &[], &resolve),
+                Ok(OsString::from("#{unknown}#{upper}"))
+            );
+        }
+
+        #[test]
+        fn unclosed() {
+            assert_eq!(
+                substitute_tokens("#{upper:abc", &[], &resolve),
+                Ok(OsString::from("#{upper:abc"))
+            );
+        }
+
+        #[test]
+        fn error() {
+            assert_eq!(
+                substitute_tokens("#{fail}", &[], &resolve),
+                Err(String::from("failed"))
+            );
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn non_utf8() {
+            use std::os::unix::ffi::OsStringExt;
+
+            let substrs = vec![OsString::from_vec(b"a\xff".to_vec())];
+            assert_eq!(
+                substitute_tokens("#1_#{1:upper}.txt", &substrs, &resolve),
+                Ok(OsString::from_vec(b"a\xff_a\xff.txt".to_vec()))
+            );
+        }
+    }
+
     mod pull_a_chain {
         use super::*;
 
@@ -459,6 +1245,259 @@ mod tests {
         }
     }
 
+    mod find_case_collisions {
+        use super::*;
+
+        #[test]
+        fn case_only() {
+            let actions = vec![
+                Action::new("a", "X/readme"),
+                Action::new("b", "X/README"),
+                Action::new("c", "X/other"),
+                Action::new("d", "X/readme"), // same name is not a case collision
+            ];
+            assert_eq!(find_case_collisions(&actions), vec![(0, 1)]);
+        }
+    }
+
+    mod find_shadowing_dirs {
+        use super::*;
+        use function_name::named;
+        use std::fs;
+
+        #[named]
+        #[test]
+        fn shadowing_dirs() {
+            let dir = PathBuf::from("temp").join(function_name!());
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("report.txt")).unwrap();
+
+            let actions = vec![
+                Action::new(dir.join("a"), dir.join("report.txt")),
+                Action::new(dir.join("b"), dir.join("other.txt")),
+            ];
+            assert_eq!(find_shadowing_dirs(&actions, "#1.txt"), vec![0]);
+            assert!(find_shadowing_dirs(&actions, "#1/report.txt").is_empty());
+            assert!(find_shadowing_dirs(&actions, "#1.txt/").is_empty());
+        }
+    }
+
+    mod find_aliased_sources {
+        use super::*;
+        use function_name::named;
+        use std::fs;
+
+        fn prepare_test(id: &str) -> PathBuf {
+            let dir = std::env::current_dir().unwrap().join("temp").join(id);
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("A"), "A").unwrap();
+            fs::write(dir.join("B"), "B").unwrap();
+            dir
+        }
+
+        #[named]
+        #[test]
+        fn distinct() {
+            let dir = prepare_test(function_name!());
+            let actions = vec![
+                Action::new(dir.join("A"), dir.join("X")),
+                Action::new(dir.join("B"), dir.join("Y")),
+            ];
+            assert_eq!(find_aliased_sources(&actions), vec![]);
+        }
+
+        #[named]
+        #[test]
+        fn hardlink() {
+            let dir = prepare_test(function_name!());
+            fs::hard_link(dir.join("A"), dir.join("C")).unwrap();
+            let actions = vec![
+                Action::new(dir.join("A"), dir.join("X")),
+                Action::new(dir.join("B"), dir.join("Y")),
+                Action::new(dir.join("C"), dir.join("Z")),
+            ];
+            let expected = if cfg!(unix) { vec![(0, 2)] } else { vec![] };
+            assert_eq!(find_aliased_sources(&actions), expected);
+        }
+
+        #[cfg(unix)]
+        #[named]
+        #[test]
+        fn symlink() {
+            let dir = prepare_test(function_name!());
+            std::os::unix::fs::symlink(dir.join("B"), dir.join("C")).unwrap();
+            let actions = vec![
+                Action::new(dir.join("C"), dir.join("X")),
+                Action::new(dir.join("B"), dir.join("Y")),
+            ];
+            assert_eq!(find_aliased_sources(&actions), vec![(0, 1)]);
+        }
+    }
+
+    mod split_via_temp {
+        use super::*;
+
+        #[test]
+        fn swap() {
+            let actions = to_absolute(vec![Action::new("A", "B"), Action::new("B", "A")]);
+            let (first, second) = split_via_temp(&actions).unwrap();
+            assert_eq!(first.len(), 2);
+            assert_eq!(second.len(), 2);
+            for i in 0..2 {
+                assert_eq!(first[i].src(), actions[i].src());
+                assert_eq!(first[i].dest(), second[i].src());
+                assert_eq!(second[i].dest(), actions[i].dest());
+                assert_eq!(first[i].dest().parent(), actions[i].dest().parent());
+            }
+        }
+
+        #[test]
+        fn shared_dest() {
+            let actions = to_absolute(vec![Action::new("A", "C"), Action::new("B", "C")]);
+            assert!(split_via_temp(&actions).is_err());
+        }
+    }
+
+    mod temp_naming {
+        use super::*;
+
+        #[test]
+        fn deterministic() {
+            let actions = to_absolute(vec![Action::new("A", "B"), Action::new("B", "A")]);
+            let naming = TempNaming::default();
+            let sorted = sort_actions_with(&actions, &naming).unwrap();
+            assert_eq!(sorted, sort_actions_with(&actions, &naming).unwrap());
+            assert_eq!(sorted[0].dest().file_name().unwrap(), "A.pmv0000");
+        }
+
+        #[test]
+        fn prefix() {
+            // The first candidate is taken by another action
+            let actions = to_absolute(vec![
+                Action::new("A", "B"),
+                Action::new("B", "A"),
+                Action::new("C", ".~A"),
+            ]);
+            let naming = TempNaming {
+                prefix: Some(String::from(".~")),
+                in_dir: false,
+            };
+            let sorted = sort_actions_with(&actions, &naming).unwrap();
+            let tmp = sorted.iter().find(|a| a.src().ends_with("B")).unwrap();
+            assert_eq!(tmp.dest().file_name().unwrap(), ".~A.1");
+        }
+
+        #[test]
+        fn in_dir() {
+            let actions = to_absolute(vec![Action::new("A", "B"), Action::new("B", "A")]);
+            let naming = TempNaming {
+                prefix: None,
+                in_dir: true,
+            };
+            let (first, second) = split_via_temp_with(&actions, &naming).unwrap();
+            let dir = std::env::current_dir().unwrap().join(TEMP_DIR);
+            assert_eq!(first[0].dest(), dir.join("B"));
+            assert_eq!(first[1].dest(), dir.join("A"));
+
+            let phases = vec![first.clone(), second];
+            assert_eq!(
+                temporaries(&actions, &phases),
+                vec![
+                    (dir.join("B"), actions[0].src().to_owned()),
+                    (dir.join("A"), actions[1].src().to_owned()),
+                ]
+            );
+        }
+    }
+
+    mod split_via_stage {
+        use super::*;
+
+        #[test]
+        fn same_names() {
+            let actions = to_absolute(vec![Action::new("a/x", "x"), Action::new("b/x", "y/x")]);
+            let stage = staging_dir(&actions).unwrap();
+            assert_eq!(
+                stage.parent(),
+                Some(std::env::current_dir().unwrap().as_path())
+            );
+            let (first, second) = split_via_stage(&actions, &stage).unwrap();
+            assert_eq!(first[0].dest(), stage.join("1_x"));
+            assert_eq!(first[1].dest(), stage.join("2_x"));
+            for i in 0..2 {
+                assert_eq!(first[i].src(), actions[i].src());
+                assert_eq!(first[i].dest(), second[i].src());
+                assert_eq!(second[i].dest(), actions[i].dest());
+            }
+        }
+
+        #[test]
+        fn shared_dest() {
+            let actions = to_absolute(vec![Action::new("A", "C"), Action::new("B", "C")]);
+            assert!(split_via_stage(&actions, Path::new("stage")).is_err());
+        }
+    }
+
+    mod resolve_conflicts {
+        use super::*;
+
+        fn clashing() -> Vec<Action> {
+            to_absolute(vec![
+                Action::new("a/x.txt", "flat/x.txt"),
+                Action::new("b/x.txt", "flat/x.txt"),
+                Action::new("c/x.txt", "flat/x.txt"),
+                Action::new("d/x_2.txt", "flat/x_2.txt"),
+            ])
+        }
+
+        #[test]
+        fn fail() {
+            let (resolved, skipped) = resolve_conflicts(clashing(), OnConflict::Fail);
+            assert_eq!(resolved, clashing());
+            assert!(skipped.is_empty());
+            assert!(sort_actions(&resolved).is_err());
+        }
+
+        #[test]
+        fn skip() {
+            let actions = clashing();
+            let (resolved, skipped) = resolve_conflicts(actions.clone(), OnConflict::Skip);
+            assert_eq!(resolved, vec![actions[0].clone(), actions[3].clone()]);
+            assert_eq!(skipped, vec![actions[1].clone(), actions[2].clone()]);
+        }
+
+        #[test]
+        fn already_in_place() {
+            let actions = to_absolute(vec![
+                Action::new("a/x.txt", "flat/x.txt"),
+                Action::new("flat/x.txt", "flat/x.txt"),
+            ]);
+            let (resolved, _) = resolve_conflicts(actions.clone(), OnConflict::Skip);
+            assert_eq!(resolved, vec![actions[1].clone()]);
+        }
+
+        #[test]
+        fn suffix() {
+            let actions = clashing();
+            let (resolved, skipped) = resolve_conflicts(actions.clone(), OnConflict::Suffix);
+            assert!(skipped.is_empty());
+            let dests: Vec<PathBuf> = resolved.iter().map(|a| a.dest().to_owned()).collect();
+            let flat = actions[0].dest().parent().unwrap();
+            assert_eq!(
+                dests,
+                vec![
+                    flat.join("x.txt"),
+                    flat.join("x_3.txt"),
+                    flat.join("x_4.txt"),
+                    flat.join("x_2.txt"),
+                ]
+            );
+            assert_eq!(resolved[1].label(), Some("numbered to avoid a name clash"));
+            assert!(sort_actions(&resolved).is_ok());
+        }
+    }
+
     mod sort_actions {
         use super::*;
 
@@ -503,15 +1542,16 @@ mod tests {
             ]);
             let sorted = sort_actions(&actions).unwrap();
             let tmp = sorted[0].dest().to_str().unwrap();
-            assert_eq!(
-                sorted,
-                to_absolute(vec![
-                    Action::new("C", tmp),
-                    Action::new("B", "C"),
-                    Action::new("A", "B"),
-                    Action::new(tmp, "A"),
-                ])
-            );
+            let label = "temporary name to break a cycle";
+            let mut expected = to_absolute(vec![
+                Action::new("C", tmp),
+                Action::new("B", "C"),
+                Action::new("A", "B"),
+                Action::new(tmp, "A"),
+            ]);
+            expected[0] = expected[0].clone().with_label(label);
+            expected[3] = expected[3].clone().with_label(label);
+            assert_eq!(sorted, expected);
         }
 
         #[test]
@@ -533,5 +1573,42 @@ mod tests {
             assert!(msg.contains("cannot move multiple files to a same location"));
             assert!(msg.contains("C'"));
         }
+
+        #[test]
+        fn conflicting_chains() {
+            let path = |name: &str| std::env::current_dir().unwrap().join(name);
+            let q = |name: &str| path(name).to_string_lossy().into_owned();
+
+            // A→B→C→A is a cycle and D→B conflicts with A→B
+            let actions = to_absolute(vec![
+                Action::new("A", "B"),
+                Action::new("B", "C"),
+                Action::new("C", "A"),
+                Action::new("D", "B"),
+            ]);
+            let msg = sort_actions(&actions).unwrap_err();
+            assert_eq!(
+                msg,
+                format!(
+                    "cannot move multiple files to a same location: '{b}'\n  \
+                     [2] '{b}' --> [3] '{c}' --> [1] '{a}' --> '{b}' (cycle)\n  \
+                     [4] '{d}' --> [2] '{b}' --> [3] '{c}' --> [1] '{a}' --> '{b}' (cycle)",
+                    a = q("A"),
+                    b = q("B"),
+                    c = q("C"),
+                    d = q("D"),
+                )
+            );
+
+            // IDs are shown instead of the indices if any
+            let actions: Vec<Action> =
+                to_absolute(vec![Action::new("A", "B"), Action::new("A", "C")])
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, a)| a.with_id(i + 10))
+                    .collect();
+            let msg = sort_actions(&actions).unwrap_err();
+            assert!(msg.ends_with(&format!("\n  [11] '{}' --> '{}'", q("A"), q("C"))));
+        }
     }
 }
@@ -0,0 +1,418 @@
+use crate::Action;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// Version of the plan file format, which `read_plan` requires to be the same.
+const VERSION: u64 = 1;
+
+/// Writes planned actions to a JSON file which `pmv apply` reads.
+///
+/// The file is an object with a format `version` and the `actions`, each of which has the `id`,
+/// the `src` and the `dest`, and `"exact_dest": true` if the action has `with_exact_dest`.
+/// A path which is not valid Unicode is written as an array of its bytes (UTF-16 code units on
+/// Windows) so that it is read back intact. `pmv apply` adds `"done": true` to the actions it has
+/// executed (see `mark_done`.)
+pub fn write_plan(path: &Path, actions: &[Action]) -> Result<(), String> {
+    let entries: Vec<Value> = actions
+        .iter()
+        .map(|a| {
+            let mut entry = json!({
+                "id": a.id(),
+                "src": path_to_json(a.src()),
+                "dest": path_to_json(a.dest()),
+            });
+            if a.exact_dest() {
+                entry["exact_dest"] = Value::Bool(true);
+            }
+            entry
+        })
+        .collect();
+    let plan = json!({ "version": VERSION, "actions": entries });
+    let text = serde_json::to_string_pretty(&plan).unwrap_or_default();
+    fs::write(path, text + "\n").map_err(|err| {
+        format!(
+            "failed to write plan \"{}\": {}",
+            path.to_string_lossy(),
+            err
+        )
+    })
+}
+
+/// Reads actions from a JSON file written by `write_plan`, with whether each of them is marked
+/// as done or not.
+pub fn read_plan(path: &Path) -> Result<Vec<(Action, bool)>, String> {
+    let plan = read_json(path)?;
+    parse_plan(&plan)
+        .map_err(|err| format!("malformed plan \"{}\": {}", path.to_string_lossy(), err))
+}
+
+/// Marks the actions with the IDs as done in a plan file, so that `pmv apply --resume` skips
+/// them.
+///
+/// The file is replaced atomically so that it stays intact even if pmv is killed meanwhile.
+pub(crate) fn mark_done(path: &Path, ids: &[usize]) -> Result<(), String> {
+    let mut plan = read_json(path)?;
+    if let Some(entries) = plan.get_mut("actions").and_then(Value::as_array_mut) {
+        for entry in entries {
+            let id = entry.get("id").and_then(Value::as_u64);
+            if id.is_some_and(|id| ids.contains(&(id as usize))) {
+                entry["done"] = Value::Bool(true);
+            }
+        }
+    }
+    let text = serde_json::to_string_pretty(&plan).unwrap_or_default();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".pmv-checkpoint");
+    fs::write(&tmp, text + "\n")
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|err| {
+            let _ = fs::remove_file(&tmp);
+            format!(
+                "failed to write plan \"{}\": {}",
+                path.to_string_lossy(),
+                err
+            )
+        })
+}
+
+fn read_json(path: &Path) -> Result<Value, String> {
+    let text = fs::read_to_string(path).map_err(|err| {
+        format!(
+            "failed to read plan \"{}\": {}",
+            path.to_string_lossy(),
+            err
+        )
+    })?;
+    serde_json::from_str(&text)
+        .map_err(|err| format!("malformed plan \"{}\": {}", path.to_string_lossy(), err))
+}
+
+fn parse_plan(plan: &Value) -> Result<Vec<(Action, bool)>, String> {
+    match plan.get("version").and_then(Value::as_u64) {
+        Some(VERSION) => (),
+        Some(version) => return Err(format!("unsupported version {}", version)),
+        None => return Err(String::from("no version")),
+    }
+    let entries = match plan.get("actions").and_then(Value::as_array) {
+        Some(entries) => entries,
+        None => return Err(String::from("no actions")),
+    };
+    entries
+        .iter()
+        .map(|entry| {
+            let id = entry.get("id").and_then(Value::as_u64);
+            let src = entry.get("src").and_then(path_from_json);
+            let dest = entry.get("dest").and_then(path_from_json);
+            let exact_dest = entry.get("exact_dest").and_then(Value::as_bool);
+            let done = entry.get("done").and_then(Value::as_bool);
+            match (id, src, dest) {
+                (Some(id), Some(src), Some(dest)) => {
+                    let action = Action::new(src, dest);
+                    let action = action.with_id(id as usize);
+                    let action = match exact_dest {
+                        Some(true) => action.with_exact_dest(),
+                        _ => action,
+                    };
+                    Ok((action, done == Some(true)))
+                }
+                _ => Err(format!("invalid action {}", entry)),
+            }
+        })
+        .collect()
+}
+
+/// Converts a path into a string, or into an array of its bytes (UTF-16 code units on Windows)
+/// if it is not valid Unicode.
+fn path_to_json(path: &Path) -> Value {
+    match path.to_str() {
+        Some(s) => Value::from(s),
+        None => Value::from(os_units(path.as_os_str().to_owned())),
+    }
+}
+
+/// Converts what `path_to_json` returned back into a path.
+fn path_from_json(value: &Value) -> Option<PathBuf> {
+    use std::convert::TryInto;
+
+    if let Some(s) = value.as_str() {
+        return Some(PathBuf::from(s));
+    }
+    let units = value
+        .as_array()?
+        .iter()
+        .map(|unit| unit.as_u64().and_then(|unit| unit.try_into().ok()))
+        .collect::<Option<Vec<_>>>()?;
+    Some(PathBuf::from(from_os_units(units)))
+}
+
+#[cfg(unix)]
+fn os_units(s: OsString) -> Vec<u8> {
+    use std::os::unix::ffi::OsStringExt;
+    s.into_vec()
+}
+
+#[cfg(unix)]
+fn from_os_units(units: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(units)
+}
+
+#[cfg(windows)]
+fn os_units(s: OsString) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    s.encode_wide().collect()
+}
+
+#[cfg(windows)]
+fn from_os_units(units: Vec<u16>) -> OsString {
+    use std::os::windows::ffi::OsStringExt;
+    OsString::from_wide(&units)
+}
+
+/// Returns a JSON Schema of the plan files `write_plan` writes.
+pub(crate) fn plan_schema() -> String {
+    let schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "pmv plan",
+        "description": "Moves planned with `pmv --save-plan` and executed with `pmv apply`",
+        "type": "object",
+        "required": ["version", "actions"],
+        "$defs": {
+            "path": {
+                "oneOf": [
+                    { "type": "string" },
+                    {
+                        "description": "Bytes (UTF-16 code units on Windows) of a path which \
+                                        is not valid Unicode",
+                        "type": "array",
+                        "items": { "type": "integer", "minimum": 0, "maximum": 65535 },
+                    },
+                ],
+            },
+        },
+        "properties": {
+            "version": {
+                "description": "Version of the format; pmv rejects versions it does not know",
+                "const": VERSION,
+            },
+            "actions": {
+                "description": "Moves to perform; pmv decides the execution order",
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["id", "src", "dest"],
+                    "properties": {
+                        "id": {
+                            "description": "ID used by `pmv apply --only` and `--skip`",
+                            "type": "integer",
+                            "minimum": 0,
+                        },
+                        "src": {
+                            "description": "Path of the file to move",
+                            "$ref": "#/$defs/path",
+                        },
+                        "dest": {
+                            "description": "Path to move the file to",
+                            "$ref": "#/$defs/path",
+                        },
+                        "exact_dest": {
+                            "description": "Moves the file to `dest` itself even if it is \
+                                            a directory, rather than into it",
+                            "type": "boolean",
+                        },
+                        "done": {
+                            "description": "Marks the move as executed by `pmv apply`, which \
+                                            `pmv apply --resume` skips",
+                            "type": "boolean",
+                        },
+                    },
+                },
+            },
+        },
+    });
+    serde_json::to_string_pretty(&schema).unwrap_or_default()
+}
+
+/// Name of the file recording the hash of the plan applied last in a directory.
+const LAST_APPLIED: &str = ".pmv-last-applied";
+
+/// Returns a hash identifying the moves of a plan.
+pub(crate) fn plan_hash(actions: &[Action]) -> String {
+    let mut hasher = Sha256::new();
+    for action in actions {
+        hasher.update(action.src().as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+        hasher.update(action.dest().as_os_str().as_encoded_bytes());
+        hasher.update([0, u8::from(action.exact_dest())]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Tests whether the plan with the hash was the one applied last in the directory.
+pub(crate) fn was_applied_last(dir: &Path, hash: &str) -> bool {
+    fs::read_to_string(dir.join(LAST_APPLIED)).is_ok_and(|text| text.trim() == hash)
+}
+
+/// Records the hash of a plan as the one applied last in the directory.
+pub(crate) fn record_applied(dir: &Path, hash: &str) -> Result<(), String> {
+    let path = dir.join(LAST_APPLIED);
+    fs::write(&path, format!("{}\n", hash))
+        .map_err(|err| format!("failed to write \"{}\": {}", path.to_string_lossy(), err))
+}
+
+/// Parses a list of IDs such as `3,7,12-20`.
+pub fn parse_ids(spec: &str) -> Result<Vec<RangeInclusive<usize>>, String> {
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("invalid ID \"{}\" in \"{}\"", s, spec))
+    };
+    spec.split(',')
+        .map(|item| match item.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if last < first {
+                    return Err(format!("invalid range \"{}\" in \"{}\"", item, spec));
+                }
+                Ok(first..=last)
+            }
+            None => parse(item).map(|id| id..=id),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use function_name::named;
+
+    #[test]
+    fn ids() {
+        assert_eq!(parse_ids("3,7,12-20"), Ok(vec![3..=3, 7..=7, 12..=20]));
+        assert_eq!(parse_ids(" 1 - 2 "), Ok(vec![1..=2]));
+        assert!(parse_ids("").is_err());
+        assert!(parse_ids("3-1").is_err());
+        assert!(parse_ids("a").is_err());
+    }
+
+    #[named]
+    #[test]
+    fn write_and_read_plan() {
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plan.json");
+
+        let actions = vec![
+            Action::new("a\"b", "c").with_id(1),
+            Action::new("d", "e\\f").with_id(2),
+            Action::new("g", "h").with_id(3).with_exact_dest(),
+        ];
+        write_plan(&path, &actions).unwrap();
+        let read: Vec<Action> = read_plan(&path)
+            .unwrap()
+            .into_iter()
+            .map(|(action, _)| action)
+            .collect();
+        assert_eq!(read, actions);
+    }
+
+    #[cfg(unix)]
+    #[named]
+    #[test]
+    fn non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plan.json");
+
+        let src = OsStr::from_bytes(b"caf\xe9");
+        let dest = OsStr::from_bytes(b"caf\xe9\xe9");
+        let actions = vec![Action::new(src, dest).with_id(1)];
+        write_plan(&path, &actions).unwrap();
+        assert_eq!(read_plan(&path), Ok(vec![(actions[0].clone(), false)]));
+        assert_ne!(
+            plan_hash(&actions),
+            plan_hash(&[Action::new("caf\u{fffd}", "caf\u{fffd}\u{fffd}")])
+        );
+    }
+
+    #[named]
+    #[test]
+    fn last_applied() {
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let hash = plan_hash(&[Action::new("a", "b")]);
+        assert_ne!(hash, plan_hash(&[Action::new("a", "c")]));
+        assert_ne!(hash, plan_hash(&[Action::new("a", "b").with_exact_dest()]));
+        assert_ne!(
+            hash,
+            plan_hash(&[Action::new("a", "b"), Action::new("c", "d")])
+        );
+        assert!(!was_applied_last(&dir, &hash));
+        record_applied(&dir, &hash).unwrap();
+        assert!(was_applied_last(&dir, &hash));
+        assert!(!was_applied_last(&dir, &plan_hash(&[])));
+    }
+
+    #[named]
+    #[test]
+    fn schema() {
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plan.json");
+        write_plan(&path, &[Action::new("a", "b").with_id(1).with_exact_dest()]).unwrap();
+        mark_done(&path, &[1]).unwrap();
+
+        // Every property written is described
+        let schema: Value = serde_json::from_str(&plan_schema()).unwrap();
+        let plan: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(schema["properties"]["version"]["const"], plan["version"]);
+        let described = &schema["properties"]["actions"]["items"]["properties"];
+        for key in plan["actions"][0].as_object().unwrap().keys() {
+            assert!(described.get(key).is_some(), "{}", key);
+        }
+    }
+
+    #[named]
+    #[test]
+    fn progress() {
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plan.json");
+
+        let actions = vec![
+            Action::new("a", "b").with_id(1),
+            Action::new("c", "d").with_id(2),
+            Action::new("e", "f").with_id(3),
+        ];
+        write_plan(&path, &actions).unwrap();
+        mark_done(&path, &[1]).unwrap();
+        mark_done(&path, &[3]).unwrap();
+        let read = read_plan(&path).unwrap();
+        let progress: Vec<bool> = read.iter().map(|(_, done)| *done).collect();
+        assert_eq!(progress, vec![true, false, true]);
+        assert!(read.iter().map(|(action, _)| action).eq(actions.iter()));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn malformed_plan() {
+        let parse = |text: &str| parse_plan(&serde_json::from_str(text).unwrap());
+        assert!(parse("[]").is_err());
+        assert!(parse(r#"{"version": 2, "actions": []}"#).is_err());
+        assert!(parse(r#"{"version": 1, "actions": [{"id": 1}]}"#).is_err());
+        assert_eq!(parse(r#"{"version": 1, "actions": []}"#), Ok(vec![]));
+    }
+}
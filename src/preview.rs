@@ -0,0 +1,169 @@
+use crate::quote::quote_path;
+use crate::Action;
+use std::cmp;
+use std::path::PathBuf;
+use unicode_width::UnicodeWidthStr;
+
+/// A set of planned moves, which can be previewed exactly as `pmv --dry-run` shows them.
+///
+/// This is for integrations such as GUIs and editor plugins which show what pmv would do before
+/// executing the same actions with `move_files`.
+///
+/// ```
+/// use pmv::{Action, Plan};
+///
+/// let plan = Plan::new(vec![
+///     Action::new("foo.txt", "bar.txt"),
+///     Action::new("a.txt", "b.txt"),
+/// ]);
+/// assert_eq!(
+///     plan.render_preview(None, false),
+///     "foo.txt --> bar.txt\na.txt   --> b.txt\n"
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Plan {
+    actions: Vec<Action>,
+}
+
+/// A line of a preview in a structured form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreviewLine {
+    /// The ID of the move, if numbered.
+    pub id: Option<usize>,
+    /// The path to move.
+    pub src: PathBuf,
+    /// Where the file will be, with the file name appended if the destination is a directory.
+    pub dest: PathBuf,
+    /// The text of the line as `Plan::render_preview` renders it without color and wrapping.
+    pub text: String,
+}
+
+impl Plan {
+    /// Creates a plan executing the actions in the given order.
+    ///
+    /// The actions should be sorted with `sort_actions` beforehand as for `move_files`.
+    pub fn new(actions: Vec<Action>) -> Plan {
+        Plan { actions }
+    }
+
+    /// Returns the actions of the plan.
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    /// Returns the lines of the preview in a structured form.
+    pub fn preview(&self) -> Vec<PreviewLine> {
+        let columns = Columns::of(&self.actions);
+        self.actions
+            .iter()
+            .map(|a| {
+                let dest = a.final_dest();
+                PreviewLine {
+                    id: a.id(),
+                    src: a.src().to_owned(),
+                    text: format_move(&columns, a.id(), &quote_path(a.src()), &quote_path(&dest)),
+                    dest,
+                }
+            })
+            .collect()
+    }
+
+    /// Renders the preview as `pmv --dry-run` prints it, one line per move.
+    ///
+    /// If `width` is given, lines wider than it are wrapped before the arrow. If `color` is true,
+    /// the destinations are colored with ANSI escape sequences.
+    pub fn render_preview(&self, width: Option<usize>, color: bool) -> String {
+        let columns = Columns::of(&self.actions);
+        let mut out = String::new();
+        for action in &self.actions {
+            let head = format_head(&columns, action.id(), &quote_path(action.src()));
+            let dest = quote_path(&action.final_dest());
+            let wrap = width.is_some_and(|width| width < head.width() + 5 + dest.width());
+            let dest = if color {
+                format!("\x1b[32m{}\x1b[0m", dest)
+            } else {
+                dest
+            };
+            if wrap {
+                let indent = " ".repeat(columns.id_prefix_width());
+                out.push_str(&format!("{}\n{}--> {}\n", head.trim_end(), indent, dest));
+            } else {
+                out.push_str(&format!("{} --> {}\n", head, dest));
+            }
+        }
+        out
+    }
+}
+
+/// Widths of the columns shared by the lines of a preview.
+pub(crate) struct Columns {
+    id: usize,
+    src: usize,
+}
+
+impl Columns {
+    /// Calculates the widths to align the moves (wide characters take two columns.)
+    pub(crate) fn of(actions: &[Action]) -> Columns {
+        Columns {
+            id: actions
+                .iter()
+                .filter_map(|a| a.id())
+                .map(|id| id.to_string().len())
+                .fold(0, cmp::max),
+            src: actions
+                .iter()
+                .map(|a| quote_path(a.src()).width())
+                .fold(0, cmp::max),
+        }
+    }
+
+    fn id_prefix_width(&self) -> usize {
+        if self.id == 0 {
+            0
+        } else {
+            self.id + 3
+        }
+    }
+}
+
+/// Formats a move as `[ID] SRC --> DEST`, aligned to the columns.
+pub(crate) fn format_move(columns: &Columns, id: Option<usize>, src: &str, dest: &str) -> String {
+    format!("{} --> {}", format_head(columns, id, src), dest)
+}
+
+/// Formats the part of a move before the arrow, padded to the columns.
+fn format_head(columns: &Columns, id: Option<usize>, src: &str) -> String {
+    let mut head = String::new();
+    if let Some(id) = id {
+        head.push_str(&format!("[{:>width$}] ", id, width = columns.id));
+    }
+    head.push_str(src);
+    for _ in src.width()..columns.src {
+        head.push(' ');
+    }
+    head
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_preview() {
+        let plan = Plan::new(vec![
+            Action::new("写真", "a").with_id(9),
+            Action::new("bbbbb", "cc").with_id(10),
+        ]);
+        assert_eq!(
+            plan.render_preview(None, false),
+            "[ 9] 写真  --> a\n[10] bbbbb --> cc\n"
+        );
+        assert_eq!(
+            plan.render_preview(Some(16), true),
+            "[ 9] 写真  --> \x1b[32ma\x1b[0m\n[10] bbbbb\n     --> \x1b[32mcc\x1b[0m\n"
+        );
+        assert_eq!(plan.preview()[1].text, "[10] bbbbb --> cc");
+        assert_eq!(plan.preview()[1].dest, PathBuf::from("cc"));
+    }
+}
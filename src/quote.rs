@@ -0,0 +1,62 @@
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static LITERAL_PATHS: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether to print paths as they are, without escaping non-printable characters.
+pub(crate) fn set_literal_paths(literal: bool) {
+    LITERAL_PATHS.store(literal, Ordering::Relaxed);
+}
+
+/// Converts a path to a string for human-readable output.
+///
+/// Control characters (e.g. an escape character starting an ANSI escape sequence) are escaped
+/// as `\t`, `\n`, `\r`, `\xHH` or `\u{HHHH}` so that a filename cannot corrupt the terminal.
+pub(crate) fn quote_path(path: &Path) -> String {
+    quote(&path.to_string_lossy()).into_owned()
+}
+
+/// Escapes control characters in a string like `quote_path`.
+pub(crate) fn quote(s: &str) -> Cow<'_, str> {
+    escape(s, false)
+}
+
+/// Escapes control characters in a message like `quote`, but keeps line breaks.
+pub(crate) fn quote_message(s: &str) -> Cow<'_, str> {
+    escape(s, true)
+}
+
+fn escape(s: &str, keep_newlines: bool) -> Cow<'_, str> {
+    let needs_escape = |c: char| c.is_control() && !(keep_newlines && c == '\n');
+    if LITERAL_PATHS.load(Ordering::Relaxed) || !s.chars().any(needs_escape) {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            c if !needs_escape(c) => escaped.push(c),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if (c as u32) < 0x80 => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push_str(&format!("\\u{{{:04x}}}", c as u32)),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_control_characters() {
+        assert_eq!(quote("写真.jpg"), "写真.jpg");
+        assert_eq!(quote("a\tb\nc\rd"), "a\\tb\\nc\\rd");
+        assert_eq!(quote("\x1b[31mred"), "\\x1b[31mred");
+        assert_eq!(quote("\u{9b}31m"), "\\u{009b}31m");
+        assert_eq!(quote("C:\\a b"), "C:\\a b");
+        assert_eq!(quote_message("a\nb\x07"), "a\nb\\x07");
+    }
+}
@@ -0,0 +1,137 @@
+use crate::fnmatch::MatchOptions;
+use regex::bytes::{Regex, RegexBuilder};
+use std::ffi::{OsStr, OsString};
+
+/// A pattern of a file name written as a regular expression.
+///
+/// The expression must match the whole name. Each capture group yields one matched part, which
+/// is empty if the group did not participate in the match.
+#[derive(Debug)]
+pub(crate) struct RegexPattern {
+    regex: Regex,
+    has_wildcard: bool,
+}
+
+impl RegexPattern {
+    /// Compiles a regular expression, failing if it is malformed.
+    pub fn new(pattern: &str, options: &MatchOptions) -> Result<RegexPattern, String> {
+        let ignore_case = options.case_sensitivity.ignores_case();
+        let regex = RegexBuilder::new(&format!("^(?:{})$", pattern))
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|err| format!("invalid regular expression `{}`: {}", pattern, err))?;
+
+        // A name without special characters can be looked up directly, unless the case
+        // sensitivity differs from the file system
+        let has_wildcard = regex::escape(pattern) != pattern
+            || (ignore_case != cfg!(windows)
+                && pattern
+                    .chars()
+                    .any(|c| c.is_lowercase() || c.is_uppercase()));
+        Ok(RegexPattern {
+            regex,
+            has_wildcard,
+        })
+    }
+
+    /// Tests whether the expression may match names other than itself.
+    pub fn has_wildcard(&self) -> bool {
+        self.has_wildcard
+    }
+
+    /// Returns the number of capture groups.
+    pub fn captures(&self) -> usize {
+        self.regex.captures_len() - 1
+    }
+
+    /// Matches a name and returns the substrings captured by the groups.
+    pub fn matches_os(&self, name: &OsStr) -> Option<Vec<OsString>> {
+        let name = to_bytes(name);
+        let captures = self.regex.captures(&name)?;
+        let parts = (1..captures.len())
+            .map(|i| {
+                captures
+                    .get(i)
+                    .map_or_else(OsString::new, |m| from_bytes(m.as_bytes()))
+            })
+            .collect();
+        Some(parts)
+    }
+}
+
+#[cfg(unix)]
+fn to_bytes(name: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    name.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn to_bytes(name: &OsStr) -> Vec<u8> {
+    name.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn from_bytes(bytes: &[u8]) -> OsString {
+    use std::os::unix::ffi::OsStrExt;
+    OsStr::from_bytes(bytes).to_owned()
+}
+
+#[cfg(not(unix))]
+fn from_bytes(bytes: &[u8]) -> OsString {
+    OsString::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fnmatch::CaseSensitivity;
+
+    fn parts(pattern: &RegexPattern, name: &str) -> Option<Vec<String>> {
+        let parts = pattern.matches_os(OsStr::new(name))?;
+        Some(
+            parts
+                .iter()
+                .map(|part| part.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn matches() {
+        let options = MatchOptions::default();
+        let pattern = RegexPattern::new(r"IMG_(\d+)(_final)?\.(jpe?g)", &options).unwrap();
+        assert_eq!(pattern.captures(), 3);
+        assert!(pattern.has_wildcard());
+        assert_eq!(
+            parts(&pattern, "IMG_0123.jpeg"),
+            Some(vec![
+                String::from("0123"),
+                String::new(),
+                String::from("jpeg")
+            ])
+        );
+        // The whole name must match
+        assert_eq!(parts(&pattern, "IMG_0123.jpg.bak"), None);
+        assert_eq!(parts(&pattern, "x_IMG_0123.jpg"), None);
+
+        assert!(!RegexPattern::new("foo", &options).unwrap().has_wildcard());
+        assert!(RegexPattern::new("(foo", &options).is_err());
+    }
+
+    #[test]
+    fn case_sensitivity() {
+        let options = MatchOptions {
+            case_sensitivity: CaseSensitivity::Insensitive,
+            ..Default::default()
+        };
+        let pattern = RegexPattern::new("img_(.*)", &options).unwrap();
+        assert_eq!(parts(&pattern, "IMG_1"), Some(vec![String::from("1")]));
+
+        let options = MatchOptions {
+            case_sensitivity: CaseSensitivity::Sensitive,
+            ..Default::default()
+        };
+        let pattern = RegexPattern::new("img_(.*)", &options).unwrap();
+        assert_eq!(parts(&pattern, "IMG_1"), None);
+    }
+}
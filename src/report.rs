@@ -0,0 +1,114 @@
+use crate::quote::quote_path;
+use std::path::{Path, PathBuf};
+
+/// Renders how the moves actually went compared with the plan, one move per line.
+///
+/// Each line starts with a mark telling the outcome of a planned move:
+///
+/// - `  SRC --> DEST` moved as planned.
+/// - `- SRC --> DEST (not moved)` was skipped or failed.
+/// - `~ SRC --> ACTUAL (planned DEST)` was moved somewhere else (e.g. edited interactively.)
+/// - `! SRC --> DEST (...)` was moved but the file system changed afterwards, such as the
+///   moved file disappearing or another file appearing at the source.
+///
+/// `planned` and `moved` are pairs of sources and where they were planned to be or actually
+/// are. Returns the listing and the number of moves which did not go as planned.
+pub(crate) fn render_diff(
+    planned: &[(PathBuf, PathBuf)],
+    moved: &[(PathBuf, PathBuf)],
+) -> (String, usize) {
+    let mut out = String::new();
+    let mut num_diffs = 0;
+    for (src, dest) in planned.iter().filter(|(src, dest)| src != dest) {
+        let actual = moved
+            .iter()
+            .find(|(s, _)| s == src)
+            .map(|(_, d)| d.as_path());
+        let (mark, to, note) = match actual {
+            None => ('-', dest.as_path(), String::from("not moved")),
+            Some(actual) if actual != dest => {
+                ('~', actual, format!("planned {}", quote_path(dest)))
+            }
+            Some(actual) if !exists(actual) => {
+                ('!', actual, String::from("destination disappeared"))
+            }
+            Some(actual) if exists(src) => ('!', actual, String::from("source reappeared")),
+            Some(actual) => (' ', actual, String::new()),
+        };
+        out.push_str(&format!(
+            "{} {} --> {}",
+            mark,
+            quote_path(src),
+            quote_path(to)
+        ));
+        if !note.is_empty() {
+            num_diffs += 1;
+            out.push_str(&format!(" ({})", note));
+        }
+        out.push('\n');
+    }
+    (out, num_diffs)
+}
+
+/// Tests whether anything exists at the path, including a broken symbolic link.
+fn exists(path: &Path) -> bool {
+    path.symlink_metadata().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use function_name::named;
+    use std::fs;
+
+    #[named]
+    #[test]
+    fn diff() {
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["b", "c", "d2", "e", "e2"].iter() {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        let pair = |src: &str, dest: &str| (dir.join(src), dir.join(dest));
+
+        let planned = vec![
+            pair("a", "a2"),
+            pair("b", "b2"),
+            pair("c", "c2"),
+            pair("d", "d2"),
+            pair("e", "e2"),
+            pair("f", "f"),
+        ];
+        let moved = vec![
+            pair("a", "a2"),
+            pair("c", "c3"),
+            pair("d", "d2"),
+            pair("e", "e2"),
+        ];
+        let (out, num_diffs) = render_diff(&planned, &moved);
+        let path = |name: &str| quote_path(&dir.join(name));
+        assert_eq!(
+            out,
+            format!(
+                "! {} --> {} (destination disappeared)\n\
+                 - {} --> {} (not moved)\n\
+                 ~ {} --> {} (planned {})\n  \
+                 {} --> {}\n\
+                 ! {} --> {} (source reappeared)\n",
+                path("a"),
+                path("a2"),
+                path("b"),
+                path("b2"),
+                path("c"),
+                path("c3"),
+                path("c2"),
+                path("d"),
+                path("d2"),
+                path("e"),
+                path("e2"),
+            )
+        );
+        assert_eq!(num_diffs, 4);
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+/// Name of the project-local configuration file, searched upward from the current directory.
+const PROJECT_FILE: &str = ".pmv.toml";
+
+/// A configuration value with the file it came from.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Setting {
+    /// Dotted name of the setting (e.g. `defaults.interactive`.)
+    pub key: String,
+    pub value: Value,
+    /// The file which set the value.
+    pub origin: PathBuf,
+}
+
+/// Returns the paths of the configuration files in the order of increasing precedence.
+///
+/// These are the system-wide file, the file of the user and the project-local `.pmv.toml`
+/// nearest to `curdir`. Files which do not exist are omitted.
+pub(crate) fn config_paths(curdir: &Path) -> Vec<PathBuf> {
+    let project = curdir
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file());
+    vec![system_path(), user_path(), project]
+        .into_iter()
+        .flatten()
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+#[cfg(windows)]
+fn system_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("ProgramData")?;
+    Some(PathBuf::from(dir).join("pmv").join("config.toml"))
+}
+
+#[cfg(not(windows))]
+fn system_path() -> Option<PathBuf> {
+    Some(PathBuf::from("/etc/pmv/config.toml"))
+}
+
+/// Returns the project-local configuration file nearest to `curdir`, or the one to be created
+/// in `curdir` if there is none.
+pub(crate) fn project_path(curdir: &Path) -> PathBuf {
+    curdir
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| curdir.join(PROJECT_FILE))
+}
+
+#[cfg(windows)]
+pub(crate) fn user_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("APPDATA")?;
+    Some(PathBuf::from(dir).join("pmv").join("config.toml"))
+}
+
+#[cfg(not(windows))]
+pub(crate) fn user_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("pmv").join("config.toml"))
+}
+
+/// Reads the configuration files and merges them; a file overrides the ones before it.
+///
+/// The settings are returned in the order of their keys.
+pub(crate) fn load(paths: &[PathBuf]) -> Result<Vec<Setting>, String> {
+    let mut settings: BTreeMap<String, Setting> = BTreeMap::new();
+    for path in paths {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read \"{}\": {}", path.to_string_lossy(), err))?;
+        for setting in parse(&text, path)? {
+            settings.insert(setting.key.clone(), setting);
+        }
+    }
+    Ok(settings.into_values().collect())
+}
+
+fn parse_table(text: &str, origin: &Path) -> Result<toml::Table, String> {
+    text.parse().map_err(|err: toml::de::Error| {
+        format!(
+            "failed to parse \"{}\": {}",
+            origin.to_string_lossy(),
+            err.message()
+        )
+    })
+}
+
+fn parse(text: &str, origin: &Path) -> Result<Vec<Setting>, String> {
+    let table = parse_table(text, origin)?;
+    let mut settings = Vec::new();
+    for (section, values) in table {
+        match (section.as_str(), values) {
+            ("defaults", Value::Table(values)) => {
+                settings.extend(values.into_iter().map(|(name, value)| Setting {
+                    key: format!("defaults.{}", name),
+                    value,
+                    origin: origin.to_owned(),
+                }))
+            }
+            (section, _) => {
+                return Err(format!(
+                    "unknown setting \"{}\" in \"{}\"",
+                    section,
+                    origin.to_string_lossy()
+                ))
+            }
+        }
+    }
+    Ok(settings)
+}
+
+/// Converts `defaults.*` settings into command line arguments of the command.
+///
+/// `defaults.NAME` is the default of the option `--NAME`: `true` turns a flag on, an integer
+/// repeats a counted flag such as `--verbose`, and other values are given as the value of the
+/// option (an array gives it several times.) Settings for options given on the command line
+/// (`is_given`) are skipped so that the command line takes precedence.
+pub(crate) fn default_args(
+    settings: &[Setting],
+    command: &clap::Command<'_>,
+    is_given: &dyn Fn(&str) -> bool,
+) -> Result<Vec<OsString>, String> {
+    let mut args = Vec::new();
+    for setting in settings {
+        let invalid = |reason: &str| {
+            format!(
+                "{} \"{}\" in \"{}\"",
+                reason,
+                setting.key,
+                setting.origin.to_string_lossy()
+            )
+        };
+        let name = match setting.key.strip_prefix("defaults.") {
+            Some(name) => name,
+            None => continue,
+        };
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(name))
+            .ok_or_else(|| invalid("unknown setting"))?;
+        if is_given(arg.get_id()) {
+            continue;
+        }
+        let option = format!("--{}", name);
+        match (arg.get_action(), &setting.value) {
+            (clap::ArgAction::SetTrue, Value::Boolean(true)) => args.push(option.into()),
+            (clap::ArgAction::SetTrue, Value::Boolean(false)) => (),
+            (clap::ArgAction::Count, Value::Integer(n)) => {
+                args.extend((0..*n).map(|_| OsString::from(&option)))
+            }
+            (clap::ArgAction::SetTrue, _) | (clap::ArgAction::Count, _) => {
+                return Err(invalid("invalid value of"))
+            }
+            (_, Value::Array(values)) => {
+                for value in values {
+                    let value = scalar(value).ok_or_else(|| invalid("invalid value of"))?;
+                    args.push(format!("{}={}", option, value).into());
+                }
+            }
+            (_, value) => {
+                let value = scalar(value).ok_or_else(|| invalid("invalid value of"))?;
+                args.push(format!("{}={}", option, value).into());
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Formats a value as it is written on the command line.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Integer(n) => Some(n.to_string()),
+        Value::Float(f) => Some(f.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the value of the setting formatted as it is written on the command line.
+pub(crate) fn get(settings: &[Setting], key: &str) -> Option<String> {
+    let setting = settings.iter().find(|s| s.key == key)?;
+    Some(scalar(&setting.value).unwrap_or_else(|| setting.value.to_string()))
+}
+
+/// Parses a value given on the command line: a TOML value such as `true`, `3` or `["a", "b"]`,
+/// or a string otherwise so that `natural` needs no quotes.
+pub(crate) fn parse_value(text: &str) -> Value {
+    format!("value = {}", text)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::from(text))
+}
+
+/// Writes a setting into the configuration file, creating the file if it does not exist.
+///
+/// The file is rewritten as a whole, so its comments and formatting are not kept.
+pub(crate) fn set(path: &Path, key: &str, value: Value) -> Result<(), String> {
+    let (section, name) = match key.split_once('.') {
+        Some(("defaults", name)) if !name.is_empty() => ("defaults", name),
+        _ => return Err(format!("unknown setting \"{}\"", key)),
+    };
+    let mut table = match fs::read_to_string(path) {
+        Ok(text) => parse_table(&text, path)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+        Err(err) => {
+            return Err(format!(
+                "failed to read \"{}\": {}",
+                path.to_string_lossy(),
+                err
+            ))
+        }
+    };
+    match table
+        .entry(section)
+        .or_insert_with(|| Value::Table(toml::Table::new()))
+    {
+        Value::Table(values) => {
+            values.insert(String::from(name), value);
+        }
+        _ => {
+            return Err(format!(
+                "unknown setting \"{}\" in \"{}\"",
+                section,
+                path.to_string_lossy()
+            ))
+        }
+    }
+
+    let write = || {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, table.to_string())
+    };
+    write().map_err(|err| format!("failed to write \"{}\": {}", path.to_string_lossy(), err))
+}
+
+/// Renders the settings as `KEY = VALUE` lines, prefixed with their origins if requested.
+pub(crate) fn render(settings: &[Setting], show_origin: bool) -> String {
+    settings
+        .iter()
+        .map(|s| {
+            let line = format!("{} = {}\n", s.key, s.value);
+            if show_origin {
+                format!("{}\t{}", s.origin.to_string_lossy(), line)
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use function_name::named;
+
+    #[named]
+    #[test]
+    fn layers() {
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("project/sub")).unwrap();
+        let user = dir.join("user.toml");
+        fs::write(
+            &user,
+            "[defaults]\ninteractive = true\ncollate = \"natural\"\n",
+        )
+        .unwrap();
+        let project = dir.join("project").join(PROJECT_FILE);
+        fs::write(&project, "[defaults]\ncollate = \"locale\"\n").unwrap();
+
+        // The project-local file is found from subdirectories
+        assert_eq!(
+            config_paths(&dir.join("project/sub")).last(),
+            Some(&project)
+        );
+
+        // Later files take precedence
+        let settings = load(&[user.clone(), project.clone()]).unwrap();
+        assert_eq!(
+            render(&settings, true),
+            format!(
+                "{}\tdefaults.collate = \"locale\"\n{}\tdefaults.interactive = true\n",
+                project.to_string_lossy(),
+                user.to_string_lossy()
+            )
+        );
+
+        fs::write(&project, "[colors]\nerror = \"red\"\n").unwrap();
+        assert!(load(std::slice::from_ref(&project)).is_err());
+        fs::write(&project, "[defaults\n").unwrap();
+        assert!(load(&[project]).is_err());
+    }
+
+    #[named]
+    #[test]
+    fn get_set() {
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("pmv").join("config.toml");
+
+        set(&path, "defaults.interactive", parse_value("true")).unwrap();
+        set(&path, "defaults.collate", parse_value("natural")).unwrap();
+        set(&path, "defaults.ext", parse_value("[\"jpg\", \"png\"]")).unwrap();
+        set(&path, "defaults.interactive", parse_value("false")).unwrap();
+        let settings = load(std::slice::from_ref(&path)).unwrap();
+        assert_eq!(
+            get(&settings, "defaults.interactive"),
+            Some(String::from("false"))
+        );
+        assert_eq!(
+            get(&settings, "defaults.collate"),
+            Some(String::from("natural"))
+        );
+        assert_eq!(
+            get(&settings, "defaults.ext"),
+            Some(String::from("[\"jpg\", \"png\"]"))
+        );
+        assert_eq!(get(&settings, "defaults.verbose"), None);
+
+        assert!(set(&path, "colors.error", parse_value("red")).is_err());
+        assert!(set(&path, "interactive", parse_value("true")).is_err());
+    }
+
+    #[test]
+    fn args() {
+        let command = clap::Command::new("pmv")
+            .arg(
+                clap::Arg::new("dry-run")
+                    .long("dry-run")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                clap::Arg::new("verbose")
+                    .long("verbose")
+                    .action(clap::ArgAction::Count),
+            )
+            .arg(
+                clap::Arg::new("ext")
+                    .long("ext")
+                    .action(clap::ArgAction::Append),
+            )
+            .arg(clap::Arg::new("collate").long("collate"));
+        let setting = |key: &str, value: Value| Setting {
+            key: String::from(key),
+            value,
+            origin: PathBuf::from("config.toml"),
+        };
+        let settings = vec![
+            setting("defaults.collate", Value::from("natural")),
+            setting("defaults.dry-run", Value::from(true)),
+            setting("defaults.ext", Value::from(vec!["jpg", "png"])),
+            setting("defaults.verbose", Value::from(2)),
+        ];
+        assert_eq!(
+            default_args(&settings, &command, &|_| false),
+            Ok([
+                "--collate=natural",
+                "--dry-run",
+                "--ext=jpg",
+                "--ext=png",
+                "--verbose",
+                "--verbose"
+            ]
+            .iter()
+            .map(OsString::from)
+            .collect())
+        );
+
+        // The command line takes precedence
+        assert_eq!(
+            default_args(&settings, &command, &|id| id != "dry-run"),
+            Ok(vec![OsString::from("--dry-run")])
+        );
+
+        let settings = vec![setting("defaults.dry-run", Value::from("yes"))];
+        assert!(default_args(&settings, &command, &|_| false).is_err());
+        let settings = vec![setting("defaults.no-such-option", Value::from(true))];
+        assert!(default_args(&settings, &command, &|_| false).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use crate::estimate::{format_bytes, size_of};
+use crate::quote::{quote, quote_path};
+use crate::Action;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use unicode_width::UnicodeWidthStr;
+
+/// Renders a summary of the actions aggregated by destination directory.
+///
+/// Directories are shown relative to `base` if possible, in the order of their paths.
+pub fn render_summary(actions: &[Action], base: &Path) -> String {
+    let mut dirs: BTreeMap<PathBuf, (usize, u64)> = BTreeMap::new();
+    for action in actions {
+        let entry = dirs.entry(dest_dir(action)).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += size_of(action.src());
+    }
+
+    let mut out = String::new();
+    for (dir, (num_files, size)) in dirs {
+        out.push_str(&format!(
+            "-> {}{}: {} file{}, {}\n",
+            relative(&dir, base),
+            MAIN_SEPARATOR,
+            num_files,
+            if num_files == 1 { "" } else { "s" },
+            format_bytes(size)
+        ));
+    }
+    out
+}
+
+/// Renders the actions grouped by destination directory.
+///
+/// Each directory is shown as a heading followed by the moves into it, in the order of the
+/// directories' paths. Paths are shown relative to `base` if possible.
+pub fn render_groups(actions: &[Action], base: &Path) -> String {
+    let mut dirs: BTreeMap<PathBuf, Vec<&Action>> = BTreeMap::new();
+    for action in actions {
+        dirs.entry(dest_dir(action)).or_default().push(action);
+    }
+    let id_max_len = actions
+        .iter()
+        .filter_map(|a| a.id())
+        .map(|id| id.to_string().len())
+        .fold(0, cmp::max);
+
+    let mut out = String::new();
+    for (dir, actions) in dirs {
+        out.push_str(&format!(
+            "{}{} ({} file{})\n",
+            relative(&dir, base),
+            MAIN_SEPARATOR,
+            actions.len(),
+            if actions.len() == 1 { "" } else { "s" },
+        ));
+        let srcs: Vec<String> = actions.iter().map(|a| relative(a.src(), base)).collect();
+        let src_max_len = srcs.iter().map(|src| src.width()).fold(0, cmp::max);
+        for (action, src) in actions.iter().zip(srcs) {
+            out.push_str("    ");
+            if let Some(id) = action.id() {
+                out.push_str(&format!("[{:>width$}] ", id, width = id_max_len));
+            }
+            let dest = action.final_dest();
+            let name = dest.file_name();
+            out.push_str(&format!(
+                "{}{} --> {}\n",
+                src,
+                " ".repeat(src_max_len - src.width()),
+                quote(&name.unwrap_or_default().to_string_lossy()),
+            ));
+        }
+    }
+    out
+}
+
+/// Renders the number of actions per destination directory, one directory per line.
+pub fn render_counts(actions: &[Action], base: &Path) -> String {
+    let mut dirs: BTreeMap<PathBuf, usize> = BTreeMap::new();
+    for action in actions {
+        *dirs.entry(dest_dir(action)).or_insert(0) += 1;
+    }
+    dirs.iter()
+        .map(|(dir, n)| format!("{} {}{}\n", n, relative(dir, base), MAIN_SEPARATOR))
+        .collect()
+}
+
+/// Returns the directory which an action moves a file into.
+/// Renders how many files each rule of `--rules` matched and moved.
+///
+/// With `dry_run`, `moved` are the numbers of files to be moved.
+pub fn render_rule_stats(
+    rules: &[(String, String)],
+    matched: &[usize],
+    moved: &[usize],
+    dry_run: bool,
+) -> String {
+    let mut out = String::new();
+    for (i, (src_ptn, dest_ptn)) in rules.iter().enumerate() {
+        out.push_str(&format!(
+            "rule {}: {} matched, {} {} (\"{}\" --> \"{}\")\n",
+            i + 1,
+            matched[i],
+            moved[i],
+            if dry_run { "to move" } else { "moved" },
+            quote(src_ptn),
+            quote(dest_ptn)
+        ));
+    }
+    out
+}
+
+fn dest_dir(action: &Action) -> PathBuf {
+    let dest = action.final_dest();
+    match dest.parent() {
+        Some(dir) => dir.to_owned(),
+        None => dest,
+    }
+}
+
+fn relative(path: &Path, base: &Path) -> String {
+    let path = path.strip_prefix(base).unwrap_or(path);
+    let mut path = quote_path(path);
+    if path.is_empty() {
+        path.push('.');
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use function_name::named;
+    use std::fs;
+    use std::path::MAIN_SEPARATOR as SEP;
+
+    #[test]
+    fn rule_stats() {
+        let rules = vec![
+            (String::from("*.txt"), String::from("txt/#1.txt")),
+            (String::from("*.png"), String::from("img/#1.png")),
+        ];
+        assert_eq!(
+            render_rule_stats(&rules, &[3, 0], &[2, 0], false),
+            "rule 1: 3 matched, 2 moved (\"*.txt\" --> \"txt/#1.txt\")\n\
+             rule 2: 0 matched, 0 moved (\"*.png\" --> \"img/#1.png\")\n"
+        );
+        assert_eq!(
+            render_rule_stats(&rules[..1], &[3], &[3], true),
+            "rule 1: 3 matched, 3 to move (\"*.txt\" --> \"txt/#1.txt\")\n"
+        );
+    }
+
+    #[named]
+    #[test]
+    fn summary() {
+        let dir = std::env::current_dir()
+            .unwrap()
+            .join("temp")
+            .join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("old")).unwrap();
+        fs::write(dir.join("a"), "aaaa").unwrap();
+        fs::write(dir.join("b"), "bb").unwrap();
+        fs::write(dir.join("c"), vec![0u8; 2048]).unwrap();
+
+        let actions = vec![
+            Action::new(dir.join("a"), dir.join("new/a")),
+            Action::new(dir.join("b"), dir.join("new/b")),
+            Action::new(dir.join("c"), dir.join("old")),
+        ];
+        assert_eq!(
+            render_summary(&actions, &dir),
+            format!(
+                "-> new{}: 2 files, 6 bytes\n-> old{}: 1 file, 2.0 KiB\n",
+                SEP, SEP
+            )
+        );
+    }
+
+    #[test]
+    fn counts() {
+        let actions = vec![
+            Action::new("a", "new/a"),
+            Action::new("b", "new/b"),
+            Action::new("c", "old/c"),
+        ];
+        assert_eq!(
+            render_counts(&actions, Path::new("")),
+            format!("2 new{0}\n1 old{0}\n", SEP)
+        );
+    }
+
+    #[named]
+    #[test]
+    fn groups() {
+        let dir = std::env::current_dir()
+            .unwrap()
+            .join("temp")
+            .join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("old")).unwrap();
+
+        let actions = vec![
+            Action::new(dir.join("a"), dir.join("new/x")).with_id(1),
+            Action::new(dir.join("写真"), dir.join("old")).with_id(2),
+            Action::new(dir.join("bb"), dir.join("old")).with_id(3),
+            Action::new(dir.join("c"), dir.join("new/c")).with_id(10),
+        ];
+        assert_eq!(
+            render_groups(&actions, &dir),
+            format!(
+                "new{0} (2 files)\n    [ 1] a --> x\n    [10] c --> c\n\
+                 old{0} (2 files)\n    [ 2] 写真 --> 写真\n    [ 3] bb   --> bb\n",
+                SEP
+            )
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// A symbolic link rewritten to point at the new location of its target.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FixedLink {
+    /// Location of the symbolic link.
+    pub link: PathBuf,
+    /// Target of the link before rewriting.
+    pub old_target: PathBuf,
+    /// Target of the link after rewriting.
+    pub new_target: PathBuf,
+}
+
+/// Rewrites symbolic links under `scope` which pointed at moved files or directories.
+///
+/// `moves` are pairs of the original and the new absolute path of each moved entry. A link
+/// pointing into a moved directory is rewritten too, and a relative link which was moved itself
+/// is rewritten so that it still points at the same file. Relative links are kept relative.
+///
+/// If `dry_run` is true, links are not rewritten but found as if the moves were done, so `scope`
+/// is searched before the moves in that case. Returns the rewritten links and errors occurred.
+pub fn fix_symlinks(
+    scope: &Path,
+    moves: &[(PathBuf, PathBuf)],
+    dry_run: bool,
+) -> Vec<Result<FixedLink, String>> {
+    let mut links = Vec::new();
+    let mut results = Vec::new();
+    find_symlinks(&normalize(scope), &mut links, &mut results);
+
+    for link in links {
+        // Locations of the link before and after the moves
+        let (before, after) = if dry_run {
+            let after = map_path(&link, moves, false).unwrap_or_else(|| link.clone());
+            (link, after)
+        } else {
+            let before = map_path(&link, moves, true).unwrap_or_else(|| link.clone());
+            (before, link)
+        };
+
+        let old_target = match fs::read_link(&after).or_else(|_| fs::read_link(&before)) {
+            Ok(target) => target,
+            Err(err) => {
+                results.push(Err(format!(
+                    "failed to read link \"{}\": {}",
+                    before.to_string_lossy(),
+                    err
+                )));
+                continue;
+            }
+        };
+        let resolved = normalize(&before.parent().unwrap_or(&before).join(&old_target));
+        let new_resolved = map_path(&resolved, moves, false);
+        let new_target = match (new_resolved, old_target.is_absolute()) {
+            (Some(path), true) => path,
+            (Some(path), false) => relative_path(after.parent().unwrap_or(&after), &path),
+            (None, false) if before != after => {
+                relative_path(after.parent().unwrap_or(&after), &resolved)
+            }
+            _ => continue,
+        };
+        if new_target == old_target {
+            continue;
+        }
+
+        if !dry_run {
+            if let Err(err) = replace_symlink(&after, &new_target) {
+                results.push(Err(format!(
+                    "failed to rewrite link \"{}\": {}",
+                    after.to_string_lossy(),
+                    err
+                )));
+                continue;
+            }
+        }
+        results.push(Ok(FixedLink {
+            link: after,
+            old_target,
+            new_target,
+        }));
+    }
+    results
+}
+
+/// Collects symbolic links under the directory, without following them.
+fn find_symlinks(
+    dir: &Path,
+    links: &mut Vec<PathBuf>,
+    errors: &mut Vec<Result<FixedLink, String>>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            errors.push(Err(format!(
+                "failed to read directory \"{}\": {}",
+                dir.to_string_lossy(),
+                err
+            )));
+            return;
+        }
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() => links.push(path),
+            Ok(file_type) if file_type.is_dir() => find_symlinks(&path, links, errors),
+            _ => (),
+        }
+    }
+}
+
+/// Maps a path to its location after the moves (or before the moves if `reverse` is true.)
+///
+/// Returns `None` if neither the path nor its ancestors were moved.
+fn map_path(path: &Path, moves: &[(PathBuf, PathBuf)], reverse: bool) -> Option<PathBuf> {
+    moves
+        .iter()
+        .map(|(src, dest)| if reverse { (dest, src) } else { (src, dest) })
+        .filter_map(|(from, to)| path.strip_prefix(from).ok().map(|rest| (from, to, rest)))
+        .max_by_key(|(from, _, _)| from.components().count())
+        .map(|(_, to, rest)| {
+            if rest.as_os_str().is_empty() {
+                to.clone()
+            } else {
+                to.join(rest)
+            }
+        })
+}
+
+/// Removes `.` and `..` in the path without accessing the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Returns a relative path from the directory `base` to `path`, both of which are normalized.
+fn relative_path(base: &Path, path: &Path) -> PathBuf {
+    let base: Vec<Component> = base.components().collect();
+    let path: Vec<Component> = path.components().collect();
+    let common = base
+        .iter()
+        .zip(path.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &path[common..] {
+        relative.push(component);
+    }
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+/// Replaces a symbolic link with a new one pointing at `target`.
+fn replace_symlink(link: &Path, target: &Path) -> io::Result<()> {
+    fs::remove_file(link)?;
+    create_symlink(link, target)
+}
+
+#[cfg(unix)]
+fn create_symlink(link: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(link: &Path, target: &Path) -> io::Result<()> {
+    let resolved = link.parent().unwrap_or(link).join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_link: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symbolic links are not supported on this platform",
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use function_name::named;
+    use std::os::unix::fs::symlink;
+
+    fn prepare_test(id: &str) -> PathBuf {
+        let dir = std::env::current_dir().unwrap().join("temp").join(id);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("assets")).unwrap();
+        fs::create_dir_all(dir.join("links")).unwrap();
+        fs::write(dir.join("assets/a.png"), "a").unwrap();
+        symlink("../assets/a.png", dir.join("links/relative")).unwrap();
+        symlink(dir.join("assets"), dir.join("links/absolute")).unwrap();
+        symlink("../missing", dir.join("links/unrelated")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(
+            relative_path(Path::new("/a/b/c"), Path::new("/a/d/e")),
+            PathBuf::from("../../d/e")
+        );
+        assert_eq!(
+            relative_path(Path::new("/a"), Path::new("/a/b")),
+            PathBuf::from("b")
+        );
+        assert_eq!(normalize(Path::new("/a/./b/../c")), PathBuf::from("/a/c"));
+    }
+
+    #[named]
+    #[test]
+    fn moved_directory() {
+        let dir = prepare_test(function_name!());
+        let moves = vec![(dir.join("assets"), dir.join("static"))];
+        fs::rename(dir.join("assets"), dir.join("static")).unwrap();
+
+        let results = fix_symlinks(&dir.join("links"), &moves, false);
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            fs::read_link(dir.join("links/relative")).unwrap(),
+            PathBuf::from("../static/a.png")
+        );
+        assert_eq!(
+            fs::read_link(dir.join("links/absolute")).unwrap(),
+            dir.join("static")
+        );
+        assert_eq!(fs::read_to_string(dir.join("links/relative")).unwrap(), "a");
+    }
+
+    #[named]
+    #[test]
+    fn moved_link() {
+        let dir = prepare_test(function_name!());
+        let moves = vec![(dir.join("links/relative"), dir.join("relative"))];
+        fs::rename(dir.join("links/relative"), dir.join("relative")).unwrap();
+
+        let results = fix_symlinks(&dir, &moves, false);
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            fs::read_link(dir.join("relative")).unwrap(),
+            PathBuf::from("assets/a.png")
+        );
+    }
+
+    #[named]
+    #[test]
+    fn fix_symlinks_dry_run() {
+        let dir = prepare_test(function_name!());
+        let moves = vec![(dir.join("assets/a.png"), dir.join("b.png"))];
+
+        let results = fix_symlinks(&dir.join("links"), &moves, true);
+        assert_eq!(
+            results,
+            vec![Ok(FixedLink {
+                link: dir.join("links/relative"),
+                old_target: PathBuf::from("../assets/a.png"),
+                new_target: PathBuf::from("../b.png"),
+            })]
+        );
+        assert_eq!(
+            fs::read_link(dir.join("links/relative")).unwrap(),
+            PathBuf::from("../assets/a.png")
+        );
+    }
+}
@@ -0,0 +1,749 @@
+use crate::audio::{read_tags, AudioTags};
+use crate::dimensions::read_dimensions;
+use crate::kind::{sniff, FileType};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use std::cell::{Cell, OnceCell, RefCell};
+use std::fs::{self, Metadata};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+/// What to do if a file lacks a tag or a property which a token refers to.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MissingTag {
+    /// Skips the file.
+    #[default]
+    Skip,
+    /// Uses `Unknown` instead.
+    Unknown,
+}
+
+/// Options for resolving tokens.
+#[derive(Debug, Default)]
+pub struct TokenOptions {
+    pub missing_tag: MissingTag,
+    /// The time `#{now}` refers to, fixed when it is first resolved so that every file of a run
+    /// gets the same time.
+    pub now: OnceCell<DateTime<Local>>,
+}
+
+/// Resolves `#{...}` tokens in a DEST pattern for a source file.
+///
+/// Metadata of the source file is read lazily, at most once, when a token needs it.
+pub struct TokenContext<'a> {
+    src: &'a Path,
+    options: &'a TokenOptions,
+    depth: usize,
+    metadata: OnceCell<Result<Metadata, String>>,
+    file_type: OnceCell<Result<FileType, String>>,
+    tags: OnceCell<Result<AudioTags, String>>,
+    dimensions: OnceCell<Result<Option<(u32, u32)>, String>>,
+    warnings: RefCell<Vec<String>>,
+    skipped: Cell<bool>,
+}
+
+impl<'a> TokenContext<'a> {
+    pub fn new(src: &'a Path, options: &'a TokenOptions) -> TokenContext<'a> {
+        TokenContext {
+            src,
+            options,
+            depth: 0,
+            metadata: OnceCell::new(),
+            file_type: OnceCell::new(),
+            tags: OnceCell::new(),
+            dimensions: OnceCell::new(),
+            warnings: RefCell::new(Vec::new()),
+            skipped: Cell::new(false),
+        }
+    }
+
+    /// Sets how deep the file is relative to the directory where searching started.
+    pub fn with_depth(mut self, depth: usize) -> TokenContext<'a> {
+        self.depth = depth;
+        self
+    }
+
+    /// Returns warnings about the tokens resolved so far.
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    /// Tests whether the file should be skipped or not.
+    pub fn skipped(&self) -> bool {
+        self.skipped.get()
+    }
+
+    /// Returns the replacement for a token, or `None` if the token is unknown.
+    pub fn resolve(&self, name: &str, arg: Option<&str>) -> Result<Option<String>, String> {
+        match name {
+            "sizebucket" => self.size_bucket(arg).map(Some),
+            "agebucket" => self.age_bucket(arg).map(Some),
+            "mime" => self.file_type().map(|t| Some(String::from(t.mime))),
+            "kind" => self.file_type().map(|t| Some(String::from(t.kind))),
+            "artist" => self.tag(name, |t| t.artist.clone()).map(Some),
+            "album" => self.tag(name, |t| t.album.clone()).map(Some),
+            "title" => self.tag(name, |t| t.title.clone()).map(Some),
+            "track" => {
+                let width = parse_width(arg)?;
+                self.tag(name, |t| {
+                    t.track.map(|n| format!("{:0width$}", n, width = width))
+                })
+                .map(Some)
+            }
+            "width" => self.dimension(name, |(width, _)| width).map(Some),
+            "height" => self.dimension(name, |(_, height)| height).map(Some),
+            "depth" => Ok(Some(self.depth.to_string())),
+            "env" => env_var(arg).map(Some),
+            "now" => format_time(self.options.now.get_or_init(Local::now), arg).map(Some),
+            "host" => HOST.get_or_init(host_name).clone().map(Some),
+            "user" => USER.get_or_init(user_name).clone().map(Some),
+            _ => Ok(self.ancestor_name(name)),
+        }
+    }
+
+    /// Resolves `-N`, the name of the N-th directory containing the file (`-1` for the parent.)
+    fn ancestor_name(&self, name: &str) -> Option<String> {
+        let n: usize = name.strip_prefix('-')?.parse().ok().filter(|n| 0 < *n)?;
+        let name = self
+            .src
+            .ancestors()
+            .nth(n)
+            .and_then(|dir| dir.file_name())
+            .map(|name| name.to_string_lossy().into_owned());
+        Some(name.unwrap_or_default())
+    }
+
+    fn metadata(&self) -> Result<&Metadata, String> {
+        self.metadata
+            .get_or_init(|| {
+                fs::metadata(self.src).map_err(|err| {
+                    format!(
+                        "failed to read metadata of \"{}\": {}",
+                        self.src.to_string_lossy(),
+                        err
+                    )
+                })
+            })
+            .as_ref()
+            .map_err(|err| err.clone())
+    }
+
+    fn file_type(&self) -> Result<FileType, String> {
+        self.file_type.get_or_init(|| sniff(self.src)).clone()
+    }
+
+    /// Returns a tag of an audio file, handling missing tags as configured.
+    ///
+    /// The value is made safe as a file name (see `sanitize_name`) as it comes from the file.
+    fn tag<F>(&self, name: &str, get: F) -> Result<String, String>
+    where
+        F: Fn(&AudioTags) -> Option<String>,
+    {
+        let tags = self
+            .tags
+            .get_or_init(|| read_tags(self.src))
+            .as_ref()
+            .map_err(|err| err.clone())?;
+        match get(tags) {
+            Some(value) => Ok(sanitize_name(&value)),
+            None => Ok(self.missing(&format!("{} tag", name))),
+        }
+    }
+
+    /// Returns the width or the height of an image or a video, handling unknown ones as
+    /// configured.
+    fn dimension<F>(&self, name: &str, get: F) -> Result<String, String>
+    where
+        F: Fn((u32, u32)) -> u32,
+    {
+        let dimensions = self
+            .dimensions
+            .get_or_init(|| read_dimensions(self.src))
+            .clone()?;
+        match dimensions {
+            Some(dimensions) => Ok(get(dimensions).to_string()),
+            None => Ok(self.missing(name)),
+        }
+    }
+
+    /// Returns the replacement for a missing value, recording a warning about it.
+    fn missing(&self, what: &str) -> String {
+        let path = self.src.to_string_lossy();
+        match self.options.missing_tag {
+            MissingTag::Skip => {
+                self.skipped.set(true);
+                self.warnings
+                    .borrow_mut()
+                    .push(format!("skipped \"{}\"; it has no {}", path, what));
+                String::new()
+            }
+            MissingTag::Unknown => {
+                self.warnings
+                    .borrow_mut()
+                    .push(format!("\"{}\" has no {}; using \"Unknown\"", path, what));
+                String::from("Unknown")
+            }
+        }
+    }
+
+    /// Labels the file size with the range of the bucket it falls in.
+    ///
+    /// For `1M,10M`, the labels are `0-1M`, `1M-10M` and `10M+`. Lower bounds are inclusive.
+    fn size_bucket(&self, arg: Option<&str>) -> Result<String, String> {
+        let arg = match arg {
+            Some(arg) if !arg.is_empty() => arg,
+            _ => return Err(String::from("#{sizebucket} requires sizes such as 1M,10M")),
+        };
+        let mut bounds = Vec::new();
+        for label in arg.split(',') {
+            let size = parse_size(label)
+                .ok_or_else(|| format!("invalid size in #{{sizebucket}}: \"{}\"", label))?;
+            if let Some(&(_, prev)) = bounds.last() {
+                if size <= prev {
+                    return Err(format!(
+                        "sizes in #{{sizebucket}} must be in ascending order: \"{}\"",
+                        arg
+                    ));
+                }
+            }
+            bounds.push((label, size));
+        }
+
+        let size = self.metadata()?.len();
+        let mut lower = "0";
+        for (label, bound) in bounds {
+            if size < bound {
+                return Ok(format!("{}-{}", lower, label));
+            }
+            lower = label;
+        }
+        Ok(format!("{}+", lower))
+    }
+
+    /// Labels the time since the file was last modified with the bucket it falls in.
+    ///
+    /// Well-known periods are labeled by name, so `7d,30d,365d` (the default) gives `week`,
+    /// `month`, `year` and `older`. Other periods are labeled as written (e.g. `3d`).
+    fn age_bucket(&self, arg: Option<&str>) -> Result<String, String> {
+        let arg = arg.filter(|arg| !arg.is_empty()).unwrap_or("7d,30d,365d");
+        let mut bounds = Vec::new();
+        for label in arg.split(',') {
+            let age = parse_duration(label)
+                .ok_or_else(|| format!("invalid period in #{{agebucket}}: \"{}\"", label))?;
+            if let Some(&(_, prev)) = bounds.last() {
+                if age <= prev {
+                    return Err(format!(
+                        "periods in #{{agebucket}} must be in ascending order: \"{}\"",
+                        arg
+                    ));
+                }
+            }
+            bounds.push((label, age));
+        }
+
+        let mtime = self.metadata()?.modified().map_err(|err| {
+            format!(
+                "failed to get modification time of \"{}\": {}",
+                self.src.to_string_lossy(),
+                err
+            )
+        })?;
+        let age = SystemTime::now()
+            .duration_since(mtime)
+            .unwrap_or(Duration::from_secs(0));
+        for (label, bound) in bounds {
+            if age < bound {
+                let days = match bound.as_secs() % 86400 {
+                    0 => bound.as_secs() / 86400,
+                    _ => 0,
+                };
+                return Ok(String::from(match days {
+                    1 => "day",
+                    7 => "week",
+                    30 | 31 => "month",
+                    365 | 366 => "year",
+                    _ => label,
+                }));
+            }
+        }
+        Ok(String::from("older"))
+    }
+}
+
+// Names of the machine and the user, which never change during a run
+static HOST: OnceLock<Result<String, String>> = OnceLock::new();
+static USER: OnceLock<Result<String, String>> = OnceLock::new();
+
+/// Returns the name of the machine for `#{host}`.
+#[cfg(unix)]
+fn host_name() -> Result<String, String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        let err = std::io::Error::last_os_error();
+        return Err(format!(
+            "failed to get the host name for #{{host}}: {}",
+            err
+        ));
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// Returns the name of the machine for `#{host}`.
+#[cfg(not(unix))]
+fn host_name() -> Result<String, String> {
+    std::env::var("COMPUTERNAME")
+        .map_err(|_| String::from("failed to get the host name for #{host}"))
+}
+
+/// Returns the name of the user running pmv for `#{user}`.
+#[cfg(unix)]
+fn user_name() -> Result<String, String> {
+    use std::ffi::CStr;
+
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let rc = unsafe {
+        libc::getpwuid_r(
+            libc::geteuid(),
+            &mut passwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if rc == 0 && !result.is_null() {
+        let name = unsafe { CStr::from_ptr(passwd.pw_name) };
+        return Ok(name.to_string_lossy().into_owned());
+    }
+    // The user may not be in the password database (e.g. in a container)
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .map_err(|_| String::from("failed to get the user name for #{user}"))
+}
+
+/// Returns the name of the user running pmv for `#{user}`.
+#[cfg(not(unix))]
+fn user_name() -> Result<String, String> {
+    std::env::var("USERNAME").map_err(|_| String::from("failed to get the user name for #{user}"))
+}
+
+/// Returns the value of the environment variable named by `#{env:VAR}`.
+///
+/// Fails if the variable is not set so that a typo in a wrapper does not silently move files to
+/// unexpected places.
+fn env_var(arg: Option<&str>) -> Result<String, String> {
+    let name = match arg {
+        Some(name) if !name.is_empty() => name,
+        _ => {
+            return Err(String::from(
+                "#{env} requires a variable name such as #{env:HOME}",
+            ))
+        }
+    };
+    match std::env::var(name) {
+        Ok(value) => Ok(value),
+        Err(std::env::VarError::NotPresent) => Err(format!(
+            "environment variable \"{}\" in #{{env}} is not set",
+            name
+        )),
+        Err(std::env::VarError::NotUnicode(_)) => Err(format!(
+            "environment variable \"{}\" in #{{env}} is not valid Unicode",
+            name
+        )),
+    }
+}
+
+/// Formats a time with a strftime-like format such as `%Y%m%d-%H%M%S` (`%Y%m%d` by default.)
+fn format_time(time: &DateTime<Local>, arg: Option<&str>) -> Result<String, String> {
+    let format = match arg {
+        Some(format) if !format.is_empty() => format,
+        _ => "%Y%m%d",
+    };
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.contains(&Item::Error) {
+        return Err(format!("invalid format in #{{now}}: \"{}\"", format));
+    }
+    Ok(time.format_with_items(items.into_iter()).to_string())
+}
+
+/// Makes a value read from a file usable as a single file name in DEST.
+///
+/// Path separators and NUL characters are replaced with `_`, and so are the dots of `.` and
+/// `..` so that a crafted value cannot refer to a parent directory.
+fn sanitize_name(value: &str) -> String {
+    if value == "." || value == ".." {
+        return "_".repeat(value.len());
+    }
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Parses a printf-like zero padding such as `%02` into the width.
+fn parse_width(arg: Option<&str>) -> Result<usize, String> {
+    let arg = match arg {
+        Some(arg) => arg,
+        None => return Ok(0),
+    };
+    arg.strip_prefix('%')
+        .and_then(|width| width.parse().ok())
+        .ok_or_else(|| format!("invalid format in #{{track}}: \"{}\"", arg))
+}
+
+/// Parses a period such as `90s`, `30m`, `12h`, `7d` or `2w` into a duration.
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+    let number: u64 = number.parse().ok()?;
+    let seconds = match unit.to_ascii_lowercase().as_str() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    number.checked_mul(seconds).map(Duration::from_secs)
+}
+
+/// Parses a size such as `512`, `64K`, `1M` or `2GB` into bytes, using binary prefixes.
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+    let number: u64 = number.parse().ok()?;
+    let unit = unit.to_ascii_uppercase();
+    let shift = match unit.strip_suffix('B').unwrap_or(&unit) {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return None,
+    };
+    number.checked_mul(1 << shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use function_name::named;
+    use std::path::PathBuf;
+
+    fn prepare_test(id: &str) -> PathBuf {
+        let dir = std::env::current_dir().unwrap().join("temp").join(id);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("empty"), "").unwrap();
+        fs::write(dir.join("2k"), vec![0u8; 2048]).unwrap();
+        fs::write(dir.join("1m"), vec![0u8; 1 << 20]).unwrap();
+        dir
+    }
+
+    mod path_tokens {
+        use super::*;
+
+        #[test]
+        fn depth() {
+            let options = TokenOptions::default();
+            let context = TokenContext::new(Path::new("a/b/c.txt"), &options).with_depth(3);
+            assert_eq!(context.resolve("depth", None), Ok(Some(String::from("3"))));
+        }
+
+        #[test]
+        fn ancestors() {
+            let options = TokenOptions::default();
+            let context = TokenContext::new(Path::new("a/b/c.txt"), &options);
+            assert_eq!(context.resolve("-1", None), Ok(Some(String::from("b"))));
+            assert_eq!(context.resolve("-2", None), Ok(Some(String::from("a"))));
+            assert_eq!(context.resolve("-3", None), Ok(Some(String::new())));
+            assert_eq!(context.resolve("-0", None), Ok(None));
+            assert_eq!(context.resolve("-x", None), Ok(None));
+        }
+
+        #[test]
+        fn env() {
+            std::env::set_var("PMV_TEST_ENV_TOKEN", "batch-1");
+            std::env::remove_var("PMV_TEST_ENV_TOKEN_UNSET");
+            let options = TokenOptions::default();
+            let context = TokenContext::new(Path::new("a/b/c.txt"), &options);
+            assert_eq!(
+                context.resolve("env", Some("PMV_TEST_ENV_TOKEN")),
+                Ok(Some(String::from("batch-1")))
+            );
+            assert!(context
+                .resolve("env", Some("PMV_TEST_ENV_TOKEN_UNSET"))
+                .is_err());
+            assert!(context.resolve("env", None).is_err());
+            assert!(context.resolve("env", Some("")).is_err());
+        }
+
+        #[test]
+        fn host_and_user() {
+            let options = TokenOptions::default();
+            let context = TokenContext::new(Path::new("a/b/c.txt"), &options);
+            let host = context.resolve("host", None).unwrap().unwrap();
+            let user = context.resolve("user", None).unwrap().unwrap();
+            assert!(!host.is_empty());
+            assert!(!user.is_empty());
+            assert_eq!(context.resolve("host", None), Ok(Some(host)));
+        }
+
+        #[test]
+        fn now() {
+            use chrono::TimeZone;
+
+            let options = TokenOptions::default();
+            let time = Local.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+            options.now.set(time).unwrap();
+            let context = TokenContext::new(Path::new("a/b/c.txt"), &options);
+            assert_eq!(
+                context.resolve("now", Some("%Y%m%d-%H%M%S")),
+                Ok(Some(String::from("20240102-030405")))
+            );
+            assert_eq!(
+                context.resolve("now", None),
+                Ok(Some(String::from("20240102")))
+            );
+            assert!(context.resolve("now", Some("%Q")).is_err());
+        }
+    }
+
+    mod parse_size {
+        use super::*;
+
+        #[test]
+        fn units() {
+            assert_eq!(parse_size("512"), Some(512));
+            assert_eq!(parse_size("64K"), Some(64 << 10));
+            assert_eq!(parse_size("1m"), Some(1 << 20));
+            assert_eq!(parse_size("2GB"), Some(2 << 30));
+            assert_eq!(parse_size("1T"), Some(1 << 40));
+        }
+
+        #[test]
+        fn invalid() {
+            assert_eq!(parse_size(""), None);
+            assert_eq!(parse_size("M"), None);
+            assert_eq!(parse_size("1X"), None);
+            assert_eq!(parse_size("1KM"), None);
+        }
+    }
+
+    mod parse_width {
+        use super::*;
+
+        #[test]
+        fn widths() {
+            assert_eq!(parse_width(None), Ok(0));
+            assert_eq!(parse_width(Some("%02")), Ok(2));
+            assert_eq!(parse_width(Some("%3")), Ok(3));
+            assert!(parse_width(Some("02")).is_err());
+            assert!(parse_width(Some("%x")).is_err());
+        }
+    }
+
+    mod parse_duration {
+        use super::*;
+
+        #[test]
+        fn units() {
+            assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+            assert_eq!(parse_duration("30m"), Some(Duration::from_secs(1800)));
+            assert_eq!(parse_duration("12H"), Some(Duration::from_secs(43200)));
+            assert_eq!(parse_duration("7d"), Some(Duration::from_secs(604800)));
+            assert_eq!(parse_duration("2w"), Some(Duration::from_secs(1209600)));
+        }
+
+        #[test]
+        fn invalid() {
+            assert_eq!(parse_duration(""), None);
+            assert_eq!(parse_duration("7"), None);
+            assert_eq!(parse_duration("d"), None);
+            assert_eq!(parse_duration("7y"), None);
+        }
+    }
+
+    mod age_bucket {
+        use super::*;
+        use std::fs::File;
+
+        const DAY: u64 = 24 * 60 * 60;
+
+        #[named]
+        #[test]
+        fn labels() {
+            let dir = prepare_test(function_name!());
+            let bucket = |days_ago: u64, arg: Option<&str>| {
+                let path = dir.join("empty");
+                let mtime = SystemTime::now() - Duration::from_secs(days_ago * DAY + 60);
+                File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(mtime)
+                    .unwrap();
+                TokenContext::new(&path, &TokenOptions::default()).resolve("agebucket", arg)
+            };
+            assert_eq!(bucket(0, None), Ok(Some(String::from("week"))));
+            assert_eq!(bucket(10, None), Ok(Some(String::from("month"))));
+            assert_eq!(bucket(100, None), Ok(Some(String::from("year"))));
+            assert_eq!(bucket(1000, None), Ok(Some(String::from("older"))));
+            assert_eq!(bucket(0, Some("1d,3d")), Ok(Some(String::from("day"))));
+            assert_eq!(bucket(2, Some("1d,3d")), Ok(Some(String::from("3d"))));
+        }
+
+        #[named]
+        #[test]
+        fn invalid_periods() {
+            let dir = prepare_test(function_name!());
+            let path = dir.join("empty");
+            let options = TokenOptions::default();
+            let context = TokenContext::new(&path, &options);
+            assert!(context.resolve("agebucket", Some("7y")).is_err());
+            assert!(context.resolve("agebucket", Some("30d,7d")).is_err());
+        }
+    }
+
+    mod size_bucket {
+        use super::*;
+
+        #[named]
+        #[test]
+        fn buckets() {
+            let dir = prepare_test(function_name!());
+            let bucket = |name: &str| {
+                let path = dir.join(name);
+                TokenContext::new(&path, &TokenOptions::default())
+                    .resolve("sizebucket", Some("1K,1M"))
+            };
+            assert_eq!(bucket("empty"), Ok(Some(String::from("0-1K"))));
+            assert_eq!(bucket("2k"), Ok(Some(String::from("1K-1M"))));
+            assert_eq!(bucket("1m"), Ok(Some(String::from("1M+"))));
+        }
+
+        #[named]
+        #[test]
+        fn invalid_args() {
+            let dir = prepare_test(function_name!());
+            let path = dir.join("empty");
+            let options = TokenOptions::default();
+            let context = TokenContext::new(&path, &options);
+            assert!(context.resolve("sizebucket", None).is_err());
+            assert!(context.resolve("sizebucket", Some("1X")).is_err());
+            assert!(context.resolve("sizebucket", Some("1M,1K")).is_err());
+        }
+
+        #[named]
+        #[test]
+        fn not_found() {
+            let dir = prepare_test(function_name!());
+            let path = dir.join("missing");
+            let options = TokenOptions::default();
+            let context = TokenContext::new(&path, &options);
+            assert!(context.resolve("sizebucket", Some("1K")).is_err());
+        }
+    }
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(sanitize_name("AC/DC"), "AC_DC");
+        assert_eq!(sanitize_name("a\\b\0c"), "a_b_c");
+        assert_eq!(sanitize_name("."), "_");
+        assert_eq!(sanitize_name(".."), "__");
+        assert_eq!(sanitize_name("...And Justice"), "...And Justice");
+    }
+
+    #[cfg(feature = "audio")]
+    mod tag {
+        use super::*;
+
+        fn prepare_flac(dir: &Path) -> PathBuf {
+            // A FLAC file having only a (truncated) STREAMINFO block and no tags
+            let path = dir.join("untagged.flac");
+            fs::write(&path, b"fLaC\x80\0\0\0").unwrap();
+            path
+        }
+
+        #[named]
+        #[test]
+        fn missing_skip() {
+            let dir = prepare_test(function_name!());
+            let path = prepare_flac(&dir);
+            let options = TokenOptions::default();
+            let context = TokenContext::new(&path, &options);
+            assert_eq!(context.resolve("artist", None), Ok(Some(String::new())));
+            assert!(context.skipped());
+            assert_eq!(context.take_warnings().len(), 1);
+        }
+
+        #[named]
+        #[test]
+        fn missing_unknown() {
+            let dir = prepare_test(function_name!());
+            let path = prepare_flac(&dir);
+            let options = TokenOptions {
+                missing_tag: MissingTag::Unknown,
+                ..Default::default()
+            };
+            let context = TokenContext::new(&path, &options);
+            assert_eq!(
+                context.resolve("track", Some("%02")),
+                Ok(Some(String::from("Unknown")))
+            );
+            assert!(!context.skipped());
+            assert_eq!(context.take_warnings().len(), 1);
+        }
+
+        #[named]
+        #[test]
+        fn separator_in_tag() {
+            let dir = prepare_test(function_name!());
+            let path = dir.join("tagged.flac");
+            let mut comments = Vec::new();
+            comments.extend_from_slice(&0u32.to_le_bytes()); // No vendor string
+            comments.extend_from_slice(&2u32.to_le_bytes());
+            for comment in ["ARTIST=AC/DC", "ALBUM=.."] {
+                comments.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+                comments.extend_from_slice(comment.as_bytes());
+            }
+            let mut data = b"fLaC\x84\0\0".to_vec();
+            data.push(comments.len() as u8);
+            data.extend_from_slice(&comments);
+            fs::write(&path, data).unwrap();
+
+            let options = TokenOptions::default();
+            let context = TokenContext::new(&path, &options);
+            assert_eq!(
+                context.resolve("artist", None),
+                Ok(Some(String::from("AC_DC")))
+            );
+            assert_eq!(context.resolve("album", None), Ok(Some(String::from("__"))));
+        }
+    }
+
+    #[cfg(feature = "dimensions")]
+    mod dimension {
+        use super::*;
+
+        #[named]
+        #[test]
+        fn unknown_format() {
+            let dir = prepare_test(function_name!());
+            let path = dir.join("empty");
+            let options = TokenOptions::default();
+            let context = TokenContext::new(&path, &options);
+            assert_eq!(context.resolve("width", None), Ok(Some(String::new())));
+            assert!(context.skipped());
+            assert_eq!(context.take_warnings().len(), 1);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::quote::quote;
+use crate::Action;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A node of a directory tree to render.
+#[derive(Debug, Default)]
+struct Node {
+    children: BTreeMap<String, Node>,
+    mark: Option<char>,
+}
+
+impl Node {
+    fn insert(&mut self, components: &[String]) -> &mut Node {
+        let mut node = self;
+        for name in components {
+            node = node.children.entry(name.clone()).or_default();
+        }
+        node
+    }
+
+    fn render(&self, prefix: &str, out: &mut String) {
+        let count = self.children.len();
+        for (i, (name, child)) in self.children.iter().enumerate() {
+            let last = i + 1 == count;
+            out.push_str(prefix);
+            out.push_str(if last { "└── " } else { "├── " });
+            out.push_str(name);
+            if !child.children.is_empty() {
+                out.push('/');
+            }
+            if let Some(mark) = child.mark {
+                out.push_str(&format!(" ({})", mark));
+            }
+            out.push('\n');
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            child.render(&prefix, out);
+        }
+    }
+}
+
+/// Renders trees of the affected paths before and after executing the actions.
+///
+/// Paths are shown relative to `base` if possible. In the "after" tree, directories which do
+/// not exist yet are marked with `+`. In the "before" tree, directories which will become empty
+/// are marked with `-`.
+pub fn render_tree(actions: &[Action], base: &Path) -> String {
+    let mut before = Node::default();
+    let mut after = Node::default();
+
+    // Count how many entries leave or enter each directory
+    let mut balance: HashMap<PathBuf, isize> = HashMap::new();
+    for action in actions {
+        if let Some(parent) = action.src().parent() {
+            *balance.entry(parent.to_owned()).or_insert(0) -= 1;
+        }
+        if let Some(parent) = action.dest().parent() {
+            *balance.entry(parent.to_owned()).or_insert(0) += 1;
+        }
+    }
+
+    for action in actions {
+        let components = relative_components(action.src(), base);
+        before.insert(&components);
+        if let Some(parent) = action.src().parent() {
+            let num_entries = fs::read_dir(parent).map(|iter| iter.count()).unwrap_or(0);
+            if num_entries as isize + balance[parent] <= 0 {
+                let n = components.len() - 1;
+                if 0 < n {
+                    before.insert(&components[..n]).mark = Some('-');
+                }
+            }
+        }
+
+        let components = relative_components(action.dest(), base);
+        after.insert(&components);
+        let mut dir = action.dest().parent();
+        let mut n = components.len() - 1;
+        while let (Some(d), true) = (dir, 0 < n) {
+            if !d.exists() {
+                after.insert(&components[..n]).mark = Some('+');
+            }
+            dir = d.parent();
+            n -= 1;
+        }
+    }
+
+    let mut out = String::new();
+    out.push_str("Before:\n.\n");
+    before.render("", &mut out);
+    out.push_str("After:\n.\n");
+    after.render("", &mut out);
+    out
+}
+
+fn relative_components(path: &Path, base: &Path) -> Vec<String> {
+    let path = path.strip_prefix(base).unwrap_or(path);
+    path.components()
+        .map(|c| quote(&c.as_os_str().to_string_lossy()).into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use function_name::named;
+
+    fn prepare_test(id: &str) -> PathBuf {
+        let dir = std::env::current_dir().unwrap().join("temp").join(id);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/a.txt"), "a").unwrap();
+        fs::write(dir.join("src/b.txt"), "b").unwrap();
+        dir
+    }
+
+    #[named]
+    #[test]
+    fn render() {
+        let dir = prepare_test(function_name!());
+        let actions = vec![
+            Action::new(dir.join("src/a.txt"), dir.join("dest/a.txt")),
+            Action::new(dir.join("src/b.txt"), dir.join("dest/b.txt")),
+        ];
+        let rendered = render_tree(&actions, &dir);
+        assert_eq!(
+            rendered,
+            "Before:\n\
+             .\n\
+             └── src/ (-)\n    \
+             ├── a.txt\n    \
+             └── b.txt\n\
+             After:\n\
+             .\n\
+             └── dest/ (+)\n    \
+             ├── a.txt\n    \
+             └── b.txt\n"
+        );
+    }
+
+    #[named]
+    #[test]
+    fn partially_moved() {
+        let dir = prepare_test(function_name!());
+        let actions = vec![Action::new(dir.join("src/a.txt"), dir.join("src/c.txt"))];
+        let rendered = render_tree(&actions, &dir);
+        assert_eq!(
+            rendered,
+            "Before:\n\
+             .\n\
+             └── src/\n    \
+             └── a.txt\n\
+             After:\n\
+             .\n\
+             └── src/\n    \
+             └── c.txt\n"
+        );
+    }
+}
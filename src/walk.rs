@@ -1,20 +1,47 @@
-use crate::fnmatch::fnmatch;
-use std::fs::{self, DirEntry};
-use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
+use crate::fnmatch::MatchOptions;
+use crate::has_extension;
+use crate::pathnorm::root_of;
+use crate::pattern::{Pattern, Segment};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, FileType};
+use std::io;
+use std::path::{Path, PathBuf};
 
 /// A directory entry found in a walk paired with pattern matched substrings.
 ///
-/// This is a pair of a `std::fs::DirEntry` found while the walk and a vector
+/// This is a pair of a path of the entry found while the walk and a vector
 /// of the substrings.
+///
+/// `matched_parts` replaces parts of names which are not valid Unicode with U+FFFD. Use
+/// `matched_os_parts` to get them without loss.
 pub struct Match {
-    pub dir_entry: DirEntry,
+    path: PathBuf,
     pub matched_parts: Vec<String>,
+    os_parts: Vec<OsString>,
 }
 
 impl Match {
+    fn new(path: PathBuf, os_parts: Vec<OsString>) -> Match {
+        let matched_parts = os_parts
+            .iter()
+            .map(|part| part.to_string_lossy().into_owned())
+            .collect();
+        Match {
+            path,
+            matched_parts,
+            os_parts,
+        }
+    }
+
+    /// Returns the path of the matched entry.
     pub fn path(&self) -> PathBuf {
         //TODO: Should we return a ref?
-        self.dir_entry.path()
+        self.path.clone()
+    }
+
+    /// Returns the matched substrings without loss, even if they are not valid Unicode.
+    pub fn matched_os_parts(&self) -> &[OsString] {
+        &self.os_parts
     }
 }
 
@@ -26,8 +53,72 @@ impl Match {
 ///
 /// Note that this function expects the current directory is available.
 /// In that case, this function fails.
-pub fn walk<P: AsRef<Path>>(dir: P, pattern: &str) -> Result<Vec<Match>, String> {
-    let dir = dir.as_ref();
+pub fn walk<P: AsRef<Path>>(
+    dir: P,
+    pattern: &str,
+    options: &MatchOptions,
+) -> Result<Vec<Match>, String> {
+    let mut matches: Vec<Match> = Vec::new();
+    walk_with(dir, pattern, options, &mut |m| {
+        matches.push(m);
+        true
+    })?;
+    Ok(matches)
+}
+
+/// Calls a function for each directory entry which matched the given pattern.
+///
+/// This is the streaming version of `walk`. Each match is passed to `on_match` as soon as it
+/// was found, and the walk stops when `on_match` returns false.
+pub fn walk_with<P: AsRef<Path>>(
+    dir: P,
+    pattern: &str,
+    options: &MatchOptions,
+    on_match: &mut dyn FnMut(Match) -> bool,
+) -> Result<(), String> {
+    let mut callbacks = Callbacks {
+        on_match,
+        on_warning: None,
+        extensions: &[],
+    };
+    walk_with_callbacks(dir.as_ref(), pattern, options, &mut callbacks)
+}
+
+/// Same as `walk_with` but directories which cannot be read are reported to `on_warning` and
+/// skipped instead of failing the whole walk.
+///
+/// If `extensions` is not empty, only files having one of them are matched (see `--ext`.)
+/// Other entries are skipped by their names without querying their metadata.
+pub(crate) fn walk_with_warnings<P: AsRef<Path>>(
+    dir: P,
+    pattern: &str,
+    options: &MatchOptions,
+    extensions: &[String],
+    on_match: &mut dyn FnMut(Match) -> bool,
+    on_warning: &mut dyn FnMut(String),
+) -> Result<(), String> {
+    let mut callbacks = Callbacks {
+        on_match,
+        on_warning: Some(on_warning),
+        extensions,
+    };
+    walk_with_callbacks(dir.as_ref(), pattern, options, &mut callbacks)
+}
+
+/// Functions called while walking.
+struct Callbacks<'a> {
+    on_match: &'a mut dyn FnMut(Match) -> bool,
+    on_warning: Option<&'a mut dyn FnMut(String)>,
+    /// Extensions which files must have to be passed to `on_match`, if any.
+    extensions: &'a [String],
+}
+
+fn walk_with_callbacks(
+    dir: &Path,
+    pattern: &str,
+    options: &MatchOptions,
+    callbacks: &mut Callbacks<'_>,
+) -> Result<(), String> {
     if !dir.is_absolute() {
         return Err(format!(
             "needs an absolute directory path: {}",
@@ -35,60 +126,154 @@ pub fn walk<P: AsRef<Path>>(dir: P, pattern: &str) -> Result<Vec<Match>, String>
         ));
     }
 
-    let mut matches: Vec<Match> = Vec::new();
-    let mut matched_parts: Vec<String> = Vec::new();
-    let patterns: Vec<Component> = Path::new(pattern).components().collect();
-    walk1(dir, &patterns[..], &mut matches, &mut matched_parts)?;
-    Ok(matches)
+    let mut matched_parts: Vec<OsString> = Vec::new();
+    let pattern = Pattern::new(pattern, options)?;
+    walk1(
+        dir,
+        pattern.segments(),
+        options,
+        callbacks,
+        &mut matched_parts,
+        true,
+    )?;
+    Ok(())
+}
+
+/// How to treat symbolic links found while walking.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FollowSymlinks {
+    /// Never follow symbolic links; they are matched as files (like `-P` of cp(1).)
+    Never,
+    /// Follow symbolic links only if they are in the leading part of the pattern without
+    /// wildcards (like `-H` of cp(1).)
+    CommandLine,
+    /// Always follow symbolic links (like `-L` of cp(1).)
+    #[default]
+    Always,
 }
 
-pub fn walk1(
+/// Returns the type of the file, following a symbolic link if requested.
+fn file_type(path: &Path, follow: bool) -> Result<FileType, io::Error> {
+    let metadata = fs::symlink_metadata(path)?;
+    if follow && metadata.file_type().is_symlink() {
+        Ok(fs::metadata(path)?.file_type())
+    } else {
+        Ok(metadata.file_type())
+    }
+}
+
+fn walk1(
     dir: &Path,
-    patterns: &[Component],
-    matches: &mut Vec<Match>,
-    matched_parts: &mut Vec<String>,
-) -> Result<(), String> {
+    patterns: &[Segment],
+    options: &MatchOptions,
+    callbacks: &mut Callbacks<'_>,
+    matched_parts: &mut Vec<OsString>,
+    on_command_line: bool,
+) -> Result<bool, String> {
     assert!(dir.is_dir());
     assert!(!patterns.is_empty());
 
     if patterns.is_empty() {
-        return Ok(());
+        return Ok(true);
     }
 
     // Match directories
-    match patterns[0] {
-        Component::Prefix(p) => {
+    match &patterns[0] {
+        Segment::Prefix(p) => {
             // Reset the curdir to the path
-            let curdir = p.as_os_str();
-            let curdir = PathBuf::from(curdir);
-            walk1(&curdir, &patterns[1..], matches, matched_parts)
+            let curdir = PathBuf::from(p);
+            walk1(
+                &curdir,
+                &patterns[1..],
+                options,
+                callbacks,
+                matched_parts,
+                on_command_line,
+            )
         }
-        Component::RootDir => {
-            // Move to the root
-            let root = MAIN_SEPARATOR.to_string();
-            let root = PathBuf::from(root);
-            walk1(root.as_path(), &patterns[1..], matches, matched_parts)
+        Segment::RootDir => {
+            // Move to the root (of the drive specified by the prefix, if any)
+            let root = root_of(dir);
+            walk1(
+                root.as_path(),
+                &patterns[1..],
+                options,
+                callbacks,
+                matched_parts,
+                on_command_line,
+            )
         }
-        Component::ParentDir => {
+        Segment::ParentDir => {
             // Move to the parent
             let parent = dir.parent().unwrap(); //TODO: Handle error
-            walk1(parent, &patterns[1..], matches, matched_parts)
+            walk1(
+                parent,
+                &patterns[1..],
+                options,
+                callbacks,
+                matched_parts,
+                on_command_line,
+            )
         }
-        Component::CurDir => {
+        Segment::CurDir => {
             // Ignore the path component
-            walk1(dir, &patterns[1..], matches, matched_parts)
+            walk1(
+                dir,
+                &patterns[1..],
+                options,
+                callbacks,
+                matched_parts,
+                on_command_line,
+            )
         }
-        Component::Normal(pattern) => {
-            // Move into the matched sub-directories
-            let entry_iter = match fs::read_dir(dir) {
-                Err(err) => {
-                    return Err(format!(
-                        "fs::read_dir() failed: dir=\"{}\", error=\"{}\"",
-                        dir.to_str().unwrap(),
-                        err
-                    ))
+        Segment::Recursive { capture } => walk_recursive(
+            dir,
+            if *capture { Some(Path::new("")) } else { None },
+            &patterns[1..],
+            options,
+            callbacks,
+            matched_parts,
+            on_command_line,
+        ),
+        Segment::Normal(pattern) => {
+            // If the pattern has no wildcard, only the entry of the same name can match so we
+            // skip scanning the directory (and so every sibling subtree is pruned).
+            if !pattern.has_wildcard() {
+                if is_pruned(pattern.as_str().as_ref(), patterns, callbacks) {
+                    return Ok(true);
                 }
-                Ok(iter) => iter,
+                let path = dir.join(pattern.as_str());
+                let follow = match options.follow_symlinks {
+                    FollowSymlinks::Never => false,
+                    FollowSymlinks::CommandLine => on_command_line,
+                    FollowSymlinks::Always => true,
+                };
+                let file_type = match file_type(&path, follow) {
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(true),
+                    Err(err) => {
+                        return Err(format!(
+                            "failed to get metadata of {:?}: {}",
+                            path.to_str().unwrap_or("<UNKNOWN>"),
+                            err
+                        ))
+                    }
+                    Ok(v) => v,
+                };
+                return visit(
+                    path,
+                    file_type,
+                    patterns,
+                    options,
+                    callbacks,
+                    matched_parts.clone(),
+                    on_command_line,
+                );
+            }
+
+            // Move into the matched sub-directories
+            let entry_iter = match read_dir(dir, callbacks)? {
+                None => return Ok(true),
+                Some(iter) => iter,
             };
 
             // Search entries of which name matches the pattern
@@ -101,10 +286,13 @@ pub fn walk1(
 
                 // Match its name
                 let fname = entry.file_name();
-                let pattern = pattern.to_str().unwrap();
-                if let Some(mut m) = fnmatch(pattern, fname.to_str().unwrap()) {
+                if is_pruned(&fname, patterns, callbacks) {
+                    continue;
+                }
+                if let Some(mut m) = pattern.matches_os(&fname) {
                     // It matched, then query its metadata
-                    let file_type = match entry.path().metadata() {
+                    let follow = options.follow_symlinks == FollowSymlinks::Always;
+                    let file_type = match file_type(&entry.path(), follow) {
                         Err(err) => {
                             return Err(format!(
                                 "failed to get metadata of {:?}: {}",
@@ -112,39 +300,161 @@ pub fn walk1(
                                 err
                             ))
                         }
-                        Ok(v) => v.file_type(),
+                        Ok(v) => v,
                     };
 
-                    // Distinguish and switch procedure according to its type
                     let mut matched_parts = matched_parts.clone();
                     matched_parts.append(&mut m);
-                    if file_type.is_dir() {
-                        let subdir = dir.join(fname);
-                        if 1 < patterns.len() {
-                            // Walk into the found sub directory
-                            let patterns_ = &patterns[1..];
-                            walk1(subdir.as_path(), patterns_, matches, &mut matched_parts)?;
-                        } else {
-                            // Found a matched directory as a leaf; store the path
-                            matches.push(Match {
-                                dir_entry: entry,
-                                matched_parts,
-                            });
-                        }
-                    } else {
-                        // Found a file; store the path only if it matched the last pattern (leaf)
-                        if patterns.len() <= 1 {
-                            matches.push(Match {
-                                dir_entry: entry,
-                                matched_parts: matched_parts.clone(),
-                            });
-                        }
+                    let proceed = visit(
+                        entry.path(),
+                        file_type,
+                        patterns,
+                        options,
+                        callbacks,
+                        matched_parts,
+                        false,
+                    )?;
+                    if !proceed {
+                        return Ok(false);
                     }
                 }
             }
-            Ok(())
+            Ok(true)
+        }
+    }
+}
+
+/// Tests whether an entry of the name can be skipped as it is matched against the last pattern
+/// but does not have any of the extensions.
+fn is_pruned(name: &OsStr, patterns: &[Segment], callbacks: &Callbacks<'_>) -> bool {
+    patterns.len() == 1 && !has_extension(Path::new(name), callbacks.extensions)
+}
+
+/// Reads a directory, or reports it as a warning and returns `None` if it cannot be read and
+/// warnings are requested.
+fn read_dir(dir: &Path, callbacks: &mut Callbacks<'_>) -> Result<Option<fs::ReadDir>, String> {
+    match fs::read_dir(dir) {
+        Err(err) => {
+            if let Some(on_warning) = callbacks.on_warning.as_mut() {
+                on_warning(format!(
+                    "skipped unreadable directory \"{}\": {}",
+                    dir.to_string_lossy(),
+                    err
+                ));
+                return Ok(None);
+            }
+            Err(format!(
+                "fs::read_dir() failed: dir=\"{}\", error=\"{}\"",
+                dir.to_string_lossy(),
+                err
+            ))
+        }
+        Ok(iter) => Ok(Some(iter)),
+    }
+}
+
+/// Matches the patterns following `**` in the directory and in every directory under it.
+///
+/// `subpath` is the path of `dir` relative to where `**` started, if it is to be captured.
+/// Symbolic links to directories are not descended into so that a link pointing to its
+/// ancestor does not make the walk endless.
+fn walk_recursive(
+    dir: &Path,
+    subpath: Option<&Path>,
+    patterns: &[Segment],
+    options: &MatchOptions,
+    callbacks: &mut Callbacks<'_>,
+    matched_parts: &mut Vec<OsString>,
+    on_command_line: bool,
+) -> Result<bool, String> {
+    let mut parts = matched_parts.clone();
+    if let Some(subpath) = subpath {
+        parts.push(subpath.as_os_str().to_owned());
+    }
+    if !walk1(
+        dir,
+        patterns,
+        options,
+        callbacks,
+        &mut parts,
+        on_command_line,
+    )? {
+        return Ok(false);
+    }
+
+    let entry_iter = match read_dir(dir, callbacks)? {
+        None => return Ok(true),
+        Some(iter) => iter,
+    };
+    for maybe_entry in entry_iter {
+        let entry = match maybe_entry {
+            Err(err) => return Err(format!("failed to get a directory entry: {}", err)),
+            Ok(entry) => entry,
+        };
+        let is_dir = match entry.file_type() {
+            Err(err) => {
+                return Err(format!(
+                    "failed to get metadata of {:?}: {}",
+                    entry.path().to_str().unwrap_or("<UNKNOWN>"),
+                    err
+                ))
+            }
+            Ok(file_type) => file_type.is_dir(),
+        };
+        if is_dir {
+            let proceed = walk_recursive(
+                &entry.path(),
+                subpath.map(|p| p.join(entry.file_name())).as_deref(),
+                patterns,
+                options,
+                callbacks,
+                matched_parts,
+                false,
+            )?;
+            if !proceed {
+                return Ok(false);
+            }
         }
     }
+    Ok(true)
+}
+
+/// Stores or walks into an entry which matched the first pattern.
+fn visit(
+    path: PathBuf,
+    file_type: FileType,
+    patterns: &[Segment],
+    options: &MatchOptions,
+    callbacks: &mut Callbacks<'_>,
+    mut matched_parts: Vec<OsString>,
+    on_command_line: bool,
+) -> Result<bool, String> {
+    // Distinguish and switch procedure according to its type
+    if file_type.is_dir() {
+        if 1 < patterns.len() {
+            // Walk into the found sub directory
+            let patterns_ = &patterns[1..];
+            walk1(
+                path.as_path(),
+                patterns_,
+                options,
+                callbacks,
+                &mut matched_parts,
+                on_command_line,
+            )
+        } else if !callbacks.extensions.is_empty() {
+            // --ext is about files; a directory named like `photos.jpg` is not one of them
+            Ok(true)
+        } else {
+            // Found a matched directory as a leaf; store the path
+            Ok((callbacks.on_match)(Match::new(path, matched_parts)))
+        }
+    } else if patterns.len() <= 1 {
+        // Found a file; store the path only if it matched the last pattern (leaf)
+        Ok((callbacks.on_match)(Match::new(path, matched_parts)))
+    } else {
+        Ok(true)
+    }
 }
 
 #[cfg(test)]
@@ -158,7 +468,7 @@ mod tests {
         fn setup(id: &str) {
             let curdir = std::env::current_dir().unwrap();
             let _ = fs::create_dir(curdir.join("temp"));
-            let _ = fs::remove_dir_all(curdir.join(&format!("temp/{}", id)));
+            let _ = fs::remove_dir_all(curdir.join(format!("temp/{}", id)));
             for dir1 in ["foo", "bar", "baz"].iter() {
                 for dir2 in ["foo", "bar", "baz"].iter() {
                     let _ =
@@ -187,12 +497,12 @@ mod tests {
                 fs::write(Path::join(workdir.as_path(), filepath), filepath.as_bytes()).unwrap();
             }
 
-            return workdir;
+            workdir
         }
 
         #[test]
         fn non_absolute_search_root() {
-            let result = walk(".", "*");
+            let result = walk(".", "*", &MatchOptions::default());
             assert!(result.is_err());
             let err = result.err().unwrap();
             assert!(err.contains("needs an absolute directory path"));
@@ -203,7 +513,12 @@ mod tests {
         fn no_specials() {
             setup(function_name!());
             let curdir = std::env::current_dir().unwrap();
-            let matches = walk(curdir.join("temp/no_specials"), "foo/bar/baz").unwrap();
+            let matches = walk(
+                curdir.join("temp/no_specials"),
+                "foo/bar/baz",
+                &MatchOptions::default(),
+            )
+            .unwrap();
             assert_eq!(matches.len(), 1);
             assert_eq!(
                 matches[0].path(),
@@ -217,9 +532,14 @@ mod tests {
         fn question() {
             setup(function_name!());
             let curdir = std::env::current_dir().unwrap();
-            let mut matches = walk(curdir.join("temp/question"), "ba?/ba?/ba?").unwrap();
+            let mut matches = walk(
+                curdir.join("temp/question"),
+                "ba?/ba?/ba?",
+                &MatchOptions::default(),
+            )
+            .unwrap();
             assert_eq!(matches.len(), 8);
-            matches.sort_by(|a, b| a.path().cmp(&b.path()));
+            matches.sort_by_key(|a| a.path());
 
             let paths: Vec<_> = matches.iter().map(|m| m.path()).collect();
             assert_eq!(
@@ -264,9 +584,14 @@ mod tests {
         fn star() {
             setup(function_name!());
             let curdir = std::env::current_dir().unwrap();
-            let mut matches = walk(curdir.join("temp/star"), "b*/b*/b*").unwrap();
+            let mut matches = walk(
+                curdir.join("temp/star"),
+                "b*/b*/b*",
+                &MatchOptions::default(),
+            )
+            .unwrap();
             assert_eq!(matches.len(), 8);
-            matches.sort_by(|a, b| a.path().cmp(&b.path()));
+            matches.sort_by_key(|a| a.path());
 
             let paths: Vec<_> = matches.iter().map(|x| x.path()).collect();
             assert_eq!(
@@ -314,7 +639,279 @@ mod tests {
             let workdir = new_setup(function_name!(), prereq_dirs, prereq_files);
 
             // pmv should not misrecognize "foo" as a directory
-            walk(workdir, "foo/bar").unwrap();
+            walk(workdir, "foo/bar", &MatchOptions::default()).unwrap();
+        }
+
+        #[named]
+        #[test]
+        fn literal_components() {
+            setup(function_name!());
+            let curdir = std::env::current_dir().unwrap();
+            let mut matches = walk(
+                curdir.join("temp/literal_components"),
+                "foo/b*/baz",
+                &MatchOptions::default(),
+            )
+            .unwrap();
+            matches.sort_by_key(|a| a.path());
+
+            let paths: Vec<_> = matches.iter().map(|m| m.path()).collect();
+            assert_eq!(
+                paths,
+                vec![
+                    curdir.join("temp/literal_components/foo/bar/baz"),
+                    curdir.join("temp/literal_components/foo/baz/baz"),
+                ]
+            );
+            assert_eq!(matches[0].matched_parts, vec![String::from("ar")]);
+            assert_eq!(matches[1].matched_parts, vec![String::from("az")]);
+        }
+
+        #[named]
+        #[test]
+        fn literal_component_not_found() {
+            setup(function_name!());
+            let curdir = std::env::current_dir().unwrap();
+            let matches = walk(
+                curdir.join("temp/literal_component_not_found"),
+                "qux/*",
+                &MatchOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(matches.len(), 0);
+        }
+
+        #[cfg(unix)]
+        #[named]
+        #[test]
+        fn follow_symlinks() {
+            let workdir = new_setup(function_name!(), vec!["real/sub"], vec!["real/sub/f"]);
+            std::os::unix::fs::symlink(workdir.join("real"), workdir.join("link")).unwrap();
+
+            let count = |pattern: &str, follow_symlinks: FollowSymlinks| {
+                let options = MatchOptions {
+                    follow_symlinks,
+                    ..Default::default()
+                };
+                walk(&workdir, pattern, &options).unwrap().len()
+            };
+
+            // A symlink in the leading literal part of the pattern
+            assert_eq!(count("link/sub/f", FollowSymlinks::Never), 0);
+            assert_eq!(count("link/sub/f", FollowSymlinks::CommandLine), 1);
+            assert_eq!(count("link/sub/f", FollowSymlinks::Always), 1);
+
+            // A symlink matched by a wildcard
+            assert_eq!(count("l*/sub/f", FollowSymlinks::Never), 0);
+            assert_eq!(count("l*/sub/f", FollowSymlinks::CommandLine), 0);
+            assert_eq!(count("l*/sub/f", FollowSymlinks::Always), 1);
+
+            // The symlink itself is matched if not followed
+            assert_eq!(count("l*", FollowSymlinks::Never), 1);
+        }
+
+        #[cfg(unix)]
+        #[named]
+        #[test]
+        fn non_utf8_names() {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            let workdir = new_setup(function_name!(), vec![], vec![]);
+            let name = OsStr::from_bytes(b"a\xff.txt");
+            fs::write(workdir.join(name), "").unwrap();
+
+            let matches = walk(&workdir, "*.txt", &MatchOptions::default()).unwrap();
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].path(), workdir.join(name));
+            assert_eq!(matches[0].matched_os_parts(), [OsStr::from_bytes(b"a\xff")]);
+            assert_eq!(matches[0].matched_parts, vec![String::from("a\u{fffd}")]);
+
+            // An invalid byte is matched as a character
+            let matches = walk(&workdir, "a?.txt", &MatchOptions::default()).unwrap();
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].matched_os_parts(), [OsStr::from_bytes(b"\xff")]);
+        }
+
+        #[named]
+        #[test]
+        fn recursive() {
+            let workdir = new_setup(
+                function_name!(),
+                vec!["a/b", "c"],
+                vec!["x.rs", "a/y.rs", "a/b/z.rs", "a/b/w.txt", "c/v.rs"],
+            );
+            let mut matches = walk(&workdir, "**/*.rs", &MatchOptions::default()).unwrap();
+            matches.sort_by_key(|m| m.path());
+            let found: Vec<(PathBuf, Vec<String>)> = matches
+                .iter()
+                .map(|m| (m.path(), m.matched_parts.clone()))
+                .collect();
+            let parts = |dir: &str, name: &str| {
+                let dir = PathBuf::from(dir).to_string_lossy().into_owned();
+                vec![dir, String::from(name)]
+            };
+            assert_eq!(
+                found,
+                vec![
+                    (workdir.join("a/b/z.rs"), parts("a/b", "z")),
+                    (workdir.join("a/y.rs"), parts("a", "y")),
+                    (workdir.join("c/v.rs"), parts("c", "v")),
+                    (workdir.join("x.rs"), parts("", "x")),
+                ]
+            );
+
+            // `**` in the middle, followed by a literal name
+            let matches = walk(&workdir, "a/**/z.rs", &MatchOptions::default()).unwrap();
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].path(), workdir.join("a/b/z.rs"));
+        }
+
+        #[named]
+        #[test]
+        fn regex() {
+            let workdir = new_setup(
+                function_name!(),
+                vec!["2023", "misc"],
+                vec!["2023/IMG_1.jpg", "2023/IMG_2.png", "misc/IMG_3.jpg"],
+            );
+            let options = MatchOptions {
+                regex: true,
+                ..Default::default()
+            };
+            let matches = walk(&workdir, r"(\d+)/IMG_(\d)\.jpg", &options).unwrap();
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].path(), workdir.join("2023/IMG_1.jpg"));
+            assert_eq!(
+                matches[0].matched_parts,
+                vec![String::from("2023"), String::from("1")]
+            );
+        }
+
+        #[cfg(feature = "unicode-normalization")]
+        #[named]
+        #[test]
+        fn normalize_unicode() {
+            // Decomposed names as macOS returns
+            let workdir = new_setup(
+                function_name!(),
+                vec!["Cafe\u{301}"],
+                vec!["Cafe\u{301}/re\u{301}sume\u{301}.txt"],
+            );
+            let options = MatchOptions {
+                normalize_unicode: true,
+                ..Default::default()
+            };
+            let matches = walk(&workdir, "Caf\u{e9}/r\u{e9}*.txt", &options).unwrap();
+            assert_eq!(matches.len(), 1);
+            assert_eq!(
+                matches[0].path(),
+                workdir.join("Cafe\u{301}/re\u{301}sume\u{301}.txt")
+            );
+            assert_eq!(matches[0].matched_parts, vec![String::from("sum\u{e9}")]);
+
+            let options = MatchOptions::default();
+            assert_eq!(
+                walk(&workdir, "Caf\u{e9}/r\u{e9}*.txt", &options)
+                    .unwrap()
+                    .len(),
+                0
+            );
+        }
+
+        #[named]
+        #[test]
+        fn ignore_case() {
+            use crate::fnmatch::CaseSensitivity;
+
+            let workdir = new_setup(function_name!(), vec!["Photos"], vec!["Photos/IMG_1.JPG"]);
+            let options = MatchOptions {
+                case_sensitivity: CaseSensitivity::Insensitive,
+                ..Default::default()
+            };
+            let matches = walk(&workdir, "photos/img_*.jpg", &options).unwrap();
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].path(), workdir.join("Photos/IMG_1.JPG"));
+            assert_eq!(matches[0].matched_parts, vec![String::from("1")]);
+
+            let options = MatchOptions {
+                case_sensitivity: CaseSensitivity::Sensitive,
+                ..Default::default()
+            };
+            assert_eq!(
+                walk(&workdir, "photos/img_*.jpg", &options).unwrap().len(),
+                0
+            );
+            assert_eq!(
+                walk(&workdir, "Photos/IMG_*.JPG", &options).unwrap().len(),
+                1
+            );
+        }
+
+        #[cfg(unix)]
+        #[named]
+        #[test]
+        fn recursive_symlink_loop() {
+            let workdir = new_setup(function_name!(), vec!["a"], vec!["a/x"]);
+            std::os::unix::fs::symlink(&workdir, workdir.join("a/loop")).unwrap();
+            let matches = walk(&workdir, "**/x", &MatchOptions::default()).unwrap();
+            assert_eq!(matches.len(), 1);
+        }
+
+        #[named]
+        #[test]
+        fn extensions() {
+            let workdir = new_setup(
+                function_name!(),
+                vec!["a.jpg", "b.txt"],
+                vec!["a.jpg/c.jpg", "a.jpg/d.txt", "b.txt/e.JPG", "f.jpg"],
+            );
+            let paths = |pattern: &str| {
+                let mut paths = Vec::new();
+                walk_with_warnings(
+                    &workdir,
+                    pattern,
+                    &MatchOptions::default(),
+                    &[String::from("jpg")],
+                    &mut |m| {
+                        paths.push(m.path().strip_prefix(&workdir).unwrap().to_owned());
+                        true
+                    },
+                    &mut |_| (),
+                )
+                .unwrap();
+                paths.sort();
+                paths
+            };
+
+            // Directories are not matched even if their names have the extension
+            assert_eq!(paths("*"), vec![PathBuf::from("f.jpg")]);
+            assert!(paths("a.jpg").is_empty());
+
+            // Only the last component is filtered
+            assert_eq!(
+                paths("*/*"),
+                vec![PathBuf::from("a.jpg/c.jpg"), PathBuf::from("b.txt/e.JPG")]
+            );
+        }
+
+        #[named]
+        #[test]
+        fn stop_walking() {
+            setup(function_name!());
+            let curdir = std::env::current_dir().unwrap();
+            let mut count = 0;
+            walk_with(
+                curdir.join("temp/stop_walking"),
+                "*/*/*",
+                &MatchOptions::default(),
+                &mut |_| {
+                    count += 1;
+                    count < 5
+                },
+            )
+            .unwrap();
+            assert_eq!(count, 5);
         }
     }
 }
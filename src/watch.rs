@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Watches destination directories for files created by other processes while moving files.
+///
+/// Each planned destination is guarded until it is moved to. If another process creates a file
+/// at a guarded destination, `check` fails so that the remaining moves can be aborted instead of
+/// overwriting the file.
+pub(crate) struct DestWatcher {
+    inner: imp::Inotify,
+    pending: HashSet<PathBuf>,
+    conflicts: Vec<PathBuf>,
+}
+
+impl DestWatcher {
+    /// Starts watching the directories of the destinations (final paths of the moves.)
+    pub(crate) fn new(dests: &[PathBuf]) -> Result<DestWatcher, String> {
+        let mut inner =
+            imp::Inotify::new().map_err(|err| format!("failed to watch destinations: {}", err))?;
+        let dests: Vec<PathBuf> = dests.iter().map(|dest| absolute(dest)).collect();
+        let mut dirs: Vec<&Path> = dests
+            .iter()
+            .filter_map(|dest| dest.parent())
+            .filter(|dir| dir.is_dir())
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in dirs {
+            inner
+                .add_watch(dir)
+                .map_err(|err| format!("failed to watch \"{}\": {}", dir.to_string_lossy(), err))?;
+        }
+        Ok(DestWatcher {
+            inner,
+            pending: dests.iter().cloned().collect(),
+            conflicts: Vec::new(),
+        })
+    }
+
+    /// Fails if another process created a file at a destination not moved to yet, and otherwise
+    /// stops guarding `dest` as it is about to be moved to.
+    pub(crate) fn check(&mut self, dest: &Path) -> Result<(), String> {
+        for path in self.inner.created_paths() {
+            if self.pending.contains(&path) && !self.conflicts.contains(&path) {
+                self.conflicts.push(path);
+            }
+        }
+        if let Some(path) = self.conflicts.first() {
+            return Err(format!(
+                "\"{}\" was created by another process; aborted the remaining moves",
+                path.to_string_lossy()
+            ));
+        }
+        self.pending.remove(&absolute(dest));
+        Ok(())
+    }
+
+    /// Returns whether `check` has detected a file created by another process.
+    pub(crate) fn conflicted(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+
+// Compares paths as absolute ones since inotify reports names in the watched directories
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::mem::size_of;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    /// A non-blocking inotify(7) instance.
+    pub(super) struct Inotify {
+        fd: libc::c_int,
+        dirs: HashMap<libc::c_int, PathBuf>,
+    }
+
+    impl Inotify {
+        pub(super) fn new() -> io::Result<Inotify> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Inotify {
+                fd,
+                dirs: HashMap::new(),
+            })
+        }
+
+        pub(super) fn add_watch(&mut self, dir: &Path) -> io::Result<()> {
+            let path = CString::new(dir.as_os_str().as_bytes())?;
+            let mask = libc::IN_CREATE | libc::IN_MOVED_TO;
+            let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), mask) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.dirs.insert(wd, dir.to_path_buf());
+            Ok(())
+        }
+
+        /// Returns paths created since the last call.
+        pub(super) fn created_paths(&mut self) -> Vec<PathBuf> {
+            let mut paths = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let len = unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) };
+                if len <= 0 {
+                    break; // No more events (EAGAIN) or an error
+                }
+                let events = &buf[..len as usize];
+                let mut offset = 0;
+                while offset + size_of::<libc::inotify_event>() <= events.len() {
+                    let event: libc::inotify_event =
+                        unsafe { std::ptr::read_unaligned(events[offset..].as_ptr().cast()) };
+                    let start = offset + size_of::<libc::inotify_event>();
+                    let end = (start + event.len as usize).min(events.len());
+                    let name = &events[start..end];
+                    let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+                    if let Some(dir) = self.dirs.get(&event.wd) {
+                        paths.push(dir.join(OsStr::from_bytes(name)));
+                    }
+                    offset = end;
+                }
+            }
+            paths
+        }
+    }
+
+    impl Drop for Inotify {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.fd) };
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    pub(super) struct Inotify;
+
+    impl Inotify {
+        pub(super) fn new() -> io::Result<Inotify> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "watching directories is supported only on Linux",
+            ))
+        }
+
+        pub(super) fn add_watch(&mut self, _dir: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        pub(super) fn created_paths(&mut self) -> Vec<PathBuf> {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use function_name::named;
+    use std::fs;
+
+    #[named]
+    #[test]
+    fn created_by_another_process() {
+        let dir = PathBuf::from("temp").join(function_name!());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let dests = vec![dir.join("a"), dir.join("b")];
+        let mut watcher = DestWatcher::new(&dests).unwrap();
+
+        // A file created by the moves themselves is not a conflict
+        assert!(watcher.check(&dir.join("a")).is_ok());
+        fs::write(dir.join("a"), "a").unwrap();
+        fs::write(dir.join("unrelated"), "").unwrap();
+        assert!(watcher.check(&dir.join("x")).is_ok());
+        assert!(!watcher.conflicted());
+
+        // A file created at a destination not moved to yet is
+        fs::write(dir.join("b"), "b").unwrap();
+        assert!(watcher.check(&dir.join("b")).is_err());
+        assert!(watcher.conflicted());
+    }
+}
@@ -1,6 +1,6 @@
 use function_name::named;
 use std::env;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
@@ -27,17 +27,17 @@ fn dry_run() {
     let temp_dir = prepare(function_name!());
 
     // Prepare files and directories to testing
-    fs::write(&temp_dir.join("AA"), "AA").unwrap();
-    fs::write(&temp_dir.join("AB"), "AB").unwrap();
+    fs::write(temp_dir.join("AA"), "AA").unwrap();
+    fs::write(temp_dir.join("AB"), "AB").unwrap();
 
     // Execute pmv with --dry-run
-    let mut args: Vec<OsString> = vec![
+    let mut args: Vec<OsString> = [
         PathBuf::from("--dry-run"),
         temp_dir.join("??"),
         temp_dir.join("B#2"),
     ]
     .iter()
-    .map(|s| OsString::from(s))
+    .map(OsString::from)
     .collect();
     args.insert(0, env::args_os().next().unwrap());
     let result = try_main(&args);
@@ -50,9 +50,9 @@ fn dry_run() {
     assert!(!path_bb.exists());
 
     // Then do the same without --dry-run
-    let mut args: Vec<OsString> = vec![temp_dir.join("??"), temp_dir.join("B#2")]
+    let mut args: Vec<OsString> = [temp_dir.join("??"), temp_dir.join("B#2")]
         .iter()
-        .map(|s| OsString::from(s))
+        .map(OsString::from)
         .collect();
     args.insert(0, env::args_os().next().unwrap());
     let result = try_main(&args);
@@ -65,6 +65,34 @@ fn dry_run() {
     assert_eq!(fs::read_to_string(&path_bb).unwrap(), "AB");
 }
 
+#[named]
+#[test]
+fn via_temp() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files to swap
+    fs::write(temp_dir.join("AB"), "AB").unwrap();
+    fs::write(temp_dir.join("BA"), "BA").unwrap();
+
+    // Execute pmv with --via-temp
+    let mut args: Vec<OsString> = [
+        PathBuf::from("--via-temp"),
+        temp_dir.join("??"),
+        temp_dir.join("#2#1"),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    let result = try_main(&args);
+    assert!(result.is_ok());
+
+    // Confirm files were swapped and no temporary file remains
+    assert_eq!(fs::read_to_string(temp_dir.join("AB")).unwrap(), "BA");
+    assert_eq!(fs::read_to_string(temp_dir.join("BA")).unwrap(), "AB");
+    assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 2);
+}
+
 #[named]
 #[test]
 fn interactive() {
@@ -73,8 +101,8 @@ fn interactive() {
     let path_b = temp_dir.join("B");
 
     // Prepare files and directories to testing
-    fs::write(&temp_dir.join("A"), "A").unwrap();
-    fs::write(&temp_dir.join("B"), "B").unwrap();
+    fs::write(temp_dir.join("A"), "A").unwrap();
+    fs::write(temp_dir.join("B"), "B").unwrap();
 
     // Execute pmv in interactive mode and enter 'N'
     let mut command = Command::new("cargo");
@@ -128,6 +156,46 @@ fn interactive() {
     assert_eq!(fs::read_to_string(&path_b).unwrap(), "A");
 }
 
+#[named]
+#[test]
+fn interactive_edit() {
+    let temp_dir = prepare(function_name!());
+    let path_a = temp_dir.join("A");
+    let path_b = temp_dir.join("B");
+    let path_c = temp_dir.join("C");
+
+    // Prepare files and directories to testing
+    fs::write(&path_a, "A").unwrap();
+
+    // Execute pmv in interactive mode and edit the destination to 'C'
+    let mut command = Command::new("cargo");
+    let mut proc = command
+        .current_dir(&temp_dir)
+        .arg("run")
+        .arg("-q")
+        .arg("--")
+        .arg("--interactive")
+        .arg("A")
+        .arg("B")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to launch pmv (debug build)");
+    let mut stdin = proc.stdin.take().expect("failed to get stdin");
+    std::thread::spawn(move || {
+        stdin
+            .write_all(b"e\nC\n")
+            .expect("failed to write to stdin");
+    });
+    let output = proc.wait_with_output().expect("wait for child proc failed");
+    assert!(output.status.success());
+
+    // Test the result
+    assert!(!path_a.exists());
+    assert!(!path_b.exists());
+    assert_eq!(fs::read_to_string(&path_c).unwrap(), "A");
+}
+
 #[named]
 #[test]
 fn swap_filenames() {
@@ -140,7 +208,7 @@ fn swap_filenames() {
     fs::write(&path_ba, "BA").unwrap();
 
     // Execute pmv
-    let mut args: Vec<OsString> = vec![
+    let mut args: Vec<OsString> = [
         PathBuf::from("-v"),
         temp_dir.join("??"),
         temp_dir.join("#2#1"),
@@ -158,3 +226,894 @@ fn swap_filenames() {
     assert_eq!(fs::read_to_string(&path_ab).unwrap(), "BA");
     assert_eq!(fs::read_to_string(&path_ba).unwrap(), "AB");
 }
+
+#[named]
+#[test]
+fn temp_dir() {
+    let temp_dir = prepare(function_name!());
+    let path_ab = temp_dir.join("AB");
+    let path_ba = temp_dir.join("BA");
+    fs::write(&path_ab, "AB").unwrap();
+    fs::write(&path_ba, "BA").unwrap();
+
+    // Swap the names via temporary files in `.pmv-tmp`
+    let mut args: Vec<OsString> = [
+        PathBuf::from("--temp-dir"),
+        PathBuf::from("--temp-prefix=~"),
+        temp_dir.join("??"),
+        temp_dir.join("#2#1"),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert_eq!(try_main(&args), Ok(()));
+
+    assert_eq!(fs::read_to_string(&path_ab).unwrap(), "BA");
+    assert_eq!(fs::read_to_string(&path_ba).unwrap(), "AB");
+    assert!(!temp_dir.join(".pmv-tmp").exists());
+}
+
+#[named]
+#[test]
+fn swap() {
+    let temp_dir = prepare(function_name!());
+    fs::write(temp_dir.join("a"), "a").unwrap();
+    fs::create_dir(temp_dir.join("b")).unwrap();
+    fs::write(temp_dir.join("b/c"), "c").unwrap();
+    let run = |args: &[&str]| {
+        let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+    let a = temp_dir.join("a").to_string_lossy().into_owned();
+    let b = temp_dir.join("b").to_string_lossy().into_owned();
+
+    // Nothing changes in dry-run mode
+    assert_eq!(run(&["swap", "-n", &a, &b]), Ok(()));
+    assert_eq!(fs::read_to_string(temp_dir.join("a")).unwrap(), "a");
+
+    // A file and a directory are swapped, and swapped back
+    assert_eq!(run(&["swap", &a, &b]), Ok(()));
+    assert_eq!(fs::read_to_string(temp_dir.join("a/c")).unwrap(), "c");
+    assert_eq!(fs::read_to_string(temp_dir.join("b")).unwrap(), "a");
+    assert_eq!(run(&["swap", &b, &a]), Ok(()));
+    assert_eq!(fs::read_to_string(temp_dir.join("a")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(temp_dir.join("b/c")).unwrap(), "c");
+    assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 2);
+
+    let missing = temp_dir.join("x").to_string_lossy().into_owned();
+    assert!(run(&["swap", &a, &missing]).is_err());
+    assert!(run(&["swap", &a, &a]).is_err());
+    let inner = temp_dir.join("b/c").to_string_lossy().into_owned();
+    assert!(run(&["swap", &b, &inner]).is_err());
+}
+
+#[named]
+#[test]
+fn rotate() {
+    let temp_dir = prepare(function_name!());
+    let paths: Vec<String> = ["a", "b", "c"]
+        .iter()
+        .map(|name| {
+            fs::write(temp_dir.join(name), name).unwrap();
+            temp_dir.join(name).to_string_lossy().into_owned()
+        })
+        .collect();
+    let run = |args: &[&str]| {
+        let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+
+    // a → b → c → a
+    assert_eq!(run(&["rotate", &paths[0], &paths[1], &paths[2]]), Ok(()));
+    assert_eq!(fs::read_to_string(temp_dir.join("a")).unwrap(), "c");
+    assert_eq!(fs::read_to_string(temp_dir.join("b")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(temp_dir.join("c")).unwrap(), "b");
+    assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 3);
+
+    assert!(run(&["rotate", &paths[0], &paths[1], &paths[0]]).is_err());
+}
+
+#[named]
+#[test]
+fn rotate_numbered() {
+    let temp_dir = prepare(function_name!());
+    for name in ["app.log", "app.log.1", "app.log.3"] {
+        fs::write(temp_dir.join(name), name).unwrap();
+    }
+    let run = |args: &[&str]| {
+        let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+    let base = temp_dir.join("app.log").to_string_lossy().into_owned();
+    let read = |name: &str| fs::read_to_string(temp_dir.join(name)).ok();
+
+    // Nothing changes in dry-run mode
+    assert_eq!(
+        run(&["rotate-numbered", "-n", "--keep", "3", &base]),
+        Ok(())
+    );
+    assert_eq!(read("app.log.3").as_deref(), Some("app.log.3"));
+
+    // The oldest copy is deleted and a missing one is skipped
+    assert_eq!(run(&["rotate-numbered", "--keep", "3", &base]), Ok(()));
+    assert_eq!(read("app.log"), None);
+    assert_eq!(read("app.log.1").as_deref(), Some("app.log"));
+    assert_eq!(read("app.log.2").as_deref(), Some("app.log.1"));
+    assert_eq!(read("app.log.3"), None);
+    assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 2);
+
+    assert!(run(&["rotate-numbered", "--keep", "3", &base]).is_err());
+
+    // The oldest copy is kept if a move fails (here, the one to a temporary name too long)
+    let name = "a".repeat(250);
+    for suffix in ["", ".1", ".2"] {
+        fs::write(temp_dir.join(name.clone() + suffix), suffix).unwrap();
+    }
+    let base = temp_dir.join(&name).to_string_lossy().into_owned();
+    assert!(run(&["rotate-numbered", "--keep", "2", &base]).is_err());
+    for suffix in ["", ".1", ".2"] {
+        assert_eq!(read(&(name.clone() + suffix)).as_deref(), Some(suffix));
+    }
+}
+
+#[named]
+#[test]
+fn subcommand_name_as_source() {
+    let temp_dir = prepare(function_name!());
+    fs::write(temp_dir.join("config"), "config").unwrap();
+
+    // A file named like a subcommand is moved if the arguments are invalid for the subcommand
+    let status = Command::new("cargo")
+        .current_dir(&temp_dir)
+        .args(["run", "-q", "--", "config", "config.bak"])
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to launch pmv (debug build)");
+    assert!(status.success());
+    assert_eq!(
+        fs::read_to_string(temp_dir.join("config.bak")).unwrap(),
+        "config"
+    );
+}
+
+#[named]
+#[test]
+fn max_files() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    fs::write(temp_dir.join("A1"), "A1").unwrap();
+    fs::write(temp_dir.join("A2"), "A2").unwrap();
+
+    // Execute pmv with a threshold exceeded and no input available
+    let status = Command::new("cargo")
+        .current_dir(&temp_dir)
+        .args(["run", "-q", "--", "--max-files", "1", "A?", "B#1"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("Failed to launch pmv (debug build)");
+    assert!(!status.success());
+    assert!(temp_dir.join("A1").exists());
+    assert!(temp_dir.join("A2").exists());
+
+    // Then do the same with --yes
+    let status = Command::new("cargo")
+        .current_dir(&temp_dir)
+        .args(["run", "-q", "--", "--max-files", "1", "--yes", "A?", "B#1"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to launch pmv (debug build)");
+    assert!(status.success());
+    assert!(temp_dir.join("B1").exists());
+    assert!(temp_dir.join("B2").exists());
+}
+
+#[named]
+#[test]
+fn warnings_as_errors() {
+    let temp_dir = prepare(function_name!());
+    let path_a = temp_dir.join("A");
+
+    // Prepare files and directories to testing
+    fs::write(&path_a, "A").unwrap();
+
+    // Execute pmv with a token which is unknown
+    let mut args: Vec<OsString> = [
+        PathBuf::from("--warnings-as-errors"),
+        temp_dir.join("A"),
+        temp_dir.join("#{unknown}"),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    let result = try_main(&args);
+    assert!(result.is_err());
+    assert!(path_a.exists());
+
+    // Then do the same without --warnings-as-errors
+    args.remove(1);
+    let result = try_main(&args);
+    assert!(result.is_ok());
+    assert!(!path_a.exists());
+    assert!(temp_dir.join("#{unknown}").exists());
+}
+
+#[named]
+#[test]
+fn dash_patterns() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    fs::write(temp_dir.join("-a"), "A").unwrap();
+
+    // Execute pmv with patterns after `--`
+    let status = Command::new("cargo")
+        .current_dir(&temp_dir)
+        .args(["run", "-q", "--", "--", "-a", "-b"])
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to launch pmv (debug build)");
+    assert!(status.success());
+    assert!(!temp_dir.join("-a").exists());
+    assert!(temp_dir.join("-b").exists());
+
+    // Then do the same with --source and --dest
+    let status = Command::new("cargo")
+        .current_dir(&temp_dir)
+        .args(["run", "-q", "--", "--source", "-b", "--dest", "-c"])
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to launch pmv (debug build)");
+    assert!(status.success());
+    assert!(!temp_dir.join("-b").exists());
+    assert!(temp_dir.join("-c").exists());
+}
+
+#[named]
+#[test]
+fn omitted_dest() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    fs::create_dir_all(temp_dir.join("a/b")).unwrap();
+    fs::write(temp_dir.join("a/x.pdf"), "X").unwrap();
+    fs::write(temp_dir.join("a/b/y.pdf"), "Y").unwrap();
+
+    // Execute pmv without DEST
+    let status = Command::new("cargo")
+        .current_dir(&temp_dir)
+        .args(["run", "-q", "--", "a/*/*.pdf"])
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to launch pmv (debug build)");
+    assert!(status.success());
+    assert!(!temp_dir.join("a/b/y.pdf").exists());
+    assert_eq!(fs::read_to_string(temp_dir.join("y.pdf")).unwrap(), "Y");
+    assert!(temp_dir.join("a/x.pdf").exists());
+}
+
+#[named]
+#[test]
+fn check() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    fs::write(temp_dir.join("A"), "A").unwrap();
+
+    // Execute pmv with --dry-run --check, which fails as a file would be moved
+    let run = || {
+        let mut args: Vec<OsString> = [
+            PathBuf::from("--dry-run"),
+            PathBuf::from("--check"),
+            temp_dir.join("?"),
+            temp_dir.join("B"),
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+    assert!(run().is_err());
+    assert!(temp_dir.join("A").exists());
+
+    // Then it succeeds once the file was moved
+    fs::rename(temp_dir.join("A"), temp_dir.join("B")).unwrap();
+    assert!(run().is_ok());
+}
+
+#[test]
+fn check_examples() {
+    let run = |example: &str| {
+        let args: Vec<OsString> = [
+            "pmv",
+            "check",
+            "--example",
+            example,
+            "*/*_test.py",
+            "tests/test_#2.py",
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+        try_main(&args)
+    };
+    assert!(run("src/foo_test.py").is_ok());
+    assert!(run("src/foo.py").is_err());
+}
+
+#[named]
+#[test]
+fn repl() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    fs::write(temp_dir.join("A1"), "A1").unwrap();
+    fs::write(temp_dir.join("A2"), "A2").unwrap();
+
+    // Preview two patterns, then apply the last one
+    let mut proc = Command::new("cargo")
+        .current_dir(&temp_dir)
+        .args(["run", "-q", "--", "repl", "A?"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to launch pmv (debug build)");
+    let mut stdin = proc.stdin.take().expect("failed to get stdin");
+    std::thread::spawn(move || {
+        stdin
+            .write_all(b"B#1\nC#1\n:apply\n")
+            .expect("failed to write to stdin");
+    });
+    let output = proc.wait_with_output().expect("wait for child proc failed");
+    assert!(output.status.success());
+    assert!(!temp_dir.join("A1").exists());
+    assert!(!temp_dir.join("B1").exists());
+    assert_eq!(fs::read_to_string(temp_dir.join("C1")).unwrap(), "A1");
+    assert_eq!(fs::read_to_string(temp_dir.join("C2")).unwrap(), "A2");
+
+    // Failed moves are reported by the exit status
+    let mut proc = Command::new("cargo")
+        .current_dir(&temp_dir)
+        .args(["run", "-q", "--", "repl", "C?"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to launch pmv (debug build)");
+    let mut stdin = proc.stdin.take().expect("failed to get stdin");
+    std::thread::spawn(move || {
+        stdin
+            .write_all(b"missing/D#1\n:apply\n")
+            .expect("failed to write to stdin");
+    });
+    let output = proc.wait_with_output().expect("wait for child proc failed");
+    assert!(!output.status.success());
+    assert!(temp_dir.join("C1").exists());
+}
+
+#[named]
+#[test]
+fn apply_plan() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    for name in ["A1", "A2", "A3"].iter() {
+        fs::write(temp_dir.join(name), name).unwrap();
+    }
+
+    // Save a plan without moving files
+    let plan = temp_dir.join("plan.json");
+    let mut args: Vec<OsString> = [
+        PathBuf::from("--dry-run"),
+        PathBuf::from("--save-plan"),
+        plan.clone(),
+        temp_dir.join("A?"),
+        temp_dir.join("B#1"),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert!(try_main(&args).is_ok());
+    assert!(temp_dir.join("A1").exists());
+
+    // Then execute a part of it
+    let mut args: Vec<OsString> = [
+        PathBuf::from("apply"),
+        PathBuf::from("--only"),
+        PathBuf::from("1-3"),
+        PathBuf::from("--skip"),
+        PathBuf::from("2"),
+        plan.clone(),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert!(try_main(&args).is_ok());
+    assert_eq!(fs::read_to_string(temp_dir.join("B1")).unwrap(), "A1");
+    assert!(temp_dir.join("A2").exists());
+    assert_eq!(fs::read_to_string(temp_dir.join("B3")).unwrap(), "A3");
+
+    // Applying the same plan again fails as the moves are marked as done, even with --force
+    assert!(try_main(&args).is_err());
+    args.insert(2, OsString::from("--force"));
+    assert!(try_main(&args).is_err());
+
+    // The moves not marked as done can be applied with --resume
+    let mut args: Vec<OsString> = [PathBuf::from("apply"), PathBuf::from("--resume"), plan]
+        .iter()
+        .map(OsString::from)
+        .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert_eq!(try_main(&args), Ok(()));
+    assert_eq!(fs::read_to_string(temp_dir.join("B2")).unwrap(), "A2");
+
+    // Resuming fails if a file moved before is not there anymore
+    fs::rename(temp_dir.join("B1"), temp_dir.join("A1")).unwrap();
+    assert!(try_main(&args).is_err());
+}
+
+#[named]
+#[test]
+fn apply_plan_failure() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    for name in ["A1", "A2"].iter() {
+        fs::write(temp_dir.join(name), name).unwrap();
+    }
+
+    // Save a plan without moving files
+    let plan = temp_dir.join("plan.json");
+    let mut args: Vec<OsString> = [
+        PathBuf::from("--dry-run"),
+        PathBuf::from("--save-plan"),
+        plan.clone(),
+        temp_dir.join("A?"),
+        temp_dir.join("B#1"),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert!(try_main(&args).is_ok());
+
+    // Applying moves of which one fails is an error
+    fs::remove_file(temp_dir.join("A2")).unwrap();
+    let mut args: Vec<OsString> = [
+        PathBuf::from("apply"),
+        PathBuf::from("--only"),
+        PathBuf::from("1-2"),
+        plan.clone(),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert!(try_main(&args).is_err());
+    assert_eq!(fs::read_to_string(temp_dir.join("B1")).unwrap(), "A1");
+    assert!(!temp_dir.join("B2").exists());
+
+    // The failed move can be retried with --resume
+    fs::write(temp_dir.join("A2"), "A2").unwrap();
+    let mut args: Vec<OsString> = [PathBuf::from("apply"), PathBuf::from("--resume"), plan]
+        .iter()
+        .map(OsString::from)
+        .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert_eq!(try_main(&args), Ok(()));
+    assert_eq!(fs::read_to_string(temp_dir.join("B2")).unwrap(), "A2");
+}
+
+#[named]
+#[test]
+fn move_failure() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    for name in ["a.txt", "b.txt", "d"].iter() {
+        fs::write(temp_dir.join(name), name).unwrap();
+    }
+
+    // Moving files under a regular file fails
+    let mut args: Vec<OsString> = [temp_dir.join("*.txt"), temp_dir.join("d/x/#1")]
+        .iter()
+        .map(OsString::from)
+        .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert_eq!(try_main(&args), Err(String::from("2 move(s) failed")));
+    assert!(temp_dir.join("a.txt").exists());
+    assert!(temp_dir.join("b.txt").exists());
+}
+
+#[named]
+#[test]
+fn apply_plan_retry() {
+    let temp_dir = prepare(function_name!());
+    let run = |args: &[&OsStr]| {
+        let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+    let plan = temp_dir.join("plan.json");
+    let save_plan = || {
+        let src = temp_dir.join("A?");
+        let dest = temp_dir.join("B#1");
+        let args = [
+            OsStr::new("--dry-run"),
+            OsStr::new("--save-plan"),
+            plan.as_os_str(),
+            src.as_os_str(),
+            dest.as_os_str(),
+        ];
+        assert!(run(&args).is_ok());
+    };
+    let apply = |options: &[&str]| {
+        let mut args: Vec<&OsStr> = vec![OsStr::new("apply")];
+        args.extend(options.iter().map(OsStr::new));
+        args.push(plan.as_os_str());
+        run(&args)
+    };
+
+    // Prepare files and directories to testing
+    fs::write(temp_dir.join("A1"), "A1").unwrap();
+    save_plan();
+
+    // A failed run is not recorded as applied
+    fs::rename(temp_dir.join("A1"), temp_dir.join("C1")).unwrap();
+    assert!(apply(&[]).is_err());
+
+    // So it can be retried even with --strict
+    fs::rename(temp_dir.join("C1"), temp_dir.join("A1")).unwrap();
+    assert_eq!(apply(&["--strict"]), Ok(()));
+    assert_eq!(fs::read_to_string(temp_dir.join("B1")).unwrap(), "A1");
+
+    // A regenerated plan having the same moves is refused with --strict unless --force is given
+    fs::rename(temp_dir.join("B1"), temp_dir.join("A1")).unwrap();
+    save_plan();
+    assert!(apply(&["--strict"]).is_err());
+    assert!(temp_dir.join("A1").exists());
+    assert_eq!(apply(&["--strict", "--force"]), Ok(()));
+    assert!(temp_dir.join("B1").exists());
+
+    // It is just warned about otherwise
+    fs::rename(temp_dir.join("B1"), temp_dir.join("A1")).unwrap();
+    save_plan();
+    assert_eq!(apply(&[]), Ok(()));
+    assert!(temp_dir.join("B1").exists());
+}
+
+#[cfg(unix)]
+#[named]
+#[test]
+fn dest_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    fs::create_dir(temp_dir.join("D")).unwrap();
+    symlink("D", temp_dir.join("L1")).unwrap();
+    symlink("D", temp_dir.join("L2")).unwrap();
+    fs::write(temp_dir.join("A1"), "A1").unwrap();
+
+    // Symbolic links to directories are followed by default, like mv(1)
+    let mut args: Vec<OsString> = [temp_dir.join("A?"), temp_dir.join("L#1")]
+        .iter()
+        .map(OsString::from)
+        .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert!(try_main(&args).is_ok());
+    assert_eq!(fs::read_to_string(temp_dir.join("D/A1")).unwrap(), "A1");
+    assert!(temp_dir.join("L1").is_symlink());
+
+    // The link itself is replaced with --no-follow-dest-symlinks
+    fs::write(temp_dir.join("A2"), "A2").unwrap();
+    let mut args: Vec<OsString> = [
+        PathBuf::from("--no-follow-dest-symlinks"),
+        temp_dir.join("A?"),
+        temp_dir.join("L#1"),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert!(try_main(&args).is_ok());
+    assert!(!temp_dir.join("L2").is_symlink());
+    assert_eq!(fs::read_to_string(temp_dir.join("L2")).unwrap(), "A2");
+    assert!(!temp_dir.join("D/A2").exists());
+}
+
+#[named]
+#[test]
+fn root() {
+    let temp_dir = prepare(function_name!());
+
+    // Prepare files and directories to testing
+    fs::create_dir_all(temp_dir.join("proj/src")).unwrap();
+    fs::create_dir_all(temp_dir.join("proj/doc")).unwrap();
+    fs::write(temp_dir.join("proj/src/a.txt"), "a").unwrap();
+
+    // SOURCE and DEST are relative to the root, not to the current directory
+    let mut args: Vec<OsString> = [
+        PathBuf::from("--root"),
+        temp_dir.join("proj"),
+        PathBuf::from("*/*.txt"),
+        PathBuf::from("doc/#1_#2.md"),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert!(try_main(&args).is_ok());
+    assert_eq!(
+        fs::read_to_string(temp_dir.join("proj/doc/src_a.md")).unwrap(),
+        "a"
+    );
+
+    // The root must be a directory
+    let mut args: Vec<OsString> = [
+        PathBuf::from("--root"),
+        temp_dir.join("missing"),
+        PathBuf::from("*.md"),
+        PathBuf::from("#1.txt"),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert!(try_main(&args).is_err());
+}
+
+#[named]
+#[test]
+fn timeout() {
+    let temp_dir = prepare(function_name!());
+    fs::write(temp_dir.join("a"), "a").unwrap();
+
+    let run = |timeout: &str| {
+        let mut args: Vec<OsString> = [
+            PathBuf::from("--timeout"),
+            PathBuf::from(timeout),
+            temp_dir.join("?"),
+            temp_dir.join("#1.txt"),
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+
+    // Nothing is moved once the limit has passed
+    let result = run("0s");
+    assert!(result.unwrap_err().contains("timed out"));
+    assert!(temp_dir.join("a").exists());
+
+    assert_eq!(run("1h"), Ok(()));
+    assert!(temp_dir.join("a.txt").exists());
+}
+
+#[named]
+#[test]
+fn stream() {
+    let temp_dir = prepare(function_name!());
+    for name in ["a1", "a2", "a3", "a4", "a5"].iter() {
+        fs::write(temp_dir.join(name), name).unwrap();
+    }
+
+    let run = |src: &str, dest: &str| {
+        let mut args: Vec<OsString> = [
+            PathBuf::from("--stream"),
+            PathBuf::from("2"),
+            temp_dir.join(src),
+            temp_dir.join(dest),
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+
+    assert_eq!(run("a?", "b#1"), Ok(()));
+    for name in ["b1", "b2", "b3", "b4", "b5"].iter() {
+        assert_eq!(
+            fs::read_to_string(temp_dir.join(name)).unwrap(),
+            name.replace('b', "a")
+        );
+    }
+
+    // Destinations which would be found again
+    assert!(run("b*", "b#1x").unwrap_err().contains("matches SOURCE"));
+    assert!(temp_dir.join("b1").exists());
+
+    // Destinations which may have been moved in an earlier window
+    fs::write(temp_dir.join("c1"), "c1").unwrap();
+    assert!(run("b?", "c#1").unwrap_err().contains("already exists"));
+    assert_eq!(fs::read_to_string(temp_dir.join("c1")).unwrap(), "c1");
+
+    // Failed moves are reported
+    assert!(run("b?", "c1/#1").unwrap_err().contains("failed to move"));
+    assert!(temp_dir.join("b1").exists());
+}
+
+#[named]
+#[test]
+fn ext_skips_directories() {
+    let temp_dir = prepare(function_name!());
+    fs::write(temp_dir.join("a.jpg"), "a").unwrap();
+    fs::write(temp_dir.join("b.txt"), "b").unwrap();
+    fs::create_dir(temp_dir.join("c.jpg")).unwrap();
+
+    let mut args: Vec<OsString> = [
+        PathBuf::from("--ext"),
+        PathBuf::from("jpg"),
+        temp_dir.join("*.*"),
+        temp_dir.join("x_#1.#2"),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.insert(0, env::args_os().next().unwrap());
+    assert_eq!(try_main(&args), Ok(()));
+
+    assert!(temp_dir.join("x_a.jpg").is_file());
+    assert!(temp_dir.join("b.txt").is_file());
+    assert!(temp_dir.join("c.jpg").is_dir());
+}
+
+#[cfg(feature = "archive")]
+#[named]
+#[test]
+fn into_archive() {
+    let temp_dir = prepare(function_name!());
+    fs::create_dir_all(temp_dir.join("proj/logs")).unwrap();
+    fs::write(temp_dir.join("proj/logs/a.log"), "a").unwrap();
+    fs::write(temp_dir.join("proj/logs/b.log"), "b").unwrap();
+    let archive = temp_dir.join("logs.tar");
+    let run = |dry_run: bool| {
+        let mut args: Vec<OsString> = [
+            PathBuf::from("--root"),
+            temp_dir.join("proj"),
+            PathBuf::from("--into-archive"),
+            archive.clone(),
+            PathBuf::from("logs/*.log"),
+            PathBuf::from("2024/#1.log"),
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+        if dry_run {
+            args.insert(0, OsString::from("--dry-run"));
+        }
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+
+    // Dry-run writes nothing
+    assert_eq!(run(true), Ok(()));
+    assert!(!archive.exists());
+    assert!(temp_dir.join("proj/logs/a.log").exists());
+
+    // Files are added as members named by DEST and then removed
+    assert_eq!(run(false), Ok(()));
+    assert!(!temp_dir.join("proj/logs/a.log").exists());
+    assert!(!temp_dir.join("proj/logs/b.log").exists());
+    let mut members = tar::Archive::new(fs::File::open(&archive).unwrap());
+    let names: Vec<String> = members
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            entry
+                .unwrap()
+                .path()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    assert_eq!(names, vec!["2024/a.log", "2024/b.log"]);
+
+    // Existing members are never replaced and the files are kept
+    fs::write(temp_dir.join("proj/logs/a.log"), "a2").unwrap();
+    assert!(run(false).unwrap_err().contains("already exists"));
+    assert!(temp_dir.join("proj/logs/a.log").exists());
+}
+
+#[cfg(feature = "archive")]
+#[named]
+#[test]
+fn from_archive() {
+    let temp_dir = prepare(function_name!());
+    fs::create_dir_all(temp_dir.join("proj/logs")).unwrap();
+    fs::write(temp_dir.join("proj/logs/a.log"), "a").unwrap();
+    fs::write(temp_dir.join("proj/logs/b.txt"), "b").unwrap();
+    let archive = temp_dir.join("logs.zip");
+    let run = |args: &[&OsStr]| {
+        let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        args.insert(0, OsString::from("--root"));
+        args.insert(1, temp_dir.join("proj").into_os_string());
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+    let into = [OsStr::new("--into-archive"), archive.as_os_str()];
+    assert_eq!(run(&[into[0], into[1], OsStr::new("logs/*")]), Ok(()));
+
+    // Dry-run extracts nothing
+    let from = [OsStr::new("--from-archive"), archive.as_os_str()];
+    let args = [
+        from[0],
+        from[1],
+        OsStr::new("*.log"),
+        OsStr::new("logs/#1.txt"),
+    ];
+    let mut dry_run = args.to_vec();
+    dry_run.insert(0, OsStr::new("--dry-run"));
+    assert_eq!(run(&dry_run), Ok(()));
+    assert!(!temp_dir.join("proj/logs/a.txt").exists());
+
+    // Only the matched members are extracted
+    assert_eq!(run(&args), Ok(()));
+    assert_eq!(
+        fs::read_to_string(temp_dir.join("proj/logs/a.txt")).unwrap(),
+        "a"
+    );
+    assert!(!temp_dir.join("proj/logs/b.txt").exists());
+
+    // Existing files are never overwritten
+    assert!(run(&args).unwrap_err().contains("already exists"));
+}
+
+#[named]
+#[test]
+fn rules() {
+    let temp_dir = prepare(function_name!());
+    fs::create_dir_all(temp_dir.join("proj/txt")).unwrap();
+    fs::create_dir_all(temp_dir.join("proj/other")).unwrap();
+    fs::write(temp_dir.join("proj/a.txt"), "a").unwrap();
+    fs::write(temp_dir.join("proj/b.md"), "b").unwrap();
+    fs::write(
+        temp_dir.join("rules.tsv"),
+        "# The first matching rule wins\n\
+         *.txt\ttxt/#1.txt\n\
+         \n\
+         *.png\timg/#1.png\n\
+         *.*\tother/#1.#2\n",
+    )
+    .unwrap();
+    let run = |options: &[&str]| {
+        let mut args: Vec<OsString> = options.iter().map(OsString::from).collect();
+        args.extend([
+            OsString::from("--root"),
+            temp_dir.join("proj").into_os_string(),
+            OsString::from("--rules"),
+            temp_dir.join("rules.tsv").into_os_string(),
+        ]);
+        args.insert(0, env::args_os().next().unwrap());
+        try_main(&args)
+    };
+
+    // A rule which matched nothing is warned
+    assert!(run(&["--warnings-as-errors"]).is_err());
+    assert!(temp_dir.join("proj/a.txt").exists());
+
+    assert_eq!(run(&[]), Ok(()));
+    assert!(temp_dir.join("proj/txt/a.txt").exists());
+    assert!(temp_dir.join("proj/other/b.md").exists());
+    assert!(!temp_dir.join("proj/other/a.txt").exists());
+
+    // Rules must be pairs separated by a tab
+    fs::write(temp_dir.join("rules.tsv"), "*.txt txt/#1.txt\n").unwrap();
+    assert!(run(&[]).unwrap_err().contains("rules.tsv:1:"));
+}